@@ -1,12 +1,13 @@
 use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
 use crate::{
+    cert::NocCatIds,
     data_model::objects::{Access, Privilege},
     error::Error,
     fabric,
     interaction_model::messages::GenericPath,
     sys::Psm,
-    tlv::{FromTLV, TLVElement, TLVList, TLVWriter, TagType, ToTLV},
+    tlv::{FabricScoped, FromTLV, TLVElement, TLVList, TLVWriter, TagType, ToTLV},
     utils::writebuf::WriteBuf,
 };
 use log::error;
@@ -56,6 +57,8 @@ pub struct Accessor {
     fab_idx: u8,
     /// Accessor's identified: could be node-id, NoC CAT, group id
     id: u64,
+    /// The CASE Authenticated Tags in the accessor's NOC
+    cats: NocCatIds,
     /// The Authmode of this session
     auth_mode: AuthMode,
     // TODO: Is this the right place for this though, or should we just use a global-acl-handle-get
@@ -67,10 +70,35 @@ impl Accessor {
         Self {
             fab_idx,
             id,
+            cats: Default::default(),
             auth_mode,
             acl_mgr,
         }
     }
+
+    pub fn set_cat_ids(&mut self, cats: &NocCatIds) {
+        self.cats = *cats;
+    }
+
+    pub fn fab_idx(&self) -> u8 {
+        self.fab_idx
+    }
+
+    fn match_subject(&self, subject: u64) -> bool {
+        if self.id == subject {
+            return true;
+        }
+        if is_valid_cat(subject) {
+            // A CAT subject grants access to any NOC that carries the same tag
+            // identifier, with a version that is at least the subject's version
+            let (subj_id, subj_ver) = ((subject >> 16) & 0xFFFF, subject & 0xFFFF);
+            return self.cats.iter().filter(|c| **c != 0).any(|c| {
+                let (id, ver) = ((*c >> 16) as u64, (*c & 0xFFFF) as u64);
+                id == subj_id && ver >= subj_ver
+            });
+        }
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -124,7 +152,7 @@ impl<'a> AccessReq<'a> {
     }
 }
 
-#[derive(FromTLV, ToTLV, Copy, Clone, Debug)]
+#[derive(FromTLV, ToTLV, Copy, Clone, Debug, PartialEq)]
 pub struct Target {
     cluster: Option<u32>,
     endpoint: Option<u16>,
@@ -143,15 +171,14 @@ impl Target {
 
 type Subjects = [Option<u64>; SUBJECTS_PER_ENTRY];
 type Targets = [Option<Target>; TARGETS_PER_ENTRY];
-#[derive(ToTLV, FromTLV, Copy, Clone, Debug)]
+#[derive(ToTLV, FromTLV, FabricScoped, Copy, Clone, Debug, PartialEq)]
 #[tlvargs(start = 1)]
 pub struct AclEntry {
     privilege: Privilege,
     auth_mode: AuthMode,
     subjects: Subjects,
     targets: Targets,
-    // TODO: Instead of the direct value, we should consider GlobalElements::FabricIndex
-    #[tagval(0xFE)]
+    #[tlv(fabric_index)]
     fab_idx: u8,
 }
 
@@ -189,6 +216,35 @@ impl AclEntry {
         Ok(())
     }
 
+    /// Checks that this entry is valid, before it is written to the ACL list
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.auth_mode {
+            // PASE sessions have an implicit grant, they are never part of the ACLs
+            AuthMode::Pase | AuthMode::Invalid => return Err(Error::Invalid),
+            // Administer privilege can't be granted to a group
+            AuthMode::Group if self.privilege.contains(Privilege::A) => return Err(Error::Invalid),
+            _ => (),
+        }
+
+        for s in self.subjects.iter().flatten() {
+            let valid = match self.auth_mode {
+                AuthMode::Case => is_valid_node_id(*s) || is_valid_cat(*s),
+                _ => *s > 0 && *s <= u16::MAX as u64,
+            };
+            if !valid {
+                return Err(Error::Invalid);
+            }
+        }
+
+        for t in self.targets.iter().flatten() {
+            let empty = t.cluster.is_none() && t.endpoint.is_none() && t.device_type.is_none();
+            if empty || (t.endpoint.is_some() && t.device_type.is_some()) {
+                return Err(Error::Invalid);
+            }
+        }
+        Ok(())
+    }
+
     fn match_accessor(&self, accessor: &Accessor) -> bool {
         if self.auth_mode != accessor.auth_mode {
             return false;
@@ -198,7 +254,7 @@ impl AclEntry {
         let mut entries_exist = false;
         for i in self.subjects.iter().flatten() {
             entries_exist = true;
-            if accessor.id == *i {
+            if accessor.match_subject(*i) {
                 allow = true;
             }
         }
@@ -251,6 +307,16 @@ impl AclEntry {
     }
 }
 
+// Operational node ids are in 0x0000_0000_0000_0001..=0xFFFF_FFEF_FFFF_FFFF
+fn is_valid_node_id(id: u64) -> bool {
+    id > 0 && id <= 0xFFFF_FFEF_FFFF_FFFF
+}
+
+// CASE Authenticated Tags are 0xFFFF_FFFD_IIII_VVVV, with a non-zero version
+fn is_valid_cat(id: u64) -> bool {
+    (id >> 32) == 0xFFFF_FFFD && (id & 0xFFFF) != 0
+}
+
 const MAX_ACL_ENTRIES: usize = ENTRIES_PER_FABRIC * fabric::MAX_SUPPORTED_FABRICS;
 type AclEntries = [Option<AclEntry>; MAX_ACL_ENTRIES];
 
@@ -270,6 +336,18 @@ impl AclMgrInner {
         psm.set_kv_slice(ACL_KV_ENTRY, wb.as_slice())
     }
 
+    // Move the empty slots to the end, so that entries that are added later on are listed
+    // after the existing ones
+    fn compact(&mut self) {
+        let mut next = 0;
+        for i in 0..MAX_ACL_ENTRIES {
+            if self.entries[i].is_some() {
+                self.entries.swap(next, i);
+                next += 1;
+            }
+        }
+    }
+
     pub fn load(psm: &MutexGuard<Psm>) -> Result<Self, Error> {
         let mut acl_tlvs = Vec::new();
         psm.get_kv_slice(ACL_KV_ENTRY, &mut acl_tlvs)?;
@@ -307,7 +385,7 @@ impl AclMgr {
         } else {
             let psm_handle = Psm::get()?;
             let inner = {
-                let psm_lock = psm_handle.lock().unwrap();
+                let psm_lock = psm_handle.lock()?;
                 AclMgrInner::load(&psm_lock)
             };
 
@@ -325,21 +403,22 @@ impl AclMgr {
         })
     }
 
-    pub fn erase_all(&self) {
-        let mut inner = self.inner.write().unwrap();
+    pub fn erase_all(&self) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
         for i in 0..MAX_ACL_ENTRIES {
             inner.entries[i] = None;
         }
         if let Some(psm) = self.psm.as_ref() {
-            let psm = psm.lock().unwrap();
+            let psm = psm.lock()?;
             let _ = inner.store(&psm).map_err(|e| {
                 error!("Error in storing ACLs {}", e);
             });
         }
+        Ok(())
     }
 
     pub fn add(&self, entry: AclEntry) -> Result<(), Error> {
-        let mut inner = self.inner.write().unwrap();
+        let mut inner = self.inner.write()?;
         let cnt = inner
             .entries
             .iter()
@@ -356,8 +435,87 @@ impl AclMgr {
             .ok_or(Error::NoSpace)?;
         inner.entries[index] = Some(entry);
 
+        self.store(&inner)
+    }
+
+    /// Replace the entry at `index` in the list of entries of the fabric
+    pub fn edit(&self, index: u16, fab_idx: u8, new: AclEntry) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
+        let old = inner
+            .entries
+            .iter_mut()
+            .flatten()
+            .filter(|a| a.fab_idx == fab_idx)
+            .nth(index as usize)
+            .ok_or(Error::NotFound)?;
+        *old = new;
+
+        self.store(&inner)
+    }
+
+    /// Delete the entry at `index` in the list of entries of the fabric
+    pub fn delete(&self, index: u16, fab_idx: u8) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
+        let old = inner
+            .entries
+            .iter_mut()
+            .filter(|a| matches!(a, Some(e) if e.fab_idx == fab_idx))
+            .nth(index as usize)
+            .ok_or(Error::NotFound)?;
+        *old = None;
+        inner.compact();
+
+        self.store(&inner)
+    }
+
+    /// Delete all the entries of the fabric
+    pub fn delete_for_fabric(&self, fab_idx: u8) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
+        for entry in inner.entries.iter_mut() {
+            if matches!(entry, Some(e) if e.fab_idx == fab_idx) {
+                *entry = None;
+            }
+        }
+        inner.compact();
+
+        self.store(&inner)
+    }
+
+    /// Replace all the entries of the fabric with 'entries'
+    ///
+    /// If the entries don't fit, this fails with NoSpace before any of the existing entries
+    /// of the fabric is touched.
+    pub fn replace_for_fabric(&self, fab_idx: u8, entries: Vec<AclEntry>) -> Result<(), Error> {
+        if entries.iter().any(|e| e.fab_idx != fab_idx) {
+            return Err(Error::Invalid);
+        }
+        let mut inner = self.inner.write()?;
+        let others = inner
+            .entries
+            .iter()
+            .flatten()
+            .filter(|e| e.fab_idx != fab_idx)
+            .count();
+        if entries.len() > ENTRIES_PER_FABRIC || others + entries.len() > MAX_ACL_ENTRIES {
+            return Err(Error::NoSpace);
+        }
+
+        for entry in inner.entries.iter_mut() {
+            if matches!(entry, Some(e) if e.fab_idx == fab_idx) {
+                *entry = None;
+            }
+        }
+        inner.compact();
+        for (slot, entry) in inner.entries[others..].iter_mut().zip(entries) {
+            *slot = Some(entry);
+        }
+
+        self.store(&inner)
+    }
+
+    fn store(&self, inner: &AclMgrInner) -> Result<(), Error> {
         if let Some(psm) = self.psm.as_ref() {
-            let psm = psm.lock().unwrap();
+            let psm = psm.lock()?;
             inner.store(&psm)
         } else {
             Ok(())
@@ -368,7 +526,7 @@ impl AclMgr {
     where
         T: FnMut(&AclEntry),
     {
-        let inner = self.inner.read().unwrap();
+        let inner = self.inner.read()?;
         for entry in &inner.entries {
             if let Some(entry) = entry {
                 f(entry)
@@ -382,7 +540,13 @@ impl AclMgr {
         if req.accessor.auth_mode == AuthMode::Pase {
             return true;
         }
-        let inner = self.inner.read().unwrap();
+        let inner = match self.inner.read() {
+            Ok(inner) => inner,
+            Err(_) => {
+                error!("ACL Disallow, the ACLs are poisoned");
+                return false;
+            }
+        };
         for e in inner.entries.iter().flatten() {
             if e.allow(req) {
                 return true;
@@ -399,7 +563,7 @@ impl AclMgr {
 
 impl std::fmt::Display for AclMgr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let inner = self.inner.read().unwrap();
+        let inner = self.inner.read().map_err(|_| std::fmt::Error)?;
         write!(f, "ACLS: [")?;
         for i in inner.entries.iter().flatten() {
             write!(f, "  {{ {:?} }}, ", i)?;
@@ -412,6 +576,7 @@ impl std::fmt::Display for AclMgr {
 mod tests {
     use crate::{
         data_model::objects::{Access, Privilege},
+        error::Error,
         interaction_model::messages::GenericPath,
     };
     use std::sync::Arc;
@@ -421,7 +586,7 @@ mod tests {
     #[test]
     fn test_basic_empty_subject_target() {
         let am = Arc::new(AclMgr::new_with(false).unwrap());
-        am.erase_all();
+        am.erase_all().unwrap();
         let accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
         let path = GenericPath::new(Some(1), Some(1234), None);
         let mut req = AccessReq::new(&accessor, &path, Access::READ);
@@ -449,7 +614,7 @@ mod tests {
     #[test]
     fn test_subject() {
         let am = Arc::new(AclMgr::new_with(false).unwrap());
-        am.erase_all();
+        am.erase_all().unwrap();
         let accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
         let path = GenericPath::new(Some(1), Some(1234), None);
         let mut req = AccessReq::new(&accessor, &path, Access::READ);
@@ -468,10 +633,45 @@ mod tests {
         assert_eq!(req.allow(), true);
     }
 
+    #[test]
+    fn test_cat_subject() {
+        let am = Arc::new(AclMgr::new_with(false).unwrap());
+        am.erase_all().unwrap();
+        let mut new = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
+        new.add_subject(0xFFFF_FFFD_0001_0002).unwrap();
+        am.add(new).unwrap();
+        let path = GenericPath::new(Some(1), Some(1234), None);
+
+        // Allow for the same CAT id with a higher version
+        let mut accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
+        accessor.set_cat_ids(&[0x0002_0005, 0x0001_0003, 0]);
+        let mut req = AccessReq::new(&accessor, &path, Access::READ);
+        req.set_target_perms(Access::RWVA);
+        assert!(req.allow());
+
+        // Allow for the same CAT id with the same version
+        accessor.set_cat_ids(&[0x0001_0002, 0, 0]);
+        let mut req = AccessReq::new(&accessor, &path, Access::READ);
+        req.set_target_perms(Access::RWVA);
+        assert!(req.allow());
+
+        // Deny for the same CAT id with a lower version
+        accessor.set_cat_ids(&[0x0001_0001, 0, 0]);
+        let mut req = AccessReq::new(&accessor, &path, Access::READ);
+        req.set_target_perms(Access::RWVA);
+        assert!(!req.allow());
+
+        // Deny for a different CAT id
+        accessor.set_cat_ids(&[0x0003_0002, 0, 0]);
+        let mut req = AccessReq::new(&accessor, &path, Access::READ);
+        req.set_target_perms(Access::RWVA);
+        assert!(!req.allow());
+    }
+
     #[test]
     fn test_target() {
         let am = Arc::new(AclMgr::new_with(false).unwrap());
-        am.erase_all();
+        am.erase_all().unwrap();
         let accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
         let path = GenericPath::new(Some(1), Some(1234), None);
         let mut req = AccessReq::new(&accessor, &path, Access::READ);
@@ -500,7 +700,7 @@ mod tests {
         assert_eq!(req.allow(), true);
 
         // Clean Slate
-        am.erase_all();
+        am.erase_all().unwrap();
 
         // Allow for endpoint match - subject wildcard
         let mut new = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
@@ -514,7 +714,7 @@ mod tests {
         assert_eq!(req.allow(), true);
 
         // Clean Slate
-        am.erase_all();
+        am.erase_all().unwrap();
 
         // Allow for exact match
         let mut new = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
@@ -532,7 +732,7 @@ mod tests {
     #[test]
     fn test_privilege() {
         let am = Arc::new(AclMgr::new_with(false).unwrap());
-        am.erase_all();
+        am.erase_all().unwrap();
         let accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
         let path = GenericPath::new(Some(1), Some(1234), None);
 
@@ -568,4 +768,131 @@ mod tests {
         req.set_target_perms(Access::RWVA);
         assert_eq!(req.allow(), true);
     }
+
+    #[test]
+    fn test_validate() {
+        // Valid node id and CAT subjects
+        let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+        new.add_subject(112233).unwrap();
+        new.add_subject(0xFFFF_FFFD_0001_0001).unwrap();
+        assert!(new.validate().is_ok());
+
+        // Invalid node id subject
+        let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+        new.add_subject(0).unwrap();
+        assert!(new.validate().is_err());
+
+        // PASE entries aren't allowed
+        let new = AclEntry::new(1, Privilege::VIEW, AuthMode::Pase);
+        assert!(new.validate().is_err());
+
+        // Groups can't be granted Administer
+        let new = AclEntry::new(1, Privilege::ADMIN, AuthMode::Group);
+        assert!(new.validate().is_err());
+
+        // Targets can't be empty, or have both an endpoint and a device type
+        let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+        new.add_target(Target::new(None, None, None)).unwrap();
+        assert!(new.validate().is_err());
+        let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+        new.add_target(Target::new(Some(1), None, Some(0x16)))
+            .unwrap();
+        assert!(new.validate().is_err());
+    }
+
+    #[test]
+    fn test_fabric_scoped_ops() {
+        let am = AclMgr::new_with(false).unwrap();
+        let entries = |am: &AclMgr, fab_idx| {
+            let mut v = Vec::new();
+            am.for_each_acl(|e| {
+                if e.fab_idx == fab_idx {
+                    v.push(e.privilege)
+                }
+            })
+            .unwrap();
+            v
+        };
+        am.add(AclEntry::new(1, Privilege::VIEW, AuthMode::Case))
+            .unwrap();
+        am.add(AclEntry::new(2, Privilege::VIEW, AuthMode::Case))
+            .unwrap();
+        am.add(AclEntry::new(1, Privilege::OPERATE, AuthMode::Case))
+            .unwrap();
+
+        // Indices are relative to the fabric
+        am.edit(1, 1, AclEntry::new(1, Privilege::MANAGE, AuthMode::Case))
+            .unwrap();
+        assert_eq!(entries(&am, 1), [Privilege::VIEW, Privilege::MANAGE]);
+        assert!(am.delete(2, 1).is_err());
+
+        // New entries go after the remaining ones
+        am.delete(0, 1).unwrap();
+        am.add(AclEntry::new(1, Privilege::ADMIN, AuthMode::Case))
+            .unwrap();
+        assert_eq!(entries(&am, 1), [Privilege::MANAGE, Privilege::ADMIN]);
+
+        am.delete_for_fabric(1).unwrap();
+        assert!(entries(&am, 1).is_empty());
+        assert_eq!(entries(&am, 2), [Privilege::VIEW]);
+    }
+
+    #[test]
+    fn test_replace_for_fabric() {
+        let am = AclMgr::new_with(false).unwrap();
+        let privileges = |am: &AclMgr| {
+            let mut v = Vec::new();
+            am.for_each_acl(|e| v.push((e.fab_idx, e.privilege)))
+                .unwrap();
+            v
+        };
+        am.add(AclEntry::new(1, Privilege::VIEW, AuthMode::Case))
+            .unwrap();
+        am.add(AclEntry::new(2, Privilege::VIEW, AuthMode::Case))
+            .unwrap();
+
+        // More entries than a fabric can have, the existing ones are left as they are
+        let too_many = vec![AclEntry::new(1, Privilege::ADMIN, AuthMode::Case); 4];
+        assert_eq!(am.replace_for_fabric(1, too_many), Err(Error::NoSpace));
+        let other_fabric = vec![AclEntry::new(2, Privilege::ADMIN, AuthMode::Case)];
+        assert_eq!(am.replace_for_fabric(1, other_fabric), Err(Error::Invalid));
+        assert_eq!(
+            privileges(&am),
+            [(1, Privilege::VIEW), (2, Privilege::VIEW)]
+        );
+
+        let new = vec![
+            AclEntry::new(1, Privilege::OPERATE, AuthMode::Case),
+            AclEntry::new(1, Privilege::ADMIN, AuthMode::Case),
+        ];
+        am.replace_for_fabric(1, new).unwrap();
+        assert_eq!(
+            privileges(&am),
+            [
+                (2, Privilege::VIEW),
+                (1, Privilege::OPERATE),
+                (1, Privilege::ADMIN)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poisoned_lock() {
+        let am = Arc::new(AclMgr::new_with(false).unwrap());
+        let accessor = Accessor::new(2, 112233, AuthMode::Case, am.clone());
+        let path = GenericPath::new(Some(1), Some(1234), None);
+        let req = AccessReq::new(&accessor, &path, Access::READ);
+
+        // A panic while the lock is held
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _inner = am.inner.write().unwrap();
+            panic!("Simulated panic with the ACLs locked");
+        }));
+        assert!(result.is_err());
+
+        let new = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
+        assert_eq!(am.add(new), Err(Error::RwLock));
+        assert_eq!(am.for_each_acl(|_| ()), Err(Error::RwLock));
+        assert!(!am.allow(&req));
+    }
 }
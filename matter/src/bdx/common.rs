@@ -0,0 +1,72 @@
+use bitflags::bitflags;
+use num_derive::FromPrimitive;
+
+use crate::error::Error;
+
+/* Bulk Data Transfer Protocol ID as per the Matter Spec */
+pub const PROTO_ID_BDX: usize = 0x02;
+
+/* The version of the BDX protocol that we support */
+pub const BDX_VERSION: u8 = 0;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    SendInit = 0x01,
+    SendAccept = 0x02,
+    ReceiveInit = 0x04,
+    ReceiveAccept = 0x05,
+    BlockQuery = 0x10,
+    Block = 0x11,
+    BlockEOF = 0x12,
+    BlockAck = 0x13,
+    BlockAckEOF = 0x14,
+    BlockQueryWithSkip = 0x15,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusCode {
+    LengthTooLarge = 0x0012,
+    LengthTooShort = 0x0013,
+    LengthMismatch = 0x0014,
+    LengthRequired = 0x0015,
+    BadMessageContents = 0x0016,
+    BadBlockCounter = 0x0017,
+    UnexpectedMessage = 0x0018,
+    ResponderBusy = 0x0019,
+    TransferFailedUnknownError = 0x001F,
+    TransferMethodNotSupported = 0x0050,
+    FileDesignatorUnknown = 0x0051,
+    StartOffsetNotSupported = 0x0052,
+    VersionNotSupported = 0x0053,
+    Unknown = 0x005F,
+}
+
+bitflags! {
+    /// The Transfer Control field: the protocol version in the lower nibble, and the drive modes
+    #[derive(Default)]
+    pub struct TransferControl: u8 {
+        const VERSION_MASK = 0x0F;
+        const SENDER_DRIVE = 0x10;
+        const RECEIVER_DRIVE = 0x20;
+        const ASYNC = 0x40;
+    }
+}
+
+bitflags! {
+    /// The Range Control field
+    #[derive(Default)]
+    pub struct RangeControl: u8 {
+        const DEFLEN = 0x01;
+        const START_OFFSET = 0x02;
+        const WIDERANGE = 0x10;
+    }
+}
+
+impl From<Error> for StatusCode {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::TruncatedPacket => StatusCode::BadMessageContents,
+            _ => StatusCode::TransferFailedUnknownError,
+        }
+    }
+}
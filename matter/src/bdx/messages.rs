@@ -0,0 +1,216 @@
+use crate::{
+    error::Error,
+    utils::{parsebuf::ParseBuf, writebuf::WriteBuf},
+};
+
+use super::common::*;
+
+fn write_range_val(wb: &mut WriteBuf, wide: bool, val: u64) -> Result<(), Error> {
+    if wide {
+        wb.le_u64(val)
+    } else {
+        wb.le_u32(val as u32)
+    }
+}
+
+fn read_range_val(pb: &mut ParseBuf, wide: bool) -> Result<u64, Error> {
+    if wide {
+        pb.le_u64()
+    } else {
+        pb.le_u32().map(|v| v as u64)
+    }
+}
+
+fn is_wide(vals: &[Option<u64>]) -> bool {
+    vals.iter()
+        .any(|v| matches!(v, Some(v) if *v > u32::MAX as u64))
+}
+
+/// The SendInit and ReceiveInit messages
+///
+/// Any Metadata that follows the File Designator is ignored.
+#[derive(Debug, PartialEq)]
+pub struct TransferInit<'a> {
+    pub version: u8,
+    /// The proposed drive modes
+    pub ctrl: TransferControl,
+    pub max_block_size: u16,
+    pub start_offset: Option<u64>,
+    pub max_length: Option<u64>,
+    pub file_designator: &'a [u8],
+}
+
+impl<'a> TransferInit<'a> {
+    pub fn encode(&self, wb: &mut WriteBuf) -> Result<(), Error> {
+        let wide = is_wide(&[self.start_offset, self.max_length]);
+        let mut range = RangeControl::empty();
+        range.set(RangeControl::DEFLEN, self.max_length.is_some());
+        range.set(RangeControl::START_OFFSET, self.start_offset.is_some());
+        range.set(RangeControl::WIDERANGE, wide);
+
+        let ctrl = self.ctrl - TransferControl::VERSION_MASK;
+        wb.le_u8(ctrl.bits() | (self.version & TransferControl::VERSION_MASK.bits()))?;
+        wb.le_u8(range.bits())?;
+        wb.le_u16(self.max_block_size)?;
+        if let Some(s) = self.start_offset {
+            write_range_val(wb, wide, s)?;
+        }
+        if let Some(l) = self.max_length {
+            write_range_val(wb, wide, l)?;
+        }
+        wb.le_u16(self.file_designator.len() as u16)?;
+        wb.copy_from_slice(self.file_designator)
+    }
+
+    pub fn decode(pb: &'a mut ParseBuf) -> Result<Self, Error> {
+        let ctrl = pb.le_u8()?;
+        let range = RangeControl::from_bits_truncate(pb.le_u8()?);
+        let wide = range.contains(RangeControl::WIDERANGE);
+        let max_block_size = pb.le_u16()?;
+        let start_offset = if range.contains(RangeControl::START_OFFSET) {
+            Some(read_range_val(pb, wide)?)
+        } else {
+            None
+        };
+        let max_length = if range.contains(RangeControl::DEFLEN) {
+            Some(read_range_val(pb, wide)?)
+        } else {
+            None
+        };
+        let fd_len = pb.le_u16()? as usize;
+        let rest = pb.as_borrow_slice();
+        if rest.len() < fd_len {
+            return Err(Error::TruncatedPacket);
+        }
+
+        Ok(Self {
+            version: ctrl & TransferControl::VERSION_MASK.bits(),
+            ctrl: TransferControl::from_bits_truncate(ctrl) - TransferControl::VERSION_MASK,
+            max_block_size,
+            start_offset,
+            max_length,
+            file_designator: &rest[..fd_len],
+        })
+    }
+}
+
+/// The ReceiveAccept message
+///
+/// Any Metadata that follows is ignored.
+#[derive(Debug, PartialEq)]
+pub struct ReceiveAccept {
+    pub version: u8,
+    /// The chosen drive mode
+    pub ctrl: TransferControl,
+    pub max_block_size: u16,
+    pub length: Option<u64>,
+}
+
+impl ReceiveAccept {
+    pub fn encode(&self, wb: &mut WriteBuf) -> Result<(), Error> {
+        let wide = is_wide(&[self.length]);
+        let mut range = RangeControl::empty();
+        range.set(RangeControl::DEFLEN, self.length.is_some());
+        range.set(RangeControl::WIDERANGE, wide);
+
+        let ctrl = self.ctrl - TransferControl::VERSION_MASK;
+        wb.le_u8(ctrl.bits() | (self.version & TransferControl::VERSION_MASK.bits()))?;
+        wb.le_u8(range.bits())?;
+        wb.le_u16(self.max_block_size)?;
+        if let Some(l) = self.length {
+            write_range_val(wb, wide, l)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode(pb: &mut ParseBuf) -> Result<Self, Error> {
+        let ctrl = pb.le_u8()?;
+        let range = RangeControl::from_bits_truncate(pb.le_u8()?);
+        let max_block_size = pb.le_u16()?;
+        let length = if range.contains(RangeControl::DEFLEN) {
+            Some(read_range_val(pb, range.contains(RangeControl::WIDERANGE))?)
+        } else {
+            None
+        };
+        Ok(Self {
+            version: ctrl & TransferControl::VERSION_MASK.bits(),
+            ctrl: TransferControl::from_bits_truncate(ctrl) - TransferControl::VERSION_MASK,
+            max_block_size,
+            length,
+        })
+    }
+}
+
+/// The Block and BlockEOF messages
+pub fn encode_block(wb: &mut WriteBuf, counter: u32, data: &[u8]) -> Result<(), Error> {
+    wb.le_u32(counter)?;
+    wb.copy_from_slice(data)
+}
+
+/// The Block and BlockEOF messages, returns the block counter and the data
+pub fn decode_block<'a>(pb: &'a mut ParseBuf) -> Result<(u32, &'a [u8]), Error> {
+    let counter = pb.le_u32()?;
+    Ok((counter, pb.as_borrow_slice()))
+}
+
+/// The BlockQuery, BlockAck and BlockAckEOF messages, which only carry the block counter
+pub fn encode_counter(wb: &mut WriteBuf, counter: u32) -> Result<(), Error> {
+    wb.le_u32(counter)
+}
+
+/// The BlockQuery, BlockAck and BlockAckEOF messages, which only carry the block counter
+pub fn decode_counter(pb: &mut ParseBuf) -> Result<u32, Error> {
+    pb.le_u32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReceiveAccept, TransferInit};
+    use crate::{
+        bdx::common::TransferControl,
+        utils::{parsebuf::ParseBuf, writebuf::WriteBuf},
+    };
+
+    #[test]
+    fn test_transfer_init() {
+        let init = TransferInit {
+            version: 0,
+            ctrl: TransferControl::RECEIVER_DRIVE | TransferControl::SENDER_DRIVE,
+            max_block_size: 1024,
+            start_offset: None,
+            max_length: Some(0x1_0000_0000),
+            file_designator: b"ota.bin",
+        };
+        let mut buf = [0; 64];
+        let len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, len);
+        init.encode(&mut wb).unwrap();
+        let expected: [u8; 21] = [
+            0x30, 0x11, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07, 0x00,
+            b'o', b't', b'a', b'.', b'b', b'i', b'n',
+        ];
+        assert_eq!(wb.as_borrow_slice(), expected);
+
+        let mut pb = ParseBuf::new(&mut buf, expected.len());
+        assert_eq!(TransferInit::decode(&mut pb).unwrap(), init);
+    }
+
+    #[test]
+    fn test_receive_accept() {
+        let accept = ReceiveAccept {
+            version: 0,
+            ctrl: TransferControl::RECEIVER_DRIVE,
+            max_block_size: 256,
+            length: Some(1000),
+        };
+        let mut buf = [0; 16];
+        let len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, len);
+        accept.encode(&mut wb).unwrap();
+        let expected: [u8; 8] = [0x20, 0x01, 0x00, 0x01, 0xe8, 0x03, 0x00, 0x00];
+        assert_eq!(wb.as_borrow_slice(), expected);
+
+        let mut pb = ParseBuf::new(&mut buf, expected.len());
+        assert_eq!(ReceiveAccept::decode(&mut pb).unwrap(), accept);
+    }
+}
@@ -0,0 +1,445 @@
+use log::{error, info};
+
+use crate::{
+    error::Error,
+    secure_channel::status_report::{create_status_report, GeneralCode},
+    transport::proto_demux::{HandleProto, ProtoCtx, ResponseRequired},
+    utils::{parsebuf::ParseBuf, writebuf::WriteBuf},
+};
+
+use super::{common::*, messages::*};
+
+/// The consumer of the data received over a BDX transfer
+///
+/// The transfer can be kept in the state that threads share, like that of the OTA Requestor.
+pub trait BdxSink: Send + Sync {
+    /// Consume the next block of data, blocks are delivered in order
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// The transfer completed, all the data has been delivered
+    fn done(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum State {
+    Idle,
+    AwaitAccept,
+    AwaitBlock,
+    Done,
+    Failed,
+}
+
+/// A BDX transfer, where we are the Receiver and the Initiator (as in an OTA download)
+///
+/// The transfer proposes the drive modes and the maximum block size in the ReceiveInit, and
+/// the Sender picks from those in its ReceiveAccept. Only the synchronous modes are supported,
+/// the Asynchronous mode is optional in the spec.
+pub struct BdxTransfer {
+    state: State,
+    proposed: TransferControl,
+    drive: TransferControl,
+    max_block_size: u16,
+    length: Option<u64>,
+    received: u64,
+    // The counter of the next block that we expect
+    block_counter: u32,
+    failure: Option<StatusCode>,
+    sink: Box<dyn BdxSink>,
+}
+
+impl BdxTransfer {
+    /// Create a Receiver that proposes the drive modes in `ctrl` and blocks of at most
+    /// `max_block_size` bytes
+    pub fn new_receiver(
+        sink: Box<dyn BdxSink>,
+        ctrl: TransferControl,
+        max_block_size: u16,
+    ) -> Self {
+        Self {
+            state: State::Idle,
+            proposed: ctrl - TransferControl::VERSION_MASK,
+            drive: TransferControl::empty(),
+            max_block_size,
+            length: None,
+            received: 0,
+            block_counter: 0,
+            failure: None,
+            sink,
+        }
+    }
+
+    /// Write the ReceiveInit that starts the transfer of `file_designator`
+    pub fn receive_init(
+        &mut self,
+        file_designator: &[u8],
+        max_length: Option<u64>,
+        tx: &mut WriteBuf,
+    ) -> Result<OpCode, Error> {
+        if self.state != State::Idle {
+            return Err(Error::InvalidState);
+        }
+        TransferInit {
+            version: BDX_VERSION,
+            ctrl: self.proposed,
+            max_block_size: self.max_block_size,
+            start_offset: None,
+            max_length,
+            file_designator,
+        }
+        .encode(tx)?;
+        self.length = max_length;
+        self.state = State::AwaitAccept;
+        Ok(OpCode::ReceiveInit)
+    }
+
+    /// Handle a message from the Sender
+    ///
+    /// Returns the opcode of the response that was written to `tx`, if any. On an error the
+    /// transfer is aborted, and the status to report to the Sender is available through
+    /// [`get_failure`](BdxTransfer::get_failure).
+    pub fn handle(
+        &mut self,
+        opcode: OpCode,
+        rx: &mut ParseBuf,
+        tx: &mut WriteBuf,
+    ) -> Result<Option<OpCode>, Error> {
+        let result = match (self.state, opcode) {
+            (State::AwaitAccept, OpCode::ReceiveAccept) => self.handle_receive_accept(rx, tx),
+            (State::AwaitBlock, OpCode::Block) => self.handle_block(rx, tx, false),
+            (State::AwaitBlock, OpCode::BlockEOF) => self.handle_block(rx, tx, true),
+            _ => Err(StatusCode::UnexpectedMessage),
+        };
+        result.map_err(|code| {
+            error!("BDX transfer failed in {:?}: {:?}", self.state, code);
+            self.state = State::Failed;
+            self.failure = Some(code);
+            Error::Invalid
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.received
+    }
+
+    /// The length of the data being transferred, if the Sender declared it
+    pub fn get_length(&self) -> Option<u64> {
+        self.length
+    }
+
+    pub fn get_failure(&self) -> Option<StatusCode> {
+        self.failure
+    }
+
+    fn handle_receive_accept(
+        &mut self,
+        rx: &mut ParseBuf,
+        tx: &mut WriteBuf,
+    ) -> Result<Option<OpCode>, StatusCode> {
+        let accept = ReceiveAccept::decode(rx)?;
+        if accept.version > BDX_VERSION {
+            return Err(StatusCode::VersionNotSupported);
+        }
+        if accept.ctrl.contains(TransferControl::ASYNC)
+            || !self.proposed.contains(accept.ctrl)
+            || (accept.ctrl != TransferControl::SENDER_DRIVE
+                && accept.ctrl != TransferControl::RECEIVER_DRIVE)
+        {
+            return Err(StatusCode::TransferMethodNotSupported);
+        }
+        if accept.max_block_size == 0 || accept.max_block_size > self.max_block_size {
+            return Err(StatusCode::BadMessageContents);
+        }
+        if let (Some(max), Some(len)) = (self.length, accept.length) {
+            if len > max {
+                return Err(StatusCode::LengthTooLarge);
+            }
+        }
+        info!(
+            "BDX transfer accepted: {:?}, block size {}, length {:?}",
+            accept.ctrl, accept.max_block_size, accept.length
+        );
+        self.drive = accept.ctrl;
+        self.max_block_size = accept.max_block_size;
+        self.length = accept.length;
+        self.state = State::AwaitBlock;
+
+        if self.drive == TransferControl::RECEIVER_DRIVE {
+            encode_counter(tx, self.block_counter)?;
+            Ok(Some(OpCode::BlockQuery))
+        } else {
+            // The Sender sends the first Block on its own
+            Ok(None)
+        }
+    }
+
+    fn handle_block(
+        &mut self,
+        rx: &mut ParseBuf,
+        tx: &mut WriteBuf,
+        eof: bool,
+    ) -> Result<Option<OpCode>, StatusCode> {
+        let (counter, data) = decode_block(rx)?;
+        if counter != self.block_counter {
+            return Err(StatusCode::BadBlockCounter);
+        }
+        if data.len() > self.max_block_size as usize || (data.is_empty() && !eof) {
+            return Err(StatusCode::BadMessageContents);
+        }
+        self.received += data.len() as u64;
+        if let Some(len) = self.length {
+            if self.received > len {
+                return Err(StatusCode::LengthTooLarge);
+            }
+            if eof && self.received < len {
+                return Err(StatusCode::LengthTooShort);
+            }
+        }
+        self.sink.write(data)?;
+
+        if eof {
+            self.sink.done()?;
+            encode_counter(tx, counter)?;
+            self.state = State::Done;
+            info!("BDX transfer complete, {} bytes", self.received);
+            Ok(Some(OpCode::BlockAckEOF))
+        } else {
+            self.block_counter = self.block_counter.wrapping_add(1);
+            if self.drive == TransferControl::RECEIVER_DRIVE {
+                encode_counter(tx, self.block_counter)?;
+                Ok(Some(OpCode::BlockQuery))
+            } else {
+                encode_counter(tx, counter)?;
+                Ok(Some(OpCode::BlockAck))
+            }
+        }
+    }
+}
+
+impl HandleProto for BdxTransfer {
+    fn handle_proto_id(&mut self, ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
+        let opcode: OpCode =
+            num::FromPrimitive::from_u8(ctx.rx.get_proto_opcode()).ok_or(Error::InvalidOpcode)?;
+        let tx_start = ctx.tx.get_writebuf()?.get_tail();
+        match self.handle(opcode, ctx.rx.get_parsebuf()?, ctx.tx.get_writebuf()?) {
+            Ok(Some(resp)) => {
+                ctx.tx.set_proto_id(PROTO_ID_BDX as u16);
+                ctx.tx.set_proto_opcode(resp as u8);
+                if self.is_done() {
+                    ctx.exch_ctx.exch.close();
+                }
+                Ok(ResponseRequired::Yes)
+            }
+            Ok(None) => Ok(ResponseRequired::No),
+            Err(_) => {
+                // Drop any partially written response, and report the failure to the Sender
+                ctx.tx.get_writebuf()?.rewind_tail_to(tx_start);
+                let code = self.failure.unwrap_or(StatusCode::Unknown);
+                create_status_report(
+                    &mut ctx.tx,
+                    GeneralCode::Failure,
+                    PROTO_ID_BDX as u32,
+                    code as u16,
+                    None,
+                )?;
+                ctx.exch_ctx.exch.close();
+                Ok(ResponseRequired::Yes)
+            }
+        }
+    }
+
+    fn get_proto_id(&self) -> usize {
+        PROTO_ID_BDX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{BdxSink, BdxTransfer};
+    use crate::{
+        bdx::{common::*, messages::*},
+        error::Error,
+        utils::{parsebuf::ParseBuf, writebuf::WriteBuf},
+    };
+
+    const SENDER_MAX_BLOCK_SIZE: u16 = 100;
+
+    struct TestSink {
+        data: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl BdxSink for TestSink {
+        fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.data.lock().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    type Msg = (OpCode, Vec<u8>);
+
+    fn write_msg<F>(f: F) -> Msg
+    where
+        F: FnOnce(&mut WriteBuf) -> OpCode,
+    {
+        let mut buf = [0; 256];
+        let len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, len);
+        let opcode = f(&mut wb);
+        (opcode, wb.as_borrow_slice().to_vec())
+    }
+
+    // An in-memory Sender, serving a payload
+    struct TestSender<'a> {
+        payload: &'a [u8],
+        drive: TransferControl,
+        block_size: usize,
+        offset: usize,
+        counter: u32,
+    }
+
+    impl<'a> TestSender<'a> {
+        fn new(payload: &'a [u8], drive: TransferControl) -> Self {
+            Self {
+                payload,
+                drive,
+                block_size: 0,
+                offset: 0,
+                counter: 0,
+            }
+        }
+
+        fn next_block(&mut self) -> Msg {
+            let end = std::cmp::min(self.offset + self.block_size, self.payload.len());
+            let data = &self.payload[self.offset..end];
+            let counter = self.counter;
+            self.offset = end;
+            self.counter += 1;
+            let eof = end == self.payload.len();
+            write_msg(|wb| {
+                encode_block(wb, counter, data).unwrap();
+                if eof {
+                    OpCode::BlockEOF
+                } else {
+                    OpCode::Block
+                }
+            })
+        }
+
+        // Returns the response to a Receiver message, if any
+        fn handle(&mut self, msg: &Msg) -> Option<Msg> {
+            let mut buf = msg.1.clone();
+            let len = buf.len();
+            let mut pb = ParseBuf::new(&mut buf, len);
+            match msg.0 {
+                OpCode::ReceiveInit => {
+                    let init = TransferInit::decode(&mut pb).unwrap();
+                    assert_eq!(init.file_designator, b"ota.bin");
+                    assert!(init.ctrl.contains(self.drive));
+                    self.block_size =
+                        std::cmp::min(init.max_block_size, SENDER_MAX_BLOCK_SIZE) as usize;
+                    let accept = ReceiveAccept {
+                        version: BDX_VERSION,
+                        ctrl: self.drive,
+                        max_block_size: self.block_size as u16,
+                        length: Some(self.payload.len() as u64),
+                    };
+                    Some(write_msg(|wb| {
+                        accept.encode(wb).unwrap();
+                        OpCode::ReceiveAccept
+                    }))
+                }
+                OpCode::BlockQuery => {
+                    assert_eq!(decode_counter(&mut pb).unwrap(), self.counter);
+                    Some(self.next_block())
+                }
+                OpCode::BlockAck => {
+                    assert_eq!(decode_counter(&mut pb).unwrap(), self.counter - 1);
+                    Some(self.next_block())
+                }
+                OpCode::BlockAckEOF => None,
+                _ => panic!("Unexpected message {:?}", msg.0),
+            }
+        }
+    }
+
+    fn receive(receiver: &mut BdxTransfer, msg: &Msg) -> Result<Option<Msg>, Error> {
+        let mut rx_buf = msg.1.clone();
+        let rx_len = rx_buf.len();
+        let mut pb = ParseBuf::new(&mut rx_buf, rx_len);
+        let mut tx_buf = [0; 256];
+        let tx_len = tx_buf.len();
+        let mut wb = WriteBuf::new(&mut tx_buf, tx_len);
+        let resp = receiver.handle(msg.0, &mut pb, &mut wb)?;
+        Ok(resp.map(|opcode| (opcode, wb.as_borrow_slice().to_vec())))
+    }
+
+    fn transfer(payload: &[u8], drive: TransferControl) -> Vec<u8> {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let mut receiver = BdxTransfer::new_receiver(
+            Box::new(TestSink { data: data.clone() }),
+            TransferControl::SENDER_DRIVE | TransferControl::RECEIVER_DRIVE,
+            128,
+        );
+        let mut sender = TestSender::new(payload, drive);
+
+        let init = write_msg(|wb| receiver.receive_init(b"ota.bin", None, wb).unwrap());
+        let mut to_receiver = sender.handle(&init).unwrap();
+        loop {
+            to_receiver = match receive(&mut receiver, &to_receiver).unwrap() {
+                Some(resp) => match sender.handle(&resp) {
+                    Some(m) => m,
+                    None => break,
+                },
+                // Sender driven, the first Block follows the ReceiveAccept
+                None => sender.next_block(),
+            };
+        }
+        assert!(receiver.is_done());
+        assert_eq!(receiver.bytes_received(), payload.len() as u64);
+        let data = data.lock().unwrap().clone();
+        data
+    }
+
+    #[test]
+    fn test_multi_block_transfer() {
+        let payload: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(transfer(&payload, TransferControl::RECEIVER_DRIVE), payload);
+        assert_eq!(transfer(&payload, TransferControl::SENDER_DRIVE), payload);
+        // The last block is a full one
+        assert_eq!(
+            transfer(&payload[..300], TransferControl::RECEIVER_DRIVE),
+            &payload[..300]
+        );
+    }
+
+    #[test]
+    fn test_bad_block_counter() {
+        let mut receiver = BdxTransfer::new_receiver(
+            Box::new(TestSink {
+                data: Arc::new(Mutex::new(Vec::new())),
+            }),
+            TransferControl::RECEIVER_DRIVE,
+            128,
+        );
+        let payload = [0xab; 200];
+        let mut sender = TestSender::new(&payload, TransferControl::RECEIVER_DRIVE);
+        let init = write_msg(|wb| receiver.receive_init(b"ota.bin", None, wb).unwrap());
+        let accept = sender.handle(&init).unwrap();
+        let query = receive(&mut receiver, &accept).unwrap().unwrap();
+        assert_eq!(query.0, OpCode::BlockQuery);
+
+        // Skip a block
+        sender.counter = 1;
+        let block = sender.next_block();
+        assert_eq!(receive(&mut receiver, &block), Err(Error::Invalid));
+        assert_eq!(receiver.get_failure(), Some(StatusCode::BadBlockCounter));
+        assert!(!receiver.is_done());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::Error;
+
+pub const TAG_SEQ: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8STR: u8 = 0x0C;
+
+/// A reader of the DER elements at one level of nesting
+///
+/// Only what is needed to pick pieces out of a certificate is supported, the
+/// tags are all expected to fit in a single byte.
+pub struct ASN1Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ASN1Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Result<u8, Error> {
+        self.buf.first().copied().ok_or(Error::TruncatedPacket)
+    }
+
+    /// Read the next element, returning its tag and contents
+    pub fn read(&mut self) -> Result<(u8, &'a [u8]), Error> {
+        let tag = self.peek_tag()?;
+        let first = *self.buf.get(1).ok_or(Error::TruncatedPacket)?;
+        let (len, offset) = if first & 0x80 == 0 {
+            (first as usize, 2)
+        } else {
+            // The long form, the lower bits are the number of length bytes that follow
+            let len_bytes = (first & 0x7f) as usize;
+            if len_bytes == 0 || len_bytes > 3 {
+                return Err(Error::InvalidData);
+            }
+            let bytes = self
+                .buf
+                .get(2..2 + len_bytes)
+                .ok_or(Error::TruncatedPacket)?;
+            let len = bytes.iter().fold(0, |len, b| (len << 8) | *b as usize);
+            (len, 2 + len_bytes)
+        };
+        let contents = self
+            .buf
+            .get(offset..offset + len)
+            .ok_or(Error::TruncatedPacket)?;
+        self.buf = &self.buf[offset + len..];
+        Ok((tag, contents))
+    }
+
+    /// Read the next element, which must have the tag 'tag'
+    pub fn read_tag(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read()? {
+            (t, contents) if t == tag => Ok(contents),
+            _ => Err(Error::InvalidData),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ASN1Reader, TAG_OID, TAG_SEQ};
+    use crate::error::Error;
+
+    #[test]
+    fn test_read() {
+        let mut long = vec![TAG_SEQ, 0x81, 0x80];
+        long.extend_from_slice(&[0xab; 0x80]);
+        long.extend_from_slice(&[TAG_OID, 0x01, 0x2a]);
+
+        let mut r = ASN1Reader::new(&long);
+        let (tag, contents) = r.read().unwrap();
+        assert_eq!(tag, TAG_SEQ);
+        assert_eq!(contents, [0xab; 0x80]);
+        assert_eq!(r.read_tag(TAG_OID).unwrap(), [0x2a]);
+        assert!(r.is_empty());
+        assert_eq!(r.read().map(|_| ()), Err(Error::TruncatedPacket));
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let mut r = ASN1Reader::new(&[TAG_SEQ, 0x03, 0x01, 0x02]);
+        assert_eq!(r.read().map(|_| ()), Err(Error::TruncatedPacket));
+
+        let mut r = ASN1Reader::new(&[TAG_SEQ, 0x82, 0x01]);
+        assert_eq!(r.read().map(|_| ()), Err(Error::TruncatedPacket));
+
+        let mut r = ASN1Reader::new(&[TAG_OID, 0x01, 0x2a]);
+        assert_eq!(r.read_tag(TAG_SEQ).map(|_| ()), Err(Error::InvalidData));
+    }
+}
@@ -1,15 +1,17 @@
-use super::{CertConsumer, MAX_DEPTH};
-use crate::error::Error;
-use chrono::{TimeZone, Utc};
+use super::{matter_epoch_to_unix, CertConsumer, MAX_DEPTH};
+use crate::{
+    error::Error,
+    utils::writebuf::{Reservation, WriteBuf},
+};
+use chrono::{Datelike, TimeZone, Utc};
 
 #[derive(Debug)]
 pub struct ASN1Writer<'a> {
-    buf: &'a mut [u8],
-    // The current write offset in the buffer
-    offset: usize,
-    // If multiple 'composite' structures are being written, their starts are
-    // captured in this
-    depth: [usize; MAX_DEPTH],
+    buf: WriteBuf<'a>,
+    // If multiple 'composite' structures are being written, the reservations
+    // for their lengths, and the offsets where their contents start are captured
+    // in this
+    depth: [Option<(Reservation, usize)>; MAX_DEPTH],
     // The current depth of operation within the depth stack
     current_depth: usize,
 }
@@ -17,115 +19,82 @@ pub struct ASN1Writer<'a> {
 const RESERVE_LEN_BYTES: usize = 3;
 impl<'a> ASN1Writer<'a> {
     pub fn new(buf: &'a mut [u8]) -> Self {
+        let len = buf.len();
         Self {
-            buf,
-            offset: 0,
-            depth: [0; MAX_DEPTH],
+            buf: WriteBuf::new(buf, len),
+            depth: Default::default(),
             current_depth: 0,
         }
     }
 
-    pub fn append_with<F>(&mut self, size: usize, f: F) -> Result<(), Error>
-    where
-        F: FnOnce(&mut Self),
-    {
-        if self.offset + size <= self.buf.len() {
-            f(self);
-            self.offset += size;
-            return Ok(());
-        }
-        Err(Error::NoSpace)
-    }
-
     pub fn append_tlv<F>(&mut self, tag: u8, len: usize, f: F) -> Result<(), Error>
     where
-        F: FnOnce(&mut Self),
+        F: FnOnce(&mut WriteBuf) -> Result<(), Error>,
     {
-        let total_len = 1 + ASN1Writer::bytes_to_encode_len(len)? + len;
-        if self.offset + total_len <= self.buf.len() {
-            self.buf[self.offset] = tag;
-            self.offset += 1;
-            self.offset = self.encode_len(self.offset, len)?;
-            f(self);
-            self.offset += len;
-            return Ok(());
+        let (len_bytes, len_size) = ASN1Writer::encode_len(len)?;
+        let total_len = 1 + len_size + len;
+        if self.buf.empty_as_mut_slice().len() < total_len {
+            return Err(Error::NoSpace);
         }
-        Err(Error::NoSpace)
+        self.buf.le_u8(tag)?;
+        self.buf.copy_from_slice(&len_bytes[..len_size])?;
+        f(&mut self.buf)
     }
 
     fn add_compound(&mut self, val: u8) -> Result<(), Error> {
-        // We reserve 3 bytes for encoding the length
-        // If a shorter length is actually required, we will move everything back
-        self.append_with(1 + RESERVE_LEN_BYTES, |t| t.buf[t.offset] = val)?;
-        self.depth[self.current_depth] = self.offset;
-        self.current_depth += 1;
         if self.current_depth >= MAX_DEPTH {
-            Err(Error::NoSpace)
-        } else {
-            Ok(())
+            return Err(Error::NoSpace);
         }
+        // We reserve 3 bytes for encoding the length (0x82 followed by 2 bytes of length)
+        // If a shorter length is actually required, the reservation is shrunk
+        self.buf.le_u8(val)?;
+        let len = self.buf.reserve_tail(RESERVE_LEN_BYTES)?;
+        self.depth[self.current_depth] = Some((len, self.buf.get_tail()));
+        self.current_depth += 1;
+        Ok(())
     }
 
-    fn encode_len(&mut self, mut at_offset: usize, len: usize) -> Result<usize, Error> {
-        let mut bytes_of_len = ASN1Writer::bytes_to_encode_len(len)?;
-        if bytes_of_len > 1 {
-            self.buf[at_offset] = (0x80 | (bytes_of_len - 1)) as u8;
-            at_offset += 1;
-            bytes_of_len -= 1;
-        }
-
-        // At this point bytes_of_len is the actual number of bytes for the length encoding
-        // after the 0x80 (if it was present)
-        let mut octet_number = bytes_of_len - 1;
-        // We start encoding the highest octest first
-        loop {
-            self.buf[at_offset] = ((len >> (octet_number * 8)) & 0xff) as u8;
-
-            at_offset += 1;
-            if octet_number == 0 {
-                break;
+    fn encode_len(len: usize) -> Result<([u8; RESERVE_LEN_BYTES], usize), Error> {
+        let bytes_of_len = ASN1Writer::bytes_to_encode_len(len)?;
+        let mut out = [0; RESERVE_LEN_BYTES];
+        if bytes_of_len == 1 {
+            out[0] = len as u8;
+        } else {
+            out[0] = (0x80 | (bytes_of_len - 1)) as u8;
+            // We start encoding the highest octet first
+            for (i, b) in out.iter_mut().enumerate().take(bytes_of_len).skip(1) {
+                *b = ((len >> ((bytes_of_len - 1 - i) * 8)) & 0xff) as u8;
             }
-            octet_number -= 1;
         }
-
-        Ok(at_offset)
+        Ok((out, bytes_of_len))
     }
 
     fn end_compound(&mut self) -> Result<(), Error> {
         if self.current_depth == 0 {
             return Err(Error::Invalid);
         }
-        let seq_len = self.get_compound_len();
-        let write_offset = self.get_length_encoding_offset();
-
-        let mut write_offset = self.encode_len(write_offset, seq_len)?;
-
-        // Shift everything by as much
-        let shift_len = self.depth[self.current_depth - 1] - write_offset;
-        if shift_len > 0 {
-            for _i in 0..seq_len {
-                self.buf[write_offset] = self.buf[write_offset + shift_len];
-                write_offset += 1;
-            }
-        }
+        let (len, start) = self.depth[self.current_depth - 1]
+            .take()
+            .ok_or(Error::Invalid)?;
+        let seq_len = self.buf.get_tail() - start;
+        let (len_bytes, len_size) = ASN1Writer::encode_len(seq_len)?;
+
+        // Shift everything back, if fewer bytes are needed for the length
+        let len = self.buf.shrink_reservation(len, len_size)?;
+        self.buf.fill_reservation(len, &len_bytes[..len_size])?;
         self.current_depth -= 1;
-        self.offset -= shift_len;
         Ok(())
     }
 
-    fn get_compound_len(&self) -> usize {
-        self.offset - self.depth[self.current_depth - 1]
-    }
-
     fn bytes_to_encode_len(len: usize) -> Result<usize, Error> {
         let len = if len < 128 {
             // This is directly encoded
             1
         } else if len < 256 {
-            // This is done with an 0xA1 followed by actual len
+            // This is done with an 0x81 followed by actual len
             2
         } else if len < 65536 {
-            // This is done with an 0xA2 followed by 2 bytes of actual len
+            // This is done with an 0x82 followed by 2 bytes of actual len
             3
         } else {
             return Err(Error::NoSpace);
@@ -133,19 +102,12 @@ impl<'a> ASN1Writer<'a> {
         Ok(len)
     }
 
-    fn get_length_encoding_offset(&self) -> usize {
-        self.depth[self.current_depth - 1] - RESERVE_LEN_BYTES
-    }
-
     pub fn as_slice(&self) -> &[u8] {
-        &self.buf[..self.offset]
+        self.buf.as_borrow_slice()
     }
 
     fn write_str(&mut self, vtype: u8, s: &[u8]) -> Result<(), Error> {
-        self.append_tlv(vtype, s.len(), |t| {
-            let end_offset = t.offset + s.len();
-            t.buf[t.offset..end_offset].copy_from_slice(s);
-        })
+        self.append_tlv(vtype, s.len(), |t| t.copy_from_slice(s))
     }
 }
 
@@ -159,7 +121,21 @@ impl<'a> CertConsumer for ASN1Writer<'a> {
     }
 
     fn integer(&mut self, _tag: &str, i: &[u8]) -> Result<(), Error> {
-        self.write_str(0x02, i)
+        // The value is an unsigned big-endian number. DER encodes the minimal two's
+        // complement form, so drop the redundant leading zeroes, and add one back if
+        // the MSB is set, to keep the value positive
+        let mut i = i;
+        while i.len() > 1 && i[0] == 0 {
+            i = &i[1..];
+        }
+        if i.is_empty() || i[0] & 0x80 != 0 {
+            self.append_tlv(0x02, i.len() + 1, |t| {
+                t.le_u8(0)?;
+                t.copy_from_slice(i)
+            })
+        } else {
+            self.write_str(0x02, i)
+        }
     }
 
     fn utf8str(&mut self, _tag: &str, s: &str) -> Result<(), Error> {
@@ -183,9 +159,8 @@ impl<'a> CertConsumer for ASN1Writer<'a> {
         }
         let s = &s[..(last_byte + 1)];
         self.append_tlv(0x03, s.len() + 1, |t| {
-            t.buf[t.offset] = num_of_zero;
-            let end_offset = t.offset + 1 + s.len();
-            t.buf[(t.offset + 1)..end_offset].copy_from_slice(s);
+            t.le_u8(num_of_zero)?;
+            t.copy_from_slice(s)
         })
     }
 
@@ -204,13 +179,7 @@ impl<'a> CertConsumer for ASN1Writer<'a> {
     }
 
     fn bool(&mut self, _tag: &str, b: bool) -> Result<(), Error> {
-        self.append_tlv(0x01, 1, |t| {
-            if b {
-                t.buf[t.offset] = 0xFF;
-            } else {
-                t.buf[t.offset] = 0x00;
-            }
-        })
+        self.append_tlv(0x01, 1, |t| t.le_u8(if b { 0xFF } else { 0x00 }))
     }
 
     fn start_set(&mut self, _tag: &str) -> Result<(), Error> {
@@ -238,11 +207,128 @@ impl<'a> CertConsumer for ASN1Writer<'a> {
     }
 
     fn utctime(&mut self, _tag: &str, epoch: u32) -> Result<(), Error> {
-        let mut matter_epoch = Utc.ymd(2000, 1, 1).and_hms(0, 0, 0).timestamp();
-        matter_epoch += epoch as i64;
+        let dt = Utc.timestamp(matter_epoch_to_unix(epoch) as i64, 0);
+
+        // RFC 5280: Dates in 2050 or later must be a GeneralizedTime, with a 4 digit year
+        if dt.year() < 2050 {
+            let time_str = format!("{}Z", dt.format("%y%m%d%H%M%S"));
+            self.write_str(0x17, time_str.as_bytes())
+        } else {
+            let time_str = format!("{}Z", dt.format("%Y%m%d%H%M%S"));
+            self.write_str(0x18, time_str.as_bytes())
+        }
+    }
+
+    fn raw(&mut self, _tag: &str, der: &[u8]) -> Result<(), Error> {
+        self.buf.copy_from_slice(der)
+    }
+}
 
-        let dt = Utc.timestamp(matter_epoch, 0);
-        let time_str = format!("{}Z", dt.format("%y%m%d%H%M%S"));
-        self.write_str(0x17, time_str.as_bytes())
+#[cfg(test)]
+mod tests {
+    use super::ASN1Writer;
+    use crate::cert::{unix_to_matter_epoch, CertConsumer, MAX_DEPTH};
+    use crate::error::Error;
+
+    #[test]
+    fn test_seq_len_encoding() {
+        // (octet string len, sequence header, octet string header)
+        let cases: [(usize, &[u8], &[u8]); 4] = [
+            // Sequence of 127 bytes, short form
+            (125, &[0x30, 0x7f], &[0x04, 0x7d]),
+            // Sequence of 128 bytes, long form with 1 byte
+            (126, &[0x30, 0x81, 0x80], &[0x04, 0x7e]),
+            // Sequence of 255 bytes, long form with 1 byte
+            (252, &[0x30, 0x81, 0xff], &[0x04, 0x81, 0xfc]),
+            // Sequence of 256 bytes, long form with 2 bytes
+            (253, &[0x30, 0x82, 0x01, 0x00], &[0x04, 0x81, 0xfd]),
+        ];
+
+        for (len, seq_hdr, ostr_hdr) in cases.iter() {
+            let data = vec![0xa5; *len];
+            let mut buf = [0u8; 300];
+            let mut w = ASN1Writer::new(&mut buf);
+            w.start_seq("").unwrap();
+            w.ostr("", &data).unwrap();
+            w.end_seq().unwrap();
+
+            let expected = [*seq_hdr, *ostr_hdr, &data].concat();
+            assert_eq!(w.as_slice(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_nested_seq_len_encoding() {
+        let data = vec![0xa5; 253];
+        let mut buf = [0u8; 300];
+        let mut w = ASN1Writer::new(&mut buf);
+        w.start_seq("").unwrap();
+        w.start_seq("").unwrap();
+        w.ostr("", &data).unwrap();
+        w.end_seq().unwrap();
+        w.bool("", true).unwrap();
+        w.end_seq().unwrap();
+
+        let expected: [&[u8]; 5] = [
+            &[0x30, 0x82, 0x01, 0x07],
+            &[0x30, 0x82, 0x01, 0x00],
+            &[0x04, 0x81, 0xfd],
+            data.as_slice(),
+            &[0x01, 0x01, 0xff],
+        ];
+        let expected = expected.concat();
+        assert_eq!(w.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_integer_encoding() {
+        let cases: [(&[u8], &[u8]); 6] = [
+            (&[0x02], &[0x02, 0x01, 0x02]),
+            // The MSB is set, a zero is prepended to keep it positive
+            (&[0x80], &[0x02, 0x02, 0x00, 0x80]),
+            (&[0xd2, 0x01, 0x7f], &[0x02, 0x04, 0x00, 0xd2, 0x01, 0x7f]),
+            // Redundant leading zeroes are stripped
+            (&[0x00, 0x00, 0x7f, 0x01], &[0x02, 0x02, 0x7f, 0x01]),
+            (&[0x00, 0x00, 0x80], &[0x02, 0x02, 0x00, 0x80]),
+            (&[], &[0x02, 0x01, 0x00]),
+        ];
+
+        for (val, expected) in cases.iter() {
+            let mut buf = [0u8; 10];
+            let mut w = ASN1Writer::new(&mut buf);
+            w.integer("", val).unwrap();
+            assert_eq!(w.as_slice(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let mut buf = [0u8; 100];
+        let mut w = ASN1Writer::new(&mut buf);
+        for _ in 0..MAX_DEPTH {
+            w.start_seq("").unwrap();
+        }
+        assert_eq!(w.start_seq(""), Err(Error::NoSpace));
+        assert_eq!(w.end_seq(), Ok(()));
+    }
+
+    #[test]
+    fn test_utctime() {
+        // The validity of the ASN1_OUTPUT test vectors: 2021-01-01 to 2030-12-30
+        let cases: [(u64, u8, &str); 4] = [
+            (1609459200, 0x17, "210101000000Z"),
+            (1924819200, 0x17, "301230000000Z"),
+            // The last second before the switch to GeneralizedTime
+            (2524607999, 0x17, "491231235959Z"),
+            (2524608000, 0x18, "20500101000000Z"),
+        ];
+        for (unix, tag, time_str) in cases {
+            let mut buf = [0; 20];
+            let mut w = ASN1Writer::new(&mut buf);
+            w.utctime("", unix_to_matter_epoch(unix).unwrap()).unwrap();
+            assert_eq!(w.as_slice()[0], tag);
+            assert_eq!(w.as_slice()[1] as usize, time_str.len());
+            assert_eq!(&w.as_slice()[2..], time_str.as_bytes());
+        }
     }
 }
@@ -1,15 +1,15 @@
-use std::fmt;
+use std::{convert::TryFrom, fmt};
 
 use crate::{
-    crypto::{CryptoKeyPair, KeyPair},
+    crypto::{self, CryptoKeyPair, KeyPair},
     error::Error,
-    tlv::{self, FromTLV, TLVArrayOwned, TLVElement, TLVWriter, TagType, ToTLV},
+    tlv::{self, ElementType, FromTLV, TLVArrayOwned, TLVElement, TLVWriter, TagType, ToTLV},
     utils::writebuf::WriteBuf,
 };
 use log::error;
 use num_derive::FromPrimitive;
 
-use self::{asn1_writer::ASN1Writer, printer::CertPrinter};
+use self::{asn1_reader::ASN1Reader, asn1_writer::ASN1Writer, printer::CertPrinter};
 
 // As per https://datatracker.ietf.org/doc/html/rfc5280
 
@@ -17,6 +17,16 @@ const OID_PUB_KEY_ECPUBKEY: [u8; 7] = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01]
 const OID_EC_TYPE_PRIME256V1: [u8; 8] = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
 const OID_ECDSA_WITH_SHA256: [u8; 8] = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
 
+const OID_MATTER_NODE_ID: [u8; 10] = [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x01];
+const OID_MATTER_FW_SIGN_ID: [u8; 10] =
+    [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x02];
+const OID_MATTER_ICA_ID: [u8; 10] = [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x03];
+const OID_MATTER_ROOT_CA_ID: [u8; 10] =
+    [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x04];
+const OID_MATTER_FABRIC_ID: [u8; 10] = [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x05];
+const OID_MATTER_NOC_CAT_ID: [u8; 10] =
+    [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x06];
+
 #[derive(FromPrimitive)]
 pub enum CertTags {
     SerialNum = 1,
@@ -59,6 +69,30 @@ pub fn get_sign_algo(algo: u8) -> Option<SignAlgoValue> {
     num::FromPrimitive::from_u8(algo)
 }
 
+/// The Matter epoch, 2000-01-01 00:00:00 UTC, in seconds since the Unix epoch
+///
+/// The validity of the certificates, and the other timestamps of the spec, are in seconds
+/// since the Matter epoch.
+pub const MATTER_EPOCH_SECS: u64 = 946684800;
+
+/// Convert seconds since the Matter epoch to seconds since the Unix epoch
+pub fn matter_epoch_to_unix(secs: u32) -> u64 {
+    MATTER_EPOCH_SECS + secs as u64
+}
+
+/// Convert seconds since the Unix epoch to seconds since the Matter epoch
+///
+/// Times before 2000, or beyond what a u32 holds (in 2136), can't be represented.
+pub fn unix_to_matter_epoch(secs: u64) -> Result<u32, Error> {
+    secs.checked_sub(MATTER_EPOCH_SECS)
+        .and_then(|s| u32::try_from(s).ok())
+        .ok_or(Error::Invalid)
+}
+
+// A 'not after' of 0 means the certificate has no well-defined expiration, which X.509
+// encodes as the GeneralizedTime 99991231235959Z
+const NO_WELL_DEFINED_EXPIRATION: &[u8] = b"\x18\x0f99991231235959Z";
+
 const KEY_USAGE_DIGITAL_SIGN: u16 = 0x0001;
 const KEY_USAGE_NON_REPUDIATION: u16 = 0x0002;
 const KEY_USAGE_KEY_ENCIPHERMENT: u16 = 0x0004;
@@ -240,7 +274,8 @@ impl Extensions {
             encode_extension_end(w)?;
         }
         if let Some(t) = &self.future_extensions {
-            error!("Future Extensions Not Yet Supported: {:x?}", t.as_slice())
+            // These are already DER encoded
+            w.raw("Future Extensions:", t.as_slice())?;
         }
         w.end_seq()?;
         w.end_ctx()?;
@@ -259,19 +294,49 @@ enum DnTags {
     NocCat = 22,
 }
 
+/// The maximum number of CASE Authenticated Tags that a NOC may carry
+pub const MAX_CAT_IDS_PER_NOC: usize = 3;
+
+/// The CASE Authenticated Tags of a NOC, unused entries are 0
+pub type NocCatIds = [u32; MAX_CAT_IDS_PER_NOC];
+
+// The value of a DN. The Matter DNs are integers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DnValue {
+    Uint(u64),
+}
+
+impl ToTLV for DnValue {
+    fn to_tlv(&self, tw: &mut TLVWriter, tag: TagType) -> Result<(), Error> {
+        match *self {
+            DnValue::Uint(v) => tw.u64(tag, v),
+        }
+    }
+}
+
 #[derive(Default)]
 struct DistNames {
     // The order in which the DNs arrive is important, as the signing
     // requires that the ASN1 notation retains the same order
-    dn: Vec<(u8, u64)>,
+    dn: Vec<(u8, DnValue)>,
 }
 
 impl DistNames {
     fn u64(&self, match_id: DnTags) -> Option<u64> {
-        self.dn
-            .iter()
-            .find(|(id, _)| *id == match_id as u8)
-            .map(|(_, value)| *value)
+        self.dn.iter().find_map(|(id, value)| match value {
+            DnValue::Uint(v) if *id == match_id as u8 => Some(*v),
+            _ => None,
+        })
+    }
+
+    fn u32_arr(&self, match_id: DnTags, output: &mut [u32]) {
+        let values = self.dn.iter().filter_map(|(id, value)| match value {
+            DnValue::Uint(v) if *id == match_id as u8 => Some(*v),
+            _ => None,
+        });
+        for (out, value) in output.iter_mut().zip(values) {
+            *out = value as u32;
+        }
     }
 }
 
@@ -280,16 +345,27 @@ impl<'a> FromTLV<'a> for DistNames {
         let mut d = Self {
             dn: Vec::with_capacity(MAX_DN_ENTRIES),
         };
-        let iter = t.confirm_list()?.iter().ok_or(Error::Invalid)?;
+        let iter = t.confirm_list()?.iter_tagged().ok_or(Error::Invalid)?;
         for t in iter {
-            if let TagType::Context(tag) = t.get_tag() {
-                let value = t.u64().map_err(|e| {
-                    // Non-integer DNs not yet supported
-                    error!("This DN is not yet supported{}", tag);
-                    e
-                })?;
-                d.dn.push((tag, value));
+            let tag = match t.ctx_tag() {
+                Some(tag) => tag,
+                // Not a DN, ignored
+                None => continue,
+            };
+            match t.element.get_element_type() {
+                // Not a value of the Matter DNs, ignored
+                ElementType::True | ElementType::False | ElementType::Null => {
+                    t.element.skip();
+                    continue;
+                }
+                _ => (),
             }
+            let value = t.element.get_u64_any().map_err(|e| {
+                // Non-integer DNs not yet supported
+                error!("This DN is not yet supported {}: {}", tag, e);
+                e
+            })?;
+            d.dn.push((tag, DnValue::Uint(value)));
         }
         Ok(d)
     }
@@ -297,76 +373,54 @@ impl<'a> FromTLV<'a> for DistNames {
 
 impl ToTLV for DistNames {
     fn to_tlv(&self, tw: &mut TLVWriter, tag: TagType) -> Result<(), Error> {
-        tw.start_list(tag)?;
-        for (name, value) in &self.dn {
-            tw.u64(TagType::Context(*name), *value)?;
-        }
-        tw.end_container()
+        let fields = self
+            .dn
+            .iter()
+            .map(|(name, value)| (TagType::Context(*name), *value));
+        tw.list_from_fields(tag, fields)
     }
 }
 
 impl DistNames {
     fn encode(&self, tag: &str, w: &mut dyn CertConsumer) -> Result<(), Error> {
-        const OID_MATTER_NODE_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x01];
-        const OID_MATTER_FW_SIGN_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x02];
-        const OID_MATTER_ICA_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x03];
-        const OID_MATTER_ROOT_CA_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x04];
-        const OID_MATTER_FABRIC_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x05];
-        const OID_MATTER_NOC_CAT_ID: [u8; 10] =
-            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x01, 0x06];
-
-        let dn_encoding = [
-            ("Chip Node Id:", &OID_MATTER_NODE_ID),
-            ("Chip Firmware Signing Id:", &OID_MATTER_FW_SIGN_ID),
-            ("Chip ICA Id:", &OID_MATTER_ICA_ID),
-            ("Chip Root CA Id:", &OID_MATTER_ROOT_CA_ID),
-            ("Chip Fabric Id:", &OID_MATTER_FABRIC_ID),
-        ];
-
         w.start_seq(tag)?;
         for (id, value) in &self.dn {
-            if let Ok(tag) = num::FromPrimitive::from_u8(*id).ok_or(Error::InvalidData) {
-                match tag {
-                    DnTags::NocCat => {
-                        w.start_set("")?;
-                        w.start_seq("")?;
-                        w.oid("Chip NOC CAT Id:", &OID_MATTER_NOC_CAT_ID)?;
-                        w.utf8str("", format!("{:08X}", value).as_str())?;
-                        w.end_seq()?;
-                        w.end_set()?;
-                    }
-                    _ => {
-                        let index: usize = (*id as usize) - (DnTags::NodeId as usize);
-                        let this = &dn_encoding[index];
-                        encode_u64_dn(*value, this.0, this.1, w)?;
-                    }
+            let tag = match num::FromPrimitive::from_u8(*id) {
+                Some(tag) => tag,
+                None => {
+                    // The DN is skipped, the signature of such a certificate won't verify
+                    error!("Non Matter DNs are not yet supported {}", id);
+                    continue;
+                }
+            };
+            let (name, oid) = u64_dn_encoding(tag);
+            w.start_set("")?;
+            w.start_seq("")?;
+            w.oid(name, oid)?;
+            match (tag, value) {
+                (DnTags::NocCat, DnValue::Uint(v)) => {
+                    w.utf8str("", format!("{:08X}", v).as_str())?
                 }
-            } else {
-                error!("Non Matter DNs are not yet supported {}", id);
+                (_, DnValue::Uint(v)) => w.utf8str("", format!("{:016X}", v).as_str())?,
             }
+            w.end_seq()?;
+            w.end_set()?;
         }
         w.end_seq()?;
         Ok(())
     }
 }
 
-fn encode_u64_dn(
-    value: u64,
-    name: &str,
-    oid: &[u8],
-    w: &mut dyn CertConsumer,
-) -> Result<(), Error> {
-    w.start_set("")?;
-    w.start_seq("")?;
-    w.oid(name, oid)?;
-    w.utf8str("", format!("{:016X}", value).as_str())?;
-    w.end_seq()?;
-    w.end_set()
+// The name and OID of the Matter DNs that have 64-bit values
+fn u64_dn_encoding(tag: DnTags) -> (&'static str, &'static [u8]) {
+    match tag {
+        DnTags::NodeId => ("Chip Node Id:", &OID_MATTER_NODE_ID),
+        DnTags::FirmwareSignId => ("Chip Firmware Signing Id:", &OID_MATTER_FW_SIGN_ID),
+        DnTags::IcaId => ("Chip ICA Id:", &OID_MATTER_ICA_ID),
+        DnTags::RootCaId => ("Chip Root CA Id:", &OID_MATTER_ROOT_CA_ID),
+        DnTags::FabricId => ("Chip Fabric Id:", &OID_MATTER_FABRIC_ID),
+        DnTags::NocCat => ("Chip NOC CAT Id:", &OID_MATTER_NOC_CAT_ID),
+    }
 }
 
 #[derive(FromTLV, ToTLV, Default)]
@@ -397,6 +451,12 @@ impl Cert {
         self.subject.u64(DnTags::NodeId).ok_or(Error::NoNodeId)
     }
 
+    pub fn get_cat_ids(&self) -> NocCatIds {
+        let mut cats = NocCatIds::default();
+        self.subject.u32_arr(DnTags::NocCat, &mut cats);
+        cats
+    }
+
     pub fn get_fabric_id(&self) -> Result<u64, Error> {
         self.subject.u64(DnTags::FabricId).ok_or(Error::NoFabricId)
     }
@@ -409,14 +469,15 @@ impl Cert {
         self.extensions.subj_key_id.as_deref().ok_or(Error::Invalid)
     }
 
+    /// Whether the Basic Constraints mark this as the certificate of a CA
+    pub fn is_ca(&self) -> bool {
+        matches!(&self.extensions.basic_const, Some(b) if b.is_ca)
+    }
+
     pub fn is_authority(&self, their: &Cert) -> Result<bool, Error> {
         if let Some(our_auth_key) = &self.extensions.auth_key_id {
             let their_subject = their.get_subject_key_id()?;
-            if our_auth_key == their_subject {
-                Ok(true)
-            } else {
-                Ok(false)
-            }
+            Ok(crypto::constant_time_eq(our_auth_key, their_subject))
         } else {
             Ok(false)
         }
@@ -426,6 +487,11 @@ impl Cert {
         self.signature.as_slice()
     }
 
+    /// Set the signature, once the TBSCertificate from as_tbs() has been signed by the issuer
+    pub fn set_signature(&mut self, signature: &[u8]) {
+        self.signature = signature.to_vec();
+    }
+
     pub fn as_tlv(&self, buf: &mut [u8]) -> Result<usize, Error> {
         let mut wb = WriteBuf::new(buf, buf.len());
         let mut tw = TLVWriter::new(&mut wb);
@@ -434,8 +500,17 @@ impl Cert {
     }
 
     pub fn as_asn1(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.as_tbs(buf)
+    }
+
+    /// The DER of the TBSCertificate, the portion of the certificate that the signature
+    /// covers
+    ///
+    /// This leaves out the signatureAlgorithm and the signatureValue, so it is what has to
+    /// be handed to the signer when building a certificate.
+    pub fn as_tbs(&self, buf: &mut [u8]) -> Result<usize, Error> {
         let mut w = ASN1Writer::new(buf);
-        let _ = self.encode(&mut w)?;
+        self.encode(&mut w)?;
         Ok(w.as_slice().len())
     }
 
@@ -463,7 +538,11 @@ impl Cert {
 
         w.start_seq("Validity:")?;
         w.utctime("Not Before:", self.not_before)?;
-        w.utctime("Not After:", self.not_after)?;
+        if self.not_after == 0 {
+            w.raw("Not After:", NO_WELL_DEFINED_EXPIRATION)?;
+        } else {
+            w.utctime("Not After:", self.not_after)?;
+        }
         w.end_seq()?;
 
         self.subject.encode("Subject:", w)?;
@@ -502,6 +581,66 @@ impl fmt::Display for Cert {
     }
 }
 
+pub const SUBJ_KEY_ID_LEN: usize = crypto::SHA1_HASH_LEN_BYTES;
+
+/// Compute the Subject Key Identifier of the public key 'pubkey'
+///
+/// This is the SHA-1 of the public key, excluding the tag, length and unused bits of its BIT
+/// STRING, as in method 1 of RFC 5280. The Authority Key Identifier of a certificate is the
+/// Subject Key Identifier of its issuer, so this works for both.
+pub fn compute_subject_key_id(pubkey: &[u8]) -> Result<[u8; SUBJ_KEY_ID_LEN], Error> {
+    let mut key_id = [0; SUBJ_KEY_ID_LEN];
+    crypto::sha1(pubkey, &mut key_id)?;
+    Ok(key_id)
+}
+
+/// Get the (fabric id, node id) from the subject of a DER encoded certificate
+///
+/// This only walks the DER up to the subject, and is a lot cheaper than building a Cert
+/// when only the ids are needed. Both a full certificate and the TBSCertificate alone, like
+/// the output of Cert::as_asn1(), are accepted.
+pub fn extract_ids_from_der(der: &[u8]) -> Result<(u64, u64), Error> {
+    let outer = ASN1Reader::new(der).read_tag(asn1_reader::TAG_SEQ)?;
+    let mut tbs = ASN1Reader::new(outer);
+    if tbs.peek_tag()? == asn1_reader::TAG_SEQ {
+        // A full certificate, that starts with the TBSCertificate
+        tbs = ASN1Reader::new(tbs.read()?.1);
+    }
+    // The optional version
+    if tbs.peek_tag()? == 0xA0 {
+        tbs.read()?;
+    }
+    // The serial number, the signature algorithm, the issuer and the validity come
+    // before the subject
+    for _ in 0..4 {
+        tbs.read()?;
+    }
+    let subject = tbs.read_tag(asn1_reader::TAG_SEQ)?;
+
+    let mut fabric_id = None;
+    let mut node_id = None;
+    let mut rdns = ASN1Reader::new(subject);
+    while !rdns.is_empty() {
+        let rdn = rdns.read_tag(asn1_reader::TAG_SET)?;
+        let mut attr = ASN1Reader::new(ASN1Reader::new(rdn).read_tag(asn1_reader::TAG_SEQ)?);
+        let oid = attr.read_tag(asn1_reader::TAG_OID)?;
+        let id = if oid == OID_MATTER_FABRIC_ID {
+            &mut fabric_id
+        } else if oid == OID_MATTER_NODE_ID {
+            &mut node_id
+        } else {
+            continue;
+        };
+        let value = attr.read_tag(asn1_reader::TAG_UTF8STR)?;
+        let value = std::str::from_utf8(value).map_err(|_| Error::InvalidData)?;
+        *id = Some(u64::from_str_radix(value, 16).map_err(|_| Error::InvalidData)?);
+    }
+    Ok((
+        fabric_id.ok_or(Error::NoFabricId)?,
+        node_id.ok_or(Error::NoNodeId)?,
+    ))
+}
+
 pub struct CertVerifier<'a> {
     cert: &'a Cert,
 }
@@ -515,12 +654,12 @@ impl<'a> CertVerifier<'a> {
         if !self.cert.is_authority(parent)? {
             return Err(Error::InvalidAuthKey);
         }
-        let mut asn1 = [0u8; MAX_ASN1_CERT_SIZE];
-        let len = self.cert.as_asn1(&mut asn1)?;
-        let asn1 = &asn1[..len];
+        let mut tbs = [0u8; MAX_ASN1_CERT_SIZE];
+        let len = self.cert.as_tbs(&mut tbs)?;
+        let tbs = &tbs[..len];
 
         let k = KeyPair::new_from_public(parent.get_pubkey())?;
-        k.verify_msg(asn1, self.cert.get_signature()).map_err(|e| {
+        k.verify_msg(tbs, self.cert.get_signature()).map_err(|e| {
             error!(
                 "Error in signature verification of certificate: {:#02x?}",
                 self.cert.get_subject_key_id()
@@ -539,6 +678,30 @@ impl<'a> CertVerifier<'a> {
     }
 }
 
+/// Verify the chain of a NOC up to its self-signed Root CA, given the certificates in
+/// their TLV form
+///
+/// The NOC may be issued directly by the Root CA, in which case there is no ICAC.
+pub fn verify_noc_chain(
+    noc_tlv: &[u8],
+    icac_tlv: Option<&[u8]>,
+    rca_tlv: &[u8],
+) -> Result<(), Error> {
+    let noc = Cert::new(noc_tlv)?;
+    if noc.is_ca() {
+        error!("The NOC is the certificate of a CA");
+        return Err(Error::Invalid);
+    }
+    let icac = icac_tlv.map(Cert::new).transpose()?;
+    let rca = Cert::new(rca_tlv)?;
+
+    let mut verifier = noc.verify_chain_start();
+    if let Some(icac) = &icac {
+        verifier = verifier.add_cert(icac)?;
+    }
+    verifier.add_cert(&rca)?.finalise()
+}
+
 pub trait CertConsumer {
     fn start_seq(&mut self, tag: &str) -> Result<(), Error>;
     fn end_seq(&mut self) -> Result<(), Error>;
@@ -556,17 +719,27 @@ pub trait CertConsumer {
     fn end_ctx(&mut self) -> Result<(), Error>;
     fn oid(&mut self, tag: &str, oid: &[u8]) -> Result<(), Error>;
     fn utctime(&mut self, tag: &str, epoch: u32) -> Result<(), Error>;
+    /// Elements that are already DER encoded
+    fn raw(&mut self, tag: &str, der: &[u8]) -> Result<(), Error>;
 }
 
 const MAX_DEPTH: usize = 10;
 const MAX_ASN1_CERT_SIZE: usize = 800;
 
+mod asn1_reader;
 mod asn1_writer;
 mod printer;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_vectors;
 
 #[cfg(test)]
-mod tests {
-    use crate::cert::Cert;
+pub(crate) mod tests {
+    use crate::cert::{
+        compute_subject_key_id, extract_ids_from_der, matter_epoch_to_unix, test_vectors,
+        unix_to_matter_epoch, verify_noc_chain, ASN1Writer, Cert, DistNames, DnTags, DnValue,
+        MATTER_EPOCH_SECS, OID_MATTER_FW_SIGN_ID, OID_MATTER_NODE_ID,
+    };
+    use crate::crypto::{self, CryptoKeyPair, KeyPair};
     use crate::error::Error;
     use crate::tlv::{self, FromTLV, TLVWriter, TagType, ToTLV};
     use crate::utils::writebuf::WriteBuf;
@@ -588,6 +761,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_dn_skips_bool() {
+        let mut buf = [0u8; 50];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_list(TagType::Anonymous).unwrap();
+        tw.u64(TagType::Context(21), 1).unwrap();
+        tw.bool(TagType::Context(20), true).unwrap();
+        tw.u64(TagType::Context(17), 0xbc5c02).unwrap();
+        tw.end_container().unwrap();
+
+        let root = tlv::get_root_node_list(wb.as_slice()).unwrap();
+        let dn = DistNames::from_tlv(&root).unwrap();
+        assert_eq!(
+            dn.dn,
+            vec![(21, DnValue::Uint(1)), (17, DnValue::Uint(0xbc5c02))]
+        );
+        assert_eq!(dn.u64(DnTags::NodeId), Some(0xbc5c02));
+        assert_eq!(dn.u64(DnTags::RootCaId), None);
+    }
+
+    #[test]
+    fn test_dn_encoding() {
+        // A Firmware Signing ID, and a DN that isn't one of the Matter DNs
+        let dn = DistNames {
+            dn: vec![
+                (18, DnValue::Uint(0x1234)),
+                (30, DnValue::Uint(5)),
+                (17, DnValue::Uint(0xbc5c02)),
+            ],
+        };
+        let mut asn1_buf = [0u8; 200];
+        dn.encode("", &mut ASN1Writer::new(&mut asn1_buf)).unwrap();
+
+        let find = |needle: &[u8]| asn1_buf.windows(needle.len()).position(|w| w == needle);
+        let fw_sign_id = find(&OID_MATTER_FW_SIGN_ID).unwrap();
+        let node_id = find(&OID_MATTER_NODE_ID).unwrap();
+        assert!(fw_sign_id < node_id);
+        // The value follows its OID, as a UTF8String
+        let value = find(b"0000000000001234").unwrap();
+        assert_eq!(value, fw_sign_id + OID_MATTER_FW_SIGN_ID.len() + 2);
+        assert_eq!(find(b"0000000000000005"), None);
+    }
+
+    #[test]
+    fn test_matter_epoch() {
+        assert_eq!(matter_epoch_to_unix(0), MATTER_EPOCH_SECS);
+        assert_eq!(unix_to_matter_epoch(MATTER_EPOCH_SECS), Ok(0));
+        assert_eq!(
+            unix_to_matter_epoch(MATTER_EPOCH_SECS - 1),
+            Err(Error::Invalid)
+        );
+        assert_eq!(
+            unix_to_matter_epoch(matter_epoch_to_unix(u32::MAX)),
+            Ok(u32::MAX)
+        );
+        assert_eq!(
+            unix_to_matter_epoch(matter_epoch_to_unix(u32::MAX) + 1),
+            Err(Error::Invalid)
+        );
+
+        // The validity of ASN1_INPUT1 is from 2021-01-01 to 2030-12-30
+        let c = Cert::new(&test_vectors::ASN1_INPUT1).unwrap();
+        assert_eq!(matter_epoch_to_unix(c.not_before), 1609459200);
+        assert_eq!(matter_epoch_to_unix(c.not_after), 1924819200);
+    }
+
+    #[test]
+    fn test_no_well_defined_expiration() {
+        let mut c = Cert::new(&test_vectors::ASN1_INPUT1).unwrap();
+        c.not_after = 0;
+        let mut asn1_buf = [0u8; 1000];
+        let len = c.as_asn1(&mut asn1_buf).unwrap();
+        let asn1 = &asn1_buf[..len];
+        assert!(asn1
+            .windows(17)
+            .any(|w| w == b"\x18\x0f99991231235959Z".as_slice()));
+        // Not the Matter epoch, 2000-01-01
+        assert!(!asn1.windows(13).any(|w| w == b"000101000000Z".as_slice()));
+    }
+
+    #[test]
+    fn test_asn1_encode_future_extensions() {
+        // The same as ASN1_INPUT1, with an extension that isn't known
+        let mut asn1_buf = [0u8; 1000];
+        let c = Cert::new(&test_vectors::ASN1_INPUT_FUTURE_EXT).unwrap();
+        let len = c.as_asn1(&mut asn1_buf).unwrap();
+        assert_eq!(&test_vectors::ASN1_OUTPUT_FUTURE_EXT, &asn1_buf[..len]);
+    }
+
+    #[test]
+    fn test_extract_ids_from_der() {
+        let noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
+        let mut asn1_buf = [0u8; 1000];
+        let len = noc.as_asn1(&mut asn1_buf).unwrap();
+        assert_eq!(
+            extract_ids_from_der(&asn1_buf[..len]),
+            Ok((noc.get_fabric_id().unwrap(), noc.get_node_id().unwrap()))
+        );
+
+        // The root CA doesn't have a node id
+        let rca = Cert::new(&test_vectors::RCA1_SUCCESS).unwrap();
+        let len = rca.as_asn1(&mut asn1_buf).unwrap();
+        assert_eq!(extract_ids_from_der(&asn1_buf[..len]), Err(Error::NoNodeId));
+
+        assert_eq!(
+            extract_ids_from_der(&asn1_buf[..len / 2]),
+            Err(Error::TruncatedPacket)
+        );
+    }
+
+    #[test]
+    fn test_compute_subject_key_id() {
+        for cert in [
+            &test_vectors::NOC1_SUCCESS[..],
+            &test_vectors::ICAC1_SUCCESS,
+            &test_vectors::RCA1_SUCCESS,
+        ] {
+            let cert = Cert::new(cert).unwrap();
+            let key_id = compute_subject_key_id(cert.get_pubkey()).unwrap();
+            assert_eq!(&key_id[..], cert.get_subject_key_id().unwrap());
+        }
+
+        // The NOC is issued by the ICAC, so its Authority Key ID is the ICAC's key id
+        let noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
+        let icac = Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap();
+        let icac_key_id = compute_subject_key_id(icac.get_pubkey()).unwrap();
+        assert_eq!(
+            noc.extensions.auth_key_id.as_deref(),
+            Some(&icac_key_id[..])
+        );
+    }
+
     #[test]
     fn test_verify_chain_success() {
         let noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
@@ -602,6 +909,71 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_sign_tbs() {
+        // A self-signed certificate, like the Root CA, but with a key of our own
+        let key = KeyPair::new().unwrap();
+        let mut pubkey = [0; crypto::EC_POINT_LEN_BYTES];
+        let len = key.get_public_key(&mut pubkey).unwrap();
+        let key_id = compute_subject_key_id(&pubkey[..len]).unwrap().to_vec();
+
+        let mut cert = Cert::new(&test_vectors::RCA1_SUCCESS).unwrap();
+        cert.pubkey = pubkey[..len].to_vec();
+        cert.extensions.subj_key_id = Some(key_id.clone());
+        cert.extensions.auth_key_id = Some(key_id);
+        // The signature of the earlier key doesn't hold anymore
+        assert_eq!(
+            cert.verify_chain_start().finalise(),
+            Err(Error::InvalidSignature)
+        );
+
+        let mut tbs = [0u8; 1000];
+        let len = cert.as_tbs(&mut tbs).unwrap();
+        let mut signature = [0; crypto::EC_SIGNATURE_LEN_BYTES];
+        let sig_len = key.sign_msg(&tbs[..len], &mut signature).unwrap();
+        cert.set_signature(&signature[..sig_len]);
+        cert.verify_chain_start().finalise().unwrap();
+
+        // The signed certificate survives the trip through TLV
+        let mut tlv = [0u8; 1000];
+        let len = cert.as_tlv(&mut tlv).unwrap();
+        Cert::new(&tlv[..len])
+            .unwrap()
+            .verify_chain_start()
+            .finalise()
+            .unwrap();
+    }
+
+    // Sign the TBSCertificate of 'cert' with 'key'
+    fn sign(cert: &mut Cert, key: &KeyPair) {
+        let mut tbs = [0u8; 1000];
+        let len = cert.as_tbs(&mut tbs).unwrap();
+        let mut signature = [0; crypto::EC_SIGNATURE_LEN_BYTES];
+        let sig_len = key.sign_msg(&tbs[..len], &mut signature).unwrap();
+        cert.set_signature(&signature[..sig_len]);
+    }
+
+    /// A Root CA with a key of our own, along with the NOC1 reissued directly by it, without
+    /// an ICAC in between
+    pub(crate) fn noc_under_root() -> (Cert, Cert) {
+        let key = KeyPair::new().unwrap();
+        let mut pubkey = [0; crypto::EC_POINT_LEN_BYTES];
+        let len = key.get_public_key(&mut pubkey).unwrap();
+        let key_id = compute_subject_key_id(&pubkey[..len]).unwrap().to_vec();
+
+        let mut root = Cert::new(&test_vectors::RCA1_SUCCESS).unwrap();
+        root.pubkey = pubkey[..len].to_vec();
+        root.extensions.subj_key_id = Some(key_id.clone());
+        root.extensions.auth_key_id = Some(key_id.clone());
+        sign(&mut root, &key);
+
+        let mut noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
+        noc.issuer.dn = root.subject.dn.clone();
+        noc.extensions.auth_key_id = Some(key_id);
+        sign(&mut noc, &key);
+        (root, noc)
+    }
+
     #[test]
     fn test_verify_chain_incomplete() {
         // The chain doesn't lead up to a self-signed certificate
@@ -614,6 +986,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_verify_noc_chain_with_icac() {
+        verify_noc_chain(
+            &test_vectors::NOC1_SUCCESS,
+            Some(&test_vectors::ICAC1_SUCCESS),
+            &test_vectors::RCA1_SUCCESS,
+        )
+        .unwrap();
+
+        assert_eq!(
+            verify_noc_chain(
+                &test_vectors::NOC1_CORRUPT_CERT,
+                Some(&test_vectors::ICAC1_SUCCESS),
+                &test_vectors::RCA1_SUCCESS,
+            ),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_noc_chain_without_icac() {
+        let (root, noc) = noc_under_root();
+        let mut root_tlv = [0u8; 1000];
+        let root_len = root.as_tlv(&mut root_tlv).unwrap();
+        let mut noc_tlv = [0u8; 1000];
+        let noc_len = noc.as_tlv(&mut noc_tlv).unwrap();
+        verify_noc_chain(&noc_tlv[..noc_len], None, &root_tlv[..root_len]).unwrap();
+
+        // The ICAC is issued directly by the Root CA, but a CA can't be the leaf
+        assert!(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap().is_ca());
+        assert_eq!(
+            verify_noc_chain(
+                &test_vectors::ICAC1_SUCCESS,
+                None,
+                &test_vectors::RCA1_SUCCESS
+            ),
+            Err(Error::Invalid)
+        );
+
+        // This NOC is issued by the ICAC, not the Root CA
+        assert_eq!(
+            verify_noc_chain(
+                &test_vectors::NOC1_SUCCESS,
+                None,
+                &test_vectors::RCA1_SUCCESS
+            ),
+            Err(Error::InvalidAuthKey)
+        );
+    }
+
     #[test]
     fn test_auth_key_chain_incorrect() {
         let noc = Cert::new(&test_vectors::NOC1_AUTH_KEY_FAIL).unwrap();
@@ -630,6 +1052,49 @@ mod tests {
         assert_eq!(Err(Error::InvalidSignature), a.add_cert(&icac).map(|_| ()));
     }
 
+    #[test]
+    fn test_cert_truncated() {
+        // Every prefix of the certificate is rejected cleanly. Only the final end of
+        // container may be left out, a container runs to the end of the buffer anyway.
+        let noc = &test_vectors::NOC1_SUCCESS;
+        for len in 0..noc.len() - 1 {
+            assert!(Cert::new(&noc[..len]).is_err(), "Prefix of {} bytes", len);
+        }
+        // Cut in the middle of the public key
+        assert_eq!(
+            Cert::new(&noc[..100]).map(|_| ()),
+            Err(Error::TruncatedPacket)
+        );
+    }
+
+    #[test]
+    fn test_cert_length_inflated() {
+        // The serial number (context tag 1) and the public key (context tag 9) are the
+        // octet strings that claim more bytes than the certificate holds
+        let serial = test_vectors::NOC1_SUCCESS
+            .windows(3)
+            .position(|w| w == [0x30, 0x01, 0x01])
+            .unwrap();
+        let key = test_vectors::NOC1_SUCCESS
+            .windows(3)
+            .position(|w| w == [0x30, 0x09, 0x41])
+            .unwrap();
+        for len_offset in [serial + 2, key + 2] {
+            for len in [0xf8, 0xff] {
+                let mut noc = test_vectors::NOC1_SUCCESS;
+                noc[len_offset] = len;
+                assert_eq!(Cert::new(&noc).map(|_| ()), Err(Error::TruncatedPacket));
+            }
+        }
+
+        // A length byte that is corrupted anywhere may fail the decoding, but must never panic
+        for offset in 0..test_vectors::NOC1_SUCCESS.len() {
+            let mut noc = test_vectors::NOC1_SUCCESS;
+            noc[offset] = 0xff;
+            let _ = Cert::new(&noc);
+        }
+    }
+
     #[test]
     fn test_tlv_conversions() {
         let test_input: [&[u8]; 3] = [
@@ -651,202 +1116,22 @@ mod tests {
         }
     }
 
-    mod test_vectors {
-        // Group 1
-        pub const NOC1_SUCCESS: [u8; 247] = [
-            0x15, 0x30, 0x1, 0x1, 0x1, 0x24, 0x2, 0x1, 0x37, 0x3, 0x24, 0x13, 0x1, 0x24, 0x15, 0x1,
-            0x18, 0x26, 0x4, 0x80, 0x22, 0x81, 0x27, 0x26, 0x5, 0x80, 0x25, 0x4d, 0x3a, 0x37, 0x6,
-            0x26, 0x11, 0x2, 0x5c, 0xbc, 0x0, 0x24, 0x15, 0x1, 0x18, 0x24, 0x7, 0x1, 0x24, 0x8,
-            0x1, 0x30, 0x9, 0x41, 0x4, 0xba, 0x22, 0x56, 0x43, 0x4f, 0x59, 0x98, 0x32, 0x8d, 0xb8,
-            0xcb, 0x3f, 0x24, 0x90, 0x9a, 0x96, 0x94, 0x43, 0x46, 0x67, 0xc2, 0x11, 0xe3, 0x80,
-            0x26, 0x65, 0xfc, 0x65, 0x37, 0x77, 0x3, 0x25, 0x18, 0xd8, 0xdc, 0x85, 0xfa, 0xe6,
-            0x42, 0xe7, 0x55, 0xc9, 0x37, 0xcc, 0xb, 0x78, 0x84, 0x3d, 0x2f, 0xac, 0x81, 0x88,
-            0x2e, 0x69, 0x0, 0xa5, 0xfc, 0xcd, 0xe0, 0xad, 0xb2, 0x69, 0xca, 0x73, 0x37, 0xa, 0x35,
-            0x1, 0x28, 0x1, 0x18, 0x24, 0x2, 0x1, 0x36, 0x3, 0x4, 0x2, 0x4, 0x1, 0x18, 0x30, 0x4,
-            0x14, 0x39, 0x68, 0x16, 0x1e, 0xb5, 0x56, 0x6d, 0xd3, 0xf8, 0x61, 0xf2, 0x95, 0xf3,
-            0x55, 0xa0, 0xfb, 0xd2, 0x82, 0xc2, 0x29, 0x30, 0x5, 0x14, 0xce, 0x60, 0xb4, 0x28,
-            0x96, 0x72, 0x27, 0x64, 0x81, 0xbc, 0x4f, 0x0, 0x78, 0xa3, 0x30, 0x48, 0xfe, 0x6e,
-            0x65, 0x86, 0x18, 0x30, 0xb, 0x40, 0x2, 0x88, 0x42, 0x0, 0x6f, 0xcc, 0xe0, 0xf0, 0x6c,
-            0xd9, 0xf9, 0x5e, 0xe4, 0xc2, 0xaa, 0x1f, 0x57, 0x71, 0x62, 0xdb, 0x6b, 0x4e, 0xe7,
-            0x55, 0x3f, 0xc6, 0xc7, 0x9f, 0xf8, 0x30, 0xeb, 0x16, 0x6e, 0x6d, 0xc6, 0x9c, 0xb,
-            0xb7, 0xe2, 0xb8, 0xe3, 0xe7, 0x57, 0x88, 0x7b, 0xda, 0xe5, 0x79, 0x39, 0x6d, 0x2c,
-            0x37, 0xb2, 0x7f, 0xc3, 0x63, 0x2f, 0x7e, 0x70, 0xab, 0x5a, 0x2c, 0xf7, 0x5b, 0x18,
-        ];
-        pub const ICAC1_SUCCESS: [u8; 237] = [
-            21, 48, 1, 1, 0, 36, 2, 1, 55, 3, 36, 20, 0, 36, 21, 1, 24, 38, 4, 128, 34, 129, 39,
-            38, 5, 128, 37, 77, 58, 55, 6, 36, 19, 1, 36, 21, 1, 24, 36, 7, 1, 36, 8, 1, 48, 9, 65,
-            4, 86, 25, 119, 24, 63, 212, 255, 43, 88, 61, 233, 121, 52, 102, 223, 233, 0, 251, 109,
-            161, 239, 224, 204, 220, 119, 48, 192, 111, 182, 45, 255, 190, 84, 160, 149, 117, 11,
-            139, 7, 188, 85, 219, 156, 182, 85, 19, 8, 184, 223, 2, 227, 64, 107, 174, 52, 245, 12,
-            186, 201, 242, 191, 241, 231, 80, 55, 10, 53, 1, 41, 1, 24, 36, 2, 96, 48, 4, 20, 206,
-            96, 180, 40, 150, 114, 39, 100, 129, 188, 79, 0, 120, 163, 48, 72, 254, 110, 101, 134,
-            48, 5, 20, 212, 86, 147, 190, 112, 121, 244, 156, 112, 107, 7, 111, 17, 28, 109, 229,
-            100, 164, 68, 116, 24, 48, 11, 64, 243, 8, 190, 128, 155, 254, 245, 21, 205, 241, 217,
-            246, 204, 182, 247, 41, 81, 91, 33, 155, 230, 223, 212, 116, 33, 162, 208, 148, 100,
-            89, 175, 253, 78, 212, 7, 69, 207, 140, 45, 129, 249, 64, 104, 70, 68, 43, 164, 19,
-            126, 114, 138, 79, 104, 238, 20, 226, 88, 118, 105, 56, 12, 92, 31, 171, 24,
-        ];
-        // A single byte in the auth key id is changed in this
-        pub const NOC1_AUTH_KEY_FAIL: [u8; 247] = [
-            0x15, 0x30, 0x1, 0x1, 0x1, 0x24, 0x2, 0x1, 0x37, 0x3, 0x24, 0x13, 0x1, 0x24, 0x15, 0x1,
-            0x18, 0x26, 0x4, 0x80, 0x22, 0x81, 0x27, 0x26, 0x5, 0x80, 0x25, 0x4d, 0x3a, 0x37, 0x6,
-            0x26, 0x11, 0x2, 0x5c, 0xbc, 0x0, 0x24, 0x15, 0x1, 0x18, 0x24, 0x7, 0x1, 0x24, 0x8,
-            0x1, 0x30, 0x9, 0x41, 0x4, 0xba, 0x22, 0x56, 0x43, 0x4f, 0x59, 0x98, 0x32, 0x8d, 0xb8,
-            0xcb, 0x3f, 0x24, 0x90, 0x9a, 0x96, 0x94, 0x43, 0x46, 0x67, 0xc2, 0x11, 0xe3, 0x80,
-            0x26, 0x65, 0xfc, 0x65, 0x37, 0x77, 0x3, 0x25, 0x18, 0xd8, 0xdc, 0x85, 0xfa, 0xe6,
-            0x42, 0xe7, 0x55, 0xc9, 0x37, 0xcc, 0xb, 0x78, 0x84, 0x3d, 0x2f, 0xac, 0x81, 0x88,
-            0x2e, 0x69, 0x0, 0xa5, 0xfc, 0xcd, 0xe0, 0xad, 0xb2, 0x69, 0xca, 0x73, 0x37, 0xa, 0x35,
-            0x1, 0x28, 0x1, 0x18, 0x24, 0x2, 0x1, 0x36, 0x3, 0x4, 0x2, 0x4, 0x1, 0x18, 0x30, 0x4,
-            0x14, 0x39, 0x68, 0x16, 0x1e, 0xb5, 0x56, 0x6d, 0xd3, 0xf8, 0x61, 0xf2, 0x95, 0xf3,
-            0x55, 0xa0, 0xfb, 0xd2, 0x82, 0xc2, 0x29, 0x30, 0x5, 0x14, 0xce, 0x61, 0xb4, 0x28,
-            0x96, 0x72, 0x27, 0x64, 0x81, 0xbc, 0x4f, 0x0, 0x78, 0xa3, 0x30, 0x48, 0xfe, 0x6e,
-            0x65, 0x86, 0x18, 0x30, 0xb, 0x40, 0x2, 0x88, 0x42, 0x0, 0x6f, 0xcc, 0xe0, 0xf0, 0x6c,
-            0xd9, 0xf9, 0x5e, 0xe4, 0xc2, 0xaa, 0x1f, 0x57, 0x71, 0x62, 0xdb, 0x6b, 0x4e, 0xe7,
-            0x55, 0x3f, 0xc6, 0xc7, 0x9f, 0xf8, 0x30, 0xeb, 0x16, 0x6e, 0x6d, 0xc6, 0x9c, 0xb,
-            0xb7, 0xe2, 0xb8, 0xe3, 0xe7, 0x57, 0x88, 0x7b, 0xda, 0xe5, 0x79, 0x39, 0x6d, 0x2c,
-            0x37, 0xb2, 0x7f, 0xc3, 0x63, 0x2f, 0x7e, 0x70, 0xab, 0x5a, 0x2c, 0xf7, 0x5b, 0x18,
-        ];
-        // A single byte in the Certificate contents is changed in this
-        pub const NOC1_CORRUPT_CERT: [u8; 247] = [
-            0x15, 0x30, 0x1, 0x1, 0x1, 0x24, 0x2, 0x1, 0x37, 0x3, 0x24, 0x13, 0x1, 0x24, 0x15, 0x1,
-            0x18, 0x26, 0x4, 0x80, 0x22, 0x81, 0x27, 0x26, 0x5, 0x80, 0x25, 0x4d, 0x3a, 0x37, 0x6,
-            0x26, 0x11, 0x2, 0x5c, 0xbc, 0x0, 0x24, 0x15, 0x1, 0x18, 0x24, 0x7, 0x1, 0x24, 0x8,
-            0x1, 0x30, 0x9, 0x41, 0x4, 0xba, 0x23, 0x56, 0x43, 0x4f, 0x59, 0x98, 0x32, 0x8d, 0xb8,
-            0xcb, 0x3f, 0x24, 0x90, 0x9a, 0x96, 0x94, 0x43, 0x46, 0x67, 0xc2, 0x11, 0xe3, 0x80,
-            0x26, 0x65, 0xfc, 0x65, 0x37, 0x77, 0x3, 0x25, 0x18, 0xd8, 0xdc, 0x85, 0xfa, 0xe6,
-            0x42, 0xe7, 0x55, 0xc9, 0x37, 0xcc, 0xb, 0x78, 0x84, 0x3d, 0x2f, 0xac, 0x81, 0x88,
-            0x2e, 0x69, 0x0, 0xa5, 0xfc, 0xcd, 0xe0, 0xad, 0xb2, 0x69, 0xca, 0x73, 0x37, 0xa, 0x35,
-            0x1, 0x28, 0x1, 0x18, 0x24, 0x2, 0x1, 0x36, 0x3, 0x4, 0x2, 0x4, 0x1, 0x18, 0x30, 0x4,
-            0x14, 0x39, 0x68, 0x16, 0x1e, 0xb5, 0x56, 0x6d, 0xd3, 0xf8, 0x61, 0xf2, 0x95, 0xf3,
-            0x55, 0xa0, 0xfb, 0xd2, 0x82, 0xc2, 0x29, 0x30, 0x5, 0x14, 0xce, 0x60, 0xb4, 0x28,
-            0x96, 0x72, 0x27, 0x64, 0x81, 0xbc, 0x4f, 0x0, 0x78, 0xa3, 0x30, 0x48, 0xfe, 0x6e,
-            0x65, 0x86, 0x18, 0x30, 0xb, 0x40, 0x2, 0x88, 0x42, 0x0, 0x6f, 0xcc, 0xe0, 0xf0, 0x6c,
-            0xd9, 0xf9, 0x5e, 0xe4, 0xc2, 0xaa, 0x1f, 0x57, 0x71, 0x62, 0xdb, 0x6b, 0x4e, 0xe7,
-            0x55, 0x3f, 0xc6, 0xc7, 0x9f, 0xf8, 0x30, 0xeb, 0x16, 0x6e, 0x6d, 0xc6, 0x9c, 0xb,
-            0xb7, 0xe2, 0xb8, 0xe3, 0xe7, 0x57, 0x88, 0x7b, 0xda, 0xe5, 0x79, 0x39, 0x6d, 0x2c,
-            0x37, 0xb2, 0x7f, 0xc3, 0x63, 0x2f, 0x7e, 0x70, 0xab, 0x5a, 0x2c, 0xf7, 0x5b, 0x18,
-        ];
-        pub const RCA1_SUCCESS: [u8; 237] = [
-            0x15, 0x30, 0x1, 0x1, 0x0, 0x24, 0x2, 0x1, 0x37, 0x3, 0x24, 0x14, 0x0, 0x24, 0x15, 0x1,
-            0x18, 0x26, 0x4, 0x80, 0x22, 0x81, 0x27, 0x26, 0x5, 0x80, 0x25, 0x4d, 0x3a, 0x37, 0x6,
-            0x24, 0x14, 0x0, 0x24, 0x15, 0x1, 0x18, 0x24, 0x7, 0x1, 0x24, 0x8, 0x1, 0x30, 0x9,
-            0x41, 0x4, 0x6d, 0x70, 0x7e, 0x4b, 0x98, 0xf6, 0x2b, 0xab, 0x44, 0xd6, 0xfe, 0xa3,
-            0x2e, 0x39, 0xd8, 0xc3, 0x0, 0xa0, 0xe, 0xa8, 0x6c, 0x83, 0xff, 0x69, 0xd, 0xe8, 0x42,
-            0x1, 0xeb, 0xd, 0xaa, 0x68, 0x5d, 0xcb, 0x97, 0x2, 0x80, 0x1d, 0xa8, 0x50, 0x2, 0x2e,
-            0x5a, 0xa2, 0x5a, 0x2e, 0x51, 0x26, 0x4, 0xd2, 0x39, 0x62, 0xcd, 0x82, 0x38, 0x63,
-            0x28, 0xbf, 0x15, 0x1c, 0xa6, 0x27, 0xe0, 0xd7, 0x37, 0xa, 0x35, 0x1, 0x29, 0x1, 0x18,
-            0x24, 0x2, 0x60, 0x30, 0x4, 0x14, 0xd4, 0x56, 0x93, 0xbe, 0x70, 0x79, 0xf4, 0x9c, 0x70,
-            0x6b, 0x7, 0x6f, 0x11, 0x1c, 0x6d, 0xe5, 0x64, 0xa4, 0x44, 0x74, 0x30, 0x5, 0x14, 0xd4,
-            0x56, 0x93, 0xbe, 0x70, 0x79, 0xf4, 0x9c, 0x70, 0x6b, 0x7, 0x6f, 0x11, 0x1c, 0x6d,
-            0xe5, 0x64, 0xa4, 0x44, 0x74, 0x18, 0x30, 0xb, 0x40, 0x3, 0xd, 0x77, 0xe1, 0x9e, 0xea,
-            0x9c, 0x5, 0x5c, 0xcc, 0x47, 0xe8, 0xb3, 0x18, 0x1a, 0xd1, 0x74, 0xee, 0xc6, 0x2e,
-            0xa1, 0x20, 0x16, 0xbd, 0x20, 0xb4, 0x3d, 0xac, 0x24, 0xbe, 0x17, 0xf9, 0xe, 0xb7,
-            0x9a, 0x98, 0xc8, 0xbc, 0x6a, 0xce, 0x99, 0x2a, 0x2e, 0x63, 0x4c, 0x76, 0x6, 0x45,
-            0x93, 0xd3, 0x7c, 0x4, 0x0, 0xe4, 0xc7, 0x78, 0xe9, 0x83, 0x5b, 0xc, 0x33, 0x61, 0x5c,
-            0x2e, 0x18,
-        ];
-        pub const ASN1_INPUT1: [u8; 237] = [
-            0x15, 0x30, 0x01, 0x01, 0x00, 0x24, 0x02, 0x01, 0x37, 0x03, 0x24, 0x14, 0x00, 0x24,
-            0x15, 0x03, 0x18, 0x26, 0x04, 0x80, 0x22, 0x81, 0x27, 0x26, 0x05, 0x80, 0x25, 0x4d,
-            0x3a, 0x37, 0x06, 0x24, 0x13, 0x01, 0x24, 0x15, 0x03, 0x18, 0x24, 0x07, 0x01, 0x24,
-            0x08, 0x01, 0x30, 0x09, 0x41, 0x04, 0x69, 0xda, 0xe9, 0x42, 0x88, 0xcf, 0x64, 0x94,
-            0x2d, 0xd5, 0x0a, 0x74, 0x2d, 0x50, 0xe8, 0x5e, 0xbe, 0x15, 0x53, 0x24, 0xe5, 0xc5,
-            0x6b, 0xe5, 0x7f, 0xc1, 0x41, 0x11, 0x21, 0xdd, 0x46, 0xa3, 0x0d, 0x63, 0xc3, 0xe3,
-            0x90, 0x7a, 0x69, 0x64, 0xdd, 0x66, 0x78, 0x10, 0xa6, 0xc8, 0x0f, 0xfd, 0xb6, 0xf2,
-            0x9b, 0x88, 0x50, 0x93, 0x77, 0x9e, 0xf7, 0xb4, 0xda, 0x94, 0x11, 0x33, 0x1e, 0xfe,
-            0x37, 0x0a, 0x35, 0x01, 0x29, 0x01, 0x18, 0x24, 0x02, 0x60, 0x30, 0x04, 0x14, 0xdf,
-            0xfb, 0x79, 0xf1, 0x2b, 0xbf, 0x68, 0x18, 0x59, 0x7f, 0xf7, 0xe8, 0xaf, 0x88, 0x91,
-            0x1c, 0x72, 0x32, 0xf7, 0x52, 0x30, 0x05, 0x14, 0xed, 0x31, 0x5e, 0x1a, 0xb7, 0xb9,
-            0x7a, 0xca, 0x04, 0x79, 0x5d, 0x82, 0x57, 0x7a, 0xd7, 0x0a, 0x75, 0xd0, 0xdb, 0x7a,
-            0x18, 0x30, 0x0b, 0x40, 0xe5, 0xd4, 0xe6, 0x0e, 0x98, 0x62, 0x2f, 0xaa, 0x59, 0xe0,
-            0x28, 0x59, 0xc2, 0xd4, 0xcd, 0x34, 0x85, 0x7f, 0x93, 0xbe, 0x14, 0x35, 0xa3, 0x76,
-            0x8a, 0xc9, 0x2f, 0x59, 0x39, 0xa0, 0xb0, 0x75, 0xe8, 0x8e, 0x11, 0xa9, 0xc1, 0x9e,
-            0xaa, 0xab, 0xa0, 0xdb, 0xb4, 0x79, 0x63, 0xfc, 0x02, 0x03, 0x27, 0x25, 0xac, 0x21,
-            0x6f, 0xef, 0x27, 0xab, 0x0f, 0x90, 0x09, 0x99, 0x05, 0xa8, 0x60, 0xd8, 0x18,
-        ];
-        pub const ASN1_INPUT2: [u8; 247] = [
-            0x15, 0x30, 0x01, 0x01, 0x01, 0x24, 0x02, 0x01, 0x37, 0x03, 0x24, 0x13, 0x01, 0x24,
-            0x15, 0x03, 0x18, 0x26, 0x04, 0x80, 0x22, 0x81, 0x27, 0x26, 0x05, 0x80, 0x25, 0x4d,
-            0x3a, 0x37, 0x06, 0x26, 0x11, 0x69, 0xb6, 0x01, 0x00, 0x24, 0x15, 0x03, 0x18, 0x24,
-            0x07, 0x01, 0x24, 0x08, 0x01, 0x30, 0x09, 0x41, 0x04, 0x93, 0x04, 0xc6, 0xc4, 0xe1,
-            0xbc, 0x9a, 0xc8, 0xf5, 0xb3, 0x7f, 0x83, 0xd6, 0x7f, 0x79, 0xc5, 0x35, 0xdc, 0x7f,
-            0xac, 0x87, 0xca, 0xcd, 0x08, 0x80, 0x4a, 0x55, 0x60, 0x80, 0x09, 0xd3, 0x9b, 0x4a,
-            0xc8, 0xe7, 0x7b, 0x4d, 0x5c, 0x82, 0x88, 0x24, 0xdf, 0x1c, 0xfd, 0xef, 0xb4, 0xbc,
-            0xb7, 0x2f, 0x36, 0xf7, 0x2b, 0xb2, 0xcc, 0x14, 0x69, 0x63, 0xcc, 0x89, 0xd2, 0x74,
-            0x3f, 0xd1, 0x98, 0x37, 0x0a, 0x35, 0x01, 0x28, 0x01, 0x18, 0x24, 0x02, 0x01, 0x36,
-            0x03, 0x04, 0x02, 0x04, 0x01, 0x18, 0x30, 0x04, 0x14, 0x9c, 0xe7, 0xd9, 0xa8, 0x6b,
-            0xf8, 0x71, 0xfa, 0x08, 0x10, 0xa3, 0xf2, 0x3a, 0x95, 0x30, 0xb1, 0x9e, 0xae, 0xc4,
-            0x2c, 0x30, 0x05, 0x14, 0xdf, 0xfb, 0x79, 0xf1, 0x2b, 0xbf, 0x68, 0x18, 0x59, 0x7f,
-            0xf7, 0xe8, 0xaf, 0x88, 0x91, 0x1c, 0x72, 0x32, 0xf7, 0x52, 0x18, 0x30, 0x0b, 0x40,
-            0xcf, 0x01, 0x37, 0x65, 0xd6, 0x8a, 0xca, 0xd8, 0x33, 0x9f, 0x0f, 0x4f, 0xd5, 0xed,
-            0x48, 0x42, 0x91, 0xca, 0xab, 0xf7, 0xae, 0xe1, 0x3b, 0x2b, 0xef, 0x9f, 0x43, 0x5a,
-            0x96, 0xe0, 0xa5, 0x38, 0x8e, 0x39, 0xd0, 0x20, 0x8a, 0x0c, 0x92, 0x2b, 0x21, 0x7d,
-            0xf5, 0x6c, 0x1d, 0x65, 0x6c, 0x0f, 0xd1, 0xe8, 0x55, 0x14, 0x5e, 0x27, 0xfd, 0xa4,
-            0xac, 0xf9, 0x93, 0xdb, 0x29, 0x49, 0xaa, 0x71, 0x18,
-        ];
+    #[test]
+    fn test_tlv_io_sink() {
+        let cert = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
 
-        pub const ASN1_OUTPUT1: [u8; 388] = [
-            0x30, 0x82, 0x01, 0x80, 0xa0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x01, 0x00, 0x30, 0x0a,
-            0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02, 0x30, 0x44, 0x31, 0x20,
-            0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xa2, 0x7c, 0x01, 0x04,
-            0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04,
-            0x01, 0x82, 0xa2, 0x7c, 0x01, 0x05, 0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x33, 0x30, 0x1e, 0x17, 0x0d,
-            0x32, 0x31, 0x30, 0x31, 0x30, 0x31, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x5a, 0x17,
-            0x0d, 0x33, 0x30, 0x31, 0x32, 0x33, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x5a,
-            0x30, 0x44, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82,
-            0xa2, 0x7c, 0x01, 0x03, 0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x31, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a,
-            0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xa2, 0x7c, 0x01, 0x05, 0x0c, 0x10, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x33,
-            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
-            0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04, 0x69,
-            0xda, 0xe9, 0x42, 0x88, 0xcf, 0x64, 0x94, 0x2d, 0xd5, 0x0a, 0x74, 0x2d, 0x50, 0xe8,
-            0x5e, 0xbe, 0x15, 0x53, 0x24, 0xe5, 0xc5, 0x6b, 0xe5, 0x7f, 0xc1, 0x41, 0x11, 0x21,
-            0xdd, 0x46, 0xa3, 0x0d, 0x63, 0xc3, 0xe3, 0x90, 0x7a, 0x69, 0x64, 0xdd, 0x66, 0x78,
-            0x10, 0xa6, 0xc8, 0x0f, 0xfd, 0xb6, 0xf2, 0x9b, 0x88, 0x50, 0x93, 0x77, 0x9e, 0xf7,
-            0xb4, 0xda, 0x94, 0x11, 0x33, 0x1e, 0xfe, 0xa3, 0x63, 0x30, 0x61, 0x30, 0x0f, 0x06,
-            0x03, 0x55, 0x1d, 0x13, 0x01, 0x01, 0xff, 0x04, 0x05, 0x30, 0x03, 0x01, 0x01, 0xff,
-            0x30, 0x0e, 0x06, 0x03, 0x55, 0x1d, 0x0f, 0x01, 0x01, 0xff, 0x04, 0x04, 0x03, 0x02,
-            0x01, 0x06, 0x30, 0x1d, 0x06, 0x03, 0x55, 0x1d, 0x0e, 0x04, 0x16, 0x04, 0x14, 0xdf,
-            0xfb, 0x79, 0xf1, 0x2b, 0xbf, 0x68, 0x18, 0x59, 0x7f, 0xf7, 0xe8, 0xaf, 0x88, 0x91,
-            0x1c, 0x72, 0x32, 0xf7, 0x52, 0x30, 0x1f, 0x06, 0x03, 0x55, 0x1d, 0x23, 0x04, 0x18,
-            0x30, 0x16, 0x80, 0x14, 0xed, 0x31, 0x5e, 0x1a, 0xb7, 0xb9, 0x7a, 0xca, 0x04, 0x79,
-            0x5d, 0x82, 0x57, 0x7a, 0xd7, 0x0a, 0x75, 0xd0, 0xdb, 0x7a,
-        ];
-        pub const ASN1_OUTPUT2: [u8; 421] = [
-            0x30, 0x82, 0x01, 0xa1, 0xa0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01, 0x30, 0x0a,
-            0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02, 0x30, 0x44, 0x31, 0x20,
-            0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xa2, 0x7c, 0x01, 0x03,
-            0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x31, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04,
-            0x01, 0x82, 0xa2, 0x7c, 0x01, 0x05, 0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x33, 0x30, 0x1e, 0x17, 0x0d,
-            0x32, 0x31, 0x30, 0x31, 0x30, 0x31, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x5a, 0x17,
-            0x0d, 0x33, 0x30, 0x31, 0x32, 0x33, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x5a,
-            0x30, 0x44, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82,
-            0xa2, 0x7c, 0x01, 0x01, 0x0c, 0x10, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x31, 0x42, 0x36, 0x36, 0x39, 0x31, 0x20, 0x30, 0x1e, 0x06, 0x0a,
-            0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xa2, 0x7c, 0x01, 0x05, 0x0c, 0x10, 0x30, 0x30,
-            0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x33,
-            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
-            0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04, 0x93,
-            0x04, 0xc6, 0xc4, 0xe1, 0xbc, 0x9a, 0xc8, 0xf5, 0xb3, 0x7f, 0x83, 0xd6, 0x7f, 0x79,
-            0xc5, 0x35, 0xdc, 0x7f, 0xac, 0x87, 0xca, 0xcd, 0x08, 0x80, 0x4a, 0x55, 0x60, 0x80,
-            0x09, 0xd3, 0x9b, 0x4a, 0xc8, 0xe7, 0x7b, 0x4d, 0x5c, 0x82, 0x88, 0x24, 0xdf, 0x1c,
-            0xfd, 0xef, 0xb4, 0xbc, 0xb7, 0x2f, 0x36, 0xf7, 0x2b, 0xb2, 0xcc, 0x14, 0x69, 0x63,
-            0xcc, 0x89, 0xd2, 0x74, 0x3f, 0xd1, 0x98, 0xa3, 0x81, 0x83, 0x30, 0x81, 0x80, 0x30,
-            0x0c, 0x06, 0x03, 0x55, 0x1d, 0x13, 0x01, 0x01, 0xff, 0x04, 0x02, 0x30, 0x00, 0x30,
-            0x0e, 0x06, 0x03, 0x55, 0x1d, 0x0f, 0x01, 0x01, 0xff, 0x04, 0x04, 0x03, 0x02, 0x07,
-            0x80, 0x30, 0x20, 0x06, 0x03, 0x55, 0x1d, 0x25, 0x01, 0x01, 0xff, 0x04, 0x16, 0x30,
-            0x14, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02, 0x06, 0x08, 0x2b,
-            0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01, 0x30, 0x1d, 0x06, 0x03, 0x55, 0x1d, 0x0e,
-            0x04, 0x16, 0x04, 0x14, 0x9c, 0xe7, 0xd9, 0xa8, 0x6b, 0xf8, 0x71, 0xfa, 0x08, 0x10,
-            0xa3, 0xf2, 0x3a, 0x95, 0x30, 0xb1, 0x9e, 0xae, 0xc4, 0x2c, 0x30, 0x1f, 0x06, 0x03,
-            0x55, 0x1d, 0x23, 0x04, 0x18, 0x30, 0x16, 0x80, 0x14, 0xdf, 0xfb, 0x79, 0xf1, 0x2b,
-            0xbf, 0x68, 0x18, 0x59, 0x7f, 0xf7, 0xe8, 0xaf, 0x88, 0x91, 0x1c, 0x72, 0x32, 0xf7,
-            0x52,
-        ];
+        let mut buf = [0u8; 1024];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        cert.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+
+        let mut out: Vec<u8> = Vec::new();
+        let mut tw = TLVWriter::new_io(&mut out);
+        cert.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        tw.finish().unwrap();
+        assert_eq!(tw.get_tail(), out.len());
+
+        assert_eq!(wb.as_slice(), out.as_slice());
     }
 }
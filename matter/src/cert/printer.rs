@@ -1,4 +1,4 @@
-use super::{CertConsumer, MAX_DEPTH};
+use super::{matter_epoch_to_unix, CertConsumer, MAX_DEPTH};
 use crate::error::Error;
 use chrono::{TimeZone, Utc};
 use std::fmt;
@@ -101,15 +101,17 @@ impl<'a, 'b> CertConsumer for CertPrinter<'a, 'b> {
         Ok(())
     }
     fn utctime(&mut self, tag: &str, epoch: u32) -> Result<(), Error> {
-        let mut matter_epoch = Utc.ymd(2000, 1, 1).and_hms(0, 0, 0).timestamp();
-        matter_epoch += epoch as i64;
         let _ = writeln!(
             self.f,
             "{} {} {}",
             SPACE[self.level],
             tag,
-            Utc.timestamp(matter_epoch, 0)
+            Utc.timestamp(matter_epoch_to_unix(epoch) as i64, 0)
         );
         Ok(())
     }
+    fn raw(&mut self, tag: &str, der: &[u8]) -> Result<(), Error> {
+        let _ = writeln!(self.f, "{} {} {:x?}", SPACE[self.level], tag, der);
+        Ok(())
+    }
 }
@@ -0,0 +1,326 @@
+//! Parsing of the onboarding payloads, the QR code and the manual pairing code, that a
+//! commissioner starts off commissioning a device with
+
+use bitflags::bitflags;
+use log::error;
+use num_derive::FromPrimitive;
+
+use crate::error::Error;
+
+/// The prefix of the QR code payload
+pub const QR_CODE_PREFIX: &str = "MT:";
+
+const BASE38_CHARS: &[u8; 38] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-.";
+
+// The length of the packed fields of the QR code payload, any optional TLV data follows
+const QR_CODE_PACKED_LEN: usize = 11;
+
+/// The commissioning flow of a device, as per its onboarding payload
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommissioningFlow {
+    Standard = 0,
+    UserIntent = 1,
+    Custom = 2,
+}
+
+bitflags! {
+    /// The ways in which a device can be discovered for commissioning
+    #[derive(Default)]
+    pub struct DiscoveryCapabilities: u8 {
+        const SOFT_AP = 0x01;
+        const BLE = 0x02;
+        const ON_NETWORK = 0x04;
+    }
+}
+
+/// The discriminator of a device, the manual pairing code only carries its upper 4 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discriminator {
+    Long(u16),
+    Short(u8),
+}
+
+impl Discriminator {
+    /// The short discriminator, as advertised in the commissionable mDNS '_S' subtype
+    pub fn short(&self) -> u8 {
+        match *self {
+            Discriminator::Long(d) => (d >> 8) as u8,
+            Discriminator::Short(d) => d,
+        }
+    }
+}
+
+/// The details of a device for commissioning, as found in its onboarding payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairingInfo {
+    pub discriminator: Discriminator,
+    pub passcode: u32,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    /// None for a manual pairing code of a flow other than the Standard one, the code
+    /// doesn't tell which
+    pub flow: Option<CommissioningFlow>,
+    /// None for a manual pairing code, that doesn't carry these
+    pub discovery: Option<DiscoveryCapabilities>,
+}
+
+/// Parse the payload of a QR code, like "MT:Y.K9042C00KA0648G00"
+///
+/// The optional TLV data that may follow the packed fields is ignored.
+pub fn parse_qr_code(code: &str) -> Result<PairingInfo, Error> {
+    let payload = code.strip_prefix(QR_CODE_PREFIX).ok_or_else(|| {
+        error!("QR code doesn't start with {}", QR_CODE_PREFIX);
+        Error::InvalidData
+    })?;
+    let bytes = base38_decode(payload)?;
+    if bytes.len() < QR_CODE_PACKED_LEN {
+        error!("QR code payload is too short: {} bytes", bytes.len());
+        return Err(Error::InvalidData);
+    }
+
+    // The fields are packed least significant bit first
+    let mut packed = [0u8; 16];
+    packed[..QR_CODE_PACKED_LEN].copy_from_slice(&bytes[..QR_CODE_PACKED_LEN]);
+    let mut packed = u128::from_le_bytes(packed);
+    let mut take = |bits: u32| {
+        let value = (packed & ((1_u128 << bits) - 1)) as u32;
+        packed >>= bits;
+        value
+    };
+
+    let version = take(3);
+    let vid = take(16) as u16;
+    let pid = take(16) as u16;
+    let flow = take(2);
+    let discovery = take(8) as u8;
+    let discriminator = take(12) as u16;
+    let passcode = take(27);
+    if version != 0 {
+        error!("Unsupported QR code version {}", version);
+        return Err(Error::InvalidData);
+    }
+    let flow = num::FromPrimitive::from_u32(flow).ok_or(Error::InvalidData)?;
+    // The bits that this doesn't know of may be in use by newer devices
+    let discovery = DiscoveryCapabilities::from_bits_truncate(discovery);
+    check_passcode(passcode)?;
+
+    Ok(PairingInfo {
+        discriminator: Discriminator::Long(discriminator),
+        passcode,
+        vid: Some(vid),
+        pid: Some(pid),
+        flow: Some(flow),
+        discovery: Some(discovery),
+    })
+}
+
+/// Parse a manual pairing code, of 11 digits, or 21 digits with the VID and PID
+///
+/// The dashes and spaces that the code is often printed with are ignored.
+pub fn parse_manual_code(code: &str) -> Result<PairingInfo, Error> {
+    let digits: Vec<u8> = code
+        .chars()
+        .filter(|c| *c != '-' && *c != ' ')
+        .map(|c| c.to_digit(10).map(|d| d as u8).ok_or(Error::InvalidData))
+        .collect::<Result<_, _>>()?;
+    if digits.len() != 11 && digits.len() != 21 {
+        error!("Manual pairing code has {} digits", digits.len());
+        return Err(Error::InvalidData);
+    }
+    if !verhoeff_valid(&digits) {
+        error!("Manual pairing code check digit doesn't match");
+        return Err(Error::InvalidData);
+    }
+
+    let chunk = |range: std::ops::Range<usize>| {
+        digits[range]
+            .iter()
+            .fold(0_u32, |acc, d| acc * 10 + *d as u32)
+    };
+    let first = chunk(0..1);
+    // The first digit 8 and 9 are reserved
+    if first > 7 {
+        return Err(Error::InvalidData);
+    }
+    let vid_pid_present = first & 0x4 != 0;
+    if vid_pid_present != (digits.len() == 21) {
+        error!("Manual pairing code length doesn't match its VID/PID flag");
+        return Err(Error::InvalidData);
+    }
+    let second = chunk(1..6);
+    let third = chunk(6..10);
+    if second > 0xFFFF || third > 0x1FFF {
+        return Err(Error::InvalidData);
+    }
+
+    let short_discriminator = (((first & 0x3) << 2) | (second >> 14)) as u8;
+    let passcode = (third << 14) | (second & 0x3FFF);
+    check_passcode(passcode)?;
+
+    let (vid, pid) = if vid_pid_present {
+        let (vid, pid) = (chunk(10..15), chunk(15..20));
+        if vid > 0xFFFF || pid > 0xFFFF {
+            return Err(Error::InvalidData);
+        }
+        (Some(vid as u16), Some(pid as u16))
+    } else {
+        (None, None)
+    };
+
+    Ok(PairingInfo {
+        discriminator: Discriminator::Short(short_discriminator),
+        passcode,
+        vid,
+        pid,
+        flow: if vid_pid_present {
+            None
+        } else {
+            Some(CommissioningFlow::Standard)
+        },
+        discovery: None,
+    })
+}
+
+// The passcodes that are too easy to guess aren't valid
+fn check_passcode(passcode: u32) -> Result<(), Error> {
+    const INVALID_PASSCODES: [u32; 12] = [
+        0, 11111111, 22222222, 33333333, 44444444, 55555555, 66666666, 77777777, 88888888,
+        99999999, 12345678, 87654321,
+    ];
+    if passcode > 99999998 || INVALID_PASSCODES.contains(&passcode) {
+        error!("Invalid passcode in the onboarding payload");
+        return Err(Error::InvalidData);
+    }
+    Ok(())
+}
+
+// Every 5 characters decode to 3 bytes, with the leftover 4 characters decoding to 2 bytes
+// and 2 characters to 1 byte. The first character is the least significant digit.
+fn base38_decode(s: &str) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 5 + 1);
+    for chars in s.as_bytes().chunks(5) {
+        let len = match chars.len() {
+            5 => 3,
+            4 => 2,
+            2 => 1,
+            _ => return Err(Error::InvalidData),
+        };
+        let mut value: u32 = 0;
+        for c in chars.iter().rev() {
+            let digit = BASE38_CHARS
+                .iter()
+                .position(|b| b == c)
+                .ok_or(Error::InvalidData)?;
+            value = value * 38 + digit as u32;
+        }
+        if value >> (8 * len) != 0 {
+            return Err(Error::InvalidData);
+        }
+        for _ in 0..len {
+            out.push(value as u8);
+            value >>= 8;
+        }
+    }
+    Ok(out)
+}
+
+// Whether the last of the digits is the Verhoeff check digit of the ones before it
+fn verhoeff_valid(digits: &[u8]) -> bool {
+    const D: [[u8; 10]; 10] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+        [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+        [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+        [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+        [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+        [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+        [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+        [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+        [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+    ];
+    const P: [[u8; 10]; 8] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+        [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+        [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+        [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+        [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+        [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+        [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+    ];
+    let c = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(0, |c, (i, d)| D[c as usize][P[i % 8][*d as usize] as usize]);
+    c == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_qr_code() {
+        // The example of the spec
+        assert_eq!(
+            parse_qr_code("MT:Y.K9042C00KA0648G00"),
+            Ok(PairingInfo {
+                discriminator: Discriminator::Long(3840),
+                passcode: 20202021,
+                vid: Some(0xfff1),
+                pid: Some(0x8000),
+                flow: Some(CommissioningFlow::Standard),
+                discovery: Some(DiscoveryCapabilities::BLE),
+            })
+        );
+        assert_eq!(Discriminator::Long(3840).short(), 15);
+
+        // The same, with a reserved discovery capability bit set
+        assert_eq!(
+            parse_qr_code("MT:Y.K9088GJ0KA0648G00").map(|i| i.discovery),
+            Ok(Some(DiscoveryCapabilities::BLE))
+        );
+
+        assert_eq!(
+            parse_qr_code("Y.K9042C00KA0648G00"),
+            Err(Error::InvalidData)
+        );
+        // Not a base38 character, and a payload that is cut short
+        assert_eq!(
+            parse_qr_code("MT:Y.K9042C00KA0648G0a"),
+            Err(Error::InvalidData)
+        );
+        assert_eq!(parse_qr_code("MT:Y.K9042C00KA06"), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn test_parse_manual_code() {
+        // The example of the spec, for the same device as the QR code
+        let info = PairingInfo {
+            discriminator: Discriminator::Short(15),
+            passcode: 20202021,
+            vid: None,
+            pid: None,
+            flow: Some(CommissioningFlow::Standard),
+            discovery: None,
+        };
+        assert_eq!(parse_manual_code("34970112332"), Ok(info));
+        assert_eq!(parse_manual_code("3497-011-2332"), Ok(info));
+
+        // The same, with the VID and PID
+        assert_eq!(
+            parse_manual_code("749701123365521327687"),
+            Ok(PairingInfo {
+                vid: Some(0xfff1),
+                pid: Some(0x8000),
+                flow: None,
+                ..info
+            })
+        );
+
+        // The check digit doesn't match
+        assert_eq!(parse_manual_code("34970112331"), Err(Error::InvalidData));
+        assert_eq!(parse_manual_code("3497011233"), Err(Error::InvalidData));
+    }
+}
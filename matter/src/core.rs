@@ -6,17 +6,25 @@ use crate::{
     },
     error::*,
     fabric::FabricMgr,
+    groups::GroupMgr,
     interaction_model::InteractionModel,
     secure_channel::core::SecureChannel,
     transport,
+    utils::tick::{self, Tick, Ticker},
 };
-use std::sync::Arc;
+#[cfg(feature = "session_diagnostics")]
+use crate::{
+    data_model::sdm::session_diagnostics::{SessionDiagCluster, SessionDiagTick},
+    transport::diag::TransportDiag,
+};
+use std::{sync::Arc, time::SystemTime};
 
 /// The primary Matter Object
 pub struct Matter {
     transport_mgr: transport::mgr::Mgr,
     data_model: DataModel,
     fabric_mgr: Arc<FabricMgr>,
+    ticker: Ticker,
 }
 
 impl Matter {
@@ -32,12 +40,20 @@ impl Matter {
     ) -> Result<Box<Matter>, Error> {
         let fabric_mgr = Arc::new(FabricMgr::new()?);
         let acl_mgr = Arc::new(AclMgr::new()?);
+        let group_mgr = Arc::new(GroupMgr::new());
         let open_comm_window = fabric_mgr.is_empty();
-        let data_model = DataModel::new(dev_det, dev_att, fabric_mgr.clone(), acl_mgr.clone())?;
+        let data_model = DataModel::new(
+            dev_det,
+            dev_att,
+            fabric_mgr.clone(),
+            acl_mgr.clone(),
+            group_mgr,
+        )?;
         let mut matter = Box::new(Matter {
             transport_mgr: transport::mgr::Mgr::new()?,
             data_model,
             fabric_mgr,
+            ticker: Ticker::new(),
         });
         let interaction_model =
             Box::new(InteractionModel::new(Box::new(matter.data_model.clone())));
@@ -47,9 +63,25 @@ impl Matter {
             secure_channel.open_comm_window();
         }
         matter.transport_mgr.register_protocol(secure_channel)?;
+        #[cfg(feature = "session_diagnostics")]
+        matter.add_session_diagnostics()?;
+        let failsafe = matter.data_model.failsafe();
+        matter.register_tick(Box::new(failsafe));
         Ok(matter)
     }
 
+    // The vendor-specific diagnostics of the sessions and exchanges in use, on endpoint 0
+    #[cfg(feature = "session_diagnostics")]
+    fn add_session_diagnostics(&mut self) -> Result<(), Error> {
+        let diag = Arc::new(TransportDiag::new());
+        self.transport_mgr.set_diag(diag.clone());
+        self.data_model
+            .add_cluster(0, SessionDiagCluster::new(diag.clone())?)?;
+        let node = self.data_model.node.clone();
+        self.register_tick(Box::new(SessionDiagTick::new(diag, node, 0)));
+        Ok(())
+    }
+
     /// Returns an Arc to [DataModel]
     ///
     /// The Data Model is where you express what is the type of your device. Typically
@@ -59,6 +91,25 @@ impl Matter {
         self.data_model.clone()
     }
 
+    /// Service 'subsystem' on every [Matter::tick] from now on
+    pub fn register_tick(&mut self, subsystem: Box<dyn Tick>) {
+        self.ticker.register(subsystem);
+    }
+
+    /// Services all the time-based subsystems at 'now'
+    ///
+    /// The daemon calls this every time it is done waiting for a message. It sends the
+    /// subscription reports, acknowledgements and retransmissions that are due, drops the
+    /// abandoned CASE handshakes, and expires the Fail-Safe, along with anything registered
+    /// through [Matter::register_tick].
+    ///
+    /// Returns the earliest time by which this should be called again, or None if nothing
+    /// is pending.
+    pub fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+        let next = self.transport_mgr.tick(now);
+        tick::earliest(next, self.ticker.tick(now))
+    }
+
     /// Starts the Matter daemon
     ///
     /// This call does NOT return
@@ -66,6 +117,9 @@ impl Matter {
     /// This call starts the Matter daemon that starts communication with other Matter
     /// devices on the network.
     pub fn start_daemon(&mut self) -> Result<(), Error> {
-        self.transport_mgr.start()
+        loop {
+            let next = self.tick(SystemTime::now());
+            self.transport_mgr.poll(next);
+        }
     }
 }
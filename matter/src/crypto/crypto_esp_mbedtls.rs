@@ -9,6 +9,11 @@ pub fn hkdf_sha256(_salt: &[u8], _ikm: &[u8], _info: &[u8], _key: &mut [u8]) ->
     Ok(())
 }
 
+pub fn sha1(_data: &[u8], _digest: &mut [u8]) -> Result<(), Error> {
+    error!("This API should never get called");
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Sha256{}
 
@@ -68,6 +73,23 @@ impl KeyPair {
 
         Ok(Self {})
     }
+
+    pub fn from_private_scalar(_priv_key: &[u8; super::BIGNUM_LEN_BYTES]) -> Result<Self, Error> {
+        error!("This API should never get called");
+
+        Ok(Self {})
+    }
+
+    pub fn from_der_pkcs8(_der: &[u8]) -> Result<Self, Error> {
+        error!("This API should never get called");
+
+        Ok(Self {})
+    }
+
+    pub fn to_der_pkcs8<'a>(&self, _out: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        error!("This API should never get called");
+        Err(Error::Invalid)
+    }
 }
 
 impl CryptoKeyPair for KeyPair {
@@ -63,8 +63,79 @@ impl KeyPair {
             key: Pk::public_from_ec_components(group, pub_key)?,
         })
     }
+
+    /// Create a KeyPair from the private key alone, the public key is derived from it
+    pub fn from_private_scalar(priv_key: &[u8; super::BIGNUM_LEN_BYTES]) -> Result<Self, Error> {
+        let priv_key = Mpi::from_binary(priv_key)?;
+        Ok(Self {
+            key: Pk::private_from_ec_components(EcGroup::new(EcGroupId::SecP256R1)?, priv_key)?,
+        })
+    }
+
+    /// Create a KeyPair from a PKCS#8 DER encoded P-256 private key
+    ///
+    /// Only the encoding generated by to_der_pkcs8() is accepted, which is the one that
+    /// includes the public key
+    pub fn from_der_pkcs8(der: &[u8]) -> Result<Self, Error> {
+        if der.len() != PKCS8_LEN
+            || der[..PKCS8_PRIV_KEY_OFFSET] != PKCS8_PREFIX
+            || der[PKCS8_PUB_KEY_OFFSET - PKCS8_PUB_KEY_PREFIX.len()..PKCS8_PUB_KEY_OFFSET]
+                != PKCS8_PUB_KEY_PREFIX
+        {
+            error!("Unsupported PKCS#8 encoding");
+            return Err(Error::Invalid);
+        }
+        let mut priv_key = [0u8; super::BIGNUM_LEN_BYTES];
+        priv_key.copy_from_slice(
+            &der[PKCS8_PRIV_KEY_OFFSET..PKCS8_PRIV_KEY_OFFSET + super::BIGNUM_LEN_BYTES],
+        );
+        let key = Self::from_private_scalar(&priv_key)?;
+
+        // The public key must be the one that belongs to the private key
+        let mut pub_key = [0u8; super::EC_POINT_LEN_BYTES];
+        key.get_public_key(&mut pub_key)?;
+        if der[PKCS8_PUB_KEY_OFFSET..] != pub_key {
+            error!("The public key doesn't match the private key");
+            return Err(Error::Invalid);
+        }
+        Ok(key)
+    }
+
+    /// Encode the private key of the KeyPair in the PKCS#8 DER format
+    pub fn to_der_pkcs8<'a>(&self, out: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        if out.len() < PKCS8_LEN {
+            return Err(Error::NoSpace);
+        }
+        // The private key is encoded with its leading zeroes, if any
+        let priv_key = self
+            .key
+            .ec_private()?
+            .to_binary_padded(super::BIGNUM_LEN_BYTES)?;
+        let out = &mut out[..PKCS8_LEN];
+        out[..PKCS8_PRIV_KEY_OFFSET].copy_from_slice(&PKCS8_PREFIX);
+        out[PKCS8_PRIV_KEY_OFFSET..PKCS8_PRIV_KEY_OFFSET + super::BIGNUM_LEN_BYTES]
+            .copy_from_slice(priv_key.as_slice());
+        out[PKCS8_PUB_KEY_OFFSET - PKCS8_PUB_KEY_PREFIX.len()..PKCS8_PUB_KEY_OFFSET]
+            .copy_from_slice(&PKCS8_PUB_KEY_PREFIX);
+        self.get_public_key(&mut out[PKCS8_PUB_KEY_OFFSET..])?;
+        Ok(out)
+    }
 }
 
+// rust-mbedtls can't write PKCS#8, but for a P-256 key it is a fixed layout:
+// the PrivateKeyInfo, with the id-ecPublicKey/prime256v1 algorithm, wrapping an
+// ECPrivateKey that carries the private key and the uncompressed public key
+const PKCS8_PREFIX: [u8; 36] = [
+    0x30, 0x81, 0x87, 0x02, 0x01, 0x00, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02,
+    0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x04, 0x6d, 0x30, 0x6b, 0x02,
+    0x01, 0x01, 0x04, 0x20,
+];
+const PKCS8_PUB_KEY_PREFIX: [u8; 5] = [0xa1, 0x44, 0x03, 0x42, 0x00];
+const PKCS8_PRIV_KEY_OFFSET: usize = PKCS8_PREFIX.len();
+const PKCS8_PUB_KEY_OFFSET: usize =
+    PKCS8_PRIV_KEY_OFFSET + super::BIGNUM_LEN_BYTES + PKCS8_PUB_KEY_PREFIX.len();
+const PKCS8_LEN: usize = PKCS8_PUB_KEY_OFFSET + super::EC_POINT_LEN_BYTES;
+
 impl CryptoKeyPair for KeyPair {
     fn get_csr<'a>(&self, out_csr: &'a mut [u8]) -> Result<&'a [u8], Error> {
         let tmp_priv = self.key.ec_private()?;
@@ -170,7 +241,7 @@ impl CryptoKeyPair for KeyPair {
         let mbedtls_sign = &mbedtls_sign[..len];
 
         if let Err(e) = tmp_key.verify(hash::Type::Sha256, &msg_hash, mbedtls_sign) {
-            println!("The error is {}", e);
+            error!("Signature verification failed: {}", e);
             Err(Error::InvalidSignature)
         } else {
             Ok(())
@@ -290,6 +361,12 @@ pub fn pbkdf2_hmac(pass: &[u8], iter: usize, salt: &[u8], key: &mut [u8]) -> Res
         .map_err(|_e| Error::TLSStack)
 }
 
+// SHA-1 is only used for the key identifiers of certificates, and never for signatures
+pub fn sha1(data: &[u8], digest: &mut [u8]) -> Result<(), Error> {
+    Md::hash(Type::Sha1, data, digest).map_err(|_e| Error::TLSStack)?;
+    Ok(())
+}
+
 pub fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], key: &mut [u8]) -> Result<(), Error> {
     Hkdf::hkdf(Type::Sha256, salt, ikm, info, key).map_err(|_e| Error::TLSStack)
 }
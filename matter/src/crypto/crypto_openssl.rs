@@ -82,6 +82,42 @@ impl KeyPair {
         })
     }
 
+    /// Create a KeyPair from the private key alone, the public key is derived from it
+    pub fn from_private_scalar(priv_key: &[u8; super::BIGNUM_LEN_BYTES]) -> Result<Self, Error> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let mut ctx = BigNumContext::new()?;
+        let priv_key = BigNum::from_slice(priv_key)?;
+        let mut pub_key = EcPoint::new(&group)?;
+        pub_key.mul_generator2(&group, &priv_key, &mut ctx)?;
+        Ok(Self {
+            key: KeyType::Private(EcKey::from_private_components(&group, &priv_key, &pub_key)?),
+        })
+    }
+
+    /// Create a KeyPair from a PKCS#8 DER encoded P-256 private key
+    pub fn from_der_pkcs8(der: &[u8]) -> Result<Self, Error> {
+        let key = PKey::private_key_from_pkcs8(der)?.ec_key()?;
+        if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            error!("Only P-256 keys are supported");
+            return Err(Error::Invalid);
+        }
+        key.check_key()?;
+        Ok(Self {
+            key: KeyType::Private(key),
+        })
+    }
+
+    /// Encode the private key of the KeyPair in the PKCS#8 DER format
+    pub fn to_der_pkcs8<'a>(&self, out: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        let der = PKey::from_ec_key(self.private_key()?.clone())?.private_key_to_pkcs8()?;
+        if der.len() > out.len() {
+            return Err(Error::NoSpace);
+        }
+        let out = &mut out[..der.len()];
+        out.copy_from_slice(der.as_slice());
+        Ok(out)
+    }
+
     fn public_key_point(&self) -> &EcPointRef {
         match &self.key {
             KeyType::Public(k) => k.public_key(),
@@ -168,11 +204,12 @@ impl CryptoKeyPair for KeyPair {
         }
         safemem::write_bytes(signature, 0);
 
+        // r and s are fixed length, including any leading zeroes
         let sig = EcdsaSig::sign(&msg, self.private_key()?)?;
-        let r = sig.r().to_vec();
-        signature[0..r.len()].copy_from_slice(r.as_slice());
-        let s = sig.s().to_vec();
-        signature[32..(32 + s.len())].copy_from_slice(s.as_slice());
+        let r = sig.r().to_vec_padded(super::BIGNUM_LEN_BYTES as i32)?;
+        signature[0..32].copy_from_slice(r.as_slice());
+        let s = sig.s().to_vec_padded(super::BIGNUM_LEN_BYTES as i32)?;
+        signature[32..64].copy_from_slice(s.as_slice());
         Ok(64)
     }
 
@@ -221,6 +258,13 @@ pub fn pbkdf2_hmac(pass: &[u8], iter: usize, salt: &[u8], key: &mut [u8]) -> Res
         .map_err(|_e| Error::TLSStack)
 }
 
+// SHA-1 is only used for the key identifiers of certificates, and never for signatures
+pub fn sha1(data: &[u8], digest: &mut [u8]) -> Result<(), Error> {
+    let h = openssl::hash::hash(MessageDigest::sha1(), data)?;
+    digest.copy_from_slice(h.as_ref());
+    Ok(())
+}
+
 pub fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], key: &mut [u8]) -> Result<(), Error> {
     let mut ctx = PkeyCtx::new_id(Id::HKDF)?;
     ctx.derive_init()?;
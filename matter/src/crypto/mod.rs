@@ -1,4 +1,5 @@
 use crate::error::Error;
+use subtle::ConstantTimeEq;
 
 pub const SYMM_KEY_LEN_BITS: usize = 128;
 pub const SYMM_KEY_LEN_BYTES: usize = SYMM_KEY_LEN_BITS / 8;
@@ -9,7 +10,12 @@ pub const AEAD_MIC_LEN_BYTES: usize = AEAD_MIC_LEN_BITS / 8;
 pub const AEAD_NONCE_LEN_BYTES: usize = 13;
 pub const AEAD_AAD_LEN_BYTES: usize = 8;
 
+// The fixed nonces for the encrypted payloads of CASE Sigma2 and Sigma3
+pub const NONCE_CASE_SIGMA2: [u8; AEAD_NONCE_LEN_BYTES] = *b"NCASE_Sigma2N";
+pub const NONCE_CASE_SIGMA3: [u8; AEAD_NONCE_LEN_BYTES] = *b"NCASE_Sigma3N";
+
 pub const SHA256_HASH_LEN_BYTES: usize = 256 / 8;
+pub const SHA1_HASH_LEN_BYTES: usize = 160 / 8;
 
 pub const BIGNUM_LEN_BYTES: usize = 32;
 pub const EC_POINT_LEN_BYTES: usize = 65;
@@ -18,6 +24,26 @@ pub const ECDH_SHARED_SECRET_LEN_BYTES: usize = 32;
 
 pub const EC_SIGNATURE_LEN_BYTES: usize = 64;
 
+/// Builds the AEAD nonce of a message
+///
+/// As defined in the Matter Spec, this is the security flags, followed by the message counter
+/// and the source node id, both in little-endian
+pub fn build_nonce(security_flags: u8, counter: u32, node_id: u64) -> [u8; AEAD_NONCE_LEN_BYTES] {
+    let mut nonce = [0u8; AEAD_NONCE_LEN_BYTES];
+    nonce[0] = security_flags;
+    nonce[1..5].copy_from_slice(&counter.to_le_bytes());
+    nonce[5..].copy_from_slice(&node_id.to_le_bytes());
+    nonce
+}
+
+/// Compares two byte slices in constant time
+///
+/// The time taken depends only on the lengths, and slices of different lengths are never
+/// equal. This is what the comparisons in the certificate and CASE paths should use.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
 // APIs particular to a KeyPair so a KeyPair object can be defined
 pub trait CryptoKeyPair {
     fn get_csr<'a>(&self, csr: &'a mut [u8]) -> Result<&'a [u8], Error>;
@@ -49,7 +75,10 @@ pub mod crypto_dummy;
 mod tests {
     use crate::error::Error;
 
-    use super::{CryptoKeyPair, KeyPair};
+    use super::{
+        build_nonce, constant_time_eq, CryptoKeyPair, KeyPair, BIGNUM_LEN_BYTES,
+        EC_POINT_LEN_BYTES, EC_SIGNATURE_LEN_BYTES, NONCE_CASE_SIGMA2,
+    };
 
     #[test]
     fn test_verify_msg_success() {
@@ -66,6 +95,104 @@ mod tests {
         );
     }
 
+    // Sign with 'signer', and verify with the public key of 'verifier'
+    fn sign_and_verify(signer: &KeyPair, verifier: &KeyPair) {
+        let mut pub_key = [0u8; EC_POINT_LEN_BYTES];
+        let len = verifier.get_public_key(&mut pub_key).unwrap();
+        let verifier = KeyPair::new_from_public(&pub_key[..len]).unwrap();
+
+        let mut signature = [0u8; EC_SIGNATURE_LEN_BYTES];
+        signer
+            .sign_msg(&test_vectors::MSG1_SUCCESS, &mut signature)
+            .unwrap();
+        verifier
+            .verify_msg(&test_vectors::MSG1_SUCCESS, &signature)
+            .unwrap();
+    }
+
+    fn public_key(key: &KeyPair) -> [u8; EC_POINT_LEN_BYTES] {
+        let mut pub_key = [0u8; EC_POINT_LEN_BYTES];
+        assert_eq!(
+            key.get_public_key(&mut pub_key).unwrap(),
+            EC_POINT_LEN_BYTES
+        );
+        pub_key
+    }
+
+    #[test]
+    fn test_key_pair_pkcs8_roundtrip() {
+        let key = KeyPair::new().unwrap();
+        let mut der = [0u8; 200];
+        let der = key.to_der_pkcs8(&mut der).unwrap();
+
+        let imported = KeyPair::from_der_pkcs8(der).unwrap();
+        assert_eq!(public_key(&imported), public_key(&key));
+        sign_and_verify(&imported, &key);
+        sign_and_verify(&key, &imported);
+
+        let mut der_again = [0u8; 200];
+        assert_eq!(imported.to_der_pkcs8(&mut der_again).unwrap(), der);
+
+        let mut small = [0u8; 16];
+        assert_eq!(key.to_der_pkcs8(&mut small), Err(Error::NoSpace));
+        assert!(KeyPair::from_der_pkcs8(&der[..der.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_key_pair_private_scalar() {
+        let key = KeyPair::new().unwrap();
+        let mut priv_key = [0u8; BIGNUM_LEN_BYTES];
+        let len = key.get_private_key(&mut priv_key).unwrap();
+        // Restore the leading zeroes, if the scalar had any
+        priv_key.rotate_right(BIGNUM_LEN_BYTES - len);
+
+        let imported = KeyPair::from_private_scalar(&priv_key).unwrap();
+        assert_eq!(public_key(&imported), public_key(&key));
+        sign_and_verify(&imported, &key);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(constant_time_eq(&[], &[]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_eq(&[0, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2]));
+        assert!(!constant_time_eq(&[1, 2], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[], &[1]));
+    }
+
+    #[test]
+    fn test_build_nonce() {
+        // The secure unicast message test vector of the Matter SDK: session id 3000, message
+        // counter 12345, from an unspecified node id
+        let plain_hdr = [0x00, 0xb8, 0x0b, 0x00, 0x39, 0x30, 0x00, 0x00];
+        let key = [
+            0x5e, 0xde, 0xd2, 0x44, 0xe5, 0x53, 0x2b, 0x3c, 0xdc, 0x23, 0x40, 0x9d, 0xba, 0xd0,
+            0x52, 0xd2,
+        ];
+        let mut payload = [
+            0x5a, 0x98, 0x9a, 0xe4, 0x2e, 0x8d, 0x84, 0x7f, 0x53, 0x5c, 0x30, 0x07, 0xe6, 0x15,
+            0x0c, 0xd6, 0x58, 0x67, 0xf2, 0xb8, 0x17, 0xdb,
+        ];
+
+        let nonce = build_nonce(0, 12345, 0);
+        assert_eq!(
+            nonce,
+            [0x00, 0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        let len = super::decrypt_in_place(&key, &nonce, &plain_hdr, &mut payload).unwrap();
+        assert_eq!(&payload[..len], [0x05, 0x64, 0xee, 0x0e, 0x20, 0x7d]);
+    }
+
+    #[test]
+    fn test_case_nonces() {
+        assert_eq!(
+            NONCE_CASE_SIGMA2,
+            [0x4e, 0x43, 0x41, 0x53, 0x45, 0x5f, 0x53, 0x69, 0x67, 0x6d, 0x61, 0x32, 0x4e]
+        );
+    }
+
     mod test_vectors {
         pub const PUB_KEY1: [u8; 65] = [
             0x4, 0x56, 0x19, 0x77, 0x18, 0x3f, 0xd4, 0xff, 0x2b, 0x58, 0x3d, 0xe9, 0x79, 0x34,
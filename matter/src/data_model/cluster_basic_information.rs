@@ -2,13 +2,17 @@ use super::objects::*;
 use crate::error::*;
 
 pub const ID: u32 = 0x0028;
-enum Attributes {
+pub enum Attributes {
     VendorId = 2,
     ProductId = 4,
+    NodeLabel = 5,
     HwVer = 7,
     SwVer = 9,
 }
 
+/// The maximum length of the NodeLabel, in bytes
+pub const NODE_LABEL_MAX_LEN: usize = 32;
+
 pub struct BasicInfoConfig {
     pub vid: u16,
     pub pid: u16,
@@ -34,6 +38,17 @@ fn attr_pid_new(pid: u16) -> Result<Attribute, Error> {
     )
 }
 
+fn attr_node_label_new() -> Result<Attribute, Error> {
+    let mut attr = Attribute::new(
+        Attributes::NodeLabel as u16,
+        AttrValue::CharString(String::new()),
+        Access::RWVM,
+        Quality::PERSISTENT,
+    )?;
+    attr.set_max_len(NODE_LABEL_MAX_LEN);
+    Ok(attr)
+}
+
 fn attr_hw_ver_new(hw_ver: u16) -> Result<Attribute, Error> {
     Attribute::new(
         Attributes::HwVer as u16,
@@ -63,6 +78,7 @@ impl BasicInfoCluster {
         });
         cluster.base.add_attribute(attr_vid_new(cfg.vid)?)?;
         cluster.base.add_attribute(attr_pid_new(cfg.pid)?)?;
+        cluster.base.add_attribute(attr_node_label_new()?)?;
         cluster.base.add_attribute(attr_hw_ver_new(cfg.hw_ver)?)?;
         cluster.base.add_attribute(attr_sw_ver_new(cfg.sw_ver)?)?;
         Ok(cluster)
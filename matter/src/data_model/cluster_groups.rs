@@ -0,0 +1,193 @@
+use super::objects::*;
+use crate::{
+    cmd_enter,
+    error::*,
+    groups::GroupMgr,
+    interaction_model::{
+        command::{CommandReq, CommandStatus},
+        core::IMStatusCode,
+    },
+    tlv::{TLVElement, TLVWriter, TagType, ToTLV},
+};
+use log::info;
+use num_derive::FromPrimitive;
+use std::sync::Arc;
+
+pub const ID: u32 = 0x0004;
+
+pub enum Attributes {
+    NameSupport = 0x0,
+}
+
+#[derive(FromPrimitive)]
+pub enum Commands {
+    AddGroup = 0x00,
+    ViewGroup = 0x01,
+    GetGroupMembership = 0x02,
+    RemoveGroup = 0x03,
+    RemoveAllGroups = 0x04,
+    AddGroupIfIdentifying = 0x05,
+}
+
+// The response commands share their ids with the requests
+pub enum RespCommands {
+    AddGroupResp = 0x00,
+    ViewGroupResp = 0x01,
+    GetGroupMembershipResp = 0x02,
+    RemoveGroupResp = 0x03,
+}
+
+#[derive(ToTLV)]
+struct GroupResponse {
+    status: u8,
+    group_id: u16,
+}
+
+fn attr_name_support_new() -> Result<Attribute, Error> {
+    // Group names aren't supported
+    Attribute::new(
+        Attributes::NameSupport as u16,
+        AttrValue::Uint8(0),
+        Access::RV,
+        Quality::FIXED,
+    )
+}
+
+/// The Groups cluster, that manages the group memberships of its endpoint
+pub struct GroupsCluster {
+    base: Cluster,
+    group_mgr: Arc<GroupMgr>,
+}
+
+impl GroupsCluster {
+    pub fn new(group_mgr: Arc<GroupMgr>) -> Result<Box<Self>, Error> {
+        let mut cluster = Box::new(GroupsCluster {
+            base: Cluster::new(ID)?,
+            group_mgr,
+        });
+        cluster.base.add_attribute(attr_name_support_new()?)?;
+        cluster.base.set_generated_commands(&[
+            RespCommands::AddGroupResp as u16,
+            RespCommands::RemoveGroupResp as u16,
+        ])?;
+        Ok(cluster)
+    }
+
+    fn handle_command_addgroup(
+        &mut self,
+        cmd_req: &mut CommandReq,
+        fab_idx: u8,
+        endpoint: u16,
+    ) -> Result<(), IMStatusCode> {
+        cmd_enter!("Add Group");
+        let group_id = get_group_id(&cmd_req.data)?;
+        let status = match self.group_mgr.add(fab_idx, group_id, endpoint) {
+            Ok(_) => IMStatusCode::Success,
+            Err(Error::Invalid) => IMStatusCode::ConstraintError,
+            Err(_) => IMStatusCode::ResourceExhausted,
+        };
+        respond(
+            &self.base,
+            cmd_req,
+            RespCommands::AddGroupResp as u16,
+            status,
+            group_id,
+        )
+    }
+
+    fn handle_command_removegroup(
+        &mut self,
+        cmd_req: &mut CommandReq,
+        fab_idx: u8,
+        endpoint: u16,
+    ) -> Result<(), IMStatusCode> {
+        cmd_enter!("Remove Group");
+        let group_id = get_group_id(&cmd_req.data)?;
+        let status = if group_id == 0 {
+            IMStatusCode::ConstraintError
+        } else if self.group_mgr.remove(fab_idx, group_id, endpoint).is_ok() {
+            IMStatusCode::Success
+        } else {
+            IMStatusCode::NotFound
+        };
+        respond(
+            &self.base,
+            cmd_req,
+            RespCommands::RemoveGroupResp as u16,
+            status,
+            group_id,
+        )
+    }
+
+    fn handle_command_removeallgroups(
+        &mut self,
+        cmd_req: &mut CommandReq,
+        fab_idx: u8,
+        endpoint: u16,
+    ) -> Result<(), IMStatusCode> {
+        cmd_enter!("Remove All Groups");
+        self.group_mgr
+            .remove_all(fab_idx, endpoint)
+            .map_err(|_| IMStatusCode::Failure)?;
+        cmd_req.trans.complete();
+        Err(IMStatusCode::Success)
+    }
+}
+
+impl ClusterType for GroupsCluster {
+    fn base(&self) -> &Cluster {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut Cluster {
+        &mut self.base
+    }
+
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
+        let cmd = cmd_req
+            .cmd
+            .path
+            .leaf
+            .map(num::FromPrimitive::from_u32)
+            .ok_or(IMStatusCode::UnsupportedCommand)?
+            .ok_or(IMStatusCode::UnsupportedCommand)?;
+        // The group memberships are scoped to the accessing fabric
+        let fab_idx = cmd_req
+            .trans
+            .session
+            .get_local_fabric_idx()
+            .ok_or(IMStatusCode::UnsupportedAccess)?;
+        let endpoint = cmd_req.cmd.path.endpoint.ok_or(IMStatusCode::Failure)?;
+        match cmd {
+            Commands::AddGroup => self.handle_command_addgroup(cmd_req, fab_idx, endpoint)?,
+            Commands::RemoveGroup => self.handle_command_removegroup(cmd_req, fab_idx, endpoint)?,
+            Commands::RemoveAllGroups => {
+                self.handle_command_removeallgroups(cmd_req, fab_idx, endpoint)?
+            }
+            _ => return Err(IMStatusCode::UnsupportedCommand),
+        }
+        Ok(CommandStatus::Complete)
+    }
+}
+
+fn get_group_id(data: &TLVElement) -> Result<u16, IMStatusCode> {
+    data.find_tag(0)
+        .and_then(|t| t.u16())
+        .map_err(|_| IMStatusCode::InvalidCommand)
+}
+
+fn respond(
+    cluster: &Cluster,
+    cmd_req: &mut CommandReq,
+    cmd: u16,
+    status: IMStatusCode,
+    group_id: u16,
+) -> Result<(), IMStatusCode> {
+    info!("Group {:#x}: {}", group_id, status);
+    let resp = GroupResponse {
+        status: status as u8,
+        group_id,
+    };
+    let _ = cmd_req.respond(cluster, cmd, EncodeValue::Value(&resp));
+    cmd_req.trans.complete();
+    Ok(())
+}
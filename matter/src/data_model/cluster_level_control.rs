@@ -0,0 +1,164 @@
+use super::objects::*;
+use crate::{
+    cmd_enter,
+    error::*,
+    interaction_model::{
+        command::{CommandReq, CommandStatus},
+        core::IMStatusCode,
+    },
+    tlv::{FromTLV, TLVElement},
+};
+use log::info;
+use num_derive::FromPrimitive;
+
+pub const ID: u32 = 0x0008;
+
+pub enum Attributes {
+    CurrentLevel = 0x0,
+    MinLevel = 0x2,
+    MaxLevel = 0x3,
+}
+
+#[derive(FromPrimitive)]
+pub enum Commands {
+    MoveToLevel = 0x0,
+    Move = 0x01,
+    Step = 0x02,
+    Stop = 0x03,
+}
+
+#[derive(FromPrimitive, PartialEq)]
+pub enum StepMode {
+    Up = 0,
+    Down = 1,
+}
+
+// The transition time and the options that follow aren't used yet
+#[derive(FromTLV)]
+struct MoveToLevelReq {
+    level: u8,
+}
+
+#[derive(FromTLV)]
+struct StepReq {
+    step_mode: u8,
+    step_size: u8,
+}
+
+fn attr_current_level_new() -> Result<Attribute, Error> {
+    // CurrentLevel, Value: null, the level isn't known until it is first set
+    Attribute::new_nullable(
+        Attributes::CurrentLevel as u16,
+        AttrType::Uint8,
+        Access::RV,
+        Quality::PERSISTENT,
+    )
+}
+
+fn attr_level_new(attr: Attributes, level: u8) -> Result<Attribute, Error> {
+    Attribute::new(
+        attr as u16,
+        AttrValue::Uint8(level),
+        Access::RV,
+        Quality::FIXED,
+    )
+}
+
+/// The Level Control cluster
+///
+/// The level moves to its target right away, the transition times of the commands aren't
+/// supported yet.
+pub struct LevelControlCluster {
+    base: Cluster,
+    min_level: u8,
+    max_level: u8,
+}
+
+impl LevelControlCluster {
+    pub fn new(min_level: u8, max_level: u8) -> Result<Box<Self>, Error> {
+        if min_level > max_level {
+            return Err(Error::Invalid);
+        }
+        let mut cluster = Box::new(LevelControlCluster {
+            base: Cluster::new(ID)?,
+            min_level,
+            max_level,
+        });
+        cluster.base.add_attribute(attr_current_level_new()?)?;
+        cluster
+            .base
+            .add_attribute(attr_level_new(Attributes::MinLevel, min_level)?)?;
+        cluster
+            .base
+            .add_attribute(attr_level_new(Attributes::MaxLevel, max_level)?)?;
+        Ok(cluster)
+    }
+
+    fn current_level(&self) -> Option<u8> {
+        match self
+            .base
+            .read_attribute_raw(Attributes::CurrentLevel as u16)
+        {
+            Ok(AttrValue::Uint8(level)) => Some(*level),
+            _ => None,
+        }
+    }
+
+    fn set_level(&mut self, level: u8) -> Result<(), IMStatusCode> {
+        if level < self.min_level || level > self.max_level {
+            return Err(IMStatusCode::ConstraintError);
+        }
+        info!("Level: {}", level);
+        self.base
+            .write_attribute_raw(Attributes::CurrentLevel as u16, AttrValue::Uint8(level))
+            .map_err(|_| IMStatusCode::Failure)
+    }
+
+    fn handle_command_movetolevel(&mut self, data: &TLVElement) -> Result<(), IMStatusCode> {
+        cmd_enter!("Move To Level");
+        let req = MoveToLevelReq::from_tlv(data)?;
+        self.set_level(req.level)
+    }
+
+    fn handle_command_step(&mut self, data: &TLVElement) -> Result<(), IMStatusCode> {
+        cmd_enter!("Step");
+        let req = StepReq::from_tlv(data)?;
+        let step_mode: StepMode =
+            num::FromPrimitive::from_u8(req.step_mode).ok_or(IMStatusCode::ConstraintError)?;
+        // There is nothing to step from while the level is unknown
+        let level = self.current_level().ok_or(IMStatusCode::Failure)?;
+        // Stepping past the limits stops at the limit
+        let level = if step_mode == StepMode::Up {
+            level.saturating_add(req.step_size).min(self.max_level)
+        } else {
+            level.saturating_sub(req.step_size).max(self.min_level)
+        };
+        self.set_level(level)
+    }
+}
+
+impl ClusterType for LevelControlCluster {
+    fn base(&self) -> &Cluster {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut Cluster {
+        &mut self.base
+    }
+
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
+        let cmd = cmd_req
+            .cmd
+            .path
+            .leaf
+            .map(num::FromPrimitive::from_u32)
+            .ok_or(IMStatusCode::UnsupportedCommand)?
+            .ok_or(IMStatusCode::UnsupportedCommand)?;
+        match cmd {
+            Commands::MoveToLevel => self.handle_command_movetolevel(&cmd_req.data)?,
+            Commands::Step => self.handle_command_step(&cmd_req.data)?,
+            _ => return Err(IMStatusCode::UnsupportedCommand),
+        }
+        cmd_req.trans.complete();
+        Err(IMStatusCode::Success)
+    }
+}
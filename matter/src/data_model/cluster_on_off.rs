@@ -2,7 +2,10 @@ use super::objects::*;
 use crate::{
     cmd_enter,
     error::*,
-    interaction_model::{command::CommandReq, core::IMStatusCode},
+    interaction_model::{
+        command::{CommandReq, CommandStatus},
+        core::IMStatusCode,
+    },
 };
 use log::info;
 use num_derive::FromPrimitive;
@@ -52,7 +55,7 @@ impl ClusterType for OnOffCluster {
         &mut self.base
     }
 
-    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
         let cmd = cmd_req
             .cmd
             .path
@@ -73,7 +76,7 @@ impl ClusterType for OnOffCluster {
                         .map_err(|_| IMStatusCode::Failure)?;
                 }
                 cmd_req.trans.complete();
-                Err(IMStatusCode::Sucess)
+                Err(IMStatusCode::Success)
             }
             Commands::On => {
                 cmd_enter!("On");
@@ -88,7 +91,7 @@ impl ClusterType for OnOffCluster {
                 }
 
                 cmd_req.trans.complete();
-                Err(IMStatusCode::Sucess)
+                Err(IMStatusCode::Success)
             }
             Commands::Toggle => {
                 cmd_enter!("Toggle");
@@ -104,7 +107,7 @@ impl ClusterType for OnOffCluster {
                     .write_attribute_raw(Attributes::OnOff as u16, AttrValue::Bool(!value))
                     .map_err(|_| IMStatusCode::Failure)?;
                 cmd_req.trans.complete();
-                Err(IMStatusCode::Sucess)
+                Err(IMStatusCode::Success)
             }
         }
     }
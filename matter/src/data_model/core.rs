@@ -2,33 +2,55 @@ use super::{
     cluster_basic_information::BasicInfoConfig,
     device_types::device_type_add_root_node,
     objects::{self, *},
-    sdm::dev_att::DevAttDataFetcher,
+    sdm::{dev_att::DevAttDataFetcher, failsafe::FailSafe},
     system_model::descriptor::DescriptorCluster,
 };
 use crate::{
     acl::{AccessReq, Accessor, AclMgr, AuthMode},
     error::*,
     fabric::FabricMgr,
+    groups::GroupMgr,
     interaction_model::{
-        command::CommandReq,
+        command::{CommandReq, CommandStatus},
         core::IMStatusCode,
         messages::{
-            ib::{self, AttrData, AttrPath},
-            msg::{self, InvReq, ReadReq, WriteReq},
+            ib::{self, AttrData},
+            msg::{self, InvReq, WriteReq},
             GenericPath,
         },
+        read::ReadState,
         InteractionConsumer, Transaction,
     },
-    tlv::{TLVWriter, TagType, ToTLV},
+    tlv::{TLVAnchor, TLVElement, TLVWriter, TagType, ToTLV},
     transport::session::{Session, SessionMode},
 };
 use log::{error, info};
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
 
+/// The default maximum number of attributes reported for a single Read Request
+///
+/// This is enough for every attribute of a full node to be reported once.
+pub const MAX_READ_ATTRIBUTES: usize = ENDPTS_PER_ACC * CLUSTERS_PER_ENDPT * ATTRS_PER_CLUSTER;
+
+/// The Data Model of the node
+///
+/// The node is behind a single lock. It is held for writing while a write or an invoke is
+/// processed, and for reading while a read is processed. Nothing that runs under the write
+/// lock, like a cluster's command handlers, may take it again: command handlers get the rest
+/// of the node in their CommandReq instead.
 #[derive(Clone)]
 pub struct DataModel {
     pub node: Arc<RwLock<Box<Node>>>,
     acl_mgr: Arc<AclMgr>,
+    group_mgr: Arc<GroupMgr>,
+    read_attr_limit: Arc<AtomicUsize>,
+    failsafe: Arc<FailSafe>,
 }
 
 impl DataModel {
@@ -37,28 +59,110 @@ impl DataModel {
         dev_att: Box<dyn DevAttDataFetcher>,
         fabric_mgr: Arc<FabricMgr>,
         acl_mgr: Arc<AclMgr>,
+        group_mgr: Arc<GroupMgr>,
     ) -> Result<Self, Error> {
         let dm = DataModel {
             node: Arc::new(RwLock::new(Node::new()?)),
             acl_mgr: acl_mgr.clone(),
+            group_mgr,
+            read_attr_limit: Arc::new(AtomicUsize::new(MAX_READ_ATTRIBUTES)),
+            failsafe: Arc::new(FailSafe::new()),
         };
         {
             let mut node = dm.node.write()?;
             node.set_changes_cb(Box::new(dm.clone()));
-            device_type_add_root_node(&mut node, dev_details, dev_att, fabric_mgr, acl_mgr)?;
+            device_type_add_root_node(
+                &mut node,
+                dev_details,
+                dev_att,
+                fabric_mgr,
+                acl_mgr,
+                dm.failsafe.clone(),
+            )?;
+            node.validate_device_types()?;
         }
         Ok(dm)
     }
 
+    /// Add an endpoint with the device types 'device_types', returning its id
+    ///
+    /// The endpoint gets its Descriptor cluster right away, the clusters that the device
+    /// types mandate are to be added with [DataModel::add_cluster].
+    pub fn add_endpoint(&self, device_types: &[DeviceType]) -> Result<u16, Error> {
+        let mut node = self.node.write()?;
+        let endpoint = node.add_endpoint()?;
+        for dev_type in device_types {
+            node.add_device_type(endpoint, *dev_type)?;
+        }
+        Ok(endpoint as u16)
+    }
+
+    /// Add 'cluster' to the endpoint 'endpoint'
+    pub fn add_cluster(&self, endpoint: u16, cluster: Box<dyn ClusterType>) -> Result<(), Error> {
+        self.node.write()?.add_cluster(endpoint as u32, cluster)
+    }
+
+    /// Set the maximum number of attributes reported for a single Read Request
+    ///
+    /// The attributes beyond this are dropped, and the report ends with a PathsExhausted
+    /// status instead.
+    pub fn set_read_attr_limit(&self, limit: usize) {
+        self.read_attr_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// The group memberships of the endpoints, for use by the Groups cluster
+    pub fn group_mgr(&self) -> Arc<GroupMgr> {
+        self.group_mgr.clone()
+    }
+
+    /// The Fail-Safe of the root node's General Commissioning cluster
+    pub fn failsafe(&self) -> Arc<FailSafe> {
+        self.failsafe.clone()
+    }
+
+    /// Encode the AttributeReportIBs for as many paths of 'state' as fit in 'tw'
+    ///
+    /// This is the report of a Read Request, and equally the report of a subscription, so
+    /// that a subscription's priming report is exactly what a read of its paths returns.
+    /// The attributes of overlapping paths are reported only once, across all the chunks.
+    pub fn generate_report(
+        &self,
+        state: &mut ReadState,
+        sess: &Session,
+        tw: &mut TLVWriter,
+    ) -> Result<(), Error> {
+        let accessor = self.sess_to_accessor(sess);
+        let node = self.node.read()?;
+        let limit = self.read_attr_limit.load(Ordering::Relaxed);
+        let chunk_start = tw.anchor();
+        while !state.is_complete() {
+            match DataModel::handle_read_attr_path(&node, &accessor, state, limit, chunk_start, tw)
+            {
+                Ok(()) => {
+                    state.next += 1;
+                    state.cursor = None;
+                }
+                // The chunk is full, the next one resumes from the cursor
+                Err(IMStatusCode::ResourceExhausted) => break,
+                Err(_) => {
+                    // The rest of the read is dropped
+                    state.next = state.attr_paths.len();
+                    state.cursor = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn read_attribute_raw(
         &self,
         endpoint: u16,
         cluster: u32,
         attr: u16,
     ) -> Result<AttrValue, IMStatusCode> {
-        let node = self.node.read().unwrap();
+        let node = self.node.read().map_err(|_| IMStatusCode::Failure)?;
         let cluster = node.get_cluster(endpoint, cluster)?;
-        cluster.base().read_attribute_raw(attr).map(|a| *a)
+        cluster.base().read_attribute_raw(attr).cloned()
     }
 
     // Encode a write attribute from a path that may or may not be wildcard
@@ -96,18 +200,23 @@ impl DataModel {
         };
 
         if gen_path.is_wildcard() {
-            // This is a wildcard path, skip error
+            // This is a wildcard path, skip the errors for paths that aren't writable
             //    This is required because there could be access control errors too that need
             //    to be taken care of.
             encoder.skip_error();
         }
 
         let result = node.for_each_cluster_mut(&gen_path, |path, c| {
-            let attr_id = if let Some(a) = path.leaf { a } else { 0 } as u16;
+            let attr = AttrDetails {
+                attr_id: if let Some(a) = path.leaf { a } else { 0 } as u16,
+                list_index: attr_data.path.list_index,
+                fab_idx: accessor.fab_idx(),
+                fab_filter: false,
+            };
             encoder.set_path(*path);
             let mut access_req = AccessReq::new(accessor, path, Access::WRITE);
-            let r = match Cluster::write_attribute(c, &mut access_req, write_data, attr_id) {
-                Ok(_) => IMStatusCode::Sucess,
+            let r = match Cluster::write_attribute(c, &mut access_req, write_data, &attr) {
+                Ok(_) => IMStatusCode::Success,
                 Err(e) => e,
             };
             encoder.encode_status(r, 0);
@@ -119,65 +228,169 @@ impl DataModel {
         }
     }
 
-    // Encode a read attribute from a path that may or may not be wildcard
+    // Encode the read attributes of the path at 'state.next', that may or may not be wildcard
+    //
+    // The traversal of the path resumes from 'state.cursor', if an earlier chunk ended in
+    // the middle of it. The attributes in 'state.reported' were already reported for an
+    // earlier path of the same read, and are skipped. Returns:
+    // - Err(PathsExhausted), after encoding it, if this takes 'reported' beyond 'limit'
+    // - Err(ResourceExhausted) if 'tw' fills up, with the cursor at the attribute that
+    //   didn't fit. An attribute that doesn't fit even at the 'chunk_start' is dropped.
     fn handle_read_attr_path(
         node: &RwLockReadGuard<Box<Node>>,
         accessor: &Accessor,
-        attr_path: AttrPath,
+        state: &mut ReadState,
+        limit: usize,
+        chunk_start: TLVAnchor,
         tw: &mut TLVWriter,
-    ) {
+    ) -> Result<(), IMStatusCode> {
+        let attr_path = state.attr_paths[state.next];
         let gen_path = attr_path.to_gp();
-        let mut attr_encoder = AttrReadEncoder::new(tw, TagType::Anonymous, gen_path);
+        let cursor = state
+            .cursor
+            .get_or_insert_with(|| AttrTraversalCursor::new(&gen_path));
+
+        let mut exhausted = false;
+        loop {
+            let resume = *cursor;
+            let anchor = tw.anchor();
+            tw.take_overflow();
+            let mut attr_encoder = AttrReadEncoder::new(tw, TagType::Anonymous, gen_path);
+            match cursor.next(node) {
+                Ok(Some((path, c, attr_id))) => {
+                    if state.reported.contains(&path) {
+                        continue;
+                    }
+                    if state.reported.len() >= limit {
+                        exhausted = true;
+                        break;
+                    }
+
+                    let attr = AttrDetails {
+                        attr_id,
+                        list_index: attr_path.list_index,
+                        fab_idx: accessor.fab_idx(),
+                        fab_filter: state.fabric_filtered,
+                    };
+                    attr_encoder.set_path(path);
+                    attr_encoder.set_data_ver(c.base().get_dataver());
+                    let mut access_req = AccessReq::new(accessor, &path, Access::READ);
+                    Cluster::read_attribute(c, &mut access_req, &mut attr_encoder, &attr);
+                    if !tw.take_overflow() {
+                        state.reported.insert(path, limit.min(MAX_READ_ATTRIBUTES));
+                        continue;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    // We hit this only if this is a non-wildcard path
+                    attr_encoder.encode_status(e, 0);
+                    if !tw.take_overflow() {
+                        break;
+                    }
+                }
+            }
 
-        let result = node.for_each_attribute(&gen_path, |path, c| {
-            let attr_id = if let Some(a) = path.leaf { a } else { 0 } as u16;
-            attr_encoder.set_path(*path);
-            let mut access_req = AccessReq::new(accessor, path, Access::READ);
-            Cluster::read_attribute(c, &mut access_req, &mut attr_encoder, attr_id);
-            Ok(())
-        });
-        if let Err(e) = result {
-            // We hit this only if this is a non-wildcard path
-            attr_encoder.encode_status(e, 0);
+            // The chunk is full
+            let _ = tw.rewind_to(anchor);
+            if anchor == chunk_start {
+                error!("The report of {:?} doesn't fit in a chunk", gen_path);
+                continue;
+            }
+            *cursor = resume;
+            return Err(IMStatusCode::ResourceExhausted);
         }
+        if exhausted {
+            // This is reported even for a wildcard path, the report is incomplete
+            error!("Read Request exceeds {} attributes", limit);
+            let status = IMStatusCode::PathsExhausted;
+            let resp = ib::AttrResp::Status(ib::AttrStatus::new(&gen_path, status, 0));
+            let _ = resp.to_tlv(tw, TagType::Anonymous);
+            return Err(status);
+        }
+        Ok(())
     }
 
     // Handle command from a path that may or may not be wildcard
-    fn handle_command_path(node: &mut Node, cmd_req: &mut CommandReq) {
-        let wildcard = cmd_req.cmd.path.is_wildcard();
-        let path = cmd_req.cmd.path;
+    //
+    // Each handler runs with its cluster detached from the node, and gets the rest of the
+    // node in the CommandReq. This is what lets a handler look at its sibling clusters
+    // while the node is locked for writing.
+    fn handle_command_path(
+        node: &mut Node,
+        cmd: ib::CmdPath,
+        data: TLVElement,
+        trans: &mut Transaction,
+        resp: &mut TLVWriter,
+    ) {
+        let wildcard = cmd.path.is_wildcard();
+
+        let mut paths = Vec::new();
+        let result = node.for_each_cluster(&cmd.path, |path, _| {
+            paths.push(*path);
+            Ok(())
+        });
+        if let Err(e) = result {
+            if !wildcard {
+                // We hit this only if this is a non-wildcard path
+                let invoke_resp = ib::InvResp::status_new(cmd, e, 0);
+                let _ = invoke_resp.to_tlv(resp, TagType::Anonymous);
+            }
+            return;
+        }
 
-        let result = node.for_each_cluster_mut(&path, |path, c| {
-            cmd_req.cmd.path = *path;
-            let result = c.handle_command(cmd_req);
+        for path in paths {
+            let mut cmd = cmd;
+            cmd.path = path;
+            let (endpoint, cluster) = match (path.endpoint, path.cluster) {
+                (Some(e), Some(c)) => (e, c),
+                _ => continue,
+            };
+            let result = node
+                .with_cluster_detached(endpoint, cluster, |c, node| {
+                    let mut cmd_req = CommandReq {
+                        cmd,
+                        data,
+                        trans: &mut *trans,
+                        resp: &mut *resp,
+                        node,
+                    };
+                    match c.handle_command(&mut cmd_req) {
+                        Ok(CommandStatus::Deferred) if !cmd_req.trans.deferred => {
+                            error!(
+                                "Command handler returned Deferred without deferring the command"
+                            );
+                            Err(IMStatusCode::Failure)
+                        }
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e),
+                    }
+                })
+                .unwrap_or(Err(IMStatusCode::Failure));
             if let Err(e) = result {
                 // It is likely that we might have to do an 'Access' aware traversal
                 // if there are other conditions in the wildcard scenario that shouldn't be
                 // encoded as CmdStatus
                 if !(wildcard && e == IMStatusCode::UnsupportedCommand) {
-                    let invoke_resp = ib::InvResp::status_new(cmd_req.cmd, e, 0);
-                    let _ = invoke_resp.to_tlv(cmd_req.resp, TagType::Anonymous);
+                    let invoke_resp = ib::InvResp::status_new(cmd, e, 0);
+                    let _ = invoke_resp.to_tlv(resp, TagType::Anonymous);
                 }
             }
-            Ok(())
-        });
-        if !wildcard {
-            if let Err(e) = result {
-                // We hit this only if this is a non-wildcard path
-                let invoke_resp = ib::InvResp::status_new(cmd_req.cmd, e, 0);
-                let _ = invoke_resp.to_tlv(cmd_req.resp, TagType::Anonymous);
-            }
         }
     }
 
     fn sess_to_accessor(&self, sess: &Session) -> Accessor {
         match sess.get_session_mode() {
-            SessionMode::Case(c) => Accessor::new(
-                c,
-                sess.get_peer_node_id().unwrap_or_default(),
-                AuthMode::Case,
-                self.acl_mgr.clone(),
-            ),
+            SessionMode::Case(c) => {
+                let mut accessor = Accessor::new(
+                    c,
+                    sess.get_peer_node_id().unwrap_or_default(),
+                    AuthMode::Case,
+                    self.acl_mgr.clone(),
+                );
+                accessor.set_cat_ids(sess.get_peer_cat_ids());
+                accessor
+            }
             SessionMode::Pase => Accessor::new(0, 1, AuthMode::Pase, self.acl_mgr.clone()),
             SessionMode::PlainText => Accessor::new(0, 1, AuthMode::Invalid, self.acl_mgr.clone()),
         }
@@ -201,7 +414,7 @@ impl InteractionConsumer for DataModel {
         let accessor = self.sess_to_accessor(trans.session);
 
         tw.start_array(TagType::Context(msg::WriteRespTag::WriteResponses as u8))?;
-        let mut node = self.node.write().unwrap();
+        let mut node = self.node.write()?;
         for attr_data in write_req.write_requests.iter() {
             DataModel::handle_write_attr_path(&mut node, &accessor, &attr_data, tw);
         }
@@ -210,41 +423,64 @@ impl InteractionConsumer for DataModel {
         Ok(())
     }
 
-    fn consume_read_attr(
+    fn consume_group_invoke_cmd(
         &self,
-        read_req: &ReadReq,
+        group_id: u16,
+        inv_req_msg: &InvReq,
         trans: &mut Transaction,
-        tw: &mut TLVWriter,
     ) -> Result<(), Error> {
-        if read_req.fabric_filtered {
-            error!("Fabric scoped attribute read not yet supported");
-        }
-        if read_req.dataver_filters.is_some() {
-            error!("Data Version Filter not yet supported");
-        }
-
-        if let Some(attr_requests) = &read_req.attr_requests {
-            let accessor = self.sess_to_accessor(trans.session);
-            let node = self.node.read().unwrap();
-            tw.start_array(TagType::Context(msg::ReportDataTag::AttributeReports as u8))?;
-            for attr_path in attr_requests.iter() {
-                DataModel::handle_read_attr_path(&node, &accessor, attr_path, tw);
+        let fab_idx = trans.session.get_local_fabric_idx().ok_or(Error::Invalid)?;
+        let endpoints = self.group_mgr.endpoints(fab_idx, group_id)?;
+        let mut node = self.node.write()?;
+        // There is no response to group commands, anything that the handlers encode is
+        // discarded
+        let mut sink = io::sink();
+        let mut tw = TLVWriter::new_io(&mut sink);
+        if let Some(inv_requests) = &inv_req_msg.inv_requests {
+            for i in inv_requests.iter() {
+                let data = if let Some(data) = i.data.unwrap_tlv() {
+                    data
+                } else {
+                    continue;
+                };
+                for endpoint in endpoints.iter() {
+                    if matches!(i.path.path.endpoint, Some(e) if e != *endpoint) {
+                        continue;
+                    }
+                    info!(
+                        "Group {:#x} Command Handler executing on endpoint {}: {:?}",
+                        group_id, endpoint, i.path
+                    );
+                    let mut cmd = i.path;
+                    cmd.path.endpoint = Some(*endpoint);
+                    DataModel::handle_command_path(&mut node, cmd, data, trans, &mut tw);
+                }
             }
-            tw.end_container()?;
         }
         Ok(())
     }
 
+    fn consume_read_attr(
+        &self,
+        state: &mut ReadState,
+        trans: &mut Transaction,
+        tw: &mut TLVWriter,
+    ) -> Result<(), Error> {
+        self.generate_report(state, trans.session, tw)
+    }
+
     fn consume_invoke_cmd(
         &self,
         inv_req_msg: &InvReq,
         trans: &mut Transaction,
         tw: &mut TLVWriter,
     ) -> Result<(), Error> {
-        let mut node = self.node.write().unwrap();
+        let mut node = self.node.write()?;
+        // Array of InvokeResponse IBs
+        // This is mandatory, and may be empty if all the commands were on wildcard paths that
+        // didn't match anything, or if there were no commands at all
+        tw.start_array(TagType::Context(msg::InvRespTag::InvokeResponses as u8))?;
         if let Some(inv_requests) = &inv_req_msg.inv_requests {
-            // Array of InvokeResponse IBs
-            tw.start_array(TagType::Context(msg::InvRespTag::InvokeResponses as u8))?;
             for i in inv_requests.iter() {
                 let data = if let Some(data) = i.data.unwrap_tlv() {
                     data
@@ -252,16 +488,10 @@ impl InteractionConsumer for DataModel {
                     continue;
                 };
                 info!("Invoke Commmand Handler executing: {:?}", i.path);
-                let mut cmd_req = CommandReq {
-                    cmd: i.path,
-                    data,
-                    trans,
-                    resp: tw,
-                };
-                DataModel::handle_command_path(&mut node, &mut cmd_req);
+                DataModel::handle_command_path(&mut node, i.path, data, trans, tw);
             }
-            tw.end_container()?;
         }
+        tw.end_container()?;
 
         Ok(())
     }
@@ -324,6 +554,19 @@ impl<'a, 'b, 'c> Encoder for AttrReadEncoder<'a, 'b, 'c> {
     }
 }
 
+// Returns false if the status indicates that the attribute path isn't writable at all,
+// as against a write that was attempted and failed
+fn is_writable_path_status(status: IMStatusCode) -> bool {
+    !matches!(
+        status,
+        IMStatusCode::UnsupportedEndpoint
+            | IMStatusCode::UnsupportedCluster
+            | IMStatusCode::UnsupportedAttribute
+            | IMStatusCode::UnsupportedWrite
+            | IMStatusCode::UnsupportedAccess
+    )
+}
+
 /// Encoder for generating a response to a write request
 pub struct AttrWriteEncoder<'a, 'b, 'c> {
     tw: &'a mut TLVWriter<'b, 'c>,
@@ -356,8 +599,8 @@ impl<'a, 'b, 'c> Encoder for AttrWriteEncoder<'a, 'b, 'c> {
     }
 
     fn encode_status(&mut self, status: IMStatusCode, cluster_status: u16) {
-        if self.skip_error && status != IMStatusCode::Sucess {
-            // Don't encode errors
+        if self.skip_error && !is_writable_path_status(status) {
+            // Only report the paths that could actually be written to
             return;
         }
         let resp = ib::AttrStatus::new(&self.path, status, cluster_status);
@@ -3,7 +3,8 @@ use super::cluster_basic_information::BasicInfoConfig;
 use super::cluster_on_off::OnOffCluster;
 use super::objects::*;
 use super::sdm::dev_att::DevAttDataFetcher;
-use super::sdm::general_commissioning::GenCommCluster;
+use super::sdm::failsafe::FailSafe;
+use super::sdm::general_commissioning::{GenCommCluster, RegLocationType};
 use super::sdm::noc::NocCluster;
 use super::sdm::nw_commissioning::NwCommCluster;
 use super::system_model::access_control::AccessControlCluster;
@@ -15,12 +16,33 @@ use std::sync::RwLockWriteGuard;
 
 type WriteNode<'a> = RwLockWriteGuard<'a, Box<Node>>;
 
+// Only the mandatory clusters that are implemented here are listed. The Descriptor cluster,
+// which every device type requires, is added to all endpoints by the Data Model.
+pub const DEV_TYPE_ROOT_NODE: DeviceType = DeviceType {
+    dtype: 0x0016,
+    drev: 1,
+    mandatory_clusters: &[
+        super::cluster_basic_information::ID,
+        super::sdm::general_commissioning::ID,
+        super::sdm::nw_commissioning::ID,
+        super::sdm::noc::ID,
+        super::system_model::access_control::ID,
+    ],
+};
+
+pub const DEV_TYPE_ON_OFF_LIGHT: DeviceType = DeviceType {
+    dtype: 0x0100,
+    drev: 2,
+    mandatory_clusters: &[super::cluster_on_off::ID],
+};
+
 pub fn device_type_add_root_node(
     node: &mut WriteNode,
     dev_info: BasicInfoConfig,
     dev_att: Box<dyn DevAttDataFetcher>,
     fabric_mgr: Arc<FabricMgr>,
     acl_mgr: Arc<AclMgr>,
+    failsafe: Arc<FailSafe>,
 ) -> Result<u32, Error> {
     // Add the root endpoint
     let endpoint = node.add_endpoint()?;
@@ -28,10 +50,12 @@ pub fn device_type_add_root_node(
         // Somehow endpoint 0 was already added, this shouldn't be the case
         return Err(Error::Invalid);
     };
+    node.add_device_type(endpoint, DEV_TYPE_ROOT_NODE)?;
     // Add the mandatory clusters
     node.add_cluster(0, BasicInfoCluster::new(dev_info)?)?;
-    let general_commissioning = GenCommCluster::new()?;
-    let failsafe = general_commissioning.failsafe();
+    // TODO: Arch-Specific
+    let general_commissioning =
+        GenCommCluster::new_with_failsafe(RegLocationType::IndoorOutdoor, failsafe.clone())?;
     node.add_cluster(0, general_commissioning)?;
     node.add_cluster(0, NwCommCluster::new()?)?;
     node.add_cluster(
@@ -44,6 +68,8 @@ pub fn device_type_add_root_node(
 
 pub fn device_type_add_on_off_light(node: &mut WriteNode) -> Result<u32, Error> {
     let endpoint = node.add_endpoint()?;
+    node.add_device_type(endpoint, DEV_TYPE_ON_OFF_LIGHT)?;
     node.add_cluster(endpoint, OnOffCluster::new()?)?;
+    node.get_endpoint(endpoint as u16)?.validate_device_type()?;
     Ok(endpoint)
 }
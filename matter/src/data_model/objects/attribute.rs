@@ -1,8 +1,9 @@
-use super::{GlobalElements, Privilege};
+use super::{AttrDetails, GlobalElements, Privilege};
 use crate::{
     error::*,
+    interaction_model::core::IMStatusCode,
     // TODO: This layer shouldn't really depend on the TLV layer, should create an abstraction layer
-    tlv::{TLVElement, TLVWriter, TagType, ToTLV},
+    tlv::{ElementType, FabricScoped, FromTLV, Nullable, TLVElement, TLVWriter, TagType, ToTLV},
 };
 use bitflags::bitflags;
 use log::error;
@@ -71,7 +72,7 @@ bitflags! {
  * - instead of arrays, can use linked-lists to conserve space and avoid the internal fragmentation
  */
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Clone)]
 pub enum AttrValue {
     Int64(i64),
     Uint8(u8),
@@ -79,6 +80,10 @@ pub enum AttrValue {
     Uint32(u32),
     Uint64(u64),
     Bool(bool),
+    OctetString(Vec<u8>),
+    CharString(String),
+    /// The current value of a nullable attribute is null
+    Null,
     Custom,
 }
 
@@ -91,6 +96,9 @@ impl Debug for AttrValue {
             AttrValue::Uint32(v) => write!(f, "{:?}", *v),
             AttrValue::Uint64(v) => write!(f, "{:?}", *v),
             AttrValue::Bool(v) => write!(f, "{:?}", *v),
+            AttrValue::OctetString(v) => write!(f, "{:02x?}", v),
+            AttrValue::CharString(v) => write!(f, "{:?}", v),
+            AttrValue::Null => write!(f, "null"),
             AttrValue::Custom => write!(f, "custom-attribute"),
         }?;
         Ok(())
@@ -102,11 +110,15 @@ impl ToTLV for AttrValue {
         // What is the time complexity of such long match statements?
         match self {
             AttrValue::Bool(v) => tw.bool(tag_type, *v),
+            AttrValue::Int64(v) => tw.i64(tag_type, *v),
             AttrValue::Uint8(v) => tw.u8(tag_type, *v),
             AttrValue::Uint16(v) => tw.u16(tag_type, *v),
             AttrValue::Uint32(v) => tw.u32(tag_type, *v),
             AttrValue::Uint64(v) => tw.u64(tag_type, *v),
-            _ => {
+            AttrValue::OctetString(v) => tw.str16(tag_type, v),
+            AttrValue::CharString(v) => tw.utf16(tag_type, v.as_bytes()),
+            AttrValue::Null => tw.null(tag_type),
+            AttrValue::Custom => {
                 error!("Attribute type not yet supported");
                 Err(Error::AttributeNotFound)
             }
@@ -114,20 +126,141 @@ impl ToTLV for AttrValue {
     }
 }
 
+/// Picks the type of the value from the TLV element
+///
+/// The integers are encoded in the smallest width that fits their value, so an element read
+/// back may be narrower than the value that was written. Use `AttrType::value_from_tlv()` to
+/// keep the type of an attribute.
+impl<'a> FromTLV<'a> for AttrValue {
+    fn from_tlv(t: &TLVElement<'a>) -> Result<Self, Error> {
+        Ok(match t.get_element_type() {
+            ElementType::True => AttrValue::Bool(true),
+            ElementType::False => AttrValue::Bool(false),
+            ElementType::S8(v) => AttrValue::Int64(v.into()),
+            ElementType::S16(v) => AttrValue::Int64(v.into()),
+            ElementType::S32(v) => AttrValue::Int64(v.into()),
+            ElementType::S64(v) => AttrValue::Int64(v),
+            ElementType::U8(v) => AttrValue::Uint8(v),
+            ElementType::U16(v) => AttrValue::Uint16(v),
+            ElementType::U32(v) => AttrValue::Uint32(v),
+            ElementType::U64(v) => AttrValue::Uint64(v),
+            ElementType::Str8l(v)
+            | ElementType::Str16l(v)
+            | ElementType::Str32l(v)
+            | ElementType::Str64l(v) => AttrValue::OctetString(v.to_vec()),
+            ElementType::Utf8l(v) | ElementType::Utf16l(v) => AttrValue::CharString(
+                String::from_utf8(v.to_vec()).map_err(|_| Error::InvalidData)?,
+            ),
+            ElementType::Null => AttrValue::Null,
+            _ => return Err(t.type_mismatch("attribute value").into()),
+        })
+    }
+}
+
 impl AttrValue {
-    pub fn update_from_tlv(&mut self, tr: &TLVElement) -> Result<(), Error> {
+    /// The type of the value, or None for a null value, that has the type of its attribute
+    pub fn attr_type(&self) -> Option<AttrType> {
+        Some(match self {
+            AttrValue::Int64(_) => AttrType::Int64,
+            AttrValue::Uint8(_) => AttrType::Uint8,
+            AttrValue::Uint16(_) => AttrType::Uint16,
+            AttrValue::Uint32(_) => AttrType::Uint32,
+            AttrValue::Uint64(_) => AttrType::Uint64,
+            AttrValue::Bool(_) => AttrType::Bool,
+            AttrValue::OctetString(_) => AttrType::OctetString,
+            AttrValue::CharString(_) => AttrType::CharString,
+            AttrValue::Null => return None,
+            AttrValue::Custom => AttrType::Custom,
+        })
+    }
+}
+
+/// The type of the values of an attribute
+///
+/// This is declared when the attribute is created, and it stays the same while the value
+/// of a nullable attribute goes to null and back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttrType {
+    Int64,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Bool,
+    OctetString,
+    CharString,
+    Custom,
+}
+
+impl AttrType {
+    /// Whether the TLV element is of a type that can be a value of this type
+    ///
+    /// A Null element matches any type, whether the attribute is nullable is checked
+    /// separately. The type of Custom values isn't known, they match anything.
+    pub fn is_type_of(&self, tr: &TLVElement) -> bool {
+        let t = tr.get_element_type();
         match self {
-            AttrValue::Bool(v) => *v = tr.bool()?,
-            AttrValue::Uint8(v) => *v = tr.u8()?,
-            AttrValue::Uint16(v) => *v = tr.u16()?,
-            AttrValue::Uint32(v) => *v = tr.u32()?,
-            AttrValue::Uint64(v) => *v = tr.u64()?,
-            _ => {
+            _ if matches!(t, ElementType::Null) => true,
+            AttrType::Bool => matches!(t, ElementType::True | ElementType::False),
+            AttrType::Int64 => matches!(
+                t,
+                ElementType::S8(_)
+                    | ElementType::S16(_)
+                    | ElementType::S32(_)
+                    | ElementType::S64(_)
+            ),
+            AttrType::Uint8 | AttrType::Uint16 | AttrType::Uint32 | AttrType::Uint64 => matches!(
+                t,
+                ElementType::U8(_)
+                    | ElementType::U16(_)
+                    | ElementType::U32(_)
+                    | ElementType::U64(_)
+            ),
+            AttrType::OctetString => matches!(
+                t,
+                ElementType::Str8l(_)
+                    | ElementType::Str16l(_)
+                    | ElementType::Str32l(_)
+                    | ElementType::Str64l(_)
+            ),
+            AttrType::CharString => {
+                matches!(t, ElementType::Utf8l(_) | ElementType::Utf16l(_))
+            }
+            AttrType::Custom => true,
+        }
+    }
+
+    /// Decode a value of this type from the TLV element
+    ///
+    /// Unlike `AttrValue::from_tlv()`, an integer that is encoded in a narrower width gets
+    /// this type. A Null element is a null value, whatever the type.
+    pub fn value_from_tlv(&self, tr: &TLVElement) -> Result<AttrValue, Error> {
+        if tr.null().is_ok() {
+            return Ok(AttrValue::Null);
+        }
+        Ok(match self {
+            AttrType::Bool => AttrValue::Bool(tr.bool()?),
+            AttrType::Int64 => AttrValue::Int64(tr.i64()?),
+            AttrType::Uint8 => AttrValue::Uint8(tr.u8()?),
+            AttrType::Uint16 => AttrValue::Uint16(tr.u16()?),
+            AttrType::Uint32 => AttrValue::Uint32(tr.u32()?),
+            AttrType::Uint64 => AttrValue::Uint64(tr.u64()?),
+            AttrType::OctetString | AttrType::CharString => {
+                let value = AttrValue::from_tlv(tr)?;
+                if value.attr_type() != Some(*self) {
+                    let expected = match self {
+                        AttrType::OctetString => "octet string",
+                        _ => "utf8 string",
+                    };
+                    return Err(tr.type_mismatch(expected).into());
+                }
+                value
+            }
+            AttrType::Custom => {
                 error!("Attribute type not yet supported");
                 return Err(Error::AttributeNotFound);
             }
-        }
-        Ok(())
+        })
     }
 }
 
@@ -135,8 +268,11 @@ impl AttrValue {
 pub struct Attribute {
     pub(super) id: u16,
     pub(super) value: AttrValue,
+    pub(super) attr_type: AttrType,
     pub(super) quality: Quality,
     pub(super) access: Access,
+    /// The maximum length of a string value, in bytes
+    pub(super) max_len: Option<usize>,
 }
 
 impl Default for Attribute {
@@ -144,28 +280,77 @@ impl Default for Attribute {
         Attribute {
             id: 0,
             value: AttrValue::Bool(true),
+            attr_type: AttrType::Bool,
             quality: Default::default(),
             access: Default::default(),
+            max_len: None,
         }
     }
 }
 
 impl Attribute {
+    /// An attribute of the type of 'value'
+    ///
+    /// The type of a null value isn't known, a nullable attribute that starts out null is
+    /// created with `new_nullable()` instead.
     pub fn new(
         id: u16,
         value: AttrValue,
         access: Access,
         quality: Quality,
     ) -> Result<Attribute, Error> {
+        let attr_type = value.attr_type().ok_or(Error::Invalid)?;
         Ok(Attribute {
             id,
             value,
+            attr_type,
             access,
             quality,
+            max_len: None,
         })
     }
 
+    /// A nullable attribute of type 'attr_type', whose value is null to begin with
+    pub fn new_nullable(
+        id: u16,
+        attr_type: AttrType,
+        access: Access,
+        quality: Quality,
+    ) -> Result<Attribute, Error> {
+        Ok(Attribute {
+            id,
+            value: AttrValue::Null,
+            attr_type,
+            access,
+            quality: quality | Quality::NULLABLE,
+            max_len: None,
+        })
+    }
+
+    /// Limit the string values of the attribute to 'max_len' bytes
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = Some(max_len);
+    }
+
+    /// Whether 'value' is within the length limit of the attribute, if any
+    pub fn is_within_len(&self, value: &AttrValue) -> bool {
+        let len = match value {
+            AttrValue::OctetString(v) => v.len(),
+            AttrValue::CharString(v) => v.len(),
+            _ => return true,
+        };
+        !matches!(self.max_len, Some(max_len) if len > max_len)
+    }
+
     pub fn set_value(&mut self, value: AttrValue) -> Result<(), Error> {
+        match value.attr_type() {
+            None if !self.is_nullable() => return Err(Error::Invalid),
+            Some(t) if t != self.attr_type => return Err(Error::Invalid),
+            _ => (),
+        }
+        if !self.is_within_len(&value) {
+            return Err(Error::Invalid);
+        }
         if !self.quality.contains(Quality::FIXED) {
             self.value = value;
             Ok(())
@@ -174,11 +359,77 @@ impl Attribute {
         }
     }
 
+    pub fn is_nullable(&self) -> bool {
+        self.quality.contains(Quality::NULLABLE)
+    }
+
     pub fn is_system_attr(attr_id: u16) -> bool {
         attr_id >= (GlobalElements::ServerGenCmd as u16)
     }
 }
 
+/// The operation requested by a write to a list attribute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOperation {
+    /// Append an item to the list
+    AddItem,
+    /// Replace the item at this index
+    EditItem(u16),
+    /// Delete the item at this index
+    DeleteItem(u16),
+    /// Delete all the items of the list
+    DeleteList,
+}
+
+/// Splits a write to a list attribute into its list operations
+///
+/// A write without a list index replaces the whole list, which is a `DeleteList` followed
+/// by an `AddItem` for each element. A write with a null list index appends its data, while
+/// a write with a list index either edits that item, or deletes it if the data is null.
+pub fn attr_list_write<F>(
+    attr: &AttrDetails,
+    data: &TLVElement,
+    mut f: F,
+) -> Result<(), IMStatusCode>
+where
+    F: FnMut(ListOperation, &TLVElement) -> Result<(), IMStatusCode>,
+{
+    match attr.list_index {
+        Some(Nullable::NotNull(index)) => {
+            if data.null().is_ok() {
+                f(ListOperation::DeleteItem(index), data)
+            } else {
+                f(ListOperation::EditItem(index), data)
+            }
+        }
+        Some(Nullable::Null) => f(ListOperation::AddItem, data),
+        None => {
+            data.confirm_array()
+                .map_err(|_| IMStatusCode::InvalidDataType)?;
+            f(ListOperation::DeleteList, data)?;
+            if let Some(tlv_iter) = data.iter() {
+                for item in tlv_iter {
+                    f(ListOperation::AddItem, &item)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Decode a fabric-scoped list entry that is written by the accessor on fabric 'fab_idx'
+///
+/// The entry always belongs to the accessor's fabric, irrespective of the FabricIndex
+/// that was written, if any.
+pub fn fab_scoped_from_tlv<'a, T>(data: &TLVElement<'a>, fab_idx: u8) -> Result<T, IMStatusCode>
+where
+    T: FromTLV<'a> + FabricScoped,
+{
+    let mut entry = T::from_tlv(data).map_err(|_| IMStatusCode::ConstraintError)?;
+    entry.set_fab_idx(fab_idx);
+    Ok(entry)
+}
+
 impl std::fmt::Display for Attribute {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {:?}", self.id, self.value)
@@ -187,8 +438,121 @@ impl std::fmt::Display for Attribute {
 
 #[cfg(test)]
 mod tests {
-    use super::Access;
-    use crate::data_model::objects::Privilege;
+    use super::{Access, AttrType, AttrValue, Attribute, Quality};
+    use crate::{
+        data_model::objects::Privilege,
+        error::Error,
+        tlv::{get_root_node, FromTLV, TLVWriter, TagType, ToTLV},
+        utils::writebuf::WriteBuf,
+    };
+
+    fn roundtrip(value: &AttrValue) -> Result<AttrValue, Error> {
+        let mut buf = [0; 400];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        value.to_tlv(&mut tw, TagType::Anonymous)?;
+        AttrValue::from_tlv(&get_root_node(wb.as_borrow_slice())?)
+    }
+
+    #[test]
+    fn test_attr_value_tlv_roundtrip() {
+        let values = [
+            AttrValue::Bool(true),
+            AttrValue::Bool(false),
+            AttrValue::Int64(-5),
+            AttrValue::Int64(i64::MIN),
+            AttrValue::Uint8(0xab),
+            AttrValue::Uint16(0xabcd),
+            AttrValue::Uint32(0xabcd_ef01),
+            AttrValue::Uint64(0xabcd_ef01_2345_6789),
+            AttrValue::OctetString(vec![]),
+            AttrValue::OctetString(vec![0x5a; 300]),
+            AttrValue::CharString("On/Off Light".to_string()),
+            AttrValue::CharString("x".repeat(300)),
+            AttrValue::Null,
+        ];
+        for value in values.iter() {
+            assert_eq!(roundtrip(value).as_ref(), Ok(value));
+        }
+        assert_eq!(roundtrip(&AttrValue::Custom), Err(Error::AttributeNotFound));
+    }
+
+    #[test]
+    fn test_attr_type_value_from_tlv() {
+        let mut buf = [0; 10];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_array(TagType::Anonymous).unwrap();
+        tw.u8(TagType::Anonymous, 5).unwrap();
+        tw.null(TagType::Anonymous).unwrap();
+        tw.end_container().unwrap();
+        let root = get_root_node(wb.as_borrow_slice()).unwrap();
+        let mut iter = root.iter().unwrap();
+        let (data, null) = (iter.next().unwrap(), iter.next().unwrap());
+
+        assert_eq!(
+            AttrType::Uint32.value_from_tlv(&data),
+            Ok(AttrValue::Uint32(5))
+        );
+        assert_eq!(
+            AttrType::CharString.value_from_tlv(&data),
+            Err(Error::TLVTypeMismatch {
+                expected: "utf8 string",
+                found: "u8"
+            })
+        );
+        // A null value keeps the type of its attribute, that the next value must be of
+        assert_eq!(AttrValue::Null.attr_type(), None);
+        assert_eq!(AttrType::Bool.value_from_tlv(&null), Ok(AttrValue::Null));
+    }
+
+    #[test]
+    fn test_null_keeps_type() {
+        let mut a =
+            Attribute::new_nullable(0, AttrType::Uint16, Access::RWVA, Quality::NONE).unwrap();
+        assert!(a.is_nullable());
+        assert_eq!(a.set_value(AttrValue::Bool(true)), Err(Error::Invalid));
+        a.set_value(AttrValue::Uint16(5)).unwrap();
+        a.set_value(AttrValue::Null).unwrap();
+        assert_eq!(a.set_value(AttrValue::Uint8(5)), Err(Error::Invalid));
+
+        // The type of a null value isn't known
+        assert_eq!(
+            Attribute::new(0, AttrValue::Null, Access::RWVA, Quality::NULLABLE).err(),
+            Some(Error::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_attr_type_is_type_of() {
+        let mut buf = [0; 20];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_array(TagType::Anonymous).unwrap();
+        tw.u8(TagType::Anonymous, 5).unwrap();
+        tw.utf8(TagType::Anonymous, b"abc").unwrap();
+        tw.null(TagType::Anonymous).unwrap();
+        tw.end_container().unwrap();
+        let root = get_root_node(wb.as_borrow_slice()).unwrap();
+        let mut iter = root.iter().unwrap();
+        let (int, string, null) = (
+            iter.next().unwrap(),
+            iter.next().unwrap(),
+            iter.next().unwrap(),
+        );
+
+        let attr_type = AttrType::Uint32;
+        assert!(attr_type.is_type_of(&int));
+        assert!(!attr_type.is_type_of(&string));
+        assert!(attr_type.is_type_of(&null));
+        assert!(!AttrType::Int64.is_type_of(&int));
+        assert!(AttrType::CharString.is_type_of(&string));
+        assert!(!AttrType::OctetString.is_type_of(&string));
+        assert!(AttrType::Custom.is_type_of(&string));
+    }
 
     #[test]
     fn test_read() {
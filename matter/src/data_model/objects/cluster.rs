@@ -1,12 +1,15 @@
 use crate::{
     acl::AccessReq,
-    data_model::objects::{Access, AttrValue, Attribute, EncodeValue, Quality},
+    data_model::objects::{Access, AttrType, AttrValue, Attribute, EncodeValue, Quality},
     error::*,
-    interaction_model::{command::CommandReq, core::IMStatusCode},
+    interaction_model::{
+        command::{CommandReq, CommandStatus},
+        core::IMStatusCode,
+    },
     // TODO: This layer shouldn't really depend on the TLV layer, should create an abstraction layer
-    tlv::{TLVElement, TLVWriter, TagType},
+    tlv::{FabricScoped, Nullable, TLVElement, TLVWriter, TagType},
 };
-use log::error;
+use log::{debug, error};
 use num_derive::FromPrimitive;
 use rand::Rng;
 use std::fmt::{self, Debug};
@@ -27,28 +30,67 @@ pub enum GlobalElements {
     FabricIndex = 0xFE,
 }
 
+/// The details of an attribute that is being read or written
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AttrDetails {
+    pub attr_id: u16,
+    /// The list index in the attribute path, if any
+    pub list_index: Option<Nullable<u16>>,
+    /// The fabric index of the accessor
+    pub fab_idx: u8,
+    /// Whether fabric-scoped lists should only report the accessor's entries
+    pub fab_filter: bool,
+}
+
+impl AttrDetails {
+    pub fn new(attr_id: u16) -> Self {
+        Self {
+            attr_id,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the fabric-scoped list entry is reported, as per the fabric filter of the read
+    pub fn fab_filter_match<T: FabricScoped>(&self, entry: &T) -> bool {
+        !self.fab_filter || entry.get_fab_idx() == self.fab_idx
+    }
+}
+
 pub trait ClusterType {
     fn base(&self) -> &Cluster;
     fn base_mut(&mut self) -> &mut Cluster;
-    fn read_custom_attribute(&self, _encoder: &mut dyn Encoder, _attr_id: u16) {}
+    fn read_custom_attribute(&self, _encoder: &mut dyn Encoder, _attr: &AttrDetails) {}
 
-    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
+    /// Handle the command in `cmd_req`
+    ///
+    /// A handler that can't complete the command right away may defer it with
+    /// `CommandReq::defer()` and return `CommandStatus::Deferred`.
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
         let cmd = cmd_req.cmd.path.leaf.map(|a| a as u16);
-        println!("Received command: {:?}", cmd);
+        debug!("Unsupported command: {:?}", cmd);
 
         Err(IMStatusCode::UnsupportedCommand)
     }
 
-    fn write_attribute(&mut self, attr_id: u16, data: &TLVElement) -> Result<(), IMStatusCode> {
-        self.base_mut().write_attribute_from_tlv(attr_id, data)
+    fn write_attribute(
+        &mut self,
+        attr: &AttrDetails,
+        data: &TLVElement,
+    ) -> Result<(), IMStatusCode> {
+        self.base_mut().write_attribute_from_tlv(attr.attr_id, data)
     }
 }
 
 pub struct Cluster {
     pub(super) id: u32,
     attributes: Vec<Attribute>,
+    // The commands that this cluster sends as responses
+    generated_cmds: Vec<u16>,
     feature_map: Option<u32>,
+    // Incremented whenever the value of any of the attributes changes
     data_ver: u32,
+    // Set for each attribute whose value changed, in the same order as the attributes
+    dirty: Vec<bool>,
 }
 
 impl Cluster {
@@ -56,8 +98,10 @@ impl Cluster {
         let mut c = Cluster {
             id,
             attributes: Vec::with_capacity(ATTRS_PER_CLUSTER),
+            generated_cmds: Vec::new(),
             feature_map: None,
             data_ver: rand::thread_rng().gen_range(0..0xFFFFFFFF),
+            dirty: Vec::with_capacity(ATTRS_PER_CLUSTER),
         };
         c.add_default_attributes()?;
         Ok(c)
@@ -71,6 +115,13 @@ impl Cluster {
         self.data_ver
     }
 
+    /// Mark a change in the cluster's data, that isn't from setting an attribute's value
+    ///
+    /// This is for the attributes whose values are held outside of the cluster
+    pub fn bump_dataver(&mut self) {
+        self.data_ver = self.data_ver.wrapping_add(1);
+    }
+
     pub fn set_feature_map(&mut self, map: u32) -> Result<(), Error> {
         if self.feature_map.is_none() {
             self.add_attribute(Attribute::new(
@@ -87,6 +138,19 @@ impl Cluster {
         Ok(())
     }
 
+    /// Declare the commands that this cluster sends as responses, its GeneratedCommandList
+    pub fn set_generated_commands(&mut self, cmds: &[u16]) -> Result<(), Error> {
+        if cmds.len() > CMDS_PER_CLUSTER {
+            return Err(Error::NoSpace);
+        }
+        self.generated_cmds = cmds.to_vec();
+        Ok(())
+    }
+
+    pub fn is_generated_command(&self, cmd: u16) -> bool {
+        self.generated_cmds.contains(&cmd)
+    }
+
     fn add_default_attributes(&mut self) -> Result<(), Error> {
         self.add_attribute(Attribute::new(
             GlobalElements::AttributeList as u16,
@@ -99,6 +163,7 @@ impl Cluster {
     pub fn add_attribute(&mut self, attr: Attribute) -> Result<(), Error> {
         if self.attributes.len() < self.attributes.capacity() {
             self.attributes.push(attr);
+            self.dirty.push(false);
             Ok(())
         } else {
             Err(Error::NoSpace)
@@ -109,6 +174,29 @@ impl Cluster {
         self.attributes.iter().position(|c| c.id == attr_id)
     }
 
+    /// Returns the attributes whose values changed since the last call, and clears them
+    pub fn take_dirty(&mut self) -> Vec<u16> {
+        let dirty = self
+            .attributes
+            .iter()
+            .zip(self.dirty.iter())
+            .filter(|(_, dirty)| **dirty)
+            .map(|(a, _)| a.id)
+            .collect();
+        self.dirty.iter_mut().for_each(|d| *d = false);
+        dirty
+    }
+
+    fn set_attribute_value(&mut self, index: usize, value: AttrValue) -> Result<(), Error> {
+        let a = &mut self.attributes[index];
+        if a.value != value {
+            a.set_value(value)?;
+            self.dirty[index] = true;
+            self.bump_dataver();
+        }
+        Ok(())
+    }
+
     fn get_attribute(&self, attr_id: u16) -> Result<&Attribute, Error> {
         let index = self
             .get_attribute_index(attr_id)
@@ -143,10 +231,11 @@ impl Cluster {
         c: &dyn ClusterType,
         access_req: &mut AccessReq,
         encoder: &mut dyn Encoder,
-        attr_id: u16,
+        attr: &AttrDetails,
     ) {
-        let mut error = IMStatusCode::Sucess;
+        let mut error = IMStatusCode::Success;
         let base = c.base();
+        let attr_id = attr.attr_id;
         let a = if let Ok(a) = base.get_attribute(attr_id) {
             a
         } else {
@@ -163,21 +252,34 @@ impl Cluster {
             error = IMStatusCode::UnsupportedAccess;
         }
 
-        if error != IMStatusCode::Sucess {
+        if error != IMStatusCode::Success {
             encoder.encode_status(error, 0);
         } else if Attribute::is_system_attr(attr_id) {
             c.base().read_system_attribute(encoder, a)
         } else if a.value != AttrValue::Custom {
             encoder.encode(EncodeValue::Value(&a.value))
         } else {
-            c.read_custom_attribute(encoder, attr_id)
+            c.read_custom_attribute(encoder, attr)
         }
     }
 
+    // The AttributeList is in numeric order, which puts the global attributes last,
+    // whatever the order in which the attributes were added
+    //
+    // There are only a few attributes, so this picks the next id with a scan rather than
+    // sorting a copy of the ids, which would allocate on every read
     fn encode_attribute_ids(&self, tag: TagType, tw: &mut TLVWriter) {
         let _ = tw.start_array(tag);
-        for a in &self.attributes {
-            let _ = tw.u16(TagType::Anonymous, a.id);
+        let mut last = None;
+        while let Some(id) = self
+            .attributes
+            .iter()
+            .map(|a| a.id)
+            .filter(|id| Some(*id) > last)
+            .min()
+        {
+            let _ = tw.u16(TagType::Anonymous, id);
+            last = Some(id);
         }
         let _ = tw.end_container();
     }
@@ -216,10 +318,10 @@ impl Cluster {
         c: &mut dyn ClusterType,
         access_req: &mut AccessReq,
         data: &TLVElement,
-        attr_id: u16,
+        attr: &AttrDetails,
     ) -> Result<(), IMStatusCode> {
         let base = c.base_mut();
-        let a = if let Ok(a) = base.get_attribute_mut(attr_id) {
+        let a = if let Ok(a) = base.get_attribute_mut(attr.attr_id) {
             a
         } else {
             return Err(IMStatusCode::UnsupportedAttribute);
@@ -234,7 +336,20 @@ impl Cluster {
             return Err(IMStatusCode::UnsupportedAccess);
         }
 
-        c.write_attribute(attr_id, data)
+        // Catch a value of the wrong type here, rather than with an opaque error from the
+        // cluster's write handler
+        if !a.attr_type.is_type_of(data) {
+            return Err(IMStatusCode::InvalidDataType);
+        }
+
+        // The values of custom attributes are held by the cluster, we can't tell whether
+        // they changed, so any successful write is treated as a change
+        let custom = a.value == AttrValue::Custom;
+        c.write_attribute(attr, data)?;
+        if custom {
+            c.base_mut().bump_dataver();
+        }
+        Ok(())
     }
 
     pub fn write_attribute_from_tlv(
@@ -242,13 +357,22 @@ impl Cluster {
         attr_id: u16,
         data: &TLVElement,
     ) -> Result<(), IMStatusCode> {
-        let a = self.get_attribute_mut(attr_id)?;
-        if a.value != AttrValue::Custom {
-            let mut value = a.value;
-            value
-                .update_from_tlv(data)
+        let index = self
+            .get_attribute_index(attr_id)
+            .ok_or(IMStatusCode::UnsupportedAttribute)?;
+        let a = &self.attributes[index];
+        if a.attr_type != AttrType::Custom {
+            let value = a
+                .attr_type
+                .value_from_tlv(data)
                 .map_err(|_| IMStatusCode::Failure)?;
-            a.set_value(value)
+            if value == AttrValue::Null && !a.is_nullable() {
+                return Err(IMStatusCode::ConstraintError);
+            }
+            if !a.is_within_len(&value) {
+                return Err(IMStatusCode::ConstraintError);
+            }
+            self.set_attribute_value(index, value)
                 .map_err(|_| IMStatusCode::UnsupportedWrite)
         } else {
             Err(IMStatusCode::UnsupportedAttribute)
@@ -256,8 +380,10 @@ impl Cluster {
     }
 
     pub fn write_attribute_raw(&mut self, attr_id: u16, value: AttrValue) -> Result<(), Error> {
-        let a = self.get_attribute_mut(attr_id)?;
-        a.set_value(value)
+        let index = self
+            .get_attribute_index(attr_id)
+            .ok_or(Error::AttributeNotFound)?;
+        self.set_attribute_value(index, value)
     }
 }
 
@@ -273,3 +399,129 @@ impl std::fmt::Display for Cluster {
         write!(f, " ], ")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Cluster, GlobalElements};
+    use crate::{
+        data_model::objects::{Access, AttrType, AttrValue, Attribute, Quality},
+        interaction_model::core::IMStatusCode,
+        tlv::{get_root_node, TLVWriter, TagType, ToTLV},
+        utils::writebuf::WriteBuf,
+    };
+
+    #[test]
+    fn test_take_dirty() {
+        let mut c = Cluster::new(6).unwrap();
+        c.add_attribute(
+            Attribute::new(0, AttrValue::Bool(false), Access::RV, Quality::NONE).unwrap(),
+        )
+        .unwrap();
+        c.add_attribute(Attribute::new(1, AttrValue::Uint8(0), Access::RV, Quality::NONE).unwrap())
+            .unwrap();
+        assert!(c.take_dirty().is_empty());
+
+        // A write from the TLV marks the attribute dirty
+        let mut buf = [0; 10];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.bool(TagType::Anonymous, true).unwrap();
+        let data = get_root_node(wb.as_borrow_slice()).unwrap();
+        c.write_attribute_from_tlv(0, &data).unwrap();
+        assert_eq!(c.take_dirty(), vec![0]);
+        assert!(c.take_dirty().is_empty());
+
+        // Writing the same value doesn't
+        c.write_attribute_raw(0, AttrValue::Bool(true)).unwrap();
+        assert!(c.take_dirty().is_empty());
+
+        c.write_attribute_raw(1, AttrValue::Uint8(5)).unwrap();
+        c.write_attribute_raw(0, AttrValue::Bool(false)).unwrap();
+        assert_eq!(c.take_dirty(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_attribute_list_order() {
+        let mut c = Cluster::new(0x402).unwrap();
+        for id in [5, 1, 3] {
+            c.add_attribute(
+                Attribute::new(id, AttrValue::Uint8(0), Access::RV, Quality::NONE).unwrap(),
+            )
+            .unwrap();
+        }
+        c.set_feature_map(1).unwrap();
+        c.add_attribute(Attribute::new(2, AttrValue::Uint8(0), Access::RV, Quality::NONE).unwrap())
+            .unwrap();
+
+        let mut buf = [0; 40];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        c.encode_attribute_ids(TagType::Anonymous, &mut tw);
+        let list: Vec<u16> = get_root_node(wb.as_borrow_slice())
+            .unwrap()
+            .confirm_array()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|a| a.u16().unwrap())
+            .collect();
+        assert_eq!(
+            list,
+            vec![
+                1,
+                2,
+                3,
+                5,
+                GlobalElements::AttributeList as u16,
+                GlobalElements::FeatureMap as u16
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nullable_attribute() {
+        let mut c = Cluster::new(0x402).unwrap();
+        c.add_attribute(
+            Attribute::new_nullable(0, AttrType::Uint16, Access::RWVA, Quality::NONE).unwrap(),
+        )
+        .unwrap();
+        c.add_attribute(
+            Attribute::new(1, AttrValue::Uint16(5), Access::RWVA, Quality::NONE).unwrap(),
+        )
+        .unwrap();
+
+        // A null value is encoded as the TLV Null
+        let mut buf = [0; 10];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        c.read_attribute_raw(0)
+            .unwrap()
+            .to_tlv(&mut tw, TagType::Anonymous)
+            .unwrap();
+        assert_eq!(wb.as_borrow_slice(), [0x14]);
+
+        // Null is only accepted by the nullable attribute
+        let data = get_root_node(wb.as_borrow_slice()).unwrap();
+        assert_eq!(
+            c.write_attribute_from_tlv(1, &data),
+            Err(IMStatusCode::ConstraintError)
+        );
+        assert!(c.write_attribute_raw(1, AttrValue::Null).is_err());
+        assert_eq!(c.read_attribute_raw(1), Ok(&AttrValue::Uint16(5)));
+
+        // The nullable attribute goes from null to a value and back
+        let mut buf = [0; 10];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.u16(TagType::Anonymous, 0x1234).unwrap();
+        let value = get_root_node(wb.as_borrow_slice()).unwrap();
+        c.write_attribute_from_tlv(0, &value).unwrap();
+        assert_eq!(c.read_attribute_raw(0), Ok(&AttrValue::Uint16(0x1234)));
+        c.write_attribute_from_tlv(0, &data).unwrap();
+        assert_eq!(c.read_attribute_raw(0), Ok(&AttrValue::Null));
+    }
+}
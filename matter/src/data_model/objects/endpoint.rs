@@ -1,20 +1,61 @@
 use crate::{data_model::objects::ClusterType, error::*, interaction_model::core::IMStatusCode};
 
+use log::error;
 use std::fmt;
 
 pub const CLUSTERS_PER_ENDPT: usize = 7;
+pub const DEV_TYPES_PER_ENDPT: usize = 2;
+
+/// A device type, along with the server clusters it mandates on its endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceType {
+    pub dtype: u32,
+    pub drev: u16,
+    pub mandatory_clusters: &'static [u32],
+}
 
 pub struct Endpoint {
+    dev_types: Vec<DeviceType>,
     clusters: Vec<Box<dyn ClusterType>>,
 }
 
 impl Endpoint {
     pub fn new() -> Result<Box<Endpoint>, Error> {
         Ok(Box::new(Endpoint {
+            dev_types: Vec::with_capacity(DEV_TYPES_PER_ENDPT),
             clusters: Vec::with_capacity(CLUSTERS_PER_ENDPT),
         }))
     }
 
+    pub fn add_device_type(&mut self, dev_type: DeviceType) -> Result<(), Error> {
+        if self.dev_types.len() < self.dev_types.capacity() {
+            self.dev_types.push(dev_type);
+            Ok(())
+        } else {
+            Err(Error::NoSpace)
+        }
+    }
+
+    pub fn get_device_types(&self) -> &[DeviceType] {
+        &self.dev_types
+    }
+
+    /// Check that the mandatory server clusters of all the declared device types are present
+    pub fn validate_device_type(&self) -> Result<(), Error> {
+        for dev_type in self.dev_types.iter() {
+            for cluster_id in dev_type.mandatory_clusters {
+                if self.get_cluster_index(*cluster_id).is_none() {
+                    error!(
+                        "Device type {:#x} requires cluster {:#x}, which isn't on the endpoint",
+                        dev_type.dtype, cluster_id
+                    );
+                    return Err(Error::MandatoryClusterMissing);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_cluster(&mut self, cluster: Box<dyn ClusterType>) -> Result<(), Error> {
         if self.clusters.len() < self.clusters.capacity() {
             self.clusters.push(cluster);
@@ -42,6 +83,22 @@ impl Endpoint {
         Ok(self.clusters[index].as_mut())
     }
 
+    // Take the cluster out of the endpoint, along with its position to put it back with
+    // attach_cluster()
+    pub(super) fn detach_cluster(
+        &mut self,
+        cluster_id: u32,
+    ) -> Result<(usize, Box<dyn ClusterType>), Error> {
+        let index = self
+            .get_cluster_index(cluster_id)
+            .ok_or(Error::ClusterNotFound)?;
+        Ok((index, self.clusters.remove(index)))
+    }
+
+    pub(super) fn attach_cluster(&mut self, index: usize, cluster: Box<dyn ClusterType>) {
+        self.clusters.insert(index, cluster);
+    }
+
     // Returns a slice of clusters, with either a single cluster or all (wildcard)
     pub fn get_wildcard_clusters(
         &self,
@@ -77,7 +134,13 @@ impl Endpoint {
 
 impl std::fmt::Display for Endpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "clusters:[")?;
+        write!(f, "device types:[")?;
+        let mut comma = "";
+        for dev_type in self.dev_types.iter() {
+            write!(f, "{}{:#x}", comma, dev_type.dtype)?;
+            comma = ", ";
+        }
+        write!(f, "], clusters:[")?;
         let mut comma = "";
         for element in self.clusters.iter() {
             write!(f, "{} {{ {} }}", comma, element.base())?;
@@ -86,3 +149,26 @@ impl std::fmt::Display for Endpoint {
         write!(f, "]")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Endpoint;
+    use crate::{
+        data_model::{cluster_on_off::OnOffCluster, device_types::DEV_TYPE_ON_OFF_LIGHT},
+        error::Error,
+    };
+
+    #[test]
+    fn test_validate_device_type() {
+        let mut endpoint = Endpoint::new().unwrap();
+        endpoint.add_device_type(DEV_TYPE_ON_OFF_LIGHT).unwrap();
+        // On/Off Light without the On/Off cluster
+        assert_eq!(
+            endpoint.validate_device_type(),
+            Err(Error::MandatoryClusterMissing)
+        );
+
+        endpoint.add_cluster(OnOffCluster::new().unwrap()).unwrap();
+        assert_eq!(endpoint.validate_device_type(), Ok(()));
+    }
+}
@@ -1,9 +1,10 @@
 use crate::{
-    data_model::objects::{ClusterType, Endpoint},
+    data_model::objects::{ClusterType, DeviceType, Endpoint},
     error::*,
     interaction_model::{core::IMStatusCode, messages::GenericPath},
     // TODO: This layer shouldn't really depend on the TLV layer, should create an abstraction layer
 };
+use log::error;
 use std::fmt;
 
 pub trait ChangeConsumer {
@@ -84,6 +85,30 @@ impl Node {
         self.get_endpoint(e)?.get_cluster(c)
     }
 
+    /// Run a closure on a cluster, with shared access to the rest of the node
+    ///
+    /// The cluster is detached from its endpoint while the closure runs, so it isn't part of
+    /// the view of the node that is passed along. This lets the cluster look at its siblings
+    /// without locking the node again.
+    pub fn with_cluster_detached<T, F>(&mut self, e: u16, c: u32, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut dyn ClusterType, &Node) -> T,
+    {
+        let (index, cluster) = self.get_endpoint_mut(e)?.detach_cluster(c)?;
+        let mut detached = DetachedCluster {
+            node: self,
+            endpoint: e,
+            index,
+            cluster: Some(cluster),
+        };
+        // The cluster is only taken out when the guard is dropped
+        let cluster = detached
+            .cluster
+            .as_deref_mut()
+            .ok_or(Error::ClusterNotFound)?;
+        Ok(f(cluster, detached.node))
+    }
+
     pub fn add_cluster(
         &mut self,
         endpoint_id: u32,
@@ -100,6 +125,31 @@ impl Node {
         }
     }
 
+    pub fn add_device_type(&mut self, endpoint_id: u32, dev_type: DeviceType) -> Result<(), Error> {
+        let endpoint_id = endpoint_id as usize;
+        if endpoint_id < ENDPTS_PER_ACC {
+            self.endpoints[endpoint_id]
+                .as_mut()
+                .ok_or(Error::NoEndpoint)?
+                .add_device_type(dev_type)
+        } else {
+            Err(Error::Invalid)
+        }
+    }
+
+    /// Check that every endpoint has the mandatory clusters of its device types
+    pub fn validate_device_types(&self) -> Result<(), Error> {
+        for (i, e) in self.endpoints.iter().enumerate() {
+            if let Some(e) = e {
+                if let Err(err) = e.validate_device_type() {
+                    error!("Endpoint {} failed device type validation", i);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Returns a slice of endpoints, with either a single endpoint or all (wildcard)
     pub fn get_wildcard_endpoints(
         &self,
@@ -258,16 +308,273 @@ impl Node {
     where
         T: FnMut(&GenericPath, &dyn ClusterType) -> Result<(), IMStatusCode>,
     {
-        self.for_each_cluster(path, |current_path, c| {
-            let mut current_path = *current_path;
-            let (attributes, wildcard) = c
-                .base()
-                .get_wildcard_attribute(path.leaf.map(|at| at as u16))?;
-            for a in attributes.iter() {
-                current_path.leaf = Some(a.id as u32);
-                f(&current_path, c).or_else(|e| if !wildcard { Err(e) } else { Ok(()) })?;
+        let wildcard = path.is_wildcard();
+        let mut cursor = AttrTraversalCursor::new(path);
+        while let Some((current_path, c, _)) = cursor.next(self)? {
+            f(&current_path, c).or_else(|e| if !wildcard { Err(e) } else { Ok(()) })?;
+        }
+        Ok(())
+    }
+}
+
+// A cluster detached from its endpoint, that is attached back when this is dropped, even if
+// the code using the cluster panics
+struct DetachedCluster<'a> {
+    node: &'a mut Node,
+    endpoint: u16,
+    index: usize,
+    cluster: Option<Box<dyn ClusterType>>,
+}
+
+impl<'a> Drop for DetachedCluster<'a> {
+    fn drop(&mut self) {
+        if let Some(cluster) = self.cluster.take() {
+            if let Ok(endpoint) = self.node.get_endpoint_mut(self.endpoint) {
+                endpoint.attach_cluster(self.index, cluster);
             }
+        }
+    }
+}
+
+/// A position in the traversal of the attributes that a (wildcard) path expands to
+///
+/// Unlike [Node::for_each_attribute], the cursor doesn't borrow the node between the
+/// attributes. It can be stored, say between the chunks of a report, and resumed on the
+/// node later on. The position is kept as indices into the node, so a cursor that is
+/// resumed after endpoints or clusters were added may skip or repeat some attributes.
+#[derive(Debug, Clone, Copy)]
+pub struct AttrTraversalCursor {
+    path: GenericPath,
+    endpoint: usize,
+    cluster: usize,
+    attribute: usize,
+}
+
+impl AttrTraversalCursor {
+    pub fn new(path: &GenericPath) -> Self {
+        Self {
+            path: *path,
+            endpoint: 0,
+            cluster: 0,
+            attribute: 0,
+        }
+    }
+
+    /// The path that is being traversed
+    pub fn path(&self) -> &GenericPath {
+        &self.path
+    }
+
+    /// Move on to the next attribute of the traversal on `node`
+    ///
+    /// Returns the concrete path of the attribute, its cluster and its ID, or None once the
+    /// traversal is complete. The endpoints, clusters and attributes that are missing are
+    /// skipped for a wildcard path. Otherwise, the status for the missing element is
+    /// returned, and that completes the traversal.
+    pub fn next<'a>(
+        &mut self,
+        node: &'a Node,
+    ) -> Result<Option<(GenericPath, &'a dyn ClusterType, u16)>, IMStatusCode> {
+        loop {
+            if self.endpoint >= ENDPTS_PER_ACC {
+                return Ok(None);
+            }
+            let (endpoints, first_endpoint, endpoint_wildcard) =
+                match node.get_wildcard_endpoints(self.path.endpoint) {
+                    Ok(e) => e,
+                    Err(e) => return Err(self.finish(e)),
+                };
+            let e = match endpoints.get(self.endpoint) {
+                Some(Some(e)) => e,
+                Some(None) => {
+                    self.next_endpoint();
+                    continue;
+                }
+                None => return Ok(self.finish(None)),
+            };
+
+            let (clusters, cluster_wildcard) = match e.get_wildcard_clusters(self.path.cluster) {
+                Ok(c) => c,
+                Err(_) if endpoint_wildcard => {
+                    self.next_endpoint();
+                    continue;
+                }
+                Err(e) => return Err(self.finish(e)),
+            };
+            let c = match clusters.get(self.cluster) {
+                Some(c) => c.as_ref(),
+                None => {
+                    self.next_endpoint();
+                    continue;
+                }
+            };
+
+            let leaf = self.path.leaf.map(|a| a as u16);
+            let attributes = match c.base().get_wildcard_attribute(leaf) {
+                Ok((a, _)) => a,
+                Err(_) if endpoint_wildcard || cluster_wildcard => {
+                    self.next_cluster();
+                    continue;
+                }
+                Err(e) => return Err(self.finish(e)),
+            };
+            let a = match attributes.get(self.attribute) {
+                Some(a) => a,
+                None => {
+                    self.next_cluster();
+                    continue;
+                }
+            };
+            self.attribute += 1;
+
+            let path = GenericPath::new(
+                Some((first_endpoint + self.endpoint) as u16),
+                Some(c.base().id),
+                Some(a.id as u32),
+            );
+            return Ok(Some((path, c, a.id)));
+        }
+    }
+
+    fn next_endpoint(&mut self) {
+        self.endpoint += 1;
+        self.cluster = 0;
+        self.attribute = 0;
+    }
+
+    fn next_cluster(&mut self) {
+        self.cluster += 1;
+        self.attribute = 0;
+    }
+
+    // Nothing is left to traverse after this
+    fn finish<T>(&mut self, result: T) -> T {
+        self.endpoint = ENDPTS_PER_ACC;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttrTraversalCursor, Node};
+    use crate::{
+        data_model::{
+            cluster_level_control::{self, LevelControlCluster},
+            cluster_on_off::{self, OnOffCluster},
+        },
+        interaction_model::{core::IMStatusCode, messages::GenericPath},
+    };
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn test_node() -> Box<Node> {
+        let mut node = Node::new().unwrap();
+        for _ in 0..2 {
+            let e = node.add_endpoint().unwrap();
+            node.add_cluster(e, OnOffCluster::new().unwrap()).unwrap();
+        }
+        node.add_cluster(1, LevelControlCluster::new(1, 254).unwrap())
+            .unwrap();
+        node
+    }
+
+    // Advance the cursor by up to 'count' attributes, returning their paths
+    fn advance(cursor: &mut AttrTraversalCursor, node: &Node, count: usize) -> Vec<GenericPath> {
+        let mut paths = Vec::new();
+        while paths.len() < count {
+            match cursor.next(node).unwrap() {
+                Some((path, c, attr_id)) => {
+                    assert_eq!(path.cluster, Some(c.base().id));
+                    assert_eq!(path.leaf, Some(attr_id as u32));
+                    paths.push(path);
+                }
+                None => break,
+            }
+        }
+        paths
+    }
+
+    #[test]
+    fn test_cursor_resume() {
+        let node = test_node();
+        let wildcard = GenericPath::new(None, None, None);
+        let mut expected = Vec::new();
+        node.for_each_attribute(&wildcard, |path, _| {
+            expected.push(*path);
             Ok(())
         })
+        .unwrap();
+        assert!(expected.len() > 5);
+
+        // The cursor is stored after the first pass, and resumed for the second one
+        let mut cursor = AttrTraversalCursor::new(&wildcard);
+        let mut visited = advance(&mut cursor, &node, 5);
+        assert_eq!(visited.len(), 5);
+        let mut stored = cursor;
+        visited.extend(advance(&mut stored, &node, usize::MAX));
+        assert_eq!(visited, expected);
+        assert!(stored.next(&node).unwrap().is_none());
+
+        // Every attribute is visited exactly once
+        let mut deduped = visited.clone();
+        deduped.sort_by_key(|p| (p.endpoint, p.cluster, p.leaf));
+        deduped.dedup();
+        assert_eq!(deduped.len(), visited.len());
+    }
+
+    #[test]
+    fn test_cursor_partial_wildcard() {
+        let node = test_node();
+        // Only endpoint 1 has the Level Control cluster, endpoint 0 is skipped
+        let path = GenericPath::new(None, Some(cluster_level_control::ID), Some(0));
+        let mut cursor = AttrTraversalCursor::new(&path);
+        assert_eq!(
+            advance(&mut cursor, &node, usize::MAX),
+            &[GenericPath::new(
+                Some(1),
+                Some(cluster_level_control::ID),
+                Some(0)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_with_cluster_detached_panic() {
+        let cluster_ids = |node: &Node| -> Vec<u32> {
+            let (clusters, _) = node
+                .get_endpoint(1)
+                .unwrap()
+                .get_wildcard_clusters(None)
+                .unwrap();
+            clusters.iter().map(|c| c.base().id).collect()
+        };
+        let mut node = test_node();
+        let expected = cluster_ids(&node);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            node.with_cluster_detached(1, cluster_on_off::ID, |_, _| panic!("cluster handler"))
+        }));
+        assert!(result.is_err());
+        // The cluster is back on its endpoint, in the same place
+        assert_eq!(cluster_ids(&node), expected);
+    }
+
+    #[test]
+    fn test_cursor_concrete_missing() {
+        let node = test_node();
+        let path = GenericPath::new(Some(2), Some(cluster_on_off::ID), Some(0));
+        let mut cursor = AttrTraversalCursor::new(&path);
+        assert_eq!(
+            cursor.next(&node).err(),
+            Some(IMStatusCode::UnsupportedEndpoint)
+        );
+        // The status completes the traversal
+        assert!(cursor.next(&node).unwrap().is_none());
+
+        let path = GenericPath::new(Some(0), Some(cluster_on_off::ID), Some(0xFFF0));
+        let mut cursor = AttrTraversalCursor::new(&path);
+        assert_eq!(
+            cursor.next(&node).err(),
+            Some(IMStatusCode::UnsupportedAttribute)
+        );
     }
 }
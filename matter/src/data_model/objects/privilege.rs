@@ -48,9 +48,9 @@ impl ToTLV for Privilege {
     ) -> Result<(), Error> {
         let val = if self.contains(Privilege::ADMIN) {
             5
-        } else if self.contains(Privilege::OPERATE) {
-            4
         } else if self.contains(Privilege::MANAGE) {
+            4
+        } else if self.contains(Privilege::OPERATE) {
             3
         } else if self.contains(Privilege::VIEW) {
             1
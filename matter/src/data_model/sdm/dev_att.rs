@@ -1,4 +1,7 @@
-use crate::error::Error;
+use crate::{
+    crypto::{self, CryptoKeyPair, KeyPair},
+    error::Error,
+};
 
 /// Device Attestation Data Type
 pub enum DataType {
@@ -26,3 +29,107 @@ pub trait DevAttDataFetcher {
     /// The type of data that can be queried is defined in the [DataType] enum.
     fn get_devatt_data(&self, data_type: DataType, data: &mut [u8]) -> Result<usize, Error>;
 }
+
+/// A Device Attestation Data Fetcher with the attestation data held in memory
+///
+/// This is useful for tests and for simple deployments, where the data isn't
+/// kept in a secure element.
+pub struct HardCodedDevAtt {
+    /// Device Attestation Certificate
+    pub dac: Vec<u8>,
+    /// Product Attestation Intermediary Certificate
+    pub pai: Vec<u8>,
+    /// Certificate Declaration
+    pub cd: Vec<u8>,
+    /// The key pair of the Device Attestation Certificate
+    pub dac_key: KeyPair,
+}
+
+impl HardCodedDevAtt {
+    /// Sign the attestation elements followed by the attestation challenge, with
+    /// the DAC private key
+    ///
+    /// This is the AttestationSignature of an AttestationResponse.
+    pub fn sign_attestation(
+        &self,
+        attest_elements: &[u8],
+        attest_challenge: &[u8],
+        signature: &mut [u8],
+    ) -> Result<usize, Error> {
+        let tbs = [attest_elements, attest_challenge].concat();
+        self.dac_key.sign_msg(&tbs, signature)
+    }
+}
+
+/// Verify the signature of an AttestationResponse, or a CSRResponse, with the public key
+/// of the DAC
+///
+/// The signature covers the elements followed by the attestation challenge of the session
+/// that the response came over, see Session::attestation_challenge().
+pub fn verify_attestation(
+    dac_pubkey: &[u8],
+    attest_elements: &[u8],
+    attest_challenge: &[u8; crypto::SYMM_KEY_LEN_BYTES],
+    signature: &[u8],
+) -> Result<(), Error> {
+    let tbs = [attest_elements, attest_challenge].concat();
+    KeyPair::new_from_public(dac_pubkey)?.verify_msg(&tbs, signature)
+}
+
+impl DevAttDataFetcher for HardCodedDevAtt {
+    fn get_devatt_data(&self, data_type: DataType, data: &mut [u8]) -> Result<usize, Error> {
+        let src = match data_type {
+            DataType::CertDeclaration => &self.cd,
+            DataType::PAI => &self.pai,
+            DataType::DAC => &self.dac,
+            DataType::DACPubKey => return self.dac_key.get_public_key(data),
+            DataType::DACPrivKey => return self.dac_key.get_private_key(data),
+        };
+        if src.len() <= data.len() {
+            data[..src.len()].copy_from_slice(src);
+            Ok(src.len())
+        } else {
+            Err(Error::NoSpace)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_attestation, DataType, DevAttDataFetcher, HardCodedDevAtt};
+    use crate::crypto::{self, CryptoKeyPair, KeyPair};
+
+    #[test]
+    fn test_hardcoded_attestation_signature() {
+        let dev_att = HardCodedDevAtt {
+            dac: vec![1, 2, 3],
+            pai: vec![4, 5],
+            cd: vec![6],
+            dac_key: KeyPair::new().unwrap(),
+        };
+        let mut buf = [0; 10];
+        let len = dev_att.get_devatt_data(DataType::DAC, &mut buf).unwrap();
+        assert_eq!(&buf[..len], &[1, 2, 3]);
+        let mut buf = [0; 2];
+        let len = dev_att.get_devatt_data(DataType::PAI, &mut buf).unwrap();
+        assert_eq!(&buf[..len], &[4, 5]);
+
+        let attest_elements = [0x15, 0x30, 0x01, 0x01, 0xaa, 0x18];
+        let attest_challenge = [0x5a; 16];
+        let mut signature = [0; crypto::EC_SIGNATURE_LEN_BYTES];
+        dev_att
+            .sign_attestation(&attest_elements, &attest_challenge, &mut signature)
+            .unwrap();
+
+        // The signature verifies with the DAC public key, as fetched by the stack
+        let mut pubkey = [0; crypto::EC_POINT_LEN_BYTES];
+        dev_att
+            .get_devatt_data(DataType::DACPubKey, &mut pubkey)
+            .unwrap();
+        assert!(
+            verify_attestation(&pubkey, &attest_elements, &attest_challenge, &signature).is_ok()
+        );
+        let verifier = KeyPair::new_from_public(&pubkey).unwrap();
+        assert!(verifier.verify_msg(&attest_elements, &signature).is_err());
+    }
+}
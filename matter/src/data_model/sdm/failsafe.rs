@@ -1,6 +1,9 @@
-use crate::{error::Error, transport::session::SessionMode};
+use crate::{error::Error, transport::session::SessionMode, utils::tick::Tick};
 use log::error;
-use std::sync::RwLock;
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
 
 #[derive(PartialEq)]
 #[allow(dead_code)]
@@ -14,10 +17,24 @@ enum NocState {
 #[derive(PartialEq)]
 pub struct ArmedCtx {
     session_mode: SessionMode,
-    timeout: u8,
+    timeout: u16,
+    armed_at: SystemTime,
     noc_state: NocState,
 }
 
+impl ArmedCtx {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        match now.duration_since(self.armed_at) {
+            Ok(elapsed) => elapsed >= Duration::from_secs(self.timeout as u64),
+            Err(_) => false,
+        }
+    }
+
+    fn expires_at(&self) -> SystemTime {
+        self.armed_at + Duration::from_secs(self.timeout as u64)
+    }
+}
+
 #[derive(PartialEq)]
 pub enum State {
     Idle,
@@ -28,6 +45,19 @@ pub struct FailSafeInner {
     state: State,
 }
 
+impl FailSafeInner {
+    // The expiry is noticed on the next access to the Fail-Safe, or on its next tick
+    // TODO: The Add/Update NOC isn't rolled back on expiry yet
+    fn expire(&mut self, now: SystemTime) {
+        if let State::Armed(c) = &self.state {
+            if c.is_expired(now) {
+                error!("Fail-Safe expired");
+                self.state = State::Idle;
+            }
+        }
+    }
+}
+
 pub struct FailSafe {
     state: RwLock<FailSafeInner>,
 }
@@ -39,13 +69,26 @@ impl FailSafe {
         }
     }
 
-    pub fn arm(&self, timeout: u8, session_mode: SessionMode) -> Result<(), Error> {
+    /// Arm the Fail-Safe for 'timeout' seconds
+    ///
+    /// A 'timeout' of 0 expires the Fail-Safe right away, if it is armed by 'session_mode'.
+    pub fn arm(&self, timeout: u16, session_mode: SessionMode) -> Result<(), Error> {
+        let now = SystemTime::now();
         let mut inner = self.state.write()?;
+        inner.expire(now);
         match &mut inner.state {
+            State::Idle if timeout == 0 => (),
+            State::Armed(c) if timeout == 0 => {
+                if c.session_mode != session_mode {
+                    return Err(Error::Invalid);
+                }
+                inner.state = State::Idle;
+            }
             State::Idle => {
                 inner.state = State::Armed(ArmedCtx {
                     session_mode,
                     timeout,
+                    armed_at: now,
                     noc_state: NocState::NocNotRecvd,
                 })
             }
@@ -55,13 +98,20 @@ impl FailSafe {
                 }
                 // re-arm
                 c.timeout = timeout;
+                c.armed_at = now;
             }
         }
         Ok(())
     }
 
+    /// Disarm the Fail-Safe on the completion of commissioning
+    ///
+    /// This fails if the Fail-Safe has expired, or if the Add/Update NOC wasn't received for
+    /// the fabric of 'session_mode'. The check and the disarm are done under the same lock,
+    /// so the Fail-Safe can't expire in between.
     pub fn disarm(&self, session_mode: SessionMode) -> Result<(), Error> {
         let mut inner = self.state.write()?;
+        inner.expire(SystemTime::now());
         match &mut inner.state {
             State::Idle => {
                 error!("Received Fail-Safe Disarm without it being armed");
@@ -86,11 +136,19 @@ impl FailSafe {
     }
 
     pub fn is_armed(&self) -> bool {
-        self.state.read().unwrap().state != State::Idle
+        // A poisoned lock is taken as not armed, which turns the commissioning changes down
+        self.state
+            .write()
+            .map(|mut s| {
+                s.expire(SystemTime::now());
+                s.state != State::Idle
+            })
+            .unwrap_or(false)
     }
 
     pub fn record_add_noc(&self, fabric_index: u8) -> Result<(), Error> {
         let mut inner = self.state.write()?;
+        inner.expire(SystemTime::now());
         match &mut inner.state {
             State::Idle => Err(Error::Invalid),
             State::Armed(c) => {
@@ -106,6 +164,7 @@ impl FailSafe {
 
     pub fn allow_noc_change(&self) -> Result<bool, Error> {
         let mut inner = self.state.write()?;
+        inner.expire(SystemTime::now());
         let allow = match &mut inner.state {
             State::Idle => false,
             State::Armed(c) => c.noc_state == NocState::NocNotRecvd,
@@ -119,3 +178,16 @@ impl Default for FailSafe {
         Self::new()
     }
 }
+
+impl Tick for Arc<FailSafe> {
+    /// Disarm the Fail-Safe once it expires, instead of on its next access
+    fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+        // A poisoned lock has nothing to wait for
+        let mut inner = self.state.write().ok()?;
+        inner.expire(now);
+        match &inner.state {
+            State::Idle => None,
+            State::Armed(c) => Some(c.expires_at()),
+        }
+    }
+}
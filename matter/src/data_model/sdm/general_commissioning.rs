@@ -2,11 +2,14 @@ use crate::cmd_enter;
 use crate::data_model::objects::*;
 use crate::data_model::sdm::failsafe::FailSafe;
 use crate::interaction_model::core::IMStatusCode;
-use crate::interaction_model::messages::ib;
-use crate::tlv::{FromTLV, TLVElement, TLVWriter, TagType, ToTLV};
-use crate::{error::*, interaction_model::command::CommandReq};
+use crate::tlv::{FromTLV, OctetStr, TLVElement, TLVWriter, TagType, TaggedElement, ToTLV};
+use crate::{
+    error::*,
+    interaction_model::command::{CommandReq, CommandStatus},
+};
 use log::{error, info};
 use num_derive::FromPrimitive;
+use std::convert::TryFrom;
 use std::sync::Arc;
 
 #[derive(Clone, Copy)]
@@ -20,6 +23,18 @@ enum CommissioningError {
 
 pub const ID: u32 = 0x0030;
 
+/// The maximum time in seconds for which the Fail-Safe can be armed
+// TODO: Arch-Specific
+pub const MAX_FAILSAFE_EXPIRY_SECS: u16 = 120;
+
+/// The default of the maximum cumulative time in seconds for which the Fail-Safe can be
+/// armed, across all of its extensions
+pub const MAX_CUMULATIVE_FAILSAFE_SECS: u16 = 900;
+
+/// The delay in milliseconds after which an ArmFailSafe that found the Fail-Safe busy may
+/// be retried
+pub const FAILSAFE_BUSY_RETRY_DELAY_MS: u16 = 1000;
+
 #[derive(FromPrimitive)]
 pub enum Attributes {
     BreadCrumb = 0,
@@ -38,12 +53,33 @@ pub enum Commands {
     CommissioningCompleteResp = 0x05,
 }
 
+impl Commands {
+    // The context tags of the fields that the command must carry
+    fn mandatory_fields(&self) -> &'static [u8] {
+        match self {
+            // ExpiryLengthSeconds, Breadcrumb
+            Commands::ArmFailsafe => &[0, 1],
+            // NewRegulatoryConfig, CountryCode, Breadcrumb
+            Commands::SetRegulatoryConfig => &[0, 1, 2],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Debug)]
 pub enum RegLocationType {
     Indoor = 0,
     Outdoor = 1,
     IndoorOutdoor = 2,
 }
 
+impl RegLocationType {
+    /// Whether a device with this location capability can be configured as 'config'
+    pub fn allows(self, config: RegLocationType) -> bool {
+        self == RegLocationType::IndoorOutdoor || self == config
+    }
+}
+
 fn attr_bread_crumb_new(bread_crumb: u64) -> Result<Attribute, Error> {
     Attribute::new(
         Attributes::BreadCrumb as u16,
@@ -80,14 +116,28 @@ fn attr_comm_info_new() -> Result<Attribute, Error> {
     )
 }
 
-#[derive(FromTLV, ToTLV)]
+#[derive(ToTLV)]
 struct FailSafeParams {
-    expiry_len: u8,
-    bread_crumb: u8,
+    expiry_len: u16,
+    bread_crumb: u64,
+}
+
+impl<'a> FromTLV<'a> for FailSafeParams {
+    // The controllers may encode these in any integer width
+    fn from_tlv(t: &TLVElement<'a>) -> Result<Self, Error> {
+        let t = t.confirm_struct()?;
+        let expiry_len = t.find_tag(0)?.get_u64_any()?;
+        Ok(Self {
+            expiry_len: u16::try_from(expiry_len).map_err(|_| Error::InvalidData)?,
+            bread_crumb: t.find_tag(1)?.get_u64_any()?,
+        })
+    }
 }
 
 pub struct GenCommCluster {
     expiry_len: u16,
+    max_cumulative_failsafe: u16,
+    location_capability: RegLocationType,
     failsafe: Arc<FailSafe>,
     base: Cluster,
 }
@@ -100,12 +150,13 @@ impl ClusterType for GenCommCluster {
         &mut self.base
     }
 
-    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr_id: u16) {
-        match num::FromPrimitive::from_u16(attr_id) {
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
             Some(Attributes::BasicCommissioningInfo) => {
                 encoder.encode(EncodeValue::Closure(&|tag, tw| {
                     let _ = tw.start_struct(tag);
                     let _ = tw.u16(TagType::Context(0), self.expiry_len);
+                    let _ = tw.u16(TagType::Context(1), self.max_cumulative_failsafe);
                     let _ = tw.end_container();
                 }))
             }
@@ -115,46 +166,67 @@ impl ClusterType for GenCommCluster {
         }
     }
 
-    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
-        let cmd = cmd_req
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
+        let cmd: Commands = cmd_req
             .cmd
             .path
             .leaf
             .map(num::FromPrimitive::from_u32)
             .ok_or(IMStatusCode::UnsupportedCommand)?
             .ok_or(IMStatusCode::UnsupportedCommand)?;
+        cmd_req.check_fields(cmd.mandatory_fields())?;
         match cmd {
-            Commands::ArmFailsafe => self.handle_command_armfailsafe(cmd_req),
-            Commands::SetRegulatoryConfig => self.handle_command_setregulatoryconfig(cmd_req),
-            Commands::CommissioningComplete => self.handle_command_commissioningcomplete(cmd_req),
-            _ => Err(IMStatusCode::UnsupportedCommand),
+            Commands::ArmFailsafe => self.handle_command_armfailsafe(cmd_req)?,
+            Commands::SetRegulatoryConfig => self.handle_command_setregulatoryconfig(cmd_req)?,
+            Commands::CommissioningComplete => {
+                self.handle_command_commissioningcomplete(cmd_req)?
+            }
+            _ => return Err(IMStatusCode::UnsupportedCommand),
         }
+        Ok(CommandStatus::Complete)
     }
 }
 
 impl GenCommCluster {
-    pub fn new() -> Result<Box<Self>, Error> {
-        let failsafe = Arc::new(FailSafe::new());
+    /// Create the cluster for a device that can be located as per 'location_capability'
+    ///
+    /// The regulatory config starts out as the location capability.
+    pub fn new(location_capability: RegLocationType) -> Result<Box<Self>, Error> {
+        Self::new_with_failsafe(location_capability, Arc::new(FailSafe::new()))
+    }
 
+    /// Create the cluster, arming and disarming 'failsafe' on the commands
+    pub fn new_with_failsafe(
+        location_capability: RegLocationType,
+        failsafe: Arc<FailSafe>,
+    ) -> Result<Box<Self>, Error> {
         let mut c = Box::new(GenCommCluster {
-            // TODO: Arch-Specific
-            expiry_len: 120,
+            expiry_len: MAX_FAILSAFE_EXPIRY_SECS,
+            max_cumulative_failsafe: MAX_CUMULATIVE_FAILSAFE_SECS,
+            location_capability,
             failsafe,
             base: Cluster::new(ID)?,
         });
         c.base.add_attribute(attr_bread_crumb_new(0)?)?;
-        // TODO: Arch-Specific
         c.base
-            .add_attribute(attr_reg_config_new(RegLocationType::IndoorOutdoor)?)?;
-        // TODO: Arch-Specific
-        c.base.add_attribute(attr_location_capability_new(
-            RegLocationType::IndoorOutdoor,
-        )?)?;
+            .add_attribute(attr_reg_config_new(location_capability)?)?;
+        c.base
+            .add_attribute(attr_location_capability_new(location_capability)?)?;
         c.base.add_attribute(attr_comm_info_new()?)?;
+        c.base.set_generated_commands(&[
+            Commands::ArmFailsafeResp as u16,
+            Commands::SetRegulatoryConfigResp as u16,
+            Commands::CommissioningCompleteResp as u16,
+        ])?;
 
         Ok(c)
     }
 
+    /// Set the MaxCumulativeFailsafeSeconds that the BasicCommissioningInfo reports
+    pub fn set_max_cumulative_failsafe(&mut self, secs: u16) {
+        self.max_cumulative_failsafe = secs;
+    }
+
     pub fn failsafe(&self) -> Arc<FailSafe> {
         self.failsafe.clone()
     }
@@ -162,27 +234,39 @@ impl GenCommCluster {
     fn handle_command_armfailsafe(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
         cmd_enter!("ARM Fail Safe");
 
-        let p = FailSafeParams::from_tlv(&cmd_req.data)?;
+        let p =
+            FailSafeParams::from_tlv(&cmd_req.data).map_err(|_| IMStatusCode::InvalidCommand)?;
 
-        if self
+        // The Fail-Safe is armed for exactly the time that was asked for, which can't be more
+        // than the maximum of the BasicCommissioningInfo
+        let (status, debug_txt) = if p.expiry_len > self.expiry_len {
+            error!(
+                "Fail-Safe expiry of {}s exceeds the maximum of {}s",
+                p.expiry_len, self.expiry_len
+            );
+            (CommissioningError::ErrValueOutsideRange, "Expiry too long")
+        } else if self
             .failsafe
             .arm(p.expiry_len, cmd_req.trans.session.get_session_mode())
             .is_err()
         {
-            return Err(IMStatusCode::Busy);
-        }
+            // Armed by another session, the peer can retry once that is done
+            let _ = cmd_req.busy(FAILSAFE_BUSY_RETRY_DELAY_MS);
+            cmd_req.trans.complete();
+            return Ok(());
+        } else {
+            (CommissioningError::Ok, "")
+        };
 
         let cmd_data = CommonResponse {
-            error_code: CommissioningError::Ok as u8,
-            debug_txt: "".to_owned(),
+            error_code: status as u8,
+            debug_txt: debug_txt.to_owned(),
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::ArmFailsafeResp as u16,
             EncodeValue::Value(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -192,25 +276,58 @@ impl GenCommCluster {
         cmd_req: &mut CommandReq,
     ) -> Result<(), IMStatusCode> {
         cmd_enter!("Set Regulatory Config");
-        let country_code = cmd_req
+        let mut reg_config = None;
+        let mut country_code = None;
+        let invalid = |t: &TaggedElement, e: Error| {
+            error!("Invalid field {:?} of Set Regulatory Config: {}", t.tag, e);
+            IMStatusCode::InvalidCommand
+        };
+        let iter = cmd_req
             .data
-            .find_tag(1)
-            .map_err(|_| IMStatusCode::InvalidCommand)?
-            .slice()
-            .map_err(|_| IMStatusCode::InvalidCommand)?;
-        info!("Received country code: {:?}", country_code);
+            .iter_tagged()
+            .ok_or(IMStatusCode::InvalidCommand)?;
+        for t in iter {
+            match t.ctx_tag() {
+                Some(0) => reg_config = Some(t.element.get_u64_any().map_err(|e| invalid(&t, e))?),
+                Some(1) => country_code = Some(t.value::<OctetStr>().map_err(|e| invalid(&t, e))?),
+                _ => (),
+            }
+        }
+        let reg_config = reg_config.ok_or(IMStatusCode::InvalidCommand)?;
+        let reg_config: RegLocationType =
+            num::FromPrimitive::from_u64(reg_config).ok_or(IMStatusCode::ConstraintError)?;
+        let country_code = country_code.ok_or(IMStatusCode::InvalidCommand)?.0;
+        info!(
+            "Received regulatory config: {:?}, country code: {:?}",
+            reg_config, country_code
+        );
+
+        // The device can't be configured for a location that it isn't capable of
+        let status = if self.location_capability.allows(reg_config) {
+            self.base
+                .write_attribute_raw(
+                    Attributes::RegConfig as u16,
+                    AttrValue::Uint8(reg_config as u8),
+                )
+                .map_err(|_| IMStatusCode::Failure)?;
+            CommissioningError::Ok
+        } else {
+            error!(
+                "Regulatory config {:?} exceeds the location capability {:?}",
+                reg_config, self.location_capability
+            );
+            CommissioningError::ErrValueOutsideRange
+        };
 
         let cmd_data = CommonResponse {
-            error_code: 0,
+            error_code: status as u8,
             debug_txt: "".to_owned(),
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::SetRegulatoryConfigResp as u16,
             EncodeValue::Value(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -222,13 +339,10 @@ impl GenCommCluster {
         cmd_enter!("Commissioning Complete");
         let mut status: u8 = CommissioningError::Ok as u8;
 
-        // Has to be a Case Session
-        if cmd_req.trans.session.get_local_fabric_idx().is_none() {
-            status = CommissioningError::ErrInvalidAuth as u8;
-        }
-
-        // AddNOC or UpdateNOC must have happened, and that too for the same fabric
-        // scope that is for this session
+        // Has to be a Case Session, and AddNOC or UpdateNOC must have happened, and that
+        // too for the same fabric scope that is for this session. The Fail-Safe checks all
+        // of this together with its expiry, so a Fail-Safe that expired after the AddNOC
+        // isn't mistaken for an armed one.
         if self
             .failsafe
             .disarm(cmd_req.trans.session.get_session_mode())
@@ -241,13 +355,11 @@ impl GenCommCluster {
             error_code: status,
             debug_txt: "".to_owned(),
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::CommissioningCompleteResp as u16,
             EncodeValue::Value(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -3,3 +3,5 @@ pub mod failsafe;
 pub mod general_commissioning;
 pub mod noc;
 pub mod nw_commissioning;
+pub mod ota_requestor;
+pub mod session_diagnostics;
@@ -2,15 +2,14 @@ use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::acl::{AclEntry, AclMgr, AuthMode};
-use crate::cert::Cert;
+use crate::cert::{self, Cert};
 use crate::crypto::{self, CryptoKeyPair, KeyPair};
 use crate::data_model::objects::*;
 use crate::data_model::sdm::dev_att;
-use crate::fabric::{Fabric, FabricMgr};
-use crate::interaction_model::command::CommandReq;
+use crate::fabric::{Fabric, FabricMgr, MAX_FABRIC_LABEL_LEN};
+use crate::interaction_model::command::{CommandReq, CommandStatus};
 use crate::interaction_model::core::IMStatusCode;
-use crate::interaction_model::messages::ib;
-use crate::tlv::{FromTLV, OctetStr, TLVElement, TLVWriter, TagType, ToTLV};
+use crate::tlv::{FabricScoped, FromTLV, OctetStr, TLVElement, TLVWriter, TagType, ToTLV, UtfStr};
 use crate::transport::session::SessionMode;
 use crate::utils::writebuf::WriteBuf;
 use crate::{cmd_enter, error::*};
@@ -58,9 +57,15 @@ pub enum Commands {
     CSRResp = 0x05,
     AddNOC = 0x06,
     NOCResp = 0x08,
+    UpdateFabricLabel = 0x09,
     AddTrustedRootCert = 0x0b,
 }
 
+#[derive(FromPrimitive)]
+pub enum Attributes {
+    Fabrics = 0x01,
+}
+
 pub struct NocCluster {
     base: Cluster,
     dev_att: Box<dyn DevAttDataFetcher>,
@@ -89,13 +94,21 @@ impl NocCluster {
         acl_mgr: Arc<AclMgr>,
         failsafe: Arc<FailSafe>,
     ) -> Result<Box<Self>, Error> {
-        Ok(Box::new(Self {
+        let mut c = Box::new(Self {
             dev_att,
             fabric_mgr,
             acl_mgr,
             failsafe,
             base: Cluster::new(ID)?,
-        }))
+        });
+        c.base.set_generated_commands(&[
+            Commands::AttReqResp as u16,
+            Commands::CertChainResp as u16,
+            Commands::CSRResp as u16,
+            Commands::NOCResp as u16,
+        ])?;
+        c.base.add_attribute(attr_fabrics_new()?)?;
+        Ok(c)
     }
 
     fn add_acl(&self, fab_idx: u8, admin_subject: u64) -> Result<(), Error> {
@@ -124,8 +137,17 @@ impl NocCluster {
 
         let noc_value = Cert::new(r.noc_value.0).map_err(|_| NocStatus::InvalidNOC)?;
         info!("Received NOC as: {}", noc_value);
-        let icac_value = Cert::new(r.icac_value.0).map_err(|_| NocStatus::InvalidNOC)?;
-        info!("Received ICAC as: {}", icac_value);
+        let icac_value = match r.icac_value {
+            Some(icac) => {
+                let icac = Cert::new(icac.0).map_err(|_| NocStatus::InvalidNOC)?;
+                info!("Received ICAC as: {}", icac);
+                Some(icac)
+            }
+            None => {
+                info!("Received no ICAC, the NOC is issued by the Root CA");
+                None
+            }
+        };
 
         let fabric = Fabric::new(
             noc_data.key_pair,
@@ -133,6 +155,7 @@ impl NocCluster {
             icac_value,
             noc_value,
             r.ipk_value.0,
+            r.vendor_id,
         )
         .map_err(|_| NocStatus::TableFull)?;
         let fab_idx = self
@@ -153,13 +176,11 @@ impl NocCluster {
             fab_idx,
             debug_txt: "".to_owned(),
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::NOCResp as u16,
             EncodeValue::Value(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -172,26 +193,77 @@ impl NocCluster {
                 fab_idx: 0,
                 debug_txt: "".to_owned(),
             };
-            let invoke_resp = ib::InvResp::cmd_new(
-                0,
-                ID,
+            let _ = cmd_req.respond(
+                &self.base,
                 Commands::NOCResp as u16,
                 EncodeValue::Value(&cmd_data),
             );
-            let _ = invoke_resp.to_tlv(cmd_req.resp, TagType::Anonymous);
             cmd_req.trans.complete();
         }
         Ok(())
     }
 
+    fn _handle_command_updatefabriclabel(
+        &mut self,
+        cmd_req: &mut CommandReq,
+        label: &str,
+    ) -> Result<u8, NocStatus> {
+        // The label is set on the accessor's fabric, there isn't one over PASE
+        let fab_idx = cmd_req
+            .trans
+            .session
+            .get_local_fabric_idx()
+            .ok_or(NocStatus::InvalidFabricIndex)?;
+        self.fabric_mgr
+            .set_label(fab_idx as usize, label)
+            .map_err(|e| match e {
+                Error::Invalid => NocStatus::LabelConflict,
+                _ => NocStatus::InvalidFabricIndex,
+            })?;
+        Ok(fab_idx)
+    }
+
+    fn handle_command_updatefabriclabel(
+        &mut self,
+        cmd_req: &mut CommandReq,
+    ) -> Result<(), IMStatusCode> {
+        cmd_enter!("UpdateFabricLabel");
+        let req = UpdateFabricLabelReq::from_tlv(&cmd_req.data)
+            .map_err(|_| IMStatusCode::InvalidCommand)?;
+        let label = std::str::from_utf8(req.label.0).map_err(|_| IMStatusCode::InvalidCommand)?;
+        if label.len() > MAX_FABRIC_LABEL_LEN {
+            return Err(IMStatusCode::ConstraintError);
+        }
+
+        let (status, fab_idx) = match self._handle_command_updatefabriclabel(cmd_req, label) {
+            Ok(fab_idx) => {
+                // The label is reported in the Fabrics attribute
+                self.base.bump_dataver();
+                (NocStatus::Ok, fab_idx)
+            }
+            Err(e) => (e, 0),
+        };
+        let cmd_data = NocResp {
+            status_code: status as u8,
+            fab_idx,
+            debug_txt: "".to_owned(),
+        };
+        let _ = cmd_req.respond(
+            &self.base,
+            Commands::NOCResp as u16,
+            EncodeValue::Value(&cmd_data),
+        );
+        cmd_req.trans.complete();
+        Ok(())
+    }
+
     fn handle_command_attrequest(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
         cmd_enter!("AttestationRequest");
 
         let req = CommonReq::from_tlv(&cmd_req.data).map_err(|_| IMStatusCode::InvalidCommand)?;
         info!("Received Attestation Nonce:{:?}", req.str);
 
-        let mut attest_challenge = [0u8; crypto::SYMM_KEY_LEN_BYTES];
-        attest_challenge.copy_from_slice(cmd_req.trans.session.get_att_challenge());
+        let attest_challenge = *cmd_req.trans.session.attestation_challenge();
 
         let cmd_data = |tag: TagType, t: &mut TLVWriter| {
             let mut buf: [u8; RESP_MAX] = [0; RESP_MAX];
@@ -207,13 +279,11 @@ impl NocCluster {
             );
             let _ = t.end_container();
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::AttReqResp as u16,
             EncodeValue::Closure(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -238,13 +308,11 @@ impl NocCluster {
         let cmd_data = CertChainResp {
             cert: OctetStr::new(buf),
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::CertChainResp as u16,
             EncodeValue::Value(&cmd_data),
         );
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         cmd_req.trans.complete();
         Ok(())
     }
@@ -260,8 +328,7 @@ impl NocCluster {
         }
 
         let noc_keypair = KeyPair::new().map_err(|_| IMStatusCode::Failure)?;
-        let mut attest_challenge = [0u8; crypto::SYMM_KEY_LEN_BYTES];
-        attest_challenge.copy_from_slice(cmd_req.trans.session.get_att_challenge());
+        let attest_challenge = *cmd_req.trans.session.attestation_challenge();
 
         let cmd_data = |tag: TagType, t: &mut TLVWriter| {
             let mut buf: [u8; RESP_MAX] = [0; RESP_MAX];
@@ -276,14 +343,11 @@ impl NocCluster {
             );
             let _ = t.end_container();
         };
-        let resp = ib::InvResp::cmd_new(
-            0,
-            ID,
+        let _ = cmd_req.respond(
+            &self.base,
             Commands::CSRResp as u16,
             EncodeValue::Closure(&cmd_data),
         );
-
-        let _ = resp.to_tlv(cmd_req.resp, TagType::Anonymous);
         let noc_data = Box::new(NocData::new(noc_keypair));
         // Store this in the session data instead of cluster data, so it gets cleared
         // if the session goes away for some reason
@@ -321,7 +385,7 @@ impl NocCluster {
         }
         cmd_req.trans.complete();
 
-        Err(IMStatusCode::Sucess)
+        Err(IMStatusCode::Success)
     }
 }
 
@@ -333,7 +397,32 @@ impl ClusterType for NocCluster {
         &mut self.base
     }
 
-    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
+            Some(Attributes::Fabrics) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
+                let _ = tw.start_array(tag);
+                let _ = self.fabric_mgr.for_each(|fab_idx, fabric| {
+                    let entry = FabricDescriptor {
+                        root_public_key: OctetStr(fabric.root_ca.get_pubkey()),
+                        vendor_id: fabric.get_vendor_id(),
+                        fabric_id: fabric.get_fabric_id(),
+                        node_id: fabric.get_node_id(),
+                        label: UtfStr(fabric.get_label().as_bytes()),
+                        fab_idx,
+                    };
+                    if attr.fab_filter_match(&entry) {
+                        let _ = entry.to_tlv(tw, TagType::Anonymous);
+                    }
+                });
+                let _ = tw.end_container();
+            })),
+            _ => {
+                error!("Attribute not yet supported: this shouldn't happen");
+            }
+        }
+    }
+
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
         let cmd = cmd_req
             .cmd
             .path
@@ -342,16 +431,27 @@ impl ClusterType for NocCluster {
             .ok_or(IMStatusCode::UnsupportedCommand)?
             .ok_or(IMStatusCode::UnsupportedCommand)?;
         match cmd {
-            Commands::AddNOC => self.handle_command_addnoc(cmd_req),
-            Commands::CSRReq => self.handle_command_csrrequest(cmd_req),
-            Commands::AddTrustedRootCert => self.handle_command_addtrustedrootcert(cmd_req),
-            Commands::AttReq => self.handle_command_attrequest(cmd_req),
-            Commands::CertChainReq => self.handle_command_certchainrequest(cmd_req),
-            _ => Err(IMStatusCode::UnsupportedCommand),
+            Commands::AddNOC => self.handle_command_addnoc(cmd_req)?,
+            Commands::UpdateFabricLabel => self.handle_command_updatefabriclabel(cmd_req)?,
+            Commands::CSRReq => self.handle_command_csrrequest(cmd_req)?,
+            Commands::AddTrustedRootCert => self.handle_command_addtrustedrootcert(cmd_req)?,
+            Commands::AttReq => self.handle_command_attrequest(cmd_req)?,
+            Commands::CertChainReq => self.handle_command_certchainrequest(cmd_req)?,
+            _ => return Err(IMStatusCode::UnsupportedCommand),
         }
+        Ok(CommandStatus::Complete)
     }
 }
 
+fn attr_fabrics_new() -> Result<Attribute, Error> {
+    Attribute::new(
+        Attributes::Fabrics as u16,
+        AttrValue::Custom,
+        Access::RV | Access::FAB_SCOPED,
+        Quality::NONE,
+    )
+}
+
 fn add_attestation_element(
     dev_att: &dyn DevAttDataFetcher,
     att_nonce: &[u8],
@@ -362,7 +462,9 @@ fn add_attestation_element(
     let len = dev_att.get_devatt_data(dev_att::DataType::CertDeclaration, &mut cert_dec)?;
     let cert_dec = &cert_dec[0..len];
 
-    let epoch = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    // The timestamp is in seconds since the Matter epoch
+    let epoch =
+        cert::unix_to_matter_epoch(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())?;
     let mut writer = TLVWriter::new(write_buf);
     writer.start_struct(TagType::Anonymous)?;
     writer.str16(TagType::Context(1), cert_dec)?;
@@ -427,10 +529,30 @@ struct NocResp {
 #[tlvargs(lifetime = "'a")]
 struct AddNocReq<'a> {
     noc_value: OctetStr<'a>,
-    icac_value: OctetStr<'a>,
+    // Absent if the NOC is issued directly by the Root CA
+    icac_value: Option<OctetStr<'a>>,
     ipk_value: OctetStr<'a>,
     case_admin_subject: u64,
-    _vendor_id: u16,
+    vendor_id: u16,
+}
+
+#[derive(FromTLV)]
+#[tlvargs(lifetime = "'a")]
+struct UpdateFabricLabelReq<'a> {
+    label: UtfStr<'a>,
+}
+
+// An entry of the Fabrics attribute
+#[derive(ToTLV, FabricScoped)]
+#[tlvargs(lifetime = "'a", start = 1)]
+struct FabricDescriptor<'a> {
+    root_public_key: OctetStr<'a>,
+    vendor_id: u16,
+    fabric_id: u64,
+    node_id: u64,
+    label: UtfStr<'a>,
+    #[tlv(fabric_index)]
+    fab_idx: u8,
 }
 
 #[derive(FromTLV)]
@@ -0,0 +1,814 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::bdx::{
+    common::{OpCode as BdxOpCode, TransferControl},
+    transfer::{BdxSink, BdxTransfer},
+};
+use crate::data_model::objects::*;
+use crate::interaction_model::command::{CommandReq, CommandStatus};
+use crate::interaction_model::core::IMStatusCode;
+use crate::tlv::{FabricScoped, FromTLV, TLVElement, TLVWriter, TagType, ToTLV};
+use crate::utils::{parsebuf::ParseBuf, writebuf::WriteBuf};
+use crate::{cmd_enter, error::*};
+use log::{error, info};
+use num_derive::FromPrimitive;
+
+// OTA Software Update Requestor Cluster
+
+pub const ID: u32 = 0x002A;
+
+// The block size that we propose for image downloads
+const OTA_BLOCK_SIZE: u16 = 1024;
+// The ReceiveInit carries the File Designator, which is at most as long as the ImageURI
+const MAX_BDX_INIT_LEN: usize = 300;
+
+const BDX_URI_SCHEME: &str = "bdx://";
+
+#[derive(FromPrimitive)]
+pub enum Attributes {
+    DefaultOtaProviders = 0,
+    UpdatePossible = 1,
+    UpdateState = 2,
+    UpdateStateProgress = 3,
+}
+
+#[derive(FromPrimitive)]
+pub enum Commands {
+    AnnounceOtaProvider = 0x00,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateState {
+    Unknown = 0,
+    Idle = 1,
+    Querying = 2,
+    DelayedOnQuery = 3,
+    Downloading = 4,
+    Applying = 5,
+    DelayedOnApply = 6,
+    RollingBack = 7,
+    DelayedOnUserConsent = 8,
+}
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq)]
+pub enum AnnouncementReason {
+    SimpleAnnouncement = 0,
+    UpdateAvailable = 1,
+    UrgentUpdateAvailable = 2,
+}
+
+/// The download protocols, as in the OTA Software Update Provider cluster
+pub const DOWNLOAD_PROTOCOL_BDX_SYNC: u8 = 0;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq)]
+pub enum QueryImageStatus {
+    UpdateAvailable = 0,
+    Busy = 1,
+    NotAvailable = 2,
+    DownloadProtocolNotSupported = 3,
+}
+
+#[derive(FromTLV, ToTLV, FabricScoped, Debug, Clone, Copy, PartialEq)]
+#[tlvargs(start = 1)]
+pub struct ProviderLocation {
+    pub provider_node_id: u64,
+    pub endpoint: u16,
+    #[tlv(fabric_index)]
+    pub fab_idx: u8,
+}
+
+type DownloadProtocols = [u8; 1];
+
+/// The QueryImage command of the OTA Software Update Provider cluster
+#[derive(FromTLV, ToTLV, Debug, Clone, PartialEq)]
+pub struct QueryImageReq {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub sw_version: u32,
+    pub protocols_supported: DownloadProtocols,
+}
+
+/// The QueryImageResponse command of the OTA Software Update Provider cluster
+#[derive(FromTLV, ToTLV, Debug, Clone, Default, PartialEq)]
+pub struct QueryImageResp {
+    pub status: u8,
+    pub delayed_action_time: Option<u32>,
+    pub image_uri: Option<String>,
+    pub sw_version: Option<u32>,
+    pub sw_version_str: Option<String>,
+    pub update_token: Option<Vec<u8>>,
+}
+
+#[derive(FromTLV)]
+struct AnnounceOtaProviderReq {
+    provider_node_id: u64,
+    vendor_id: u16,
+    announcement_reason: u8,
+    #[tagval(4)]
+    endpoint: u16,
+}
+
+/// How the Requestor reaches the OTA Providers
+pub trait OtaProviderLink: Send {
+    /// Invoke QueryImage on the OTA Provider
+    fn query_image(
+        &mut self,
+        provider: &ProviderLocation,
+        req: &QueryImageReq,
+    ) -> Result<QueryImageResp, Error>;
+
+    /// Open a BDX exchange with the node, and send it `msg`, the ReceiveInit of the download
+    ///
+    /// The BDX messages that follow on this exchange must be passed to
+    /// [`OtaRequestor::handle_bdx`].
+    fn start_download(&mut self, node_id: u64, msg: &[u8]) -> Result<(), Error>;
+}
+
+/// The consumer of the downloaded image
+pub trait OtaImageConsumer: Send {
+    /// Consume the next chunk of the image
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// The image of `sw_version` was downloaded completely, and can be applied
+    fn apply(&mut self, sw_version: u32) -> Result<(), Error>;
+}
+
+struct ImageSink {
+    consumer: Arc<Mutex<Box<dyn OtaImageConsumer>>>,
+}
+
+impl BdxSink for ImageSink {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.consumer.lock()?.write(data)
+    }
+}
+
+// Returns the Node ID and the File Designator of a BDX ImageURI
+fn parse_bdx_uri(uri: &str) -> Result<(u64, &str), Error> {
+    let rest = uri.strip_prefix(BDX_URI_SCHEME).ok_or_else(|| {
+        error!("Unsupported ImageURI {}", uri);
+        Error::Invalid
+    })?;
+    let (node_id, file_designator) = rest.split_once('/').ok_or(Error::Invalid)?;
+    if node_id.len() != 16 || file_designator.is_empty() {
+        return Err(Error::Invalid);
+    }
+    let node_id = u64::from_str_radix(node_id, 16).map_err(|_| Error::Invalid)?;
+    Ok((node_id, file_designator))
+}
+
+struct OtaRequestorInner {
+    state: UpdateState,
+    progress: Option<u8>,
+    default_providers: Vec<ProviderLocation>,
+    announced: Option<ProviderLocation>,
+    // The software version of the image being downloaded
+    sw_version: u32,
+    transfer: Option<BdxTransfer>,
+}
+
+/// The OTA Requestor state machine
+///
+/// This queries the OTA Provider for an image, and downloads any available image over BDX,
+/// handing it to the [`OtaImageConsumer`].
+pub struct OtaRequestor {
+    vid: u16,
+    pid: u16,
+    sw_ver: u32,
+    link: Mutex<Box<dyn OtaProviderLink>>,
+    consumer: Arc<Mutex<Box<dyn OtaImageConsumer>>>,
+    inner: RwLock<OtaRequestorInner>,
+}
+
+impl OtaRequestor {
+    pub fn new(
+        vid: u16,
+        pid: u16,
+        sw_ver: u32,
+        link: Box<dyn OtaProviderLink>,
+        consumer: Box<dyn OtaImageConsumer>,
+    ) -> Self {
+        Self {
+            vid,
+            pid,
+            sw_ver,
+            link: Mutex::new(link),
+            consumer: Arc::new(Mutex::new(consumer)),
+            inner: RwLock::new(OtaRequestorInner {
+                state: UpdateState::Idle,
+                progress: None,
+                default_providers: Vec::new(),
+                announced: None,
+                sw_version: 0,
+                transfer: None,
+            }),
+        }
+    }
+
+    pub fn get_state(&self) -> Result<UpdateState, Error> {
+        Ok(self.inner.read()?.state)
+    }
+
+    pub fn get_progress(&self) -> Result<Option<u8>, Error> {
+        Ok(self.inner.read()?.progress)
+    }
+
+    /// Add the default OTA Provider for a fabric, there can be only one per fabric
+    pub fn add_default_provider(&self, provider: ProviderLocation) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
+        if inner
+            .default_providers
+            .iter()
+            .any(|p| p.fab_idx == provider.fab_idx)
+        {
+            return Err(Error::Invalid);
+        }
+        inner.default_providers.push(provider);
+        Ok(())
+    }
+
+    /// Remove the default OTA Provider of a fabric
+    pub fn remove_default_provider(&self, fab_idx: u8) -> Result<(), Error> {
+        let mut inner = self.inner.write()?;
+        let index = inner
+            .default_providers
+            .iter()
+            .position(|p| p.fab_idx == fab_idx)
+            .ok_or(Error::NotFound)?;
+        inner.default_providers.remove(index);
+        Ok(())
+    }
+
+    pub fn for_each_default_provider<T>(&self, mut f: T) -> Result<(), Error>
+    where
+        T: FnMut(&ProviderLocation),
+    {
+        let inner = self.inner.read()?;
+        for p in inner.default_providers.iter() {
+            f(p);
+        }
+        Ok(())
+    }
+
+    /// Record an OTA Provider that announced itself
+    ///
+    /// The announced provider is preferred over the default ones by the next
+    /// [`query`](OtaRequestor::query), which the application triggers.
+    pub fn announce(
+        &self,
+        provider: ProviderLocation,
+        reason: AnnouncementReason,
+    ) -> Result<(), Error> {
+        info!("OTA Provider {:?} announced: {:?}", provider, reason);
+        self.inner.write()?.announced = Some(provider);
+        Ok(())
+    }
+
+    /// Query the OTA Provider for a new image, and start downloading it if one is available
+    ///
+    /// The state isn't locked while the provider is being talked to, the Querying state keeps
+    /// any other query out in the meantime.
+    pub fn query(&self) -> Result<(), Error> {
+        let provider = {
+            let mut inner = self.inner.write()?;
+            if inner.state != UpdateState::Idle && inner.state != UpdateState::DelayedOnQuery {
+                return Err(Error::InvalidState);
+            }
+            let provider = inner
+                .announced
+                .or_else(|| inner.default_providers.first().copied())
+                .ok_or_else(|| {
+                    error!("No OTA Provider to query");
+                    Error::NotFound
+                })?;
+            inner.state = UpdateState::Querying;
+            provider
+        };
+
+        let req = QueryImageReq {
+            vendor_id: self.vid,
+            product_id: self.pid,
+            sw_version: self.sw_ver,
+            protocols_supported: [DOWNLOAD_PROTOCOL_BDX_SYNC],
+        };
+        let resp = self.link.lock()?.query_image(&provider, &req);
+        let result = resp.and_then(|resp| self.handle_query_image_resp(resp));
+        if result.is_err() {
+            self.inner.write()?.state = UpdateState::Idle;
+        }
+        result
+    }
+
+    fn handle_query_image_resp(&self, resp: QueryImageResp) -> Result<(), Error> {
+        let status = num::FromPrimitive::from_u8(resp.status).ok_or(Error::Invalid)?;
+        info!("QueryImage returned {:?}", status);
+        match status {
+            QueryImageStatus::UpdateAvailable => {
+                let uri = resp.image_uri.as_ref().ok_or(Error::Invalid)?;
+                let sw_version = resp.sw_version.ok_or(Error::Invalid)?;
+                let (node_id, file_designator) = parse_bdx_uri(uri)?;
+
+                let mut transfer = BdxTransfer::new_receiver(
+                    Box::new(ImageSink {
+                        consumer: self.consumer.clone(),
+                    }),
+                    TransferControl::SENDER_DRIVE | TransferControl::RECEIVER_DRIVE,
+                    OTA_BLOCK_SIZE,
+                );
+                let mut buf = [0; MAX_BDX_INIT_LEN];
+                let buf_len = buf.len();
+                let mut wb = WriteBuf::new(&mut buf, buf_len);
+                transfer.receive_init(file_designator.as_bytes(), None, &mut wb)?;
+                self.link
+                    .lock()?
+                    .start_download(node_id, wb.as_borrow_slice())?;
+
+                let mut inner = self.inner.write()?;
+                inner.transfer = Some(transfer);
+                inner.sw_version = sw_version;
+                inner.progress = Some(0);
+                inner.state = UpdateState::Downloading;
+            }
+            QueryImageStatus::Busy => self.inner.write()?.state = UpdateState::DelayedOnQuery,
+            QueryImageStatus::NotAvailable | QueryImageStatus::DownloadProtocolNotSupported => {
+                self.inner.write()?.state = UpdateState::Idle
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a BDX message of the download
+    ///
+    /// Returns the opcode of the response that was written to `tx`, if any
+    pub fn handle_bdx(
+        &self,
+        opcode: BdxOpCode,
+        rx: &mut ParseBuf,
+        tx: &mut WriteBuf,
+    ) -> Result<Option<BdxOpCode>, Error> {
+        let mut inner = self.inner.write()?;
+        let transfer = inner.transfer.as_mut().ok_or(Error::InvalidState)?;
+        let result = transfer.handle(opcode, rx, tx);
+        let progress = transfer
+            .get_length()
+            .filter(|len| *len > 0)
+            .map(|len| (transfer.bytes_received() * 100 / len) as u8);
+        let done = transfer.is_done();
+
+        if result.is_err() {
+            error!("OTA image download failed");
+            inner.transfer = None;
+            inner.progress = None;
+            inner.state = UpdateState::Idle;
+        } else if done {
+            inner.transfer = None;
+            inner.progress = None;
+            inner.state = UpdateState::Applying;
+            self.consumer.lock()?.apply(inner.sw_version)?;
+        } else if progress.is_some() {
+            inner.progress = progress;
+        }
+        result
+    }
+}
+
+pub struct OtaRequestorCluster {
+    base: Cluster,
+    requestor: Arc<OtaRequestor>,
+}
+
+impl OtaRequestorCluster {
+    pub fn new(requestor: Arc<OtaRequestor>) -> Result<Box<Self>, Error> {
+        let mut c = Box::new(OtaRequestorCluster {
+            base: Cluster::new(ID)?,
+            requestor,
+        });
+        c.base.add_attribute(attr_default_ota_providers_new()?)?;
+        c.base.add_attribute(attr_update_possible_new()?)?;
+        c.base.add_attribute(attr_update_state_new()?)?;
+        c.base.add_attribute(attr_update_state_progress_new()?)?;
+        Ok(c)
+    }
+
+    fn write_default_providers(
+        &mut self,
+        op: ListOperation,
+        data: &TLVElement,
+        fab_idx: u8,
+    ) -> Result<(), IMStatusCode> {
+        match op {
+            ListOperation::AddItem | ListOperation::EditItem(0) => {
+                let provider: ProviderLocation = fab_scoped_from_tlv(data, fab_idx)?;
+                if let ListOperation::EditItem(_) = op {
+                    self.requestor
+                        .remove_default_provider(fab_idx)
+                        .map_err(|_| IMStatusCode::NotFound)?;
+                }
+                self.requestor
+                    .add_default_provider(provider)
+                    .map_err(|_| IMStatusCode::ConstraintError)
+            }
+            ListOperation::DeleteItem(0) => self
+                .requestor
+                .remove_default_provider(fab_idx)
+                .map_err(|_| IMStatusCode::NotFound),
+            ListOperation::DeleteList => {
+                // Nothing to delete is fine too
+                let _ = self.requestor.remove_default_provider(fab_idx);
+                Ok(())
+            }
+            // There is at most one entry per fabric
+            ListOperation::EditItem(_) | ListOperation::DeleteItem(_) => {
+                Err(IMStatusCode::NotFound)
+            }
+        }
+    }
+
+    fn handle_command_announce(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
+        cmd_enter!("Announce OTA Provider");
+        let req = AnnounceOtaProviderReq::from_tlv(&cmd_req.data)?;
+        let reason = num::FromPrimitive::from_u8(req.announcement_reason)
+            .ok_or(IMStatusCode::InvalidCommand)?;
+        info!("Announcement from vendor {:#x}", req.vendor_id);
+        let fab_idx = cmd_req
+            .trans
+            .session
+            .get_local_fabric_idx()
+            .ok_or(IMStatusCode::UnsupportedAccess)?;
+        self.requestor
+            .announce(
+                ProviderLocation {
+                    provider_node_id: req.provider_node_id,
+                    endpoint: req.endpoint,
+                    fab_idx,
+                },
+                reason,
+            )
+            .map_err(|_| IMStatusCode::Failure)?;
+        cmd_req.trans.complete();
+        Err(IMStatusCode::Success)
+    }
+}
+
+impl ClusterType for OtaRequestorCluster {
+    fn base(&self) -> &Cluster {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut Cluster {
+        &mut self.base
+    }
+
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
+            Some(Attributes::DefaultOtaProviders) => {
+                encoder.encode(EncodeValue::Closure(&|tag, tw| {
+                    let _ = tw.start_array(tag);
+                    let _ = self.requestor.for_each_default_provider(|p| {
+                        if attr.fab_filter_match(p) {
+                            let _ = p.to_tlv(tw, TagType::Anonymous);
+                        }
+                    });
+                    let _ = tw.end_container();
+                }))
+            }
+            Some(Attributes::UpdateState) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
+                let state = self.requestor.get_state().unwrap_or(UpdateState::Unknown);
+                let _ = tw.u8(tag, state as u8);
+            })),
+            Some(Attributes::UpdateStateProgress) => {
+                encoder.encode(EncodeValue::Closure(&|tag, tw| {
+                    let _ = match self.requestor.get_progress() {
+                        Ok(Some(p)) => tw.u8(tag, p),
+                        _ => tw.null(tag),
+                    };
+                }))
+            }
+            _ => {
+                error!("Attribute not supported: this shouldn't happen");
+            }
+        }
+    }
+
+    fn write_attribute(
+        &mut self,
+        attr: &AttrDetails,
+        data: &TLVElement,
+    ) -> Result<(), IMStatusCode> {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
+            Some(Attributes::DefaultOtaProviders) => attr_list_write(attr, data, |op, data| {
+                self.write_default_providers(op, data, attr.fab_idx)
+            }),
+            _ => {
+                error!("Attribute not yet supported: this shouldn't happen");
+                Err(IMStatusCode::UnsupportedWrite)
+            }
+        }
+    }
+
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
+        let cmd = cmd_req
+            .cmd
+            .path
+            .leaf
+            .map(num::FromPrimitive::from_u32)
+            .ok_or(IMStatusCode::UnsupportedCommand)?
+            .ok_or(IMStatusCode::UnsupportedCommand)?;
+        match cmd {
+            Commands::AnnounceOtaProvider => self.handle_command_announce(cmd_req)?,
+        }
+        Ok(CommandStatus::Complete)
+    }
+}
+
+fn attr_default_ota_providers_new() -> Result<Attribute, Error> {
+    Attribute::new(
+        Attributes::DefaultOtaProviders as u16,
+        AttrValue::Custom,
+        Access::RWVA | Access::FAB_SCOPED,
+        Quality::NONE,
+    )
+}
+
+fn attr_update_possible_new() -> Result<Attribute, Error> {
+    Attribute::new(
+        Attributes::UpdatePossible as u16,
+        AttrValue::Bool(true),
+        Access::RV,
+        Quality::NONE,
+    )
+}
+
+fn attr_update_state_new() -> Result<Attribute, Error> {
+    Attribute::new(
+        Attributes::UpdateState as u16,
+        AttrValue::Custom,
+        Access::RV,
+        Quality::NONE,
+    )
+}
+
+fn attr_update_state_progress_new() -> Result<Attribute, Error> {
+    Attribute::new(
+        Attributes::UpdateStateProgress as u16,
+        AttrValue::Custom,
+        Access::RV,
+        Quality::NONE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::bdx::{
+        common::BDX_VERSION,
+        messages::{encode_block, ReceiveAccept, TransferInit},
+    };
+    use crate::tlv::{get_root_node_struct, Nullable};
+
+    const PROVIDER_NODE_ID: u64 = 0x1122334455667788;
+    const IMAGE: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+    #[derive(Default)]
+    struct Recorded {
+        queried: Option<ProviderLocation>,
+        init: Option<(u64, Vec<u8>)>,
+        image: Vec<u8>,
+        applied: Option<u32>,
+    }
+
+    struct TestProvider {
+        recorded: Arc<Mutex<Recorded>>,
+        status: QueryImageStatus,
+    }
+
+    impl OtaProviderLink for TestProvider {
+        fn query_image(
+            &mut self,
+            provider: &ProviderLocation,
+            req: &QueryImageReq,
+        ) -> Result<QueryImageResp, Error> {
+            assert_eq!(req.sw_version, 1);
+            self.recorded.lock().unwrap().queried = Some(*provider);
+            Ok(QueryImageResp {
+                status: self.status as u8,
+                image_uri: Some("bdx://1122334455667788/image-v2.ota".to_owned()),
+                sw_version: Some(2),
+                ..Default::default()
+            })
+        }
+
+        fn start_download(&mut self, node_id: u64, msg: &[u8]) -> Result<(), Error> {
+            self.recorded.lock().unwrap().init = Some((node_id, msg.to_vec()));
+            Ok(())
+        }
+    }
+
+    struct TestConsumer {
+        recorded: Arc<Mutex<Recorded>>,
+    }
+
+    impl OtaImageConsumer for TestConsumer {
+        fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.recorded.lock().unwrap().image.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn apply(&mut self, sw_version: u32) -> Result<(), Error> {
+            self.recorded.lock().unwrap().applied = Some(sw_version);
+            Ok(())
+        }
+    }
+
+    fn requestor(status: QueryImageStatus) -> (OtaRequestor, Arc<Mutex<Recorded>>) {
+        let recorded = Arc::new(Mutex::new(Recorded::default()));
+        let requestor = OtaRequestor::new(
+            0xFFF1,
+            0x8000,
+            1,
+            Box::new(TestProvider {
+                recorded: recorded.clone(),
+                status,
+            }),
+            Box::new(TestConsumer {
+                recorded: recorded.clone(),
+            }),
+        );
+        (requestor, recorded)
+    }
+
+    fn send_bdx<F>(requestor: &OtaRequestor, opcode: BdxOpCode, f: F) -> Option<BdxOpCode>
+    where
+        F: FnOnce(&mut WriteBuf),
+    {
+        let mut rx_buf = [0; 64];
+        let rx_len = rx_buf.len();
+        let mut wb = WriteBuf::new(&mut rx_buf, rx_len);
+        f(&mut wb);
+        let msg_len = wb.as_borrow_slice().len();
+        let mut pb = ParseBuf::new(&mut rx_buf, msg_len);
+
+        let mut tx_buf = [0; 64];
+        let tx_len = tx_buf.len();
+        let mut tx = WriteBuf::new(&mut tx_buf, tx_len);
+        requestor.handle_bdx(opcode, &mut pb, &mut tx).unwrap()
+    }
+
+    #[test]
+    fn test_query_and_download() {
+        let (requestor, recorded) = requestor(QueryImageStatus::UpdateAvailable);
+        assert_eq!(requestor.query(), Err(Error::NotFound));
+
+        let provider = ProviderLocation {
+            provider_node_id: PROVIDER_NODE_ID,
+            endpoint: 0,
+            fab_idx: 1,
+        };
+        requestor.add_default_provider(provider).unwrap();
+        requestor.query().unwrap();
+        assert_eq!(requestor.get_state(), Ok(UpdateState::Downloading));
+        assert_eq!(requestor.get_progress(), Ok(Some(0)));
+
+        // The download was started with the image's provider and file
+        let (node_id, mut init) = recorded.lock().unwrap().init.take().unwrap();
+        assert_eq!(recorded.lock().unwrap().queried, Some(provider));
+        assert_eq!(node_id, PROVIDER_NODE_ID);
+        let init_len = init.len();
+        let mut pb = ParseBuf::new(&mut init, init_len);
+        let init = TransferInit::decode(&mut pb).unwrap();
+        assert_eq!(init.file_designator, b"image-v2.ota");
+
+        // The Provider accepts, and we query blocks
+        let resp = send_bdx(&requestor, BdxOpCode::ReceiveAccept, |wb| {
+            ReceiveAccept {
+                version: BDX_VERSION,
+                ctrl: TransferControl::RECEIVER_DRIVE,
+                max_block_size: 8,
+                length: Some(IMAGE.len() as u64),
+            }
+            .encode(wb)
+            .unwrap()
+        });
+        assert_eq!(resp, Some(BdxOpCode::BlockQuery));
+
+        let resp = send_bdx(&requestor, BdxOpCode::Block, |wb| {
+            encode_block(wb, 0, &IMAGE[..8]).unwrap()
+        });
+        assert_eq!(resp, Some(BdxOpCode::BlockQuery));
+        assert_eq!(requestor.get_progress(), Ok(Some(80)));
+
+        let resp = send_bdx(&requestor, BdxOpCode::BlockEOF, |wb| {
+            encode_block(wb, 1, &IMAGE[8..]).unwrap()
+        });
+        assert_eq!(resp, Some(BdxOpCode::BlockAckEOF));
+        assert_eq!(requestor.get_state(), Ok(UpdateState::Applying));
+        assert_eq!(recorded.lock().unwrap().image, IMAGE);
+        assert_eq!(recorded.lock().unwrap().applied, Some(2));
+    }
+
+    #[test]
+    fn test_query_busy() {
+        let (requestor, recorded) = requestor(QueryImageStatus::Busy);
+        let provider = ProviderLocation {
+            provider_node_id: PROVIDER_NODE_ID,
+            endpoint: 0,
+            fab_idx: 1,
+        };
+        requestor.add_default_provider(provider).unwrap();
+        // Only one default provider per fabric
+        assert_eq!(
+            requestor.add_default_provider(provider),
+            Err(Error::Invalid)
+        );
+
+        // The announced provider is preferred
+        let announced = ProviderLocation {
+            provider_node_id: 5,
+            endpoint: 1,
+            fab_idx: 1,
+        };
+        requestor
+            .announce(announced, AnnouncementReason::UpdateAvailable)
+            .unwrap();
+        requestor.query().unwrap();
+        assert_eq!(requestor.get_state(), Ok(UpdateState::DelayedOnQuery));
+        assert_eq!(recorded.lock().unwrap().queried, Some(announced));
+        assert!(recorded.lock().unwrap().init.is_none());
+    }
+
+    #[test]
+    fn test_write_default_provider_fabric_index() {
+        let (requestor, _) = requestor(QueryImageStatus::Busy);
+        let requestor = Arc::new(requestor);
+        let mut cluster = OtaRequestorCluster::new(requestor.clone()).unwrap();
+        let attr = AttrDetails {
+            attr_id: Attributes::DefaultOtaProviders as u16,
+            list_index: Some(Nullable::Null),
+            fab_idx: 2,
+            fab_filter: false,
+        };
+        let mut buf = [0; 64];
+        let buf_len = buf.len();
+
+        // The client writes an entry with the wrong FabricIndex
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        let written = ProviderLocation {
+            provider_node_id: PROVIDER_NODE_ID,
+            endpoint: 0,
+            fab_idx: 7,
+        };
+        written.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        let data = get_root_node_struct(wb.as_borrow_slice()).unwrap();
+        cluster.write_attribute(&attr, &data).unwrap();
+
+        // The client leaves the FabricIndex out
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.u64(TagType::Context(1), 5).unwrap();
+        tw.u16(TagType::Context(2), 1).unwrap();
+        tw.end_container().unwrap();
+        let data = get_root_node_struct(wb.as_borrow_slice()).unwrap();
+        let attr = AttrDetails { fab_idx: 3, ..attr };
+        cluster.write_attribute(&attr, &data).unwrap();
+
+        // The entries are stored with the accessor's fabric index
+        let mut stored = Vec::new();
+        requestor
+            .for_each_default_provider(|p| stored.push(*p))
+            .unwrap();
+        assert_eq!(
+            stored,
+            [
+                ProviderLocation {
+                    fab_idx: 2,
+                    ..written
+                },
+                ProviderLocation {
+                    provider_node_id: 5,
+                    endpoint: 1,
+                    fab_idx: 3,
+                }
+            ]
+        );
+        assert!(attr.fab_filter_match(&stored[1]));
+        assert!(!AttrDetails {
+            fab_filter: true,
+            ..attr
+        }
+        .fab_filter_match(&stored[0]));
+    }
+
+    #[test]
+    fn test_parse_bdx_uri() {
+        assert_eq!(
+            parse_bdx_uri("bdx://00000000000000AB/a/b.ota"),
+            Ok((0xAB, "a/b.ota"))
+        );
+        assert!(parse_bdx_uri("https://example.com/a.ota").is_err());
+        assert!(parse_bdx_uri("bdx://AB/a.ota").is_err());
+        assert!(parse_bdx_uri("bdx://00000000000000AB/").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use num_derive::FromPrimitive;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::data_model::objects::*;
+use crate::error::*;
+use crate::transport::diag::TransportDiag;
+use crate::utils::tick::Tick;
+use log::error;
+
+// A manufacturer-specific cluster, for the Test Vendor, that reports how much of the
+// session and exchange capacity is in use. This helps with debugging 'sessions full'.
+pub const ID: u32 = 0xFFF1_FC00;
+
+#[derive(FromPrimitive)]
+pub enum Attributes {
+    SessionCapacity = 0,
+    SessionsInUse = 1,
+    ExchangeCapacity = 2,
+    ExchangesInUse = 3,
+}
+
+pub struct SessionDiagCluster {
+    diag: Arc<TransportDiag>,
+    base: Cluster,
+}
+
+impl SessionDiagCluster {
+    pub fn new(diag: Arc<TransportDiag>) -> Result<Box<Self>, Error> {
+        let mut c = Box::new(SessionDiagCluster {
+            diag,
+            base: Cluster::new(ID)?,
+        });
+        for attr in [
+            Attributes::SessionCapacity,
+            Attributes::SessionsInUse,
+            Attributes::ExchangeCapacity,
+            Attributes::ExchangesInUse,
+        ] {
+            c.base.add_attribute(Attribute::new(
+                attr as u16,
+                AttrValue::Custom,
+                Access::RV,
+                Quality::NONE,
+            )?)?;
+        }
+        Ok(c)
+    }
+}
+
+impl ClusterType for SessionDiagCluster {
+    fn base(&self) -> &Cluster {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut Cluster {
+        &mut self.base
+    }
+
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        let value = match num::FromPrimitive::from_u16(attr.attr_id) {
+            Some(Attributes::SessionCapacity) => self.diag.session_capacity(),
+            Some(Attributes::SessionsInUse) => self.diag.sessions_in_use(),
+            Some(Attributes::ExchangeCapacity) => self.diag.exchange_capacity(),
+            Some(Attributes::ExchangesInUse) => self.diag.exchanges_in_use(),
+            None => {
+                error!("Attribute not supported: this shouldn't happen");
+                return;
+            }
+        };
+        encoder.encode(EncodeValue::Value(&(value as u16)));
+    }
+}
+
+/// Bumps the data version of the cluster whenever the values that it reports change
+///
+/// The values are refreshed on the tick of the transport, so this is to be ticked after it.
+pub struct SessionDiagTick {
+    diag: Arc<TransportDiag>,
+    node: Arc<RwLock<Box<Node>>>,
+    endpoint: u16,
+    seen_changes: u32,
+}
+
+impl SessionDiagTick {
+    pub fn new(diag: Arc<TransportDiag>, node: Arc<RwLock<Box<Node>>>, endpoint: u16) -> Self {
+        let seen_changes = diag.changes();
+        Self {
+            diag,
+            node,
+            endpoint,
+            seen_changes,
+        }
+    }
+}
+
+impl Tick for SessionDiagTick {
+    fn tick(&mut self, _now: SystemTime) -> Option<SystemTime> {
+        let changes = self.diag.changes();
+        if changes != self.seen_changes {
+            // A poisoned lock is tried again on the next tick
+            if let Ok(mut node) = self.node.write() {
+                if let Ok(c) = node.get_cluster_mut(self.endpoint, ID) {
+                    c.base_mut().bump_dataver();
+                }
+                self.seen_changes = changes;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionDiagCluster, SessionDiagTick, ID};
+    use crate::{data_model::objects::Node, transport::diag::TransportDiag, utils::tick::Tick};
+    use std::{
+        sync::{Arc, RwLock},
+        time::SystemTime,
+    };
+
+    #[test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn test_dataver_on_change() {
+        let diag = Arc::new(TransportDiag::new());
+        let mut node = Node::new().unwrap();
+        let e = node.add_endpoint().unwrap();
+        node.add_cluster(e, SessionDiagCluster::new(diag.clone()).unwrap())
+            .unwrap();
+        let node = Arc::new(RwLock::new(node));
+        let dataver = || {
+            node.read()
+                .unwrap()
+                .get_cluster(0, ID)
+                .unwrap()
+                .base()
+                .get_dataver()
+        };
+        let mut tick = SessionDiagTick::new(diag.clone(), node.clone(), 0);
+        let start = dataver();
+
+        diag.update_sessions(1, 16);
+        diag.update_exchanges(2, 20);
+        tick.tick(SystemTime::now());
+        assert_eq!(dataver(), start.wrapping_add(1));
+
+        // The same values are no change
+        diag.update_sessions(1, 16);
+        tick.tick(SystemTime::now());
+        assert_eq!(dataver(), start.wrapping_add(1));
+
+        diag.update_exchanges(1, 20);
+        tick.tick(SystemTime::now());
+        assert_eq!(dataver(), start.wrapping_add(2));
+    }
+}
@@ -2,16 +2,20 @@ use std::sync::Arc;
 
 use num_derive::FromPrimitive;
 
-use crate::acl::{self, AclMgr};
+use crate::acl::{self, AclEntry, AclMgr};
 use crate::data_model::objects::*;
 use crate::error::*;
-use crate::tlv::{TagType, ToTLV};
-use log::error;
+use crate::interaction_model::core::IMStatusCode;
+use crate::tlv::{FabricScoped, FromTLV, TLVElement, TLVWriter, TagType, ToTLV};
+use log::{error, info};
 
 pub const ID: u32 = 0x001F;
 
+/// The longest data of an Extension entry
+pub const MAX_EXTENSION_DATA_LEN: usize = 128;
+
 #[derive(FromPrimitive)]
-enum Attributes {
+pub enum Attributes {
     Acl = 0,
     Extension = 1,
     SubjectsPerEntry = 2,
@@ -19,9 +23,22 @@ enum Attributes {
     EntriesPerFabric = 4,
 }
 
+/// An entry of the Extension attribute, a fabric can have at most one
+///
+/// The data is opaque to the node. Unlike the ACL entries, the entries are only kept in
+/// memory.
+#[derive(ToTLV, FromTLV, FabricScoped, Clone, Debug, PartialEq)]
+#[tlvargs(start = 1)]
+pub struct AclExtension {
+    pub data: Vec<u8>,
+    #[tlv(fabric_index)]
+    pub fab_idx: u8,
+}
+
 pub struct AccessControlCluster {
     base: Cluster,
     acl_mgr: Arc<AclMgr>,
+    extensions: Vec<AclExtension>,
 }
 
 impl AccessControlCluster {
@@ -29,6 +46,7 @@ impl AccessControlCluster {
         let mut c = Box::new(AccessControlCluster {
             base: Cluster::new(ID)?,
             acl_mgr,
+            extensions: Vec::new(),
         });
         c.base.add_attribute(attr_acl_new()?)?;
         c.base.add_attribute(attr_extension_new()?)?;
@@ -37,6 +55,83 @@ impl AccessControlCluster {
         c.base.add_attribute(attr_entries_per_fabric_new()?)?;
         Ok(c)
     }
+
+    /// Apply a list operation on the ACL entries of the accessor's fabric
+    fn write_acl_attr(
+        &mut self,
+        op: ListOperation,
+        data: &TLVElement,
+        fab_idx: u8,
+    ) -> Result<(), IMStatusCode> {
+        info!("Performing ACL operation {:?}", op);
+        let result = match op {
+            ListOperation::AddItem => self.acl_mgr.add(acl_entry_from_tlv(data, fab_idx)?),
+            ListOperation::EditItem(index) => {
+                let acl_entry = acl_entry_from_tlv(data, fab_idx)?;
+                self.acl_mgr.edit(index, fab_idx, acl_entry)
+            }
+            ListOperation::DeleteItem(index) => self.acl_mgr.delete(index, fab_idx),
+            ListOperation::DeleteList => self.acl_mgr.delete_for_fabric(fab_idx),
+        };
+        result.map_err(acl_error_to_status)
+    }
+
+    /// Replace the ACL entries of the accessor's fabric with the list in 'data'
+    ///
+    /// Unlike the other list attributes, this isn't a DeleteList followed by an AddItem for
+    /// each entry. An entry that is invalid, or that doesn't fit, would leave the fabric with
+    /// only a part of its entries, or none at all, and the admin may lose its access. So all
+    /// the entries are checked before any of the existing ones is replaced.
+    fn write_acl_list(&mut self, data: &TLVElement, fab_idx: u8) -> Result<(), IMStatusCode> {
+        info!("Replacing the ACL entries of fabric {}", fab_idx);
+        data.confirm_array()
+            .map_err(|_| IMStatusCode::InvalidDataType)?;
+        let mut entries = Vec::new();
+        if let Some(tlv_iter) = data.iter() {
+            for item in tlv_iter {
+                entries.push(acl_entry_from_tlv(&item, fab_idx)?);
+            }
+        }
+        self.acl_mgr
+            .replace_for_fabric(fab_idx, entries)
+            .map_err(acl_error_to_status)
+    }
+
+    /// Apply a list operation on the Extension entries of the accessor's fabric
+    fn write_extension_attr(
+        &mut self,
+        op: ListOperation,
+        data: &TLVElement,
+        fab_idx: u8,
+    ) -> Result<(), IMStatusCode> {
+        info!("Performing Extension operation {:?}", op);
+        // A fabric has one entry at most, it is the only valid list index
+        let existing = self
+            .extensions
+            .iter()
+            .position(|e| e.get_fab_idx() == fab_idx);
+        match op {
+            ListOperation::AddItem | ListOperation::EditItem(_) => {
+                let extension: AclExtension = fab_scoped_from_tlv(data, fab_idx)?;
+                if extension.data.len() > MAX_EXTENSION_DATA_LEN {
+                    return Err(IMStatusCode::ConstraintError);
+                }
+                match (op, existing) {
+                    (ListOperation::AddItem, None) => self.extensions.push(extension),
+                    (ListOperation::EditItem(0), Some(i)) => self.extensions[i] = extension,
+                    _ => return Err(IMStatusCode::ConstraintError),
+                }
+            }
+            ListOperation::DeleteItem(index) => match existing {
+                Some(i) if index == 0 => {
+                    self.extensions.remove(i);
+                }
+                _ => return Err(IMStatusCode::ConstraintError),
+            },
+            ListOperation::DeleteList => self.extensions.retain(|e| e.get_fab_idx() != fab_idx),
+        }
+        Ok(())
+    }
 }
 
 impl ClusterType for AccessControlCluster {
@@ -47,19 +142,24 @@ impl ClusterType for AccessControlCluster {
         &mut self.base
     }
 
-    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr_id: u16) {
-        match num::FromPrimitive::from_u16(attr_id) {
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
             Some(Attributes::Acl) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
-                // Empty for now
                 let _ = tw.start_array(tag);
                 let _ = self.acl_mgr.for_each_acl(|entry| {
-                    let _ = entry.to_tlv(tw, TagType::Anonymous);
+                    if attr.fab_filter_match(entry) {
+                        let _ = entry.to_tlv(tw, TagType::Anonymous);
+                    }
                 });
                 let _ = tw.end_container();
             })),
             Some(Attributes::Extension) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
-                // Empty for now
                 let _ = tw.start_array(tag);
+                for extension in self.extensions.iter() {
+                    if attr.fab_filter_match(extension) {
+                        let _ = extension.to_tlv(tw, TagType::Anonymous);
+                    }
+                }
                 let _ = tw.end_container();
             })),
             _ => {
@@ -67,6 +167,47 @@ impl ClusterType for AccessControlCluster {
             }
         }
     }
+
+    fn write_attribute(
+        &mut self,
+        attr: &AttrDetails,
+        data: &TLVElement,
+    ) -> Result<(), IMStatusCode> {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
+            Some(Attributes::Acl) if attr.list_index.is_none() => {
+                self.write_acl_list(data, attr.fab_idx)
+            }
+            Some(Attributes::Acl) => attr_list_write(attr, data, |op, data| {
+                self.write_acl_attr(op, data, attr.fab_idx)
+            }),
+            Some(Attributes::Extension) => attr_list_write(attr, data, |op, data| {
+                self.write_extension_attr(op, data, attr.fab_idx)
+            }),
+            _ => {
+                error!("Attribute not yet supported: this shouldn't happen");
+                Err(IMStatusCode::UnsupportedWrite)
+            }
+        }
+    }
+}
+
+// Decode an ACL entry that is written by the accessor on fabric 'fab_idx', and check that
+// it is valid
+fn acl_entry_from_tlv(data: &TLVElement, fab_idx: u8) -> Result<AclEntry, IMStatusCode> {
+    let acl_entry: AclEntry = fab_scoped_from_tlv(data, fab_idx)?;
+    acl_entry
+        .validate()
+        .map_err(|_| IMStatusCode::ConstraintError)?;
+    Ok(acl_entry)
+}
+
+fn acl_error_to_status(e: Error) -> IMStatusCode {
+    match e {
+        Error::NoSpace => IMStatusCode::ResourceExhausted,
+        // The list index is out of range for the fabric's entries
+        Error::NotFound => IMStatusCode::ConstraintError,
+        _ => IMStatusCode::Failure,
+    }
 }
 
 fn attr_acl_new() -> Result<Attribute, Error> {
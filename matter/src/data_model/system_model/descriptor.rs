@@ -10,7 +10,7 @@ use log::error;
 pub const ID: u32 = 0x001D;
 
 #[derive(FromPrimitive)]
-enum Attributes {
+pub enum Attributes {
     DeviceTypeList = 0,
     ServerList = 1,
     ClientList = 2,
@@ -40,8 +40,14 @@ impl DescriptorCluster {
             cluster: None,
             leaf: None,
         };
+        let dm = match self.data_model.node.read() {
+            Ok(dm) => dm,
+            Err(_) => {
+                error!("Node lock poisoned");
+                return;
+            }
+        };
         let _ = tw.start_array(tag);
-        let dm = self.data_model.node.read().unwrap();
         let _ = dm.for_each_cluster(&path, |_current_path, c| {
             let _ = tw.u32(TagType::Anonymous, c.base().id());
             Ok(())
@@ -58,8 +64,8 @@ impl ClusterType for DescriptorCluster {
         &mut self.base
     }
 
-    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr_id: u16) {
-        match num::FromPrimitive::from_u16(attr_id) {
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
             Some(Attributes::ServerList) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
                 self.encode_server_list(tag, tw)
             })),
@@ -40,9 +40,13 @@ pub enum Error {
     InvalidAuthKey,
     InvalidSignature,
     InvalidState,
+    MandatoryClusterMissing,
     RwLock,
     TLVNotFound,
-    TLVTypeMismatch,
+    TLVTypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
     TruncatedPacket,
 }
 
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use log::info;
@@ -9,11 +9,16 @@ use crate::{
     crypto::{self, crypto_dummy::KeyPairDummy, hkdf_sha256, CryptoKeyPair, HmacSha256, KeyPair},
     error::Error,
     group_keys::KeySet,
-    sys::{Mdns, MdnsService, Psm},
+    sys::{KvStore, Mdns, MdnsService, Psm},
+    tlv::{FromTLV, OctetStr, TLVElement, TLVList, TLVWriter, TagType, ToTLV},
+    transport::queue::{Msg, WorkQ},
+    utils::writebuf::WriteBuf,
 };
 
 const MAX_CERT_TLV_LEN: usize = 300;
 const COMPRESSED_FABRIC_ID_LEN: usize = 8;
+/// The maximum length of a fabric's label, as per the spec
+pub const MAX_FABRIC_LABEL_LEN: usize = 32;
 
 macro_rules! fb_key {
     ($index:ident, $key:ident) => {
@@ -27,6 +32,32 @@ const ST_NOC: &str = "noc";
 const ST_IPK: &str = "ipk";
 const ST_PBKEY: &str = "pubkey";
 const ST_PRKEY: &str = "privkey";
+const ST_VID: &str = "vid";
+const ST_LABEL: &str = "label";
+
+// The serialized form of a fabric, as used by FabricMgr::store()
+#[derive(FromTLV, ToTLV)]
+#[tlvargs(lifetime = "'a")]
+struct FabricData<'a> {
+    fab_idx: u8,
+    root_ca: OctetStr<'a>,
+    // Absent for a NOC that is issued directly by the Root CA
+    icac: Option<OctetStr<'a>>,
+    noc: OctetStr<'a>,
+    ipk: OctetStr<'a>,
+    pub_key: OctetStr<'a>,
+    priv_key: OctetStr<'a>,
+    // Absent in the data stored before these were introduced
+    vendor_id: Option<u16>,
+    label: Option<String>,
+}
+
+// Upper bound on the serialized size of a fabric
+const MAX_FABRIC_DATA_LEN: usize = 3 * MAX_CERT_TLV_LEN
+    + crypto::EC_POINT_LEN_BYTES
+    + crypto::BIGNUM_LEN_BYTES
+    + MAX_FABRIC_LABEL_LEN
+    + 64;
 
 #[allow(dead_code)]
 pub struct Fabric {
@@ -34,20 +65,27 @@ pub struct Fabric {
     fabric_id: u64,
     key_pair: Box<dyn CryptoKeyPair>,
     pub root_ca: Cert,
-    pub icac: Cert,
+    // None if the NOC is issued directly by the Root CA
+    pub icac: Option<Cert>,
     pub noc: Cert,
     pub ipk: KeySet,
+    // The VendorID of the admin that commissioned this fabric
+    vendor_id: u16,
+    label: String,
     compressed_id: [u8; COMPRESSED_FABRIC_ID_LEN],
     mdns_service: Option<MdnsService>,
+    // Bumped every time the credentials of the fabric are updated
+    generation: u32,
 }
 
 impl Fabric {
     pub fn new(
         key_pair: KeyPair,
         root_ca: Cert,
-        icac: Cert,
+        icac: Option<Cert>,
         noc: Cert,
         ipk: &[u8],
+        vendor_id: u16,
     ) -> Result<Self, Error> {
         let node_id = noc.get_node_id()?;
         let fabric_id = noc.get_fabric_id()?;
@@ -60,25 +98,84 @@ impl Fabric {
             icac,
             noc,
             ipk: KeySet::default(),
+            vendor_id,
+            label: String::new(),
             compressed_id: [0; COMPRESSED_FABRIC_ID_LEN],
             mdns_service: None,
+            generation: 0,
         };
         Fabric::get_compressed_id(f.root_ca.get_pubkey(), fabric_id, &mut f.compressed_id)?;
         f.ipk = KeySet::new(ipk, &f.compressed_id)?;
+        f.publish_mdns()?;
+        Ok(f)
+    }
 
+    fn publish_mdns(&mut self) -> Result<(), Error> {
         let mut mdns_service_name = String::with_capacity(33);
-        for c in f.compressed_id {
+        for c in self.compressed_id {
             mdns_service_name.push_str(&format!("{:02X}", c));
         }
         mdns_service_name.push('-');
         let mut node_id_be: [u8; 8] = [0; 8];
-        BigEndian::write_u64(&mut node_id_be, node_id);
+        BigEndian::write_u64(&mut node_id_be, self.node_id);
         for c in node_id_be {
             mdns_service_name.push_str(&format!("{:02X}", c));
         }
         info!("MDNS Service Name: {}", mdns_service_name);
-        f.mdns_service = Some(Mdns::publish_service(&mdns_service_name)?);
-        Ok(f)
+        // Drop the earlier service, if any, before publishing the new one
+        self.mdns_service = None;
+        self.mdns_service = Some(Mdns::publish_service(&mdns_service_name)?);
+        Ok(())
+    }
+
+    /// Replace the NOC and ICAC of this fabric, as on an UpdateNOC
+    ///
+    /// The chain is expected to have been validated by the caller.
+    pub fn update_noc(&mut self, noc: Cert, icac: Option<Cert>) -> Result<(), Error> {
+        let node_id = noc.get_node_id()?;
+        let fabric_id = noc.get_fabric_id()?;
+
+        let mut compressed_id = [0; COMPRESSED_FABRIC_ID_LEN];
+        Fabric::get_compressed_id(self.root_ca.get_pubkey(), fabric_id, &mut compressed_id)?;
+        // The operational IPK is derived from the compressed fabric ID
+        let ipk = KeySet::new(self.ipk.epoch_key(), &compressed_id)?;
+
+        self.node_id = node_id;
+        self.fabric_id = fabric_id;
+        self.noc = noc;
+        self.icac = icac;
+        self.compressed_id = compressed_id;
+        self.ipk = ipk;
+        self.generation = self.generation.wrapping_add(1);
+        self.publish_mdns()
+    }
+
+    /// Replace the IPK epoch key of this fabric
+    pub fn set_ipk(&mut self, ipk: &[u8]) -> Result<(), Error> {
+        self.ipk = KeySet::new(ipk, &self.compressed_id)?;
+        self.generation = self.generation.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn get_vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    /// Set the user-visible label of this fabric, as on an UpdateFabricLabel
+    pub fn set_label(&mut self, label: &str) -> Result<(), Error> {
+        if label.len() > MAX_FABRIC_LABEL_LEN {
+            return Err(Error::InvalidData);
+        }
+        self.label = label.to_owned();
+        Ok(())
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.generation
     }
 
     pub fn dummy() -> Result<Self, Error> {
@@ -87,11 +184,14 @@ impl Fabric {
             fabric_id: 0,
             key_pair: Box::new(KeyPairDummy::new()?),
             root_ca: Cert::default(),
-            icac: Cert::default(),
+            icac: None,
             noc: Cert::default(),
             ipk: KeySet::default(),
+            vendor_id: 0,
+            label: String::new(),
             compressed_id: [0; COMPRESSED_FABRIC_ID_LEN],
             mdns_service: None,
+            generation: 0,
         })
     }
 
@@ -108,31 +208,38 @@ impl Fabric {
     }
 
     pub fn match_dest_id(&self, random: &[u8], target: &[u8]) -> Result<(), Error> {
-        let mut mac = HmacSha256::new(self.ipk.op_key())?;
-
-        mac.update(random)?;
-        mac.update(self.root_ca.get_pubkey())?;
-
-        let mut buf: [u8; 8] = [0; 8];
-        LittleEndian::write_u64(&mut buf, self.fabric_id);
-        mac.update(&buf)?;
-
-        LittleEndian::write_u64(&mut buf, self.node_id);
-        mac.update(&buf)?;
-
-        let mut id = [0_u8; crypto::SHA256_HASH_LEN_BYTES];
-        mac.finish(&mut id)?;
-        if id.as_slice() == target {
+        let id = self.compute_dest_id(random, self.ipk.op_key())?;
+        if crypto::constant_time_eq(id.as_slice(), target) {
             Ok(())
         } else {
             Err(Error::NotFound)
         }
     }
 
+    /// Compute the CASE Destination Identifier of this fabric's node, as seen in a Sigma1
+    pub fn compute_dest_id(
+        &self,
+        initiator_random: &[u8],
+        ipk: &[u8],
+    ) -> Result<[u8; crypto::SHA256_HASH_LEN_BYTES], Error> {
+        compute_dest_id(
+            initiator_random,
+            self.root_ca.get_pubkey(),
+            self.fabric_id,
+            self.node_id,
+            ipk,
+        )
+    }
+
     pub fn sign_msg(&self, msg: &[u8], signature: &mut [u8]) -> Result<usize, Error> {
         self.key_pair.sign_msg(msg, signature)
     }
 
+    /// Get the public key of this fabric's operational key pair
+    pub fn get_public_key(&self, pub_key: &mut [u8]) -> Result<usize, Error> {
+        self.key_pair.get_public_key(pub_key)
+    }
+
     pub fn get_node_id(&self) -> u64 {
         self.node_id
     }
@@ -141,11 +248,15 @@ impl Fabric {
         self.fabric_id
     }
 
-    fn store(&self, index: usize, psm: &MutexGuard<Psm>) -> Result<(), Error> {
+    fn store(&self, index: usize, psm: &dyn KvStore) -> Result<(), Error> {
         let mut key = [0u8; MAX_CERT_TLV_LEN];
         let len = self.root_ca.as_tlv(&mut key)?;
         psm.set_kv_slice(fb_key!(index, ST_RCA), &key[..len])?;
-        let len = self.icac.as_tlv(&mut key)?;
+        // An empty ICAC stands for a NOC that is issued directly by the Root CA
+        let len = match &self.icac {
+            Some(icac) => icac.as_tlv(&mut key)?,
+            None => 0,
+        };
         psm.set_kv_slice(fb_key!(index, ST_ICA), &key[..len])?;
         let len = self.noc.as_tlv(&mut key)?;
         psm.set_kv_slice(fb_key!(index, ST_NOC), &key[..len])?;
@@ -161,17 +272,71 @@ impl Fabric {
         let key = &key[..len];
         psm.set_kv_slice(fb_key!(index, ST_PRKEY), key)?;
 
+        psm.set_kv_slice(fb_key!(index, ST_VID), &self.vendor_id.to_le_bytes())?;
+        psm.set_kv_slice(fb_key!(index, ST_LABEL), self.label.as_bytes())?;
+
         Ok(())
     }
 
-    fn load(index: usize, psm: &MutexGuard<Psm>) -> Result<Self, Error> {
+    // Serialize the fabric, with the certificates in their TLV form
+    fn encode(&self, fab_idx: u8, tw: &mut TLVWriter) -> Result<(), Error> {
+        let mut root_ca = [0u8; MAX_CERT_TLV_LEN];
+        let root_ca_len = self.root_ca.as_tlv(&mut root_ca)?;
+        let mut icac = [0u8; MAX_CERT_TLV_LEN];
+        let icac_len = match &self.icac {
+            Some(c) => Some(c.as_tlv(&mut icac)?),
+            None => None,
+        };
+        let mut noc = [0u8; MAX_CERT_TLV_LEN];
+        let noc_len = self.noc.as_tlv(&mut noc)?;
+        let mut pub_key = [0_u8; crypto::EC_POINT_LEN_BYTES];
+        let pub_key_len = self.key_pair.get_public_key(&mut pub_key)?;
+        let mut priv_key = [0_u8; crypto::BIGNUM_LEN_BYTES];
+        let priv_key_len = self.key_pair.get_private_key(&mut priv_key)?;
+
+        FabricData {
+            fab_idx,
+            root_ca: OctetStr(&root_ca[..root_ca_len]),
+            icac: icac_len.map(|len| OctetStr(&icac[..len])),
+            noc: OctetStr(&noc[..noc_len]),
+            ipk: OctetStr(self.ipk.epoch_key()),
+            pub_key: OctetStr(&pub_key[..pub_key_len]),
+            priv_key: OctetStr(&priv_key[..priv_key_len]),
+            vendor_id: Some(self.vendor_id),
+            label: Some(self.label.clone()),
+        }
+        .to_tlv(tw, TagType::Anonymous)
+    }
+
+    fn decode(t: &TLVElement) -> Result<(u8, Self), Error> {
+        let data = FabricData::from_tlv(t)?;
+        let keypair = KeyPair::new_from_components(data.pub_key.0, data.priv_key.0)?;
+        let mut fabric = Fabric::new(
+            keypair,
+            Cert::new(data.root_ca.0)?,
+            data.icac.map(|icac| Cert::new(icac.0)).transpose()?,
+            Cert::new(data.noc.0)?,
+            data.ipk.0,
+            data.vendor_id.unwrap_or(0),
+        )?;
+        if let Some(label) = data.label {
+            fabric.set_label(&label)?;
+        }
+        Ok((data.fab_idx, fabric))
+    }
+
+    fn load(index: usize, psm: &dyn KvStore) -> Result<Self, Error> {
         let mut root_ca = Vec::new();
         psm.get_kv_slice(fb_key!(index, ST_RCA), &mut root_ca)?;
         let root_ca = Cert::new(root_ca.as_slice())?;
 
         let mut icac = Vec::new();
         psm.get_kv_slice(fb_key!(index, ST_ICA), &mut icac)?;
-        let icac = Cert::new(icac.as_slice())?;
+        let icac = if icac.is_empty() {
+            None
+        } else {
+            Some(Cert::new(icac.as_slice())?)
+        };
 
         let mut noc = Vec::new();
         psm.get_kv_slice(fb_key!(index, ST_NOC), &mut noc)?;
@@ -186,10 +351,49 @@ impl Fabric {
         psm.get_kv_slice(fb_key!(index, ST_PRKEY), &mut priv_key)?;
         let keypair = KeyPair::new_from_components(pub_key.as_slice(), priv_key.as_slice())?;
 
-        Fabric::new(keypair, root_ca, icac, noc, ipk.as_slice())
+        // The VendorID and label aren't there for the fabrics stored by earlier versions
+        let mut vendor_id = Vec::new();
+        let vendor_id = match psm.get_kv_slice(fb_key!(index, ST_VID), &mut vendor_id) {
+            Ok(_) => LittleEndian::read_u16(vendor_id.get(..2).ok_or(Error::InvalidData)?),
+            Err(_) => 0,
+        };
+        let mut label = Vec::new();
+        let label = match psm.get_kv_slice(fb_key!(index, ST_LABEL), &mut label) {
+            Ok(_) => String::from_utf8(label).map_err(|_| Error::InvalidData)?,
+            Err(_) => String::new(),
+        };
+
+        let mut fabric = Fabric::new(keypair, root_ca, icac, noc, ipk.as_slice(), vendor_id)?;
+        fabric.set_label(&label)?;
+        Ok(fabric)
     }
 }
 
+// HMAC-SHA256 over initiatorRandom || rootPublicKey || fabricId || nodeId, keyed by the IPK
+fn compute_dest_id(
+    initiator_random: &[u8],
+    root_pubkey: &[u8],
+    fabric_id: u64,
+    node_id: u64,
+    ipk: &[u8],
+) -> Result<[u8; crypto::SHA256_HASH_LEN_BYTES], Error> {
+    let mut mac = HmacSha256::new(ipk)?;
+
+    mac.update(initiator_random)?;
+    mac.update(root_pubkey)?;
+
+    let mut buf: [u8; 8] = [0; 8];
+    LittleEndian::write_u64(&mut buf, fabric_id);
+    mac.update(&buf)?;
+
+    LittleEndian::write_u64(&mut buf, node_id);
+    mac.update(&buf)?;
+
+    let mut id = [0_u8; crypto::SHA256_HASH_LEN_BYTES];
+    mac.finish(&mut id)?;
+    Ok(id)
+}
+
 pub const MAX_SUPPORTED_FABRICS: usize = 3;
 #[derive(Default)]
 pub struct FabricMgrInner {
@@ -200,32 +404,37 @@ pub struct FabricMgrInner {
 
 pub struct FabricMgr {
     inner: RwLock<FabricMgrInner>,
-    psm: Arc<Mutex<Psm>>,
+    psm: Arc<Mutex<dyn KvStore>>,
 }
 
 impl FabricMgr {
     pub fn new() -> Result<Self, Error> {
+        FabricMgr::new_with_store(Psm::get()?)
+    }
+
+    /// Create the Fabric Manager, with the fabrics persisted in `store`
+    pub fn new_with_store(store: Arc<Mutex<dyn KvStore>>) -> Result<Self, Error> {
         let dummy_fabric = Fabric::dummy()?;
         let mut mgr = FabricMgrInner::default();
         mgr.fabrics[0] = Some(dummy_fabric);
         let mut fm = Self {
             inner: RwLock::new(mgr),
-            psm: Psm::get()?,
+            psm: store,
         };
-        fm.load()?;
+        fm.load_all()?;
         Ok(fm)
     }
 
-    fn store(&self, index: usize, fabric: &Fabric) -> Result<(), Error> {
-        let psm = self.psm.lock().unwrap();
-        fabric.store(index, &psm)
+    fn store_one(&self, index: usize, fabric: &Fabric) -> Result<(), Error> {
+        let psm = self.psm.lock()?;
+        fabric.store(index, &*psm)
     }
 
-    fn load(&mut self) -> Result<(), Error> {
+    fn load_all(&mut self) -> Result<(), Error> {
         let mut mgr = self.inner.write()?;
-        let psm = self.psm.lock().unwrap();
+        let psm = self.psm.lock()?;
         for i in 0..MAX_SUPPORTED_FABRICS {
-            let result = Fabric::load(i, &psm);
+            let result = Fabric::load(i, &*psm);
             if let Ok(fabric) = result {
                 info!("Adding new fabric at index {}", i);
                 mgr.fabrics[i] = Some(fabric);
@@ -234,6 +443,58 @@ impl FabricMgr {
         Ok(())
     }
 
+    /// Serialize all the fabrics, as a TLV array
+    pub fn store(&self) -> Result<Vec<u8>, Error> {
+        let mgr = self.inner.read()?;
+        let mut buf = vec![0u8; MAX_SUPPORTED_FABRICS * MAX_FABRIC_DATA_LEN];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+
+        tw.start_array(TagType::Anonymous)?;
+        // Index 0 is the placeholder fabric, that isn't persisted
+        for (i, fabric) in mgr.fabrics.iter().enumerate().skip(1) {
+            if let Some(fabric) = fabric {
+                fabric.encode(i as u8, &mut tw)?;
+            }
+        }
+        tw.end_container()?;
+
+        let len = wb.as_slice().len();
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Restore the fabrics serialized by store()
+    ///
+    /// The restored fabrics replace the ones at the same fabric index, and are
+    /// also persisted to the KvStore.
+    pub fn load(&self, data: &[u8]) -> Result<(), Error> {
+        let root = TLVList::new(data)
+            .iter()
+            .next()
+            .ok_or(Error::InvalidData)?
+            .confirm_array()?;
+
+        let mut fabrics = Vec::new();
+        for t in root.iter().ok_or(Error::Invalid)? {
+            let (index, fabric) = Fabric::decode(&t)?;
+            let index = index as usize;
+            if index == 0 || index >= MAX_SUPPORTED_FABRICS {
+                return Err(Error::Invalid);
+            }
+            fabrics.push((index, fabric));
+        }
+
+        let mut mgr = self.inner.write()?;
+        for (index, fabric) in fabrics {
+            self.store_one(index, &fabric)?;
+            info!("Restored fabric at index {}", index);
+            mgr.fabrics[index] = Some(fabric);
+        }
+        Ok(())
+    }
+
     pub fn add(&self, f: Fabric) -> Result<u8, Error> {
         let mut mgr = self.inner.write()?;
         let index = mgr
@@ -242,12 +503,70 @@ impl FabricMgr {
             .position(|f| f.is_none())
             .ok_or(Error::NoSpace)?;
 
-        self.store(index, &f)?;
+        self.store_one(index, &f)?;
 
         mgr.fabrics[index] = Some(f);
         Ok(index as u8)
     }
 
+    /// Replace the NOC and ICAC of the fabric at `index`
+    ///
+    /// The CASE sessions established with the earlier credentials are evicted.
+    pub fn update_noc(&self, index: usize, noc: Cert, icac: Option<Cert>) -> Result<(), Error> {
+        self.update(index, |f| f.update_noc(noc, icac))
+    }
+
+    /// Replace the IPK epoch key of the fabric at `index`
+    ///
+    /// The CASE sessions established with the earlier IPK are evicted.
+    pub fn set_ipk(&self, index: usize, ipk: &[u8]) -> Result<(), Error> {
+        self.update(index, |f| f.set_ipk(ipk))
+    }
+
+    /// Set the label of the fabric at `index`
+    ///
+    /// Fails with Error::Invalid if another fabric already has the same label. Unlike
+    /// the credentials, the label doesn't affect the sessions on the fabric.
+    pub fn set_label(&self, index: usize, label: &str) -> Result<(), Error> {
+        let mut mgr = self.inner.write()?;
+        let conflict = mgr
+            .fabrics
+            .iter()
+            .enumerate()
+            .any(|(i, f)| i != index && matches!(f, Some(f) if f.label == label));
+        if !label.is_empty() && conflict {
+            return Err(Error::Invalid);
+        }
+        let fabric = mgr
+            .fabrics
+            .get_mut(index)
+            .and_then(|f| f.as_mut())
+            .ok_or(Error::NotFound)?;
+        fabric.set_label(label)?;
+        self.store_one(index, fabric)
+    }
+
+    fn update<F>(&self, index: usize, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Fabric) -> Result<(), Error>,
+    {
+        let mut mgr = self.inner.write()?;
+        let fabric = mgr
+            .fabrics
+            .get_mut(index)
+            .and_then(|f| f.as_mut())
+            .ok_or(Error::NotFound)?;
+        f(fabric)?;
+        self.store_one(index, fabric)?;
+
+        // The sessions are owned by the transport, let it know that they are stale
+        match WorkQ::get() {
+            Ok(q) => q.sync_send(Msg::FabricUpdated(index as u8))?,
+            Err(_) => info!("No transport to evict the sessions on fabric {}", index),
+        }
+        Ok(())
+    }
+
     pub fn match_dest_id(&self, random: &[u8], target: &[u8]) -> Result<usize, Error> {
         let mgr = self.inner.read()?;
         for i in 0..MAX_SUPPORTED_FABRICS {
@@ -267,8 +586,39 @@ impl FabricMgr {
         Ok(RwLockReadGuardRef::new(self.inner.read()?).map(|fm| &fm.fabrics[idx]))
     }
 
+    /// Drop the fabric at `index` from the table
+    ///
+    /// This doesn't remove the fabric from the KvStore, it is for the tests that need a
+    /// fabric to go away mid-operation.
+    #[cfg(test)]
+    pub(crate) fn remove(&self, index: usize) -> Result<(), Error> {
+        let mut mgr = self.inner.write()?;
+        mgr.fabrics[index].take().ok_or(Error::NotFound)?;
+        Ok(())
+    }
+
+    /// Call `f` on each of the fabrics, along with its fabric index
+    pub fn for_each<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(u8, &Fabric),
+    {
+        let mgr = self.inner.read()?;
+        // Index 0 is the placeholder fabric
+        for (i, fabric) in mgr.fabrics.iter().enumerate().skip(1) {
+            if let Some(fabric) = fabric {
+                f(i as u8, fabric);
+            }
+        }
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
-        let mgr = self.inner.read().unwrap();
+        // A poisoned lock is taken as there being fabrics, so that the node isn't
+        // opened up for commissioning
+        let mgr = match self.inner.read() {
+            Ok(mgr) => mgr,
+            Err(_) => return false,
+        };
         for i in 1..MAX_SUPPORTED_FABRICS {
             if mgr.fabrics[i].is_some() {
                 return false;
@@ -277,3 +627,183 @@ impl FabricMgr {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{compute_dest_id, Fabric, FabricMgr, MAX_FABRIC_LABEL_LEN};
+    use crate::{
+        cert::{test_vectors, Cert},
+        crypto::KeyPair,
+        error::Error,
+        test_utils::MemKvStore,
+    };
+
+    fn test_fabric() -> Fabric {
+        Fabric::new(
+            KeyPair::new().unwrap(),
+            Cert::new(&test_vectors::RCA1_SUCCESS).unwrap(),
+            Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+            Cert::new(&test_vectors::NOC1_SUCCESS).unwrap(),
+            &[0x5a; 16],
+            0xfff1,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_update_noc() {
+        let mut fabric = test_fabric();
+        let random = [0x11; 32];
+        let old_dest_id = fabric
+            .compute_dest_id(&random, fabric.ipk.op_key())
+            .unwrap();
+        assert_eq!(fabric.match_dest_id(&random, &old_dest_id), Ok(()));
+
+        // The same NOC, but for another node ID
+        let mut noc = test_vectors::NOC1_SUCCESS;
+        noc[33] = 0x3;
+        let noc = Cert::new(&noc).unwrap();
+        let icac = Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap());
+        assert_eq!(fabric.get_node_id(), 0xbc5c02);
+        fabric.update_noc(noc, icac).unwrap();
+
+        assert_eq!(fabric.get_node_id(), 0xbc5c03);
+        assert_eq!(fabric.get_fabric_id(), 1);
+        assert_eq!(fabric.get_generation(), 1);
+        // The Sigma1s meant for the earlier node ID don't resolve to this fabric anymore
+        assert!(fabric.match_dest_id(&random, &old_dest_id).is_err());
+        let new_dest_id = fabric
+            .compute_dest_id(&random, fabric.ipk.op_key())
+            .unwrap();
+        assert_eq!(fabric.match_dest_id(&random, &new_dest_id), Ok(()));
+    }
+
+    #[test]
+    fn test_set_ipk() {
+        let mut fabric = test_fabric();
+        let random = [0x11; 32];
+        let old_dest_id = fabric
+            .compute_dest_id(&random, fabric.ipk.op_key())
+            .unwrap();
+
+        fabric.set_ipk(&[0xa5; 16]).unwrap();
+        assert_eq!(fabric.ipk.epoch_key(), &[0xa5; 16]);
+        assert_eq!(fabric.get_generation(), 1);
+        assert!(fabric.match_dest_id(&random, &old_dest_id).is_err());
+    }
+
+    #[test]
+    fn test_set_label() {
+        let store = Arc::new(Mutex::new(MemKvStore::default()));
+        let mgr = FabricMgr::new_with_store(store.clone()).unwrap();
+        let idx1 = mgr.add(test_fabric()).unwrap() as usize;
+        let idx2 = mgr.add(test_fabric()).unwrap() as usize;
+
+        mgr.set_label(idx1, "Home").unwrap();
+        // Setting the same label again isn't a conflict, another fabric with it is
+        mgr.set_label(idx1, "Home").unwrap();
+        assert_eq!(mgr.set_label(idx2, "Home"), Err(Error::Invalid));
+        assert_eq!(
+            mgr.set_label(idx2, &"x".repeat(MAX_FABRIC_LABEL_LEN + 1)),
+            Err(Error::InvalidData)
+        );
+        // Any number of fabrics may be without a label
+        mgr.set_label(idx1, "").unwrap();
+        mgr.set_label(idx2, "").unwrap();
+        assert_eq!(mgr.set_label(3, "Office"), Err(Error::NotFound));
+
+        mgr.set_label(idx2, "Office").unwrap();
+        let mut labels = Vec::new();
+        mgr.for_each(|idx, f| labels.push((idx as usize, f.get_label().to_owned())))
+            .unwrap();
+        assert_eq!(
+            labels,
+            vec![(idx1, "".to_owned()), (idx2, "Office".to_owned())]
+        );
+
+        // The label and VendorID are persisted with the rest of the fabric
+        let loaded = FabricMgr::new_with_store(store).unwrap();
+        let f = loaded.get_fabric(idx2).unwrap();
+        let f = f.as_ref().as_ref().unwrap();
+        assert_eq!(f.get_label(), "Office");
+        assert_eq!(f.get_vendor_id(), 0xfff1);
+    }
+
+    #[test]
+    fn test_store_load() {
+        let mgr = FabricMgr::new_with_store(Arc::new(Mutex::new(MemKvStore::default()))).unwrap();
+        let mut noc = test_vectors::NOC1_SUCCESS;
+        noc[33] = 0x3;
+        let fabric2 = Fabric::new(
+            KeyPair::new().unwrap(),
+            Cert::new(&test_vectors::RCA1_SUCCESS).unwrap(),
+            Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+            Cert::new(&noc).unwrap(),
+            &[0xa5; 16],
+            0xfff2,
+        )
+        .unwrap();
+        let idx1 = mgr.add(test_fabric()).unwrap() as usize;
+        let idx2 = mgr.add(fabric2).unwrap() as usize;
+        mgr.set_label(idx2, "Home").unwrap();
+
+        let random = [0x11; 32];
+        let dest_id = |idx| {
+            let f = mgr.get_fabric(idx).unwrap();
+            let f = f.as_ref().as_ref().unwrap();
+            f.compute_dest_id(&random, f.ipk.op_key()).unwrap()
+        };
+        let (dest_id1, dest_id2) = (dest_id(idx1), dest_id(idx2));
+        let data = mgr.store().unwrap();
+
+        // Restore on a fresh node, that has nothing persisted
+        let restored =
+            FabricMgr::new_with_store(Arc::new(Mutex::new(MemKvStore::default()))).unwrap();
+        assert!(restored.is_empty());
+        restored.load(&data).unwrap();
+        assert_eq!(restored.match_dest_id(&random, &dest_id1), Ok(idx1));
+        assert_eq!(restored.match_dest_id(&random, &dest_id2), Ok(idx2));
+        let f = restored.get_fabric(idx2).unwrap();
+        let f = f.as_ref().as_ref().unwrap();
+        assert_eq!(f.get_node_id(), 0xbc5c03);
+        assert_eq!(f.ipk.epoch_key(), &[0xa5; 16]);
+        assert_eq!(f.get_vendor_id(), 0xfff2);
+        assert_eq!(f.get_label(), "Home");
+
+        // The restored fabrics are also persisted to the KvStore
+        assert_eq!(restored.store().unwrap(), data);
+    }
+
+    #[test]
+    fn test_dest_id_spec_vector() {
+        // Test vector from the Matter specification (Destination Identifier)
+        let root_pubkey = [
+            0x04, 0x4a, 0x9f, 0x42, 0xb1, 0xca, 0x48, 0x40, 0xd3, 0x72, 0x92, 0xbb, 0xc7, 0xf6,
+            0xa7, 0xe1, 0x1e, 0x22, 0x20, 0x0c, 0x97, 0x6f, 0xc9, 0x00, 0xdb, 0xc9, 0x8a, 0x7a,
+            0x38, 0x3a, 0x64, 0x1c, 0xb8, 0x25, 0x4a, 0x2e, 0x56, 0xd4, 0xe2, 0x95, 0xa8, 0x47,
+            0x94, 0x3b, 0x4e, 0x38, 0x97, 0xc4, 0xa7, 0x73, 0xe9, 0x30, 0x27, 0x7b, 0x4d, 0x9f,
+            0xbe, 0xde, 0x8a, 0x05, 0x26, 0x86, 0xbf, 0xac, 0xfa,
+        ];
+        let fabric_id = 0x2906_c908_d115_d362;
+        let node_id = 0xcd55_44aa_7b13_ef14;
+        let ipk = [
+            0x9b, 0xc6, 0x1c, 0xd9, 0xc6, 0x2a, 0x2d, 0xf6, 0xd6, 0x4d, 0xfc, 0xaa, 0x9d, 0xc4,
+            0x72, 0xd4,
+        ];
+        let initiator_random = [
+            0x7e, 0x17, 0x12, 0x31, 0x56, 0x8d, 0xfa, 0x17, 0x20, 0x6b, 0x3a, 0xcc, 0xf8, 0xfa,
+            0xec, 0x2f, 0x4d, 0x21, 0xb5, 0x80, 0x11, 0x31, 0x96, 0xf4, 0x7c, 0x7c, 0x4d, 0xeb,
+            0x81, 0x0a, 0x73, 0xdc,
+        ];
+        let expected = [
+            0xdc, 0x35, 0xdd, 0x5f, 0xc9, 0x13, 0x4c, 0xc5, 0x54, 0x45, 0x38, 0xc9, 0xc3, 0xfc,
+            0x42, 0x97, 0xc1, 0xec, 0x33, 0x70, 0xc8, 0x39, 0x13, 0x6a, 0x80, 0xe1, 0x07, 0x96,
+            0x45, 0x1d, 0x4c, 0x53,
+        ];
+
+        let id = compute_dest_id(&initiator_random, &root_pubkey, fabric_id, node_id, &ipk);
+        assert_eq!(id, Ok(expected));
+    }
+}
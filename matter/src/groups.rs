@@ -0,0 +1,137 @@
+use std::sync::RwLock;
+
+use crate::error::Error;
+
+/// The maximum number of (group, endpoint) memberships across all fabrics
+pub const MAX_GROUP_MEMBERSHIPS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Membership {
+    fab_idx: u8,
+    group_id: u16,
+    endpoint: u16,
+}
+
+/// The Group Manager, tracking the endpoints that are members of each group
+///
+/// Groups are scoped to a fabric, the same group id on different fabrics refers to
+/// different groups. The memberships aren't persisted yet.
+pub struct GroupMgr {
+    memberships: RwLock<Vec<Membership>>,
+}
+
+impl Default for GroupMgr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GroupMgr {
+    pub fn new() -> Self {
+        Self {
+            memberships: RwLock::new(Vec::with_capacity(MAX_GROUP_MEMBERSHIPS)),
+        }
+    }
+
+    /// Add 'endpoint' to the group, this is a no-op if it is already a member
+    pub fn add(&self, fab_idx: u8, group_id: u16, endpoint: u16) -> Result<(), Error> {
+        if group_id == 0 {
+            return Err(Error::Invalid);
+        }
+        let m = Membership {
+            fab_idx,
+            group_id,
+            endpoint,
+        };
+        let mut memberships = self.memberships.write()?;
+        if memberships.contains(&m) {
+            return Ok(());
+        }
+        if memberships.len() >= MAX_GROUP_MEMBERSHIPS {
+            return Err(Error::NoSpace);
+        }
+        memberships.push(m);
+        Ok(())
+    }
+
+    pub fn remove(&self, fab_idx: u8, group_id: u16, endpoint: u16) -> Result<(), Error> {
+        let m = Membership {
+            fab_idx,
+            group_id,
+            endpoint,
+        };
+        let mut memberships = self.memberships.write()?;
+        let index = memberships
+            .iter()
+            .position(|x| *x == m)
+            .ok_or(Error::NotFound)?;
+        memberships.remove(index);
+        Ok(())
+    }
+
+    /// Remove 'endpoint' from all the groups of the fabric
+    pub fn remove_all(&self, fab_idx: u8, endpoint: u16) -> Result<(), Error> {
+        self.memberships
+            .write()?
+            .retain(|m| !(m.fab_idx == fab_idx && m.endpoint == endpoint));
+        Ok(())
+    }
+
+    pub fn is_member(&self, fab_idx: u8, group_id: u16, endpoint: u16) -> bool {
+        let m = Membership {
+            fab_idx,
+            group_id,
+            endpoint,
+        };
+        self.memberships
+            .read()
+            .map(|x| x.contains(&m))
+            .unwrap_or(false)
+    }
+
+    /// The endpoints that are members of the group
+    pub fn endpoints(&self, fab_idx: u8, group_id: u16) -> Result<Vec<u16>, Error> {
+        Ok(self
+            .memberships
+            .read()?
+            .iter()
+            .filter(|m| m.fab_idx == fab_idx && m.group_id == group_id)
+            .map(|m| m.endpoint)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GroupMgr, MAX_GROUP_MEMBERSHIPS};
+    use crate::error::Error;
+
+    #[test]
+    fn test_membership() {
+        let gm = GroupMgr::new();
+        gm.add(1, 0x10, 1).unwrap();
+        gm.add(1, 0x10, 2).unwrap();
+        gm.add(1, 0x10, 2).unwrap();
+        gm.add(2, 0x10, 3).unwrap();
+        assert_eq!(gm.endpoints(1, 0x10).unwrap(), vec![1, 2]);
+        assert!(gm.is_member(2, 0x10, 3));
+        assert!(!gm.is_member(1, 0x10, 3));
+
+        gm.remove(1, 0x10, 1).unwrap();
+        assert_eq!(gm.remove(1, 0x10, 1), Err(Error::NotFound));
+        gm.remove_all(1, 2).unwrap();
+        assert!(gm.endpoints(1, 0x10).unwrap().is_empty());
+        assert_eq!(gm.endpoints(2, 0x10).unwrap(), vec![3]);
+
+        assert_eq!(gm.add(1, 0, 1), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn test_membership_full() {
+        let gm = GroupMgr::new();
+        for i in 0..MAX_GROUP_MEMBERSHIPS {
+            gm.add(1, 0x10, i as u16).unwrap();
+        }
+        assert_eq!(gm.add(1, 0x11, 1), Err(Error::NoSpace));
+    }
+}
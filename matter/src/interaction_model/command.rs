@@ -1,13 +1,21 @@
+use super::core::IMStatusCode;
 use super::core::OpCode;
+use super::core::PROTO_ID_INTERACTION_MODEL;
 use super::messages::ib;
 use super::messages::msg;
 use super::messages::msg::InvReq;
 use super::InteractionModel;
 use super::Transaction;
 use crate::{
+    data_model::objects::{Cluster, EncodeValue, Node},
     error::*,
-    tlv::{get_root_node_struct, print_tlv_list, FromTLV, TLVElement, TLVWriter, TagType},
-    transport::{packet::Packet, proto_demux::ResponseRequired},
+    tlv::{get_root_node_struct, print_tlv_list, FromTLV, TLVElement, TLVWriter, TagType, ToTLV},
+    transport::{
+        packet::{Packet, MAX_TX_BUF_SIZE},
+        proto_demux::ResponseRequired,
+        queue::{DeferredResponse, Msg, WorkQ},
+    },
+    utils::writebuf::WriteBuf,
 };
 use log::error;
 
@@ -19,11 +27,153 @@ macro_rules! cmd_enter {
     }};
 }
 
+/// The outcome of a command handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// The command was handled, its response (if any) is already encoded
+    Complete,
+    /// The command was deferred with `CommandReq::defer()`, the response will
+    /// be sent out of band through the `DeferredCmd`
+    Deferred,
+}
+
 pub struct CommandReq<'a, 'b, 'c, 'd> {
     pub cmd: ib::CmdPath,
     pub data: TLVElement<'a>,
     pub resp: &'a mut TLVWriter<'b, 'c>,
     pub trans: &'a mut Transaction<'d>,
+    /// The rest of the node, the cluster handling the command isn't part of it
+    ///
+    /// Handlers must look at their sibling clusters through this, and never lock the
+    /// DataModel's node, which is held for writing while the command is handled.
+    pub node: &'a Node,
+}
+
+impl<'a, 'b, 'c, 'd> CommandReq<'a, 'b, 'c, 'd> {
+    /// Encode the response command `cmd` of `cluster`, with the given data
+    ///
+    /// The response command must be in the cluster's GeneratedCommandList. Anything else is a
+    /// programming error, which panics in debug builds.
+    pub fn respond(&mut self, cluster: &Cluster, cmd: u16, data: EncodeValue) -> Result<(), Error> {
+        let generated = cluster.is_generated_command(cmd);
+        debug_assert!(
+            generated,
+            "Command {:#x} isn't in the GeneratedCommandList of cluster {:#x}",
+            cmd,
+            cluster.id()
+        );
+        if !generated {
+            error!(
+                "Command {:#x} isn't in the GeneratedCommandList of cluster {:#x}",
+                cmd,
+                cluster.id()
+            );
+            return Err(Error::Invalid);
+        }
+
+        let mut path = self.cmd;
+        path.path.leaf = Some(cmd as u32);
+        let resp = ib::InvResp::Cmd(ib::CmdData::new(path, data));
+        resp.to_tlv(self.resp, TagType::Anonymous)
+    }
+
+    /// Respond to the command with a Busy status, asking the peer to retry after
+    /// 'retry_delay' milliseconds
+    pub fn busy(&mut self, retry_delay: u16) -> Result<(), Error> {
+        ib::InvResp::busy_new(self.cmd, retry_delay).to_tlv(self.resp, TagType::Anonymous)
+    }
+
+    /// Check that the command carries all of its 'mandatory' fields
+    ///
+    /// A missing field is an InvalidCommand. Fields that aren't known to the handler are
+    /// not checked at all, so that commands from newer revisions of a cluster are accepted.
+    pub fn check_fields(&self, mandatory: &[u8]) -> Result<(), IMStatusCode> {
+        for tag in mandatory {
+            if self.data.find_tag(*tag as u32).is_err() {
+                error!(
+                    "Command {:?} is missing the mandatory field {}",
+                    self.cmd.path.leaf, tag
+                );
+                return Err(IMStatusCode::InvalidCommand);
+            }
+        }
+        Ok(())
+    }
+
+    /// Defer the completion of this command
+    ///
+    /// The handler should return `CommandStatus::Deferred` after this, and complete
+    /// the command later through the returned `DeferredCmd`, for instance from
+    /// another thread. Deferral is only possible for an Invoke Request with a
+    /// single, concrete command path.
+    pub fn defer(&mut self) -> Result<DeferredCmd, Error> {
+        let exch_id = self.trans.defer_exch_id.ok_or(Error::Invalid)?;
+        self.trans.deferred = true;
+        Ok(DeferredCmd {
+            exch_id,
+            cmd: self.cmd,
+            done: false,
+        })
+    }
+}
+
+/// A command whose response is sent after its handler has returned
+///
+/// If this is dropped without being completed, the command is cancelled and a
+/// Failure status is sent to the peer.
+pub struct DeferredCmd {
+    exch_id: u16,
+    cmd: ib::CmdPath,
+    done: bool,
+}
+
+impl DeferredCmd {
+    /// Complete the command with the response command `cmd`, with the given data
+    pub fn respond(mut self, cmd: u16, data: EncodeValue) -> Result<(), Error> {
+        let mut path = self.cmd;
+        path.path.leaf = Some(cmd as u32);
+        self.send(&ib::InvResp::Cmd(ib::CmdData::new(path, data)))
+    }
+
+    /// Complete the command with a status
+    pub fn complete(mut self, status: IMStatusCode) -> Result<(), Error> {
+        self.send(&ib::InvResp::status_new(self.cmd, status, 0))
+    }
+
+    fn send(&mut self, resp: &ib::InvResp) -> Result<(), Error> {
+        self.done = true;
+
+        let mut buf = [0u8; MAX_TX_BUF_SIZE];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous)?;
+        tw.bool(
+            TagType::Context(msg::InvRespTag::SupressResponse as u8),
+            false,
+        )?;
+        tw.start_array(TagType::Context(msg::InvRespTag::InvokeResponses as u8))?;
+        resp.to_tlv(&mut tw, TagType::Anonymous)?;
+        tw.end_container()?;
+        tw.end_container()?;
+
+        WorkQ::get()?.sync_send(Msg::DeferredResponse(DeferredResponse {
+            exch_id: self.exch_id,
+            proto_id: PROTO_ID_INTERACTION_MODEL as u16,
+            proto_opcode: OpCode::InvokeResponse as u8,
+            payload: wb.as_slice().to_vec(),
+        }))
+    }
+}
+
+impl Drop for DeferredCmd {
+    fn drop(&mut self) {
+        if !self.done {
+            error!("Deferred command {:?} was cancelled", self.cmd.path);
+            let resp = ib::InvResp::status_new(self.cmd, IMStatusCode::Failure, 0);
+            let _ = self.send(&resp);
+        }
+    }
 }
 
 impl InteractionModel {
@@ -38,7 +188,11 @@ impl InteractionModel {
         let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
         let root = get_root_node_struct(rx_buf)?;
         let inv_req = InvReq::from_tlv(&root)?;
+        if !can_defer(&inv_req) {
+            trans.defer_exch_id = None;
+        }
 
+        let anchor = tw.anchor();
         tw.start_struct(TagType::Anonymous)?;
         // Suppress Response -> TODO: Need to revisit this for cases where we send a command back
         tw.bool(
@@ -54,6 +208,100 @@ impl InteractionModel {
                 e
             })?;
         tw.end_container()?;
+        if trans.deferred {
+            // The response is sent once the deferred command completes
+            tw.rewind_to(anchor)?;
+            return Ok(ResponseRequired::No);
+        }
         Ok(ResponseRequired::Yes)
     }
+
+    /// Handle an Invoke Request that was addressed to the group 'group_id'
+    ///
+    /// The commands are invoked on all the endpoints that are members of the group, on
+    /// the fabric of the session. Group commands never have a response.
+    // TODO: The transport doesn't support group sessions yet
+    pub fn handle_group_invoke_req(
+        &mut self,
+        trans: &mut Transaction,
+        group_id: u16,
+        rx_buf: &[u8],
+    ) -> Result<ResponseRequired, Error> {
+        let root = get_root_node_struct(rx_buf)?;
+        let inv_req = InvReq::from_tlv(&root)?;
+        self.consumer
+            .consume_group_invoke_cmd(group_id, &inv_req, trans)?;
+        trans.complete();
+        Ok(ResponseRequired::No)
+    }
+}
+
+// Only a single, concrete command can be deferred, its response then makes up the
+// entire Invoke Response
+fn can_defer(inv_req: &InvReq) -> bool {
+    match &inv_req.inv_requests {
+        Some(cmds) => {
+            let mut iter = cmds.iter();
+            matches!((iter.next(), iter.next()), (Some(c), None) if !c.path.path.is_wildcard())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandReq;
+    use crate::{
+        data_model::objects::{Cluster, EncodeValue, Node},
+        interaction_model::{messages::ib::CmdPath, Transaction},
+        tlv::{get_root_node_struct, ElementType, TLVElement, TLVWriter, TagType},
+        transport::{network::Address, session::Session},
+        utils::writebuf::WriteBuf,
+    };
+
+    fn respond(cluster: &Cluster, cmd: u16, buf: &mut [u8]) -> usize {
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        let mut sess = Session::new(Address::default(), None);
+        let mut trans = Transaction::new(&mut sess);
+        let data = TLVElement::new(TagType::Anonymous, ElementType::U8(0));
+        let node = Node::new().unwrap();
+        let mut cmd_req = CommandReq {
+            cmd: CmdPath::new(Some(1), Some(cluster.id()), Some(0)),
+            data,
+            resp: &mut tw,
+            trans: &mut trans,
+            node: &node,
+        };
+        cmd_req
+            .respond(cluster, cmd, EncodeValue::Value(&5u8))
+            .unwrap();
+        wb.as_slice().len()
+    }
+
+    #[test]
+    fn test_respond_generated_cmd() {
+        let mut cluster = Cluster::new(0xabcd).unwrap();
+        cluster.set_generated_commands(&[1]).unwrap();
+
+        let mut buf = [0u8; 50];
+        let len = respond(&cluster, 1, &mut buf);
+        let root = get_root_node_struct(&buf[..len]).unwrap();
+        let path = root.find_tag(0).unwrap().find_tag(0).unwrap();
+        assert_eq!(path.find_tag(0).unwrap().u16().unwrap(), 1);
+        assert_eq!(path.find_tag(1).unwrap().u32().unwrap(), 0xabcd);
+        assert_eq!(path.find_tag(2).unwrap().u32().unwrap(), 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "GeneratedCommandList")]
+    fn test_respond_undeclared_cmd() {
+        let mut cluster = Cluster::new(0xabcd).unwrap();
+        cluster.set_generated_commands(&[1]).unwrap();
+
+        let mut buf = [0u8; 50];
+        respond(&cluster, 2, &mut buf);
+    }
 }
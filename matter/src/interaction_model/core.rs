@@ -2,6 +2,7 @@ use crate::{
     error::*,
     tlv::{self, FromTLV, TLVElement, TLVWriter, TagType, ToTLV},
     transport::{
+        exchange::ExchangeMgr,
         proto_demux::{self, ProtoCtx, ResponseRequired},
         session::Session,
     },
@@ -10,6 +11,7 @@ use colored::Colorize;
 use log::{error, info};
 use num;
 use num_derive::FromPrimitive;
+use std::{fmt, time::SystemTime};
 
 use super::InteractionConsumer;
 use super::InteractionModel;
@@ -20,7 +22,7 @@ use super::TransactionState;
  */
 
 /* Interaction Model ID as per the Matter Spec */
-const PROTO_ID_INTERACTION_MODEL: usize = 0x01;
+pub(super) const PROTO_ID_INTERACTION_MODEL: usize = 0x01;
 
 #[derive(FromPrimitive, Debug, Copy, Clone)]
 pub enum OpCode {
@@ -43,6 +45,8 @@ impl<'a> Transaction<'a> {
             state: TransactionState::Ongoing,
             data: None,
             session,
+            defer_exch_id: None,
+            deferred: false,
         }
     }
 
@@ -57,13 +61,18 @@ impl<'a> Transaction<'a> {
 
 impl InteractionModel {
     pub fn new(consumer: Box<dyn InteractionConsumer>) -> InteractionModel {
-        InteractionModel { consumer }
+        InteractionModel {
+            consumer,
+            subscriptions: Default::default(),
+        }
     }
 }
 
 impl proto_demux::HandleProto for InteractionModel {
     fn handle_proto_id(&mut self, ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
         let mut trans = Transaction::new(&mut ctx.exch_ctx.sess);
+        // Resume any interaction that is in progress on this exchange
+        trans.data = ctx.exch_ctx.exch.take_exchange_data_any();
         let proto_opcode: OpCode =
             num::FromPrimitive::from_u8(ctx.rx.get_proto_opcode()).ok_or(Error::Invalid)?;
         ctx.tx.set_proto_id(PROTO_ID_INTERACTION_MODEL as u16);
@@ -72,9 +81,14 @@ impl proto_demux::HandleProto for InteractionModel {
         info!("{} {:?}", "Received command".cyan(), proto_opcode);
         tlv::print_tlv_list(buf);
         let result = match proto_opcode {
-            OpCode::InvokeRequest => self.handle_invoke_req(&mut trans, buf, &mut ctx.tx)?,
+            OpCode::InvokeRequest => {
+                trans.defer_exch_id = Some(ctx.exch_ctx.exch.get_id());
+                self.handle_invoke_req(&mut trans, buf, &mut ctx.tx)?
+            }
             OpCode::ReadRequest => self.handle_read_req(&mut trans, buf, &mut ctx.tx)?,
             OpCode::WriteRequest => self.handle_write_req(&mut trans, buf, &mut ctx.tx)?,
+            OpCode::SubscribeRequest => self.handle_subscribe_req(&mut trans, buf, &mut ctx.tx)?,
+            OpCode::StatusResponse => self.handle_status_response(&mut trans, buf, &mut ctx.tx)?,
             _ => {
                 error!("Opcode Not Handled: {:?}", proto_opcode);
                 return Err(Error::InvalidOpcode);
@@ -87,6 +101,8 @@ impl proto_demux::HandleProto for InteractionModel {
         }
         if trans.is_complete() {
             ctx.exch_ctx.exch.close();
+        } else if let Some(data) = trans.data.take() {
+            ctx.exch_ctx.exch.set_exchange_data(data);
         }
         Ok(result)
     }
@@ -94,11 +110,15 @@ impl proto_demux::HandleProto for InteractionModel {
     fn get_proto_id(&self) -> usize {
         PROTO_ID_INTERACTION_MODEL as usize
     }
+
+    fn handle_tick(&mut self, now: SystemTime, exch_mgr: &mut ExchangeMgr) -> Option<SystemTime> {
+        self.report_subscriptions(now, exch_mgr)
+    }
 }
 
-#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IMStatusCode {
-    Sucess = 0,
+    Success = 0,
     Failure = 1,
     InvalidSubscription = 0x7D,
     UnsupportedAccess = 0x7E,
@@ -120,6 +140,57 @@ pub enum IMStatusCode {
     UnsupportedCluster = 0xc3,
     NoUpstreamSubscription = 0xc5,
     NeedsTimedInteraction = 0xc6,
+    PathsExhausted = 0xc8,
+}
+
+impl IMStatusCode {
+    #[deprecated(note = "use IMStatusCode::Success")]
+    #[allow(non_upper_case_globals)]
+    pub const Sucess: IMStatusCode = IMStatusCode::Success;
+
+    /// Ok for a Success, the closest Error otherwise
+    pub fn to_result(self) -> Result<(), Error> {
+        match self {
+            IMStatusCode::Success => Ok(()),
+            _ => Err(self.into()),
+        }
+    }
+
+    /// A short description of the status code
+    pub fn description(&self) -> &'static str {
+        match self {
+            IMStatusCode::Success => "Operation was successful",
+            IMStatusCode::Failure => "Operation was not successful",
+            IMStatusCode::InvalidSubscription => "Subscription ID is not active",
+            IMStatusCode::UnsupportedAccess => "Insufficient privilege for the operation",
+            IMStatusCode::UnsupportedEndpoint => "The endpoint doesn't exist",
+            IMStatusCode::InvalidAction => "The action is malformed or not allowed",
+            IMStatusCode::UnsupportedCommand => "The command isn't supported",
+            IMStatusCode::InvalidCommand => "The command has invalid data",
+            IMStatusCode::UnsupportedAttribute => "The attribute isn't supported",
+            IMStatusCode::ConstraintError => "The value is out of range",
+            IMStatusCode::UnsupportedWrite => "The attribute can't be written",
+            IMStatusCode::ResourceExhausted => "The resources are exhausted",
+            IMStatusCode::NotFound => "The entry wasn't found",
+            IMStatusCode::UnreportableAttribute => "The attribute can't be reported",
+            IMStatusCode::InvalidDataType => "The data type is invalid",
+            IMStatusCode::UnsupportedRead => "The attribute can't be read",
+            IMStatusCode::DataVersionMismatch => "The data version didn't match",
+            IMStatusCode::Timeout => "The transaction timed out",
+            IMStatusCode::Busy => "The receiver is busy",
+            IMStatusCode::UnsupportedCluster => "The cluster isn't supported",
+            IMStatusCode::NoUpstreamSubscription => "There is no upstream subscription",
+            IMStatusCode::NeedsTimedInteraction => "A timed interaction is required",
+            IMStatusCode::PathsExhausted => "The request has more paths than can be handled",
+        }
+    }
+}
+
+impl fmt::Display for IMStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The spec names match the variants
+        fmt::Debug::fmt(self, f)
+    }
 }
 
 impl From<Error> for IMStatusCode {
@@ -134,6 +205,33 @@ impl From<Error> for IMStatusCode {
     }
 }
 
+/// Map a status received from a peer, like the responses to a controller, to an Error
+///
+/// The status codes that have no counterpart map to Error::Invalid. Success isn't an error
+/// either, use IMStatusCode::to_result() when the status may be a Success.
+impl From<IMStatusCode> for Error {
+    fn from(status: IMStatusCode) -> Self {
+        match status {
+            IMStatusCode::UnsupportedEndpoint => Error::EndpointNotFound,
+            IMStatusCode::UnsupportedCluster => Error::ClusterNotFound,
+            IMStatusCode::UnsupportedAttribute => Error::AttributeNotFound,
+            IMStatusCode::UnsupportedCommand => Error::CommandNotFound,
+            IMStatusCode::NotFound => Error::NotFound,
+            IMStatusCode::ResourceExhausted => Error::NoSpace,
+            // The peer doesn't say which types didn't match
+            IMStatusCode::InvalidDataType => Error::TLVTypeMismatch {
+                expected: "unknown",
+                found: "unknown",
+            },
+            IMStatusCode::InvalidCommand => Error::InvalidData,
+            IMStatusCode::InvalidSubscription | IMStatusCode::DataVersionMismatch => {
+                Error::InvalidState
+            }
+            _ => Error::Invalid,
+        }
+    }
+}
+
 impl FromTLV<'_> for IMStatusCode {
     fn from_tlv(t: &TLVElement) -> Result<Self, Error> {
         num::FromPrimitive::from_u16(t.u16()?).ok_or(Error::Invalid)
@@ -145,3 +243,58 @@ impl ToTLV for IMStatusCode {
         tw.u16(tag_type, *self as u16)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IMStatusCode;
+    use crate::error::Error;
+
+    #[test]
+    fn test_status_code_display() {
+        assert_eq!(
+            IMStatusCode::UnsupportedCluster.to_string(),
+            "UnsupportedCluster"
+        );
+        assert_eq!(IMStatusCode::Success.to_string(), "Success");
+        assert_eq!(
+            IMStatusCode::UnsupportedCluster.description(),
+            "The cluster isn't supported"
+        );
+
+        #[allow(deprecated)]
+        let old = IMStatusCode::Sucess;
+        assert_eq!(old, IMStatusCode::Success);
+    }
+
+    #[test]
+    fn test_status_code_to_error() {
+        // The 'not found' family maps both ways
+        for (status, error) in [
+            (IMStatusCode::UnsupportedEndpoint, Error::EndpointNotFound),
+            (IMStatusCode::UnsupportedCluster, Error::ClusterNotFound),
+            (IMStatusCode::UnsupportedAttribute, Error::AttributeNotFound),
+            (IMStatusCode::UnsupportedCommand, Error::CommandNotFound),
+        ] {
+            assert_eq!(Error::from(status), error);
+            assert_eq!(IMStatusCode::from(error), status);
+        }
+
+        assert_eq!(Error::from(IMStatusCode::ResourceExhausted), Error::NoSpace);
+        assert_eq!(
+            Error::from(IMStatusCode::InvalidDataType),
+            Error::TLVTypeMismatch {
+                expected: "unknown",
+                found: "unknown"
+            }
+        );
+        // Without a counterpart, the Error doesn't map back to the same status
+        assert_eq!(Error::from(IMStatusCode::Busy), Error::Invalid);
+        assert_eq!(IMStatusCode::from(Error::Invalid), IMStatusCode::Failure);
+
+        assert_eq!(IMStatusCode::Success.to_result(), Ok(()));
+        assert_eq!(
+            IMStatusCode::UnsupportedCluster.to_result(),
+            Err(Error::ClusterNotFound)
+        );
+    }
+}
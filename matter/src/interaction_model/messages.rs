@@ -2,10 +2,11 @@ use crate::{
     error::Error,
     tlv::{FromTLV, TLVElement, TLVWriter, TagType, ToTLV},
 };
+use std::convert::TryFrom;
 
 // A generic path with endpoint, clusters, and a leaf
 // The leaf could be command, attribute, event
-#[derive(Default, Clone, Copy, Debug, PartialEq, FromTLV, ToTLV)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, FromTLV, ToTLV)]
 #[tlvargs(datatype = "list")]
 pub struct GenericPath {
     pub endpoint: Option<u16>,
@@ -22,6 +23,26 @@ impl GenericPath {
         }
     }
 
+    /// Parses a path of the form "endpoint/cluster/leaf"
+    ///
+    /// Each segment is either a decimal or a 0x-prefixed hex number, or
+    /// '*' for a wildcard. For example, "*/0x6/1" is the On command of
+    /// the On/Off cluster on all endpoints.
+    pub fn parse(path: &str) -> Result<Self, Error> {
+        let mut segments = path.split('/');
+        let endpoint = parse_segment(segments.next())?;
+        let cluster = parse_segment(segments.next())?;
+        let leaf = parse_segment(segments.next())?;
+        if segments.next().is_some() {
+            return Err(Error::Invalid);
+        }
+
+        let endpoint = endpoint
+            .map(|e| u16::try_from(e).map_err(|_| Error::Invalid))
+            .transpose()?;
+        Ok(Self::new(endpoint, cluster, leaf))
+    }
+
     /// Returns Ok, if the path is non wildcard, otherwise returns an error
     pub fn not_wildcard(&self) -> Result<(u16, u32, u32), Error> {
         match *self {
@@ -46,15 +67,54 @@ impl GenericPath {
     }
 }
 
+fn parse_segment(segment: Option<&str>) -> Result<Option<u32>, Error> {
+    match segment.ok_or(Error::Invalid)? {
+        "*" => Ok(None),
+        s => {
+            let val = if let Some(hex) = s.strip_prefix("0x") {
+                u32::from_str_radix(hex, 16)
+            } else {
+                s.parse::<u32>()
+            };
+            val.map(Some).map_err(|_| Error::Invalid)
+        }
+    }
+}
+
 pub mod msg {
 
     use crate::{
         error::Error,
+        interaction_model::core::IMStatusCode,
         tlv::{FromTLV, TLVArray, TLVElement, TLVWriter, TagType, ToTLV},
     };
 
     use super::ib::{AttrData, AttrPath, CmdData};
 
+    #[derive(Debug, PartialEq, FromTLV, ToTLV)]
+    pub struct StatusResp {
+        pub status: IMStatusCode,
+        /// The time in milliseconds after which a Busy request may be retried
+        pub retry_delay: Option<u16>,
+    }
+
+    impl StatusResp {
+        pub fn new(status: IMStatusCode) -> Self {
+            Self {
+                status,
+                retry_delay: None,
+            }
+        }
+
+        /// A Busy status, asking the peer to retry after 'retry_delay' milliseconds
+        pub fn busy(retry_delay: u16) -> Self {
+            Self {
+                status: IMStatusCode::Busy,
+                retry_delay: Some(retry_delay),
+            }
+        }
+    }
+
     #[derive(FromTLV)]
     #[tlvargs(lifetime = "'a")]
     pub struct InvReq<'a> {
@@ -98,13 +158,57 @@ pub mod msg {
         }
     }
 
+    #[derive(Default, ToTLV, FromTLV)]
+    #[tlvargs(lifetime = "'a")]
+    pub struct SubscribeReq<'a> {
+        pub keep_subs: bool,
+        pub min_int_floor: u16,
+        pub max_int_ceil: u16,
+        pub attr_requests: Option<TLVArray<'a, AttrPath>>,
+        event_requests: Option<bool>,
+        event_filters: Option<bool>,
+        #[tagval(7)]
+        pub fabric_filtered: bool,
+        #[tagval(8)]
+        pub dataver_filters: Option<TLVArray<'a, bool>>,
+    }
+
+    impl<'a> SubscribeReq<'a> {
+        pub fn new(fabric_filtered: bool, min_int_floor: u16, max_int_ceil: u16) -> Self {
+            Self {
+                fabric_filtered,
+                min_int_floor,
+                max_int_ceil,
+                ..Default::default()
+            }
+        }
+
+        pub fn set_attr_requests(mut self, requests: &'a [AttrPath]) -> Self {
+            self.attr_requests = Some(TLVArray::new(requests));
+            self
+        }
+    }
+
+    #[derive(Debug, PartialEq, FromTLV, ToTLV)]
+    pub struct SubscribeResp {
+        pub subs_id: u32,
+        #[tagval(2)]
+        pub max_int: u16,
+    }
+
+    impl SubscribeResp {
+        pub fn new(subs_id: u32, max_int: u16) -> Self {
+            Self { subs_id, max_int }
+        }
+    }
+
     #[derive(ToTLV, FromTLV)]
     #[tlvargs(lifetime = "'b")]
     pub struct WriteReq<'a, 'b> {
         pub supress_response: Option<bool>,
         timed_request: Option<bool>,
         pub write_requests: TLVArray<'a, AttrData<'b>>,
-        more_chunked: Option<bool>,
+        pub more_chunked: Option<bool>,
     }
 
     impl<'a, 'b> WriteReq<'a, 'b> {
@@ -124,10 +228,10 @@ pub mod msg {
 
     // Report Data
     pub enum ReportDataTag {
-        _SubscriptionId = 0,
+        SubscriptionId = 0,
         AttributeReports = 1,
-        _EventReport = 2,
-        _MoreChunkedMsgs = 3,
+        EventReports = 2,
+        MoreChunkedMsgs = 3,
         SupressResponse = 4,
     }
 
@@ -144,7 +248,7 @@ pub mod ib {
         data_model::objects::EncodeValue,
         error::Error,
         interaction_model::core::IMStatusCode,
-        tlv::{FromTLV, TLVElement, TLVWriter, TagType, ToTLV},
+        tlv::{FromTLV, Nullable, TLVElement, TLVWriter, TagType, ToTLV},
     };
     use log::error;
     use num_derive::FromPrimitive;
@@ -179,6 +283,14 @@ pub mod ib {
             })
         }
 
+        /// A Busy status for 'cmd_path', asking the peer to retry after 'retry_delay' milliseconds
+        pub fn busy_new(cmd_path: CmdPath, retry_delay: u16) -> Self {
+            Self::Status(CmdStatus {
+                path: cmd_path,
+                status: Status::busy(retry_delay),
+            })
+        }
+
         pub fn from_tlv(resp: &TLVElement<'a>) -> Result<Self, Error> {
             let resp = resp
                 .confirm_struct()?
@@ -189,7 +301,7 @@ pub mod ib {
             let tag = match resp.get_tag() {
                 TagType::Context(a) => a,
                 _ => {
-                    return Err(Error::TLVTypeMismatch);
+                    return Err(Error::Invalid);
                 }
             };
 
@@ -225,12 +337,13 @@ pub mod ib {
         pub fn new(path: CmdPath, status: IMStatusCode, cluster_status: u16) -> Self {
             Self {
                 path,
-                status: Status {
-                    status,
-                    cluster_status,
-                },
+                status: Status::new(status, cluster_status),
             }
         }
+
+        pub fn status(&self) -> &Status {
+            &self.status
+        }
     }
 
     #[derive(Debug, Clone, Copy, FromTLV, ToTLV)]
@@ -251,6 +364,8 @@ pub mod ib {
     pub struct Status {
         pub status: IMStatusCode,
         pub cluster_status: u16,
+        /// The time in milliseconds after which a Busy request may be retried
+        pub retry_delay: Option<u16>,
     }
 
     impl Status {
@@ -258,6 +373,15 @@ pub mod ib {
             Status {
                 status,
                 cluster_status,
+                retry_delay: None,
+            }
+        }
+
+        /// A Busy status, asking the peer to retry after 'retry_delay' milliseconds
+        pub fn busy(retry_delay: u16) -> Status {
+            Status {
+                retry_delay: Some(retry_delay),
+                ..Status::new(IMStatusCode::Busy, 0)
             }
         }
     }
@@ -307,7 +431,7 @@ pub mod ib {
             let tag = match resp.get_tag() {
                 TagType::Context(a) => a,
                 _ => {
-                    return Err(Error::TLVTypeMismatch);
+                    return Err(Error::Invalid);
                 }
             };
 
@@ -367,7 +491,7 @@ pub mod ib {
         pub endpoint: Option<u16>,
         pub cluster: Option<u32>,
         pub attr: Option<u16>,
-        pub list_index: Option<u16>,
+        pub list_index: Option<Nullable<u16>>,
     }
 
     impl AttrPath {
@@ -380,6 +504,15 @@ pub mod ib {
             }
         }
 
+        /// Parses an "endpoint/cluster/attribute" string, see GenericPath::parse()
+        pub fn parse(path: &str) -> Result<Self, Error> {
+            let path = GenericPath::parse(path)?;
+            if matches!(path.leaf, Some(a) if a > u16::MAX as u32) {
+                return Err(Error::Invalid);
+            }
+            Ok(Self::new(&path))
+        }
+
         pub fn to_gp(&self) -> GenericPath {
             GenericPath::new(self.endpoint, self.cluster, self.attr.map(|x| x as u32))
         }
@@ -415,6 +548,15 @@ pub mod ib {
                 },
             }
         }
+
+        /// Parses an "endpoint/cluster/command" string, see GenericPath::parse()
+        pub fn parse(path: &str) -> Result<Self, Error> {
+            let path = GenericPath::parse(path)?;
+            if matches!(path.leaf, Some(a) if a > u16::MAX as u32) {
+                return Err(Error::Invalid);
+            }
+            Ok(Self { path })
+        }
     }
 
     impl FromTLV<'_> for CmdPath {
@@ -438,3 +580,44 @@ pub mod ib {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ib::{AttrPath, CmdPath},
+        GenericPath,
+    };
+
+    #[test]
+    fn test_parse_concrete_path() {
+        assert_eq!(
+            CmdPath::parse("0/6/1").unwrap(),
+            CmdPath::new(Some(0), Some(6), Some(1))
+        );
+        assert_eq!(
+            AttrPath::parse("1/0x28/0x0").unwrap(),
+            AttrPath::new(&GenericPath::new(Some(1), Some(0x28), Some(0)))
+        );
+    }
+
+    #[test]
+    fn test_parse_wildcard_path() {
+        assert_eq!(
+            CmdPath::parse("*/6/1").unwrap(),
+            CmdPath::new(None, Some(6), Some(1))
+        );
+        assert_eq!(
+            AttrPath::parse("0/*/*").unwrap(),
+            AttrPath::new(&GenericPath::new(Some(0), None, None))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_path() {
+        assert!(CmdPath::parse("0/6").is_err());
+        assert!(CmdPath::parse("0/6/1/2").is_err());
+        assert!(CmdPath::parse("0/six/1").is_err());
+        assert!(CmdPath::parse("70000/6/1").is_err());
+        assert!(AttrPath::parse("0/6/70000").is_err());
+    }
+}
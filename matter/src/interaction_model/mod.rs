@@ -2,7 +2,11 @@ use std::any::Any;
 
 use crate::{error::Error, tlv::TLVWriter, transport::session::Session};
 
-use self::messages::msg::{InvReq, ReadReq, WriteReq};
+use self::{
+    messages::msg::{InvReq, WriteReq},
+    read::ReadState,
+    subscribe::Subscriptions,
+};
 
 #[derive(PartialEq)]
 pub enum TransactionState {
@@ -13,6 +17,11 @@ pub struct Transaction<'a> {
     pub state: TransactionState,
     pub data: Option<Box<dyn Any>>,
     pub session: &'a mut Session,
+    // The exchange that a deferred command responds on, None if the request can't
+    // be deferred
+    pub(crate) defer_exch_id: Option<u16>,
+    // Set when a command handler has deferred its response
+    pub(crate) deferred: bool,
 }
 
 pub trait InteractionConsumer {
@@ -23,9 +32,21 @@ pub trait InteractionConsumer {
         tw: &mut TLVWriter,
     ) -> Result<(), Error>;
 
+    /// Invoke the commands on the endpoints that are members of the group 'group_id'
+    fn consume_group_invoke_cmd(
+        &self,
+        group_id: u16,
+        req: &InvReq,
+        trans: &mut Transaction,
+    ) -> Result<(), Error>;
+
+    /// Encode the AttributeReportIBs of the read, into the AttributeReports array
+    ///
+    /// This reports as much of 'state' as fits in 'tw', and moves it on to what is left for
+    /// the next chunk.
     fn consume_read_attr(
         &self,
-        req: &ReadReq,
+        state: &mut ReadState,
         trans: &mut Transaction,
         tw: &mut TLVWriter,
     ) -> Result<(), Error>;
@@ -40,9 +61,11 @@ pub trait InteractionConsumer {
 
 pub struct InteractionModel {
     consumer: Box<dyn InteractionConsumer>,
+    subscriptions: Subscriptions,
 }
 pub mod command;
 pub mod core;
 pub mod messages;
 pub mod read;
+pub mod subscribe;
 pub mod write;
@@ -1,15 +1,234 @@
 use crate::{
+    crypto,
+    data_model::objects::AttrTraversalCursor,
     error::Error,
-    interaction_model::core::OpCode,
+    interaction_model::core::{IMStatusCode, OpCode},
     tlv::{get_root_node_struct, FromTLV, TLVWriter, TagType},
     transport::{packet::Packet, proto_demux::ResponseRequired},
 };
+use log::error;
 
 use super::{
-    messages::msg::{self, ReadReq},
+    messages::{
+        ib::AttrPath,
+        msg::{self, ReadReq, StatusResp},
+        GenericPath,
+    },
+    subscribe::SubscribePrimingState,
     InteractionModel, Transaction,
 };
 
+// The space that the AttributeReports leave for what follows them in the message: the
+// end of the array, the flags and the end of the ReportData, and the MIC of the message
+const REPORT_TRAILER_LEN: usize = 6 + crypto::AEAD_MIC_LEN_BYTES;
+
+/// The progress of a read, which is kept across the chunks of its report
+///
+/// A report goes on for as long as there is space left in the packet, and the rest of it
+/// follows in more chunks. The peer acknowledges each chunk with a StatusResponse. The
+/// attributes that an earlier chunk reported aren't reported again for an overlapping
+/// path, and they count towards the limit of attributes of the read. A chunk that fills up
+/// in the middle of a (wildcard) path ends there, and the next chunk resumes the path. The
+/// reports of a subscription are chunked the same way.
+pub struct ReadState {
+    pub(crate) fabric_filtered: bool,
+    pub(crate) attr_paths: Vec<AttrPath>,
+    // The index of the next path to report
+    pub(crate) next: usize,
+    // The traversal of the path at 'next', if a chunk ended in the middle of it
+    pub(crate) cursor: Option<AttrTraversalCursor>,
+    // The attributes reported so far
+    pub(crate) reported: ReportedAttrs,
+    // The subscription that the report is of, None for a Read Request
+    pub(crate) subscription_id: Option<u32>,
+}
+
+impl ReadState {
+    /// The read of 'attr_paths'
+    pub fn new(fabric_filtered: bool, attr_paths: Vec<AttrPath>) -> Self {
+        Self {
+            fabric_filtered,
+            attr_paths,
+            next: 0,
+            cursor: None,
+            reported: ReportedAttrs::default(),
+            subscription_id: None,
+        }
+    }
+
+    /// The read of the attribute paths of 'req'
+    pub fn from_req(req: &ReadReq) -> Self {
+        Self::new(
+            req.fabric_filtered,
+            req.attr_requests.iter().flat_map(|a| a.iter()).collect(),
+        )
+    }
+
+    /// Whether all the paths of the read were reported
+    pub fn is_complete(&self) -> bool {
+        self.next >= self.attr_paths.len()
+    }
+}
+
+/// The concrete paths of the attributes that a read has reported, kept sorted
+#[derive(Default)]
+pub(crate) struct ReportedAttrs {
+    paths: Vec<GenericPath>,
+}
+
+impl ReportedAttrs {
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn contains(&self, path: &GenericPath) -> bool {
+        self.paths.binary_search(path).is_ok()
+    }
+
+    /// Add 'path', which isn't there yet
+    ///
+    /// Room for 'capacity' paths is made on the first insert, so that a read that
+    /// doesn't go beyond it allocates only once, however many attributes it reports.
+    pub fn insert(&mut self, path: GenericPath, capacity: usize) {
+        if let Err(index) = self.paths.binary_search(&path) {
+            if self.paths.capacity() == 0 {
+                self.paths.reserve_exact(capacity);
+            }
+            self.paths.insert(index, path);
+        }
+    }
+}
+
+/// The kind of ReportData that is being generated
+///
+/// This decides the values of the SupressResponse and MoreChunkedMsgs flags
+/// that are encoded at the end of the ReportData
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportDataType {
+    /// A one-shot report in response to a Read Request, or the last chunk of it
+    Read,
+    /// The priming report of a Subscription, the peer must respond with a StatusResponse
+    SubscriptionPriming,
+    /// A report of changed attributes on an established Subscription
+    SubscriptionReport,
+    /// A chunk of a report that will be followed by more chunks
+    Chunk,
+}
+
+impl ReportDataType {
+    pub fn supress_response(&self) -> bool {
+        match self {
+            ReportDataType::Read => true,
+            ReportDataType::SubscriptionPriming
+            | ReportDataType::SubscriptionReport
+            | ReportDataType::Chunk => false,
+        }
+    }
+
+    pub fn more_chunks(&self) -> bool {
+        *self == ReportDataType::Chunk
+    }
+
+    // Encode the trailing flags of the ReportData
+    fn encode_flags(&self, tw: &mut TLVWriter) -> Result<(), Error> {
+        if self.more_chunks() {
+            tw.bool(
+                TagType::Context(msg::ReportDataTag::MoreChunkedMsgs as u8),
+                true,
+            )?;
+        }
+        tw.bool(
+            TagType::Context(msg::ReportDataTag::SupressResponse as u8),
+            self.supress_response(),
+        )
+    }
+}
+
+/// Assembles a ReportData message
+///
+/// The elements of the ReportData must be encoded in the order of their tags, which is
+/// SubscriptionId, AttributeReports, EventReports and then the flags. An element that is
+/// added out of order is an error. The flags are encoded by `finish()`, as per the
+/// ReportDataType.
+pub struct ReportDataBuilder<'a, 'b, 'c> {
+    tw: &'a mut TLVWriter<'b, 'c>,
+    report_type: ReportDataType,
+    last_tag: Option<u8>,
+}
+
+impl<'a, 'b, 'c> ReportDataBuilder<'a, 'b, 'c> {
+    pub fn new(tw: &'a mut TLVWriter<'b, 'c>, report_type: ReportDataType) -> Result<Self, Error> {
+        tw.start_struct(TagType::Anonymous)?;
+        Ok(Self {
+            tw,
+            report_type,
+            last_tag: None,
+        })
+    }
+
+    pub fn subscription_id(&mut self, id: u32) -> Result<(), Error> {
+        let tag = self.next_tag(msg::ReportDataTag::SubscriptionId)?;
+        self.tw.u32(tag, id)
+    }
+
+    /// Set the kind of the ReportData, once it is known only after its reports
+    pub fn set_report_type(&mut self, report_type: ReportDataType) {
+        self.report_type = report_type;
+    }
+
+    /// Encode the AttributeReports array, 'f' encodes the AttributeReportIBs in it
+    ///
+    /// The end of the buffer is held back while 'f' runs, so that the rest of the
+    /// ReportData always fits after the AttributeReportIBs.
+    pub fn attr_reports<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut TLVWriter) -> Result<(), Error>,
+    {
+        let tag = self.next_tag(msg::ReportDataTag::AttributeReports)?;
+        self.array(tag, |tw| {
+            tw.shrink(REPORT_TRAILER_LEN)?;
+            let result = f(tw);
+            tw.expand(REPORT_TRAILER_LEN);
+            result
+        })
+    }
+
+    /// Encode the EventReports array, 'f' encodes the EventReportIBs in it
+    pub fn event_reports<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut TLVWriter) -> Result<(), Error>,
+    {
+        let tag = self.next_tag(msg::ReportDataTag::EventReports)?;
+        self.array(tag, f)
+    }
+
+    /// Encode the flags, and close the ReportData
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.next_tag(msg::ReportDataTag::MoreChunkedMsgs)?;
+        self.report_type.encode_flags(self.tw)?;
+        self.tw.end_container()
+    }
+
+    fn array<F>(&mut self, tag: TagType, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut TLVWriter) -> Result<(), Error>,
+    {
+        self.tw.start_array(tag)?;
+        f(self.tw)?;
+        self.tw.end_container()
+    }
+
+    fn next_tag(&mut self, tag: msg::ReportDataTag) -> Result<TagType, Error> {
+        let tag = tag as u8;
+        if Some(tag) <= self.last_tag {
+            error!("ReportData tag {} is out of order", tag);
+            return Err(Error::Invalid);
+        }
+        self.last_tag = Some(tag);
+        Ok(TagType::Context(tag))
+    }
+}
+
 impl InteractionModel {
     pub fn handle_read_req(
         &mut self,
@@ -22,17 +241,194 @@ impl InteractionModel {
         let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
         let root = get_root_node_struct(rx_buf)?;
         let read_req = ReadReq::from_tlv(&root)?;
+        if read_req.dataver_filters.is_some() {
+            error!("Data Version Filter not yet supported");
+        }
 
-        tw.start_struct(TagType::Anonymous)?;
-        self.consumer.consume_read_attr(&read_req, trans, &mut tw)?;
-        // Supress response always true for read interaction
-        tw.bool(
-            TagType::Context(msg::ReportDataTag::SupressResponse as u8),
-            true,
-        )?;
-        tw.end_container()?;
+        let state = Box::new(ReadState::from_req(&read_req));
+        self.send_report_chunk(trans, state, &mut tw)
+    }
 
-        trans.complete();
+    pub fn handle_status_response(
+        &mut self,
+        trans: &mut Transaction,
+        rx_buf: &[u8],
+        proto_tx: &mut Packet,
+    ) -> Result<ResponseRequired, Error> {
+        let root = get_root_node_struct(rx_buf)?;
+        let status_resp = StatusResp::from_tlv(&root)?;
+
+        let data = trans.data.take();
+        if status_resp.status != IMStatusCode::Success {
+            error!("Peer responded with status {:?}", status_resp.status);
+            trans.complete();
+            return Ok(ResponseRequired::No);
+        }
+
+        let data = match data.map(|d| d.downcast::<SubscribePrimingState>()) {
+            Some(Ok(state)) => return self.handle_priming_status(trans, state, proto_tx),
+            Some(Err(d)) => Some(d),
+            None => None,
+        };
+        let state = data.and_then(|d| d.downcast::<ReadState>().ok());
+        if let Some(state) = state {
+            // The peer has acknowledged the previous chunk, send the next one
+            proto_tx.set_proto_opcode(OpCode::ReportData as u8);
+            let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
+            self.send_report_chunk(trans, state, &mut tw)
+        } else {
+            trans.complete();
+            Ok(ResponseRequired::No)
+        }
+    }
+
+    // Send the next chunk of the report of 'state'
+    //
+    // The exchange is done with the last chunk of a read. The last chunk of a subscription
+    // report still expects a StatusResponse, the exchange is done once that comes in.
+    pub(super) fn send_report_chunk(
+        &mut self,
+        trans: &mut Transaction,
+        mut state: Box<ReadState>,
+        tw: &mut TLVWriter,
+    ) -> Result<ResponseRequired, Error> {
+        let last = if state.subscription_id.is_some() {
+            ReportDataType::SubscriptionReport
+        } else {
+            ReportDataType::Read
+        };
+        self.encode_report_chunk(trans, &mut state, last, tw)?;
+
+        if !state.is_complete() {
+            trans.data = Some(state);
+        } else if state.subscription_id.is_none() {
+            trans.complete();
+        }
         Ok(ResponseRequired::Yes)
     }
+
+    // Encode the next chunk of the report of 'state', 'last' is the kind of its last chunk
+    pub(super) fn encode_report_chunk(
+        &self,
+        trans: &mut Transaction,
+        state: &mut ReadState,
+        last: ReportDataType,
+        tw: &mut TLVWriter,
+    ) -> Result<(), Error> {
+        let mut report = ReportDataBuilder::new(tw, last)?;
+        if let Some(id) = state.subscription_id {
+            report.subscription_id(id)?;
+        }
+        report.attr_reports(|tw| self.consumer.consume_read_attr(state, trans, tw))?;
+        if !state.is_complete() {
+            report.set_report_type(ReportDataType::Chunk);
+        }
+        report.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReportDataBuilder, ReportDataType};
+    use crate::{
+        error::Error,
+        interaction_model::messages::msg::ReportDataTag,
+        tlv::{get_root_node_struct, TLVWriter, TagType},
+        utils::writebuf::WriteBuf,
+    };
+
+    fn encode_and_check(report_type: ReportDataType, supress: bool, more_chunks: bool) {
+        let mut buf = [0u8; 20];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+
+        ReportDataBuilder::new(&mut tw, report_type)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let root = get_root_node_struct(wb.as_slice()).unwrap();
+        assert_eq!(
+            root.find_tag(ReportDataTag::SupressResponse as u32)
+                .unwrap()
+                .bool()
+                .unwrap(),
+            supress
+        );
+        match root.find_tag(ReportDataTag::MoreChunkedMsgs as u32) {
+            Ok(t) => assert_eq!(t.bool().unwrap(), more_chunks),
+            Err(_) => assert!(!more_chunks),
+        }
+    }
+
+    #[test]
+    fn test_read_report_flags() {
+        encode_and_check(ReportDataType::Read, true, false);
+    }
+
+    #[test]
+    fn test_subscription_priming_report_flags() {
+        encode_and_check(ReportDataType::SubscriptionPriming, false, false);
+    }
+
+    #[test]
+    fn test_subscription_report_flags() {
+        encode_and_check(ReportDataType::SubscriptionReport, false, false);
+    }
+
+    #[test]
+    fn test_chunk_report_flags() {
+        encode_and_check(ReportDataType::Chunk, false, true);
+    }
+
+    #[test]
+    fn test_report_data_tag_order() {
+        let mut buf = [0u8; 40];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+
+        let mut report = ReportDataBuilder::new(&mut tw, ReportDataType::Read).unwrap();
+        report.subscription_id(5).unwrap();
+        report
+            .attr_reports(|tw| tw.u8(TagType::Anonymous, 1))
+            .unwrap();
+        report.finish().unwrap();
+
+        let root = get_root_node_struct(wb.as_slice()).unwrap();
+        let tags: Vec<TagType> = root.iter().unwrap().map(|e| e.get_tag()).collect();
+        assert_eq!(
+            tags,
+            [
+                ReportDataTag::SubscriptionId,
+                ReportDataTag::AttributeReports,
+                ReportDataTag::SupressResponse
+            ]
+            .map(|t| TagType::Context(t as u8))
+        );
+        assert!(root
+            .find_tag(ReportDataTag::SupressResponse as u32)
+            .unwrap()
+            .bool()
+            .unwrap());
+        let reports = root
+            .find_tag(ReportDataTag::AttributeReports as u32)
+            .unwrap();
+        assert_eq!(reports.confirm_array().unwrap().iter().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_report_data_out_of_order() {
+        let mut buf = [0u8; 40];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+
+        let mut report = ReportDataBuilder::new(&mut tw, ReportDataType::Read).unwrap();
+        report.attr_reports(|_| Ok(())).unwrap();
+        assert_eq!(report.subscription_id(5), Err(Error::Invalid));
+        assert_eq!(report.attr_reports(|_| Ok(())), Err(Error::Invalid));
+        report.event_reports(|_| Ok(())).unwrap();
+    }
 }
@@ -0,0 +1,435 @@
+use std::time::{Duration, SystemTime};
+
+use boxslab::Slab;
+use log::{error, info};
+
+use crate::{
+    error::Error,
+    interaction_model::core::{OpCode, PROTO_ID_INTERACTION_MODEL},
+    tlv::{get_root_node_struct, FromTLV, TLVWriter, TagType, ToTLV},
+    transport::{
+        exchange::ExchangeMgr,
+        packet::{Packet, PacketPool},
+        proto_demux::ResponseRequired,
+    },
+};
+
+use super::{
+    messages::{
+        ib::AttrPath,
+        msg::{SubscribeReq, SubscribeResp},
+        GenericPath,
+    },
+    read::{ReadState, ReportDataType},
+    InteractionModel, Transaction,
+};
+
+/// Maximum number of subscriptions that are maintained at a time
+pub const MAX_SUBSCRIPTIONS: usize = 3;
+
+/// The default for the server's minimum reporting interval floor (in seconds)
+///
+/// Reports are never sent faster than this, irrespective of the MinIntervalFloor
+/// requested by the peer.
+pub const DEFAULT_MIN_INTERVAL_FLOOR: u16 = 1;
+
+pub struct Subscription {
+    id: u32,
+    fab_idx: u8,
+    peer_node_id: u64,
+    fabric_filtered: bool,
+    attr_paths: Vec<AttrPath>,
+    min_int: u16,
+    max_int: u16,
+    last_report: SystemTime,
+    // Set when an attribute changes, the change is reported once the min
+    // interval has elapsed
+    dirty: bool,
+}
+
+impl Subscription {
+    fn new(
+        id: u32,
+        fab_idx: u8,
+        peer_node_id: u64,
+        req: &SubscribeReq,
+        min_int_floor: u16,
+    ) -> Self {
+        let min_int = u16::max(req.min_int_floor, min_int_floor);
+        let max_int = u16::max(req.max_int_ceil, min_int);
+        Self {
+            id,
+            fab_idx,
+            peer_node_id,
+            fabric_filtered: req.fabric_filtered,
+            attr_paths: req.attr_requests.iter().flat_map(|a| a.iter()).collect(),
+            min_int,
+            max_int,
+            last_report: SystemTime::now(),
+            dirty: false,
+        }
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_peer_node_id(&self) -> u64 {
+        self.peer_node_id
+    }
+
+    /// The negotiated minimum interval (in seconds)
+    pub fn get_min_interval(&self) -> u16 {
+        self.min_int
+    }
+
+    /// The negotiated maximum interval (in seconds)
+    pub fn get_max_interval(&self) -> u16 {
+        self.max_int
+    }
+
+    /// Returns true if a report has to be sent at time 'now'
+    ///
+    /// Changes are reported no faster than the min interval, and a report is
+    /// always sent once the max interval has elapsed.
+    pub fn is_report_due(&self, now: SystemTime) -> bool {
+        let elapsed = now.duration_since(self.last_report).unwrap_or_default();
+        if elapsed < Duration::from_secs(self.min_int as u64) {
+            return false;
+        }
+        self.dirty || elapsed >= Duration::from_secs(self.max_int as u64)
+    }
+
+    /// The time at which the next report is due, unless something changes before then
+    pub fn next_report_at(&self) -> SystemTime {
+        let interval = if self.dirty {
+            self.min_int
+        } else {
+            self.max_int
+        };
+        self.last_report + Duration::from_secs(interval as u64)
+    }
+
+    // The read of the subscribed paths, that a report goes through
+    fn read_state(&self) -> ReadState {
+        let mut state = ReadState::new(self.fabric_filtered, self.attr_paths.clone());
+        state.subscription_id = Some(self.id);
+        state
+    }
+
+    fn matches(&self, path: &GenericPath) -> bool {
+        fn wc_match<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            a.is_none() || b.is_none() || a == b
+        }
+
+        self.attr_paths.iter().any(|a| {
+            wc_match(a.endpoint, path.endpoint)
+                && wc_match(a.cluster, path.cluster)
+                && wc_match(a.attr.map(|x| x as u32), path.leaf)
+        })
+    }
+}
+
+/// The state of a Subscribe Request whose priming report is awaiting a StatusResponse
+///
+/// The priming report may take more than one chunk, the subscription is confirmed once the
+/// peer has acknowledged the last one.
+pub(super) struct SubscribePrimingState {
+    subscription: Subscription,
+    read: ReadState,
+}
+
+pub struct Subscriptions {
+    subs: Vec<Subscription>,
+    next_id: u32,
+    min_int_floor: u16,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            subs: Vec::with_capacity(MAX_SUBSCRIPTIONS),
+            next_id: 1,
+            min_int_floor: DEFAULT_MIN_INTERVAL_FLOOR,
+        }
+    }
+}
+
+impl Subscriptions {
+    pub fn get(&self, id: u32) -> Option<&Subscription> {
+        self.subs.iter().find(|s| s.id == id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut Subscription> {
+        self.subs.iter_mut().find(|s| s.id == id)
+    }
+
+    fn remove_peer(&mut self, peer_node_id: u64) {
+        self.subs.retain(|s| s.peer_node_id != peer_node_id);
+    }
+}
+
+impl InteractionModel {
+    /// Set the server's minimum reporting interval floor (in seconds)
+    ///
+    /// This only applies to subscriptions that are established after this call.
+    pub fn set_min_interval_floor(&mut self, secs: u16) {
+        self.subscriptions.min_int_floor = secs;
+    }
+
+    pub fn get_subscription(&self, id: u32) -> Option<&Subscription> {
+        self.subscriptions.get(id)
+    }
+
+    /// Mark the attributes at 'path' as changed
+    ///
+    /// The subscriptions that include this path will report the latest value of
+    /// the attributes, once their min interval has elapsed.
+    pub fn notify_change(&mut self, path: &GenericPath) {
+        for s in self.subscriptions.subs.iter_mut() {
+            if s.matches(path) {
+                s.dirty = true;
+            }
+        }
+    }
+
+    pub fn handle_subscribe_req(
+        &mut self,
+        trans: &mut Transaction,
+        rx_buf: &[u8],
+        proto_tx: &mut Packet,
+    ) -> Result<ResponseRequired, Error> {
+        proto_tx.set_proto_opcode(OpCode::ReportData as u8);
+
+        let root = get_root_node_struct(rx_buf)?;
+        let req = SubscribeReq::from_tlv(&root)?;
+        let peer_node_id = trans.session.get_peer_node_id().ok_or(Error::Invalid)?;
+        let fab_idx = trans.session.get_local_fabric_idx().ok_or(Error::Invalid)?;
+        if !req.keep_subs {
+            self.subscriptions.remove_peer(peer_node_id);
+        }
+        if self.subscriptions.subs.len() >= MAX_SUBSCRIPTIONS {
+            error!("No space for a new subscription");
+            return Err(Error::NoSpace);
+        }
+
+        let id = self.subscriptions.next_id;
+        self.subscriptions.next_id = self.subscriptions.next_id.wrapping_add(1);
+        let subscription = Subscription::new(
+            id,
+            fab_idx,
+            peer_node_id,
+            &req,
+            self.subscriptions.min_int_floor,
+        );
+        info!(
+            "Subscription {}: min interval {}s, max interval {}s",
+            id, subscription.min_int, subscription.max_int
+        );
+
+        let read = subscription.read_state();
+        let state = Box::new(SubscribePrimingState { subscription, read });
+        let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
+        self.send_priming_chunk(trans, state, &mut tw)
+    }
+
+    /// The peer has acknowledged a chunk of the priming report
+    ///
+    /// This sends the next chunk, or confirms the subscription once the whole report is
+    /// acknowledged.
+    pub(super) fn handle_priming_status(
+        &mut self,
+        trans: &mut Transaction,
+        state: Box<SubscribePrimingState>,
+        proto_tx: &mut Packet,
+    ) -> Result<ResponseRequired, Error> {
+        if state.read.is_complete() {
+            return self.send_subscribe_resp(trans, *state, proto_tx);
+        }
+        proto_tx.set_proto_opcode(OpCode::ReportData as u8);
+        let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
+        self.send_priming_chunk(trans, state, &mut tw)
+    }
+
+    fn send_priming_chunk(
+        &mut self,
+        trans: &mut Transaction,
+        mut state: Box<SubscribePrimingState>,
+        tw: &mut TLVWriter,
+    ) -> Result<ResponseRequired, Error> {
+        self.encode_report_chunk(
+            trans,
+            &mut state.read,
+            ReportDataType::SubscriptionPriming,
+            tw,
+        )?;
+        trans.data = Some(state);
+        Ok(ResponseRequired::Yes)
+    }
+
+    // The peer has acknowledged the whole priming report, confirm the subscription
+    fn send_subscribe_resp(
+        &mut self,
+        trans: &mut Transaction,
+        state: SubscribePrimingState,
+        proto_tx: &mut Packet,
+    ) -> Result<ResponseRequired, Error> {
+        let subscription = state.subscription;
+        if self.subscriptions.subs.len() >= MAX_SUBSCRIPTIONS {
+            error!("No space for a new subscription");
+            return Err(Error::NoSpace);
+        }
+        proto_tx.set_proto_opcode(OpCode::SubscriptResponse as u8);
+        let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
+        let resp = SubscribeResp::new(subscription.id, subscription.max_int);
+        resp.to_tlv(&mut tw, TagType::Anonymous)?;
+
+        self.subscriptions.subs.push(subscription);
+        trans.complete();
+        Ok(ResponseRequired::Yes)
+    }
+
+    /// Generate the ReportData for subscription 'id', if one is due at time 'now'
+    ///
+    /// Returns true if a report was written to 'proto_tx'. The report carries the
+    /// latest values of the subscribed attributes, so multiple changes within the
+    /// min interval are reported only once. A report that doesn't fit in a single message
+    /// leaves the rest of it in 'trans', for the exchange that the report goes out on, and
+    /// the next chunks follow as the peer acknowledges them.
+    pub fn report_subscription(
+        &mut self,
+        id: u32,
+        now: SystemTime,
+        trans: &mut Transaction,
+        proto_tx: &mut Packet,
+    ) -> Result<bool, Error> {
+        let subscription = self.subscriptions.get_mut(id).ok_or(Error::NotFound)?;
+        if !subscription.is_report_due(now) {
+            return Ok(false);
+        }
+        subscription.dirty = false;
+        subscription.last_report = now;
+        let state = Box::new(subscription.read_state());
+
+        proto_tx.set_proto_opcode(OpCode::ReportData as u8);
+        let mut tw = TLVWriter::new(proto_tx.get_writebuf()?);
+        self.send_report_chunk(trans, state, &mut tw)?;
+        Ok(true)
+    }
+
+    /// Send the reports of all the subscriptions that are due at 'now'
+    ///
+    /// Every report goes out on a new exchange in 'exch_mgr', over the CASE session with the
+    /// subscriber. A subscription whose subscriber has no session left can't be reported
+    /// anymore, it is dropped. Returns the time at which the next report is due, if any.
+    pub fn report_subscriptions(
+        &mut self,
+        now: SystemTime,
+        exch_mgr: &mut ExchangeMgr,
+    ) -> Option<SystemTime> {
+        let due: Vec<(u32, u8, u64)> = self
+            .subscriptions
+            .subs
+            .iter()
+            .filter(|s| s.is_report_due(now))
+            .map(|s| (s.id, s.fab_idx, s.peer_node_id))
+            .collect();
+        for (id, fab_idx, peer_node_id) in due {
+            let sess_mgr = exch_mgr.get_sess_mgr();
+            match sess_mgr.get_index_with_peer(fab_idx, peer_node_id) {
+                Some(sess_idx) => {
+                    if let Err(e) = self.send_subscription_report(id, now, sess_idx, exch_mgr) {
+                        error!("Error reporting subscription {}: {:?}", id, e);
+                    }
+                }
+                None => {
+                    error!(
+                        "No session with the subscriber, dropping subscription {}",
+                        id
+                    );
+                    self.subscriptions.subs.retain(|s| s.id != id);
+                }
+            }
+        }
+        self.subscriptions
+            .subs
+            .iter()
+            .map(|s| s.next_report_at())
+            .min()
+    }
+
+    fn send_subscription_report(
+        &mut self,
+        id: u32,
+        now: SystemTime,
+        sess_idx: usize,
+        exch_mgr: &mut ExchangeMgr,
+    ) -> Result<(), Error> {
+        let mut proto_tx =
+            Slab::<PacketPool>::new(Packet::new_tx()?).ok_or(Error::PacketPoolExhaust)?;
+        proto_tx.set_proto_id(PROTO_ID_INTERACTION_MODEL as u16);
+
+        let ctx = exch_mgr.initiate(sess_idx)?;
+        let exch_id = ctx.exch.get_id();
+        let mut sess = ctx.sess;
+        let mut trans = Transaction::new(&mut sess);
+        match self.report_subscription(id, now, &mut trans, &mut proto_tx) {
+            Ok(true) => (),
+            result => {
+                ctx.exch.close();
+                return result.map(|_| ());
+            }
+        }
+        // The rest of the report follows as the subscriber acknowledges the chunks
+        if let Some(data) = trans.data.take() {
+            ctx.exch.set_exchange_data(data);
+        }
+        exch_mgr.send(exch_id, proto_tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Subscription;
+    use crate::interaction_model::messages::{ib::AttrPath, msg::SubscribeReq, GenericPath};
+    use std::time::Duration;
+
+    #[test]
+    fn test_negotiate_intervals() {
+        // The server's floor overrides a smaller floor requested by the peer
+        let req = SubscribeReq::new(false, 0, 0);
+        let s = Subscription::new(1, 1, 10, &req, 5);
+        assert_eq!(s.get_min_interval(), 5);
+        assert_eq!(s.get_max_interval(), 5);
+
+        let req = SubscribeReq::new(false, 10, 60);
+        let s = Subscription::new(1, 1, 10, &req, 5);
+        assert_eq!(s.get_min_interval(), 10);
+        assert_eq!(s.get_max_interval(), 60);
+    }
+
+    #[test]
+    fn test_report_rate_limited() {
+        let paths = [AttrPath::new(&GenericPath::new(Some(0), Some(6), None))];
+        let req = SubscribeReq::new(false, 0, 60).set_attr_requests(&paths);
+        let mut s = Subscription::new(1, 1, 10, &req, 2);
+        let start = s.last_report;
+
+        // Nothing has changed
+        assert!(!s.is_report_due(start + Duration::from_secs(3)));
+
+        // A change isn't reported before the min interval
+        assert!(s.matches(&GenericPath::new(Some(0), Some(6), Some(0))));
+        assert!(!s.matches(&GenericPath::new(Some(1), Some(6), Some(0))));
+        s.dirty = true;
+        assert!(!s.is_report_due(start + Duration::from_secs(1)));
+        assert!(s.is_report_due(start + Duration::from_secs(2)));
+
+        assert_eq!(s.next_report_at(), start + Duration::from_secs(2));
+
+        // The max interval always results in a report
+        s.dirty = false;
+        assert_eq!(s.next_report_at(), start + Duration::from_secs(60));
+        assert!(s.is_report_due(start + Duration::from_secs(60)));
+    }
+}
@@ -1,12 +1,86 @@
 use log::error;
 
 use crate::{
+    data_model::objects::{AttrValue, EncodeValue},
     error::Error,
-    tlv::{get_root_node_struct, FromTLV, TLVWriter, TagType},
+    tlv::{get_root_node_struct, FromTLV, TLVWriter, TagType, ToTLV},
     transport::{packet::Packet, proto_demux::ResponseRequired},
 };
 
-use super::{core::OpCode, messages::msg::WriteReq, InteractionModel, Transaction};
+use super::{
+    core::OpCode,
+    messages::{
+        ib::{AttrData, AttrPath},
+        msg::WriteReq,
+    },
+    InteractionModel, Transaction,
+};
+
+/// Assembles the WriteRequest messages of a write, on the client side
+///
+/// The AttributeDataIBs are split across as many WriteRequests as it takes for each of them
+/// to fit in 'max_size' bytes. All the WriteRequests but the last have the
+/// MoreChunkedMessages flag set.
+pub struct WriteReqBuilder {
+    max_size: usize,
+}
+
+impl WriteReqBuilder {
+    pub fn new(max_size: usize) -> Self {
+        Self { max_size }
+    }
+
+    /// Encode the WriteRequests that write 'value' to 'path', for each of the 'attrs'
+    ///
+    /// Returns Error::NoSpace if a single AttributeDataIB doesn't fit in a WriteRequest.
+    pub fn build(&self, attrs: &[(AttrPath, AttrValue)]) -> Result<Vec<Vec<u8>>, Error> {
+        let attr_data: Vec<AttrData> = attrs
+            .iter()
+            .map(|(path, value)| AttrData::new(None, *path, EncodeValue::Value(value)))
+            .collect();
+
+        // The WriteRequest without any AttributeDataIBs, but with the flag that is set on
+        // all the chunks but the last
+        let mut empty = WriteReq::new(false, &[]);
+        empty.more_chunked = Some(true);
+        let overhead = encoded(&empty)?.len();
+
+        let mut msgs = Vec::new();
+        let mut start = 0;
+        while start < attr_data.len() {
+            let mut end = start;
+            let mut size = overhead;
+            while end < attr_data.len() {
+                let ib_size = encoded(&attr_data[end])?.len();
+                if size + ib_size > self.max_size {
+                    break;
+                }
+                size += ib_size;
+                end += 1;
+            }
+            if end == start {
+                error!("AttributeDataIB doesn't fit in a WriteRequest");
+                return Err(Error::NoSpace);
+            }
+
+            let mut req = WriteReq::new(false, &attr_data[start..end]);
+            if end < attr_data.len() {
+                req.more_chunked = Some(true);
+            }
+            msgs.push(encoded(&req)?);
+            start = end;
+        }
+        Ok(msgs)
+    }
+}
+
+fn encoded<T: ToTLV>(t: &T) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    let mut tw = TLVWriter::new_io(&mut out);
+    t.to_tlv(&mut tw, TagType::Anonymous)?;
+    tw.finish()?;
+    Ok(out)
+}
 
 impl InteractionModel {
     pub fn handle_write_req(
@@ -27,6 +101,11 @@ impl InteractionModel {
             .consume_write_attr(&write_req, trans, &mut tw)?;
         tw.end_container()?;
 
+        // TODO: Only notify the attributes that were actually written
+        for a in write_req.write_requests.iter() {
+            self.notify_change(&a.path.to_gp());
+        }
+
         trans.complete();
         if supress_response {
             error!("Supress response is set, is this the expected handling?");
@@ -36,3 +115,55 @@ impl InteractionModel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::WriteReqBuilder;
+    use crate::{
+        data_model::objects::AttrValue,
+        error::Error,
+        interaction_model::messages::{ib::AttrPath, msg::WriteReq, GenericPath},
+        tlv::{get_root_node_struct, FromTLV},
+    };
+
+    fn attrs(count: u32) -> Vec<(AttrPath, AttrValue)> {
+        (0..count)
+            .map(|i| {
+                let path = AttrPath::new(&GenericPath::new(Some(1), Some(0x0006), Some(i)));
+                (path, AttrValue::Uint32(0x12345678))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_req_chunks() {
+        let attrs = attrs(6);
+        let single = WriteReqBuilder::new(usize::MAX).build(&attrs).unwrap();
+        assert_eq!(single.len(), 1);
+
+        // Half of the AttributeDataIBs fit in a chunk
+        let max_size = single[0].len() / 2 + 8;
+        let msgs = WriteReqBuilder::new(max_size).build(&attrs).unwrap();
+        assert_eq!(msgs.len(), 2);
+
+        let mut paths = Vec::new();
+        for (i, msg) in msgs.iter().enumerate() {
+            assert!(msg.len() <= max_size);
+            let root = get_root_node_struct(msg).unwrap();
+            let req = WriteReq::from_tlv(&root).unwrap();
+            let more_chunked = if i < msgs.len() - 1 { Some(true) } else { None };
+            assert_eq!(req.more_chunked, more_chunked);
+            paths.extend(req.write_requests.iter().map(|a| a.path));
+        }
+        let expected: Vec<AttrPath> = attrs.iter().map(|(p, _)| *p).collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn test_write_req_ib_too_large() {
+        assert_eq!(
+            WriteReqBuilder::new(16).build(&attrs(1)),
+            Err(Error::NoSpace)
+        );
+    }
+}
@@ -42,16 +42,21 @@
 //! Start off exploring by going to the [Matter] object.
 
 pub mod acl;
+pub mod bdx;
 pub mod cert;
+pub mod commissioning;
 pub mod core;
 pub mod crypto;
 pub mod data_model;
 pub mod error;
 pub mod fabric;
 pub mod group_keys;
+pub mod groups;
 pub mod interaction_model;
 pub mod secure_channel;
 pub mod sys;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod tlv;
 pub mod transport;
 pub mod utils;
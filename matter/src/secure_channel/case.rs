@@ -1,8 +1,14 @@
-use std::sync::Arc;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 
-use log::{error, trace};
+use log::{error, info, trace};
 use owning_ref::RwLockReadGuardRef;
-use rand::prelude::*;
 
 use crate::{
     cert::Cert,
@@ -14,14 +20,31 @@ use crate::{
     tlv::{get_root_node_struct, FromTLV, OctetStr, TLVElement, TLVWriter, TagType},
     transport::{
         network::Address,
+        packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
         proto_demux::ProtoCtx,
         queue::{Msg, WorkQ},
-        session::{CloneData, SessionMode},
+        session::{CloneData, SessionMode, REDACTED},
+    },
+    utils::{
+        rand::{RandomSource, ThreadRandom},
+        writebuf::WriteBuf,
     },
-    utils::writebuf::WriteBuf,
 };
 
-#[derive(PartialEq)]
+/// The time within which a CASE handshake must complete, after Sigma1 is received
+pub const CASE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The default for the maximum number of CASE handshakes that are in progress at a time
+///
+/// Every handshake holds an ephemeral key pair and its CaseSession until it completes, so
+/// Sigma1s beyond this are rejected as Busy.
+pub const MAX_CASE_HANDSHAKES: usize = 4;
+
+// The minimum time that the initiator should wait before retrying a Busy Sigma1, in
+// milliseconds (little-endian)
+const BUSY_WAIT_TIME_MS: [u8; 2] = [0xf4, 0x01];
+
+#[derive(PartialEq, Debug)]
 enum State {
     Sigma1Rx,
     Sigma3Rx,
@@ -36,6 +59,8 @@ pub struct CaseSession {
     our_pub_key: [u8; crypto::EC_POINT_LEN_BYTES],
     peer_pub_key: [u8; crypto::EC_POINT_LEN_BYTES],
     local_fabric_idx: usize,
+    start_time: SystemTime,
+    slot: Option<HandshakeSlot>,
 }
 impl CaseSession {
     pub fn new(peer_sessid: u16, local_sessid: u16) -> Result<Self, Error> {
@@ -48,17 +73,102 @@ impl CaseSession {
             our_pub_key: [0; crypto::EC_POINT_LEN_BYTES],
             peer_pub_key: [0; crypto::EC_POINT_LEN_BYTES],
             local_fabric_idx: 0,
+            start_time: SystemTime::now(),
+            slot: None,
         })
     }
+
+    /// The time by which the handshake must complete, if it is to complete within `timeout`
+    pub fn expires_at(&self, timeout: Duration) -> SystemTime {
+        self.start_time + timeout
+    }
+
+    /// Returns true if the handshake hasn't completed within `timeout` of receiving Sigma1
+    pub fn is_expired(&self, timeout: Duration) -> bool {
+        match SystemTime::now().duration_since(self.start_time) {
+            Ok(elapsed) => elapsed > timeout,
+            // The clock went backwards, we can't really tell
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Debug for CaseSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaseSession")
+            .field("state", &self.state)
+            .field("peer_sessid", &self.peer_sessid)
+            .field("local_sessid", &self.local_sessid)
+            .field("shared_secret", &REDACTED)
+            .field("our_pub_key", &self.our_pub_key)
+            .field("peer_pub_key", &self.peer_pub_key)
+            .field("local_fabric_idx", &self.local_fabric_idx)
+            .finish()
+    }
+}
+
+// A slot of a CASE handshake that is in progress
+//
+// This is held by the CaseSession, so the slot is freed whenever the CaseSession is dropped,
+// which is when the handshake completes, fails or times out.
+struct HandshakeSlot(Arc<AtomicUsize>);
+
+impl HandshakeSlot {
+    fn acquire(in_progress: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        in_progress
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()?;
+        Some(Self(in_progress.clone()))
+    }
+}
+
+impl Drop for HandshakeSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 pub struct Case {
     fabric_mgr: Arc<FabricMgr>,
+    // The number of handshakes that are in progress
+    handshakes: Arc<AtomicUsize>,
+    max_handshakes: usize,
+    // The ephemeral public key of the previous handshake
+    last_ephemeral_key: Option<[u8; crypto::EC_POINT_LEN_BYTES]>,
+    // The source of the ephemeral keys, the randoms and the resumption IDs of the handshakes
+    rand: Box<dyn RandomSource>,
+    // Remove the fabric that the Destination ID matched, right after the match
+    #[cfg(test)]
+    remove_matched_fabric: bool,
 }
 
 impl Case {
     pub fn new(fabric_mgr: Arc<FabricMgr>) -> Self {
-        Self { fabric_mgr }
+        Self {
+            fabric_mgr,
+            handshakes: Arc::new(AtomicUsize::new(0)),
+            max_handshakes: MAX_CASE_HANDSHAKES,
+            last_ephemeral_key: None,
+            rand: Box::new(ThreadRandom),
+            #[cfg(test)]
+            remove_matched_fabric: false,
+        }
+    }
+
+    /// Set the source of the randoms and the resumption IDs of the handshakes
+    pub fn set_random_source(&mut self, rand: Box<dyn RandomSource>) {
+        self.rand = rand;
+    }
+
+    /// Set the maximum number of CASE handshakes that are in progress at a time
+    pub fn set_max_handshakes(&mut self, max: usize) {
+        self.max_handshakes = max;
     }
 
     pub fn handle_casesigma3(&mut self, ctx: &mut ProtoCtx) -> Result<(), Error> {
@@ -88,7 +198,7 @@ impl Case {
         let root = get_root_node_struct(ctx.rx.as_borrow_slice())?;
         let encrypted = root.find_tag(1)?.slice()?;
 
-        let mut decrypted: [u8; 800] = [0; 800];
+        let mut decrypted: [u8; MAX_RX_BUF_SIZE] = [0; MAX_RX_BUF_SIZE];
         if encrypted.len() > decrypted.len() {
             error!("Data too large");
             return Err(Error::NoSpace);
@@ -103,8 +213,21 @@ impl Case {
         let d = Sigma3Decrypt::from_tlv(&root)?;
 
         let initiator_noc = Cert::new(d.initiator_noc.0)?;
-        let initiator_icac = Cert::new(d.initiator_icac.0)?;
-        if let Err(e) = Case::validate_certs(fabric, &initiator_noc, &initiator_icac) {
+        let initiator_icac = d.initiator_icac.map(|icac| Cert::new(icac.0)).transpose()?;
+        if let Err(e) = Case::validate_fabric(fabric, &initiator_noc, initiator_icac.as_ref()) {
+            error!(
+                "Sigma3 NOC isn't of the fabric {} that Sigma1 resolved to: {}",
+                case_session.local_fabric_idx, e
+            );
+            common::create_sc_status_report(
+                &mut ctx.tx,
+                common::SCStatusCodes::InvalidParameter,
+                None,
+            )?;
+            ctx.exch_ctx.exch.close();
+            return Ok(());
+        }
+        if let Err(e) = Case::validate_certs(fabric, &initiator_noc, initiator_icac.as_ref()) {
             error!("Certificate Chain doesn't match: {}", e);
             common::create_sc_status_report(
                 &mut ctx.tx,
@@ -117,7 +240,7 @@ impl Case {
 
         if Case::validate_sigma3_sign(
             d.initiator_noc.0,
-            d.initiator_icac.0,
+            d.initiator_icac.map(|icac| icac.0),
             &initiator_noc,
             d.signature.0,
             &case_session,
@@ -136,13 +259,14 @@ impl Case {
 
         // Only now do we add this message to the TT Hash
         case_session.tt_hash.update(ctx.rx.as_borrow_slice())?;
-        let clone_data = Case::get_session_clone_data(
+        let mut clone_data = Case::get_session_clone_data(
             fabric.ipk.op_key(),
             fabric.get_node_id(),
             initiator_noc.get_node_id()?,
             ctx.exch_ctx.sess.get_peer_addr(),
             &case_session,
         )?;
+        clone_data.peer_cats = initiator_noc.get_cat_ids();
         // Queue a transport mgr request to add a new session
         WorkQ::get()?.sync_send(Msg::NewSession(clone_data))?;
 
@@ -162,11 +286,23 @@ impl Case {
         let root = get_root_node_struct(rx_buf)?;
         let r = Sigma1Req::from_tlv(&root)?;
 
+        let slot = HandshakeSlot::acquire(&self.handshakes, self.max_handshakes);
+        if slot.is_none() {
+            info!("Too many CASE handshakes in progress, denying new request");
+            common::create_sc_status_report(
+                &mut ctx.tx,
+                SCStatusCodes::Busy,
+                Some(&BUSY_WAIT_TIME_MS),
+            )?;
+            ctx.exch_ctx.exch.close();
+            return Ok(());
+        }
+
         let local_fabric_idx = self
             .fabric_mgr
             .match_dest_id(r.initiator_random.0, r.dest_id.0);
         if local_fabric_idx.is_err() {
-            error!("Fabric Index mismatch");
+            error!("No fabric matches the Destination ID");
             common::create_sc_status_report(
                 &mut ctx.tx,
                 common::SCStatusCodes::NoSharedTrustRoots,
@@ -175,9 +311,14 @@ impl Case {
             ctx.exch_ctx.exch.close();
             return Ok(());
         }
+        #[cfg(test)]
+        if let (true, Ok(idx)) = (self.remove_matched_fabric, &local_fabric_idx) {
+            let _ = self.fabric_mgr.remove(*idx);
+        }
 
         let local_sessid = ctx.exch_ctx.sess.reserve_new_sess_id();
         let mut case_session = Box::new(CaseSession::new(r.initiator_sessid, local_sessid)?);
+        case_session.slot = slot;
         case_session.tt_hash.update(rx_buf)?;
         case_session.local_fabric_idx = local_fabric_idx?;
         if r.peer_pub_key.0.len() != crypto::EC_POINT_LEN_BYTES {
@@ -190,32 +331,39 @@ impl Case {
             case_session.local_fabric_idx
         );
 
-        // Create an ephemeral Key Pair
-        let key_pair = KeyPair::new()?;
-        let _ = key_pair.get_public_key(&mut case_session.our_pub_key)?;
-
-        // Derive the Shared Secret
-        let len = key_pair.derive_secret(r.peer_pub_key.0, &mut case_session.shared_secret)?;
-        if len != 32 {
-            error!("Derived secret length incorrect");
-            return Err(Error::Invalid);
-        }
-        //        println!("Derived secret: {:x?} len: {}", secret, len);
+        // Create an ephemeral Key Pair and derive the Shared Secret
+        let mut op_pub_key = [0u8; crypto::EC_POINT_LEN_BYTES];
+        let op_pub_key_len = match &*self.fabric_mgr.get_fabric(case_session.local_fabric_idx)? {
+            Some(fabric) => fabric.get_public_key(&mut op_pub_key)?,
+            None => 0,
+        };
+        let key_pair = self.gen_ephemeral_key(&op_pub_key[..op_pub_key_len])?;
+        Case::derive_shared_secret(
+            key_pair,
+            r.peer_pub_key.0,
+            &mut case_session.our_pub_key,
+            &mut case_session.shared_secret,
+        )?;
 
         let mut our_random: [u8; 32] = [0; 32];
-        rand::thread_rng().fill_bytes(&mut our_random);
+        self.rand.fill(&mut our_random);
+        let mut resumption_id: [u8; 16] = [0; 16];
+        self.rand.fill(&mut resumption_id);
 
         // Derive the Encrypted Part
-        const MAX_ENCRYPTED_SIZE: usize = 800;
-
-        let mut encrypted: [u8; MAX_ENCRYPTED_SIZE] = [0; MAX_ENCRYPTED_SIZE];
+        let mut encrypted: [u8; MAX_TX_BUF_SIZE] = [0; MAX_TX_BUF_SIZE];
         let encrypted_len = {
             let mut signature = [0u8; crypto::EC_SIGNATURE_LEN_BYTES];
             let fabric = self.fabric_mgr.get_fabric(case_session.local_fabric_idx)?;
             if fabric.is_none() {
+                // The Destination ID matched this fabric, it has gone away since
+                error!(
+                    "No fabric at the matched fabric index {}",
+                    case_session.local_fabric_idx
+                );
                 common::create_sc_status_report(
                     &mut ctx.tx,
-                    common::SCStatusCodes::NoSharedTrustRoots,
+                    common::SCStatusCodes::GeneralFailure,
                     None,
                 )?;
                 ctx.exch_ctx.exch.close();
@@ -233,6 +381,7 @@ impl Case {
             Case::get_sigma2_encryption(
                 &fabric,
                 &our_random,
+                &resumption_id,
                 &mut case_session,
                 signature,
                 &mut encrypted,
@@ -246,7 +395,7 @@ impl Case {
         tw.str8(TagType::Context(1), &our_random)?;
         tw.u16(TagType::Context(2), local_sessid)?;
         tw.str8(TagType::Context(3), &case_session.our_pub_key)?;
-        tw.str16(TagType::Context(4), encrypted)?;
+        tw.octet_str(TagType::Context(4), encrypted)?;
         tw.end_container()?;
         case_session.tt_hash.update(ctx.tx.as_borrow_slice())?;
         ctx.exch_ctx.exch.set_exchange_data(case_session);
@@ -287,7 +436,7 @@ impl Case {
 
     fn validate_sigma3_sign(
         initiator_noc: &[u8],
-        initiator_icac: &[u8],
+        initiator_icac: Option<&[u8]>,
         initiator_noc_cert: &Cert,
         sign: &[u8],
         case_session: &CaseSession,
@@ -297,8 +446,10 @@ impl Case {
         let mut write_buf = WriteBuf::new(&mut buf, MAX_TBS_SIZE);
         let mut tw = TLVWriter::new(&mut write_buf);
         tw.start_struct(TagType::Anonymous)?;
-        tw.str8(TagType::Context(1), initiator_noc)?;
-        tw.str8(TagType::Context(2), initiator_icac)?;
+        tw.octet_str(TagType::Context(1), initiator_noc)?;
+        if let Some(icac) = initiator_icac {
+            tw.octet_str(TagType::Context(2), icac)?;
+        }
         tw.str8(TagType::Context(3), &case_session.peer_pub_key)?;
         tw.str8(TagType::Context(4), &case_session.our_pub_key)?;
         tw.end_container()?;
@@ -308,8 +459,10 @@ impl Case {
         Ok(())
     }
 
-    fn validate_certs(fabric: &Fabric, noc: &Cert, icac: &Cert) -> Result<(), Error> {
-        if let Ok(fid) = icac.get_fabric_id() {
+    // The initiator's chain must be of the fabric that the Destination ID of Sigma1
+    // resolved to, with the same Fabric ID and issued under the same root
+    fn validate_fabric(fabric: &Fabric, noc: &Cert, icac: Option<&Cert>) -> Result<(), Error> {
+        if let Some(Ok(fid)) = icac.map(Cert::get_fabric_id) {
             if fid != fabric.get_fabric_id() {
                 return Err(Error::Invalid);
             }
@@ -319,14 +472,73 @@ impl Case {
             return Err(Error::Invalid);
         }
 
-        noc.verify_chain_start()
-            .add_cert(icac)?
-            .add_cert(&fabric.root_ca)?
-            .finalise()?;
+        // Without an ICAC, the NOC is issued directly by the Root CA
+        if !icac.unwrap_or(noc).is_authority(&fabric.root_ca)? {
+            return Err(Error::Invalid);
+        }
+        Ok(())
+    }
+
+    fn validate_certs(fabric: &Fabric, noc: &Cert, icac: Option<&Cert>) -> Result<(), Error> {
+        let mut verifier = noc.verify_chain_start();
+        if let Some(icac) = icac {
+            verifier = verifier.add_cert(icac)?;
+        }
+        verifier.add_cert(&fabric.root_ca)?.finalise()?;
 
         Ok(())
     }
 
+    /// Generate the ephemeral key pair of a handshake
+    ///
+    /// The private key comes from the same RandomSource as the randoms of the handshake, so
+    /// that a deterministic source makes for a deterministic handshake. A public key that is
+    /// the same as that of the previous handshake, or as the node's operational key
+    /// `op_pub_key`, means that the source is broken.
+    fn gen_ephemeral_key(&mut self, op_pub_key: &[u8]) -> Result<KeyPair, Error> {
+        let mut priv_key = [0u8; crypto::BIGNUM_LEN_BYTES];
+        self.rand.fill(&mut priv_key);
+        let key_pair = KeyPair::from_private_scalar(&priv_key)?;
+        let mut pub_key = [0u8; crypto::EC_POINT_LEN_BYTES];
+        let len = key_pair.get_public_key(&mut pub_key)?;
+        if len != crypto::EC_POINT_LEN_BYTES {
+            error!("Public key length incorrect: {}", len);
+            return Err(Error::Crypto);
+        }
+        if self.last_ephemeral_key == Some(pub_key) || pub_key[..] == *op_pub_key {
+            error!("Ephemeral key reused, the RNG may have failed");
+            return Err(Error::Crypto);
+        }
+        self.last_ephemeral_key = Some(pub_key);
+        Ok(key_pair)
+    }
+
+    fn derive_shared_secret(
+        key_pair: KeyPair,
+        peer_pub_key: &[u8],
+        our_pub_key: &mut [u8; crypto::EC_POINT_LEN_BYTES],
+        shared_secret: &mut [u8; crypto::ECDH_SHARED_SECRET_LEN_BYTES],
+    ) -> Result<(), Error> {
+        let len = key_pair.get_public_key(our_pub_key)?;
+        if len != crypto::EC_POINT_LEN_BYTES {
+            error!("Public key length incorrect: {}", len);
+            return Err(Error::Crypto);
+        }
+
+        // A peer public key that isn't a valid P-256 point fails in the backend
+        let len = key_pair
+            .derive_secret(peer_pub_key, shared_secret)
+            .map_err(|e| {
+                error!("Failed to derive shared secret: {}", e);
+                Error::Crypto
+            })?;
+        if len != crypto::ECDH_SHARED_SECRET_LEN_BYTES {
+            error!("Derived secret length incorrect: {}", len);
+            return Err(Error::Crypto);
+        }
+        Ok(())
+    }
+
     fn get_session_keys(
         ipk: &[u8],
         tt: &Sha256,
@@ -368,12 +580,8 @@ impl Case {
         )?;
         // println!("Sigma3 Key: {:x?}", sigma3_key);
 
-        let nonce: [u8; 13] = [
-            0x4e, 0x43, 0x41, 0x53, 0x45, 0x5f, 0x53, 0x69, 0x67, 0x6d, 0x61, 0x33, 0x4e,
-        ];
-
         let encrypted_len = encrypted.len();
-        crypto::decrypt_in_place(&sigma3_key, &nonce, &[], encrypted)?;
+        crypto::decrypt_in_place(&sigma3_key, &crypto::NONCE_CASE_SIGMA3, &[], encrypted)?;
         Ok(encrypted_len - crypto::AEAD_MIC_LEN_BYTES)
     }
 
@@ -436,13 +644,11 @@ impl Case {
     fn get_sigma2_encryption(
         fabric: &RwLockReadGuardRef<FabricMgrInner, Option<Fabric>>,
         our_random: &[u8],
+        resumption_id: &[u8],
         case_session: &mut CaseSession,
         signature: &[u8],
         out: &mut [u8],
     ) -> Result<usize, Error> {
-        let mut resumption_id: [u8; 16] = [0; 16];
-        rand::thread_rng().fill_bytes(&mut resumption_id);
-
         // We are guaranteed this unwrap will work
         let fabric = fabric.as_ref().as_ref().unwrap();
 
@@ -458,14 +664,13 @@ impl Case {
         let mut tw = TLVWriter::new(&mut write_buf);
         tw.start_struct(TagType::Anonymous)?;
         tw.str16_as(TagType::Context(1), |buf| fabric.noc.as_tlv(buf))?;
-        tw.str16_as(TagType::Context(2), |buf| fabric.icac.as_tlv(buf))?;
+        if let Some(icac) = &fabric.icac {
+            tw.str16_as(TagType::Context(2), |buf| icac.as_tlv(buf))?;
+        }
         tw.str8(TagType::Context(3), signature)?;
-        tw.str8(TagType::Context(4), &resumption_id)?;
+        tw.str8(TagType::Context(4), resumption_id)?;
         tw.end_container()?;
         //println!("TBE is {:x?}", write_buf.as_borrow_slice());
-        let nonce: [u8; crypto::AEAD_NONCE_LEN_BYTES] = [
-            0x4e, 0x43, 0x41, 0x53, 0x45, 0x5f, 0x53, 0x69, 0x67, 0x6d, 0x61, 0x32, 0x4e,
-        ];
         //        let nonce = GenericArray::from_slice(&nonce);
         //        type AesCcm = Ccm<Aes128, U16, U13>;
         //        let cipher = AesCcm::new(GenericArray::from_slice(key));
@@ -476,7 +681,7 @@ impl Case {
 
         crypto::encrypt_in_place(
             &sigma2_key,
-            &nonce,
+            &crypto::NONCE_CASE_SIGMA2,
             &[],
             cipher_text,
             cipher_text.len() - TAG_LEN,
@@ -498,7 +703,9 @@ impl Case {
         let mut tw = TLVWriter::new(&mut write_buf);
         tw.start_struct(TagType::Anonymous)?;
         tw.str16_as(TagType::Context(1), |buf| fabric.noc.as_tlv(buf))?;
-        tw.str16_as(TagType::Context(2), |buf| fabric.icac.as_tlv(buf))?;
+        if let Some(icac) = &fabric.icac {
+            tw.str16_as(TagType::Context(2), |buf| icac.as_tlv(buf))?;
+        }
         tw.str8(TagType::Context(3), our_pub_key)?;
         tw.str8(TagType::Context(4), peer_pub_key)?;
         tw.end_container()?;
@@ -520,6 +727,468 @@ struct Sigma1Req<'a> {
 #[tlvargs(start = 1, lifetime = "'a")]
 struct Sigma3Decrypt<'a> {
     initiator_noc: OctetStr<'a>,
-    initiator_icac: OctetStr<'a>,
+    initiator_icac: Option<OctetStr<'a>>,
     signature: OctetStr<'a>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cert::{self, test_vectors},
+        test_utils::MemKvStore,
+        tlv::{TLVWriter, TagType},
+        transport::{
+            exchange::{Exchange, ExchangeCtx},
+            packet::{Packet, PacketPool},
+            session::SessionMgr,
+        },
+        utils::writebuf::WriteBuf,
+    };
+    use boxslab::Slab;
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        sync::Mutex,
+    };
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn new_case() -> Case {
+        Case::new(Arc::new(FabricMgr::new().unwrap()))
+    }
+
+    // Returns the payload of the response
+    fn handle_sigma1(data: &[u8]) -> Result<Vec<u8>, Error> {
+        handle_sigma1_on(&mut new_case(), data)
+    }
+
+    fn handle_sigma1_on(case: &mut Case, data: &[u8]) -> Result<Vec<u8>, Error> {
+        handle_on(&mut Default::default(), data, |ctx| {
+            case.handle_casesigma1(ctx)
+        })
+    }
+
+    // Returns the payload of the response of `handler` to `data`, received on `exch`
+    fn handle_on<F>(exch: &mut Exchange, data: &[u8], handler: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(&mut ProtoCtx) -> Result<(), Error>,
+    {
+        let mut sess_mgr: SessionMgr = Default::default();
+        let sess_idx = sess_mgr
+            .get_or_add(
+                0,
+                Address::Udp(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    5542,
+                )),
+                None,
+                false,
+            )
+            .unwrap();
+        let exch_ctx = ExchangeCtx {
+            exch,
+            sess: sess_mgr.get_session_handle(sess_idx),
+        };
+        let mut rx = Slab::<PacketPool>::new(Packet::new_rx().unwrap()).unwrap();
+        let tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        rx.as_borrow_slice()[..data.len()].copy_from_slice(data);
+        rx.get_parsebuf().unwrap().set_len(data.len());
+
+        let mut ctx = ProtoCtx::new(exch_ctx, rx, tx);
+        handler(&mut ctx)?;
+        Ok(ctx.tx.get_writebuf()?.as_borrow_slice().to_vec())
+    }
+
+    #[test]
+    fn test_sigma1_truncated() {
+        assert_eq!(handle_sigma1(&[]), Err(Error::TruncatedPacket));
+        assert!(handle_sigma1(&[0x15]).is_err());
+    }
+
+    // A Sigma1 whose Destination ID doesn't match any fabric
+    fn unmatched_sigma1() -> Vec<u8> {
+        let mut buf = [0u8; 200];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.str8(TagType::Context(1), &[0x11; 32]).unwrap();
+        tw.u16(TagType::Context(2), 1).unwrap();
+        tw.str8(TagType::Context(3), &[0x22; 32]).unwrap();
+        tw.str8(TagType::Context(4), &[0x04; crypto::EC_POINT_LEN_BYTES])
+            .unwrap();
+        tw.end_container().unwrap();
+        wb.as_slice().to_vec()
+    }
+
+    #[test]
+    fn test_sigma1_no_matching_fabric() {
+        // General code Failure, Secure Channel, NoSharedTrustRoots
+        assert_eq!(
+            handle_sigma1(&unmatched_sigma1()).unwrap(),
+            [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_sigma1_busy() {
+        let mut case = new_case();
+        case.set_max_handshakes(2);
+
+        // Two handshakes are in progress
+        let mut in_progress: Vec<HandshakeSlot> = (0..2)
+            .map(|_| HandshakeSlot::acquire(&case.handshakes, 2).unwrap())
+            .collect();
+
+        // General code Failure, Secure Channel, Busy, with the minimum wait time
+        assert_eq!(
+            handle_sigma1_on(&mut case, &unmatched_sigma1()).unwrap(),
+            [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0xf4, 0x01]
+        );
+
+        // One of them completes, and frees its slot
+        in_progress.pop();
+        assert_eq!(
+            handle_sigma1_on(&mut case, &unmatched_sigma1()).unwrap(),
+            [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]
+        );
+        // The failed handshake doesn't hold on to its slot
+        assert_eq!(case.handshakes.load(Ordering::SeqCst), 1);
+    }
+
+    // An RNG that is stuck, so that the same key pair is generated every time
+    struct ConstantRandom;
+
+    impl RandomSource for ConstantRandom {
+        fn fill(&mut self, buf: &mut [u8]) {
+            buf.fill(0x5a);
+        }
+    }
+
+    // A Sigma1 whose Destination ID matches `fabric`
+    fn matched_sigma1(fabric: &Fabric) -> Vec<u8> {
+        let initiator_random = [0x11; 32];
+        let dest_id = fabric
+            .compute_dest_id(&initiator_random, fabric.ipk.op_key())
+            .unwrap();
+        let mut peer_pub_key = [0; crypto::EC_POINT_LEN_BYTES];
+        KeyPair::new()
+            .unwrap()
+            .get_public_key(&mut peer_pub_key)
+            .unwrap();
+
+        let mut buf = [0u8; 200];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.str8(TagType::Context(1), &initiator_random).unwrap();
+        tw.u16(TagType::Context(2), 1).unwrap();
+        tw.str8(TagType::Context(3), &dest_id).unwrap();
+        tw.str8(TagType::Context(4), &peer_pub_key).unwrap();
+        tw.end_container().unwrap();
+        wb.as_slice().to_vec()
+    }
+
+    fn case_with_fabric(op_key: KeyPair) -> (Case, Vec<u8>) {
+        case_with_chain(
+            op_key,
+            Cert::new(&test_vectors::RCA1_SUCCESS).unwrap(),
+            Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+            Cert::new(&test_vectors::NOC1_SUCCESS).unwrap(),
+        )
+    }
+
+    // A Case with a fabric of the given chain, along with a Sigma1 that matches the fabric
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn case_with_chain(
+        op_key: KeyPair,
+        root_ca: Cert,
+        icac: Option<Cert>,
+        noc: Cert,
+    ) -> (Case, Vec<u8>) {
+        let fabric = Fabric::new(op_key, root_ca, icac, noc, &[0x5a; 16], 0).unwrap();
+        let sigma1 = matched_sigma1(&fabric);
+        let fabric_mgr =
+            FabricMgr::new_with_store(Arc::new(Mutex::new(MemKvStore::default()))).unwrap();
+        fabric_mgr.add(fabric).unwrap();
+        (Case::new(Arc::new(fabric_mgr)), sigma1)
+    }
+
+    #[test]
+    fn test_sigma1_reused_ephemeral_key() {
+        let (mut case, sigma1) = case_with_fabric(KeyPair::new().unwrap());
+        case.set_random_source(Box::new(ConstantRandom));
+        assert!(handle_sigma1_on(&mut case, &sigma1).is_ok());
+        // The RNG produced the same ephemeral key as in the previous handshake
+        assert_eq!(handle_sigma1_on(&mut case, &sigma1), Err(Error::Crypto));
+        assert_eq!(case.handshakes.load(Ordering::SeqCst), 0);
+
+        // The ephemeral key is the same as the operational key
+        let op_key = KeyPair::from_private_scalar(&[0x5a; crypto::BIGNUM_LEN_BYTES]).unwrap();
+        let (mut case, sigma1) = case_with_fabric(op_key);
+        case.set_random_source(Box::new(ConstantRandom));
+        assert_eq!(handle_sigma1_on(&mut case, &sigma1), Err(Error::Crypto));
+    }
+
+    // A deterministic source, that counts up from 0
+    struct CountingRandom(u8);
+
+    impl RandomSource for CountingRandom {
+        fn fill(&mut self, buf: &mut [u8]) {
+            for b in buf {
+                *b = self.0;
+                self.0 = self.0.wrapping_add(1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sigma2_random() {
+        let sigma2 = || {
+            let (mut case, sigma1) = case_with_fabric(KeyPair::new().unwrap());
+            case.set_random_source(Box::new(CountingRandom(0)));
+            handle_sigma1_on(&mut case, &sigma1).unwrap()
+        };
+        let (first, second) = (sigma2(), sigma2());
+        let (first, second) = (
+            get_root_node_struct(&first).unwrap(),
+            get_root_node_struct(&second).unwrap(),
+        );
+
+        // The ephemeral key takes the first bytes of the source, the random the next ones
+        let expected: Vec<u8> = (32..64).collect();
+        assert_eq!(first.find_tag(1).unwrap().slice().unwrap(), expected);
+        // The same source gives the same ephemeral key
+        assert_eq!(
+            first.find_tag(3).unwrap().slice().unwrap(),
+            second.find_tag(3).unwrap().slice().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sigma2_without_icac() {
+        let (root, noc) = cert::tests::noc_under_root();
+        let (mut case, sigma1) = case_with_chain(KeyPair::new().unwrap(), root, None, noc);
+        let sigma2 = handle_sigma1_on(&mut case, &sigma1).unwrap();
+        assert!(get_root_node_struct(&sigma2).unwrap().find_tag(4).is_ok());
+
+        // The TBEData2 has the NOC, but no ICAC
+        let fabric = case.fabric_mgr.get_fabric(1).unwrap();
+        let mut case_session = CaseSession::new(1, 2).unwrap();
+        let our_random = [0x11; 32];
+        let mut encrypted = [0u8; MAX_TX_BUF_SIZE];
+        let len = Case::get_sigma2_encryption(
+            &fabric,
+            &our_random,
+            &[0x22; 16],
+            &mut case_session,
+            &[0x33; crypto::EC_SIGNATURE_LEN_BYTES],
+            &mut encrypted,
+        )
+        .unwrap();
+        let mut key = [0u8; crypto::SYMM_KEY_LEN_BYTES];
+        let ipk = fabric.as_ref().as_ref().unwrap().ipk.op_key();
+        Case::get_sigma2_key(ipk, &our_random, &mut case_session, &mut key).unwrap();
+        let decrypted = &mut encrypted[..len];
+        let len =
+            crypto::decrypt_in_place(&key, &crypto::NONCE_CASE_SIGMA2, &[], decrypted).unwrap();
+        let tbe = get_root_node_struct(&decrypted[..len]).unwrap();
+        assert!(tbe.find_tag(1).is_ok());
+        assert!(tbe.find_tag(2).is_err());
+        assert_eq!(tbe.find_tag(4).unwrap().slice().unwrap(), &[0x22; 16]);
+    }
+
+    #[test]
+    fn test_validate_without_icac() {
+        let (root, noc) = cert::tests::noc_under_root();
+        let fabric = Fabric::new(KeyPair::new().unwrap(), root, None, noc, &[0x5a; 16], 0).unwrap();
+        assert_eq!(Case::validate_fabric(&fabric, &fabric.noc, None), Ok(()));
+        assert_eq!(Case::validate_certs(&fabric, &fabric.noc, None), Ok(()));
+
+        // A NOC issued by another root
+        let (_, other_noc) = cert::tests::noc_under_root();
+        assert_eq!(
+            Case::validate_fabric(&fabric, &other_noc, None),
+            Err(Error::Invalid)
+        );
+        // A NOC issued by an ICAC needs it to be part of the chain
+        let icac_noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
+        assert_eq!(
+            Case::validate_fabric(&fabric, &icac_noc, None),
+            Err(Error::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_sigma1_missing_fabric_status() {
+        let (mut case, sigma1) = case_with_fabric(KeyPair::new().unwrap());
+        // The fabric goes away after its Destination ID matched
+        case.remove_matched_fabric = true;
+
+        // General code Unexpected, Secure Channel, GeneralFailure
+        assert_eq!(
+            handle_sigma1_on(&mut case, &sigma1).unwrap(),
+            [0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff]
+        );
+        assert!(case.fabric_mgr.get_fabric(1).unwrap().is_none());
+        // The failed handshake doesn't hold on to its slot
+        assert_eq!(case.handshakes.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_validate_fabric() {
+        let fabric = |root: &[u8]| {
+            Fabric::new(
+                KeyPair::new().unwrap(),
+                Cert::new(root).unwrap(),
+                Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+                Cert::new(&test_vectors::NOC1_SUCCESS).unwrap(),
+                &[0x5a; 16],
+                0,
+            )
+            .unwrap()
+        };
+        let noc = Cert::new(&test_vectors::NOC1_SUCCESS).unwrap();
+        let icac = Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap();
+        let sigma1_fabric = fabric(&test_vectors::RCA1_SUCCESS);
+        assert_eq!(
+            Case::validate_fabric(&sigma1_fabric, &noc, Some(&icac)),
+            Ok(())
+        );
+
+        // A NOC of the Fabric ID 2, the Sigma1 resolved to the Fabric ID 1
+        let mut other_noc = test_vectors::NOC1_SUCCESS;
+        other_noc[39] = 0x2;
+        let other_noc = Cert::new(&other_noc).unwrap();
+        assert_eq!(other_noc.get_fabric_id(), Ok(2));
+        assert_eq!(
+            Case::validate_fabric(&sigma1_fabric, &other_noc, Some(&icac)),
+            Err(Error::Invalid)
+        );
+
+        // The same Fabric ID, but the fabric has another root
+        let other_root = fabric(&test_vectors::NOC1_SUCCESS);
+        assert_eq!(
+            Case::validate_fabric(&other_root, &noc, Some(&icac)),
+            Err(Error::Invalid)
+        );
+    }
+
+    // A Sigma3 with the given NOC, for the handshake of `case_session` on `fabric`
+    fn sigma3(fabric: &Fabric, case_session: &CaseSession, noc: &[u8]) -> Vec<u8> {
+        let mut tbe = [0u8; MAX_TX_BUF_SIZE];
+        let tbe_len = tbe.len();
+        let mut wb = WriteBuf::new(&mut tbe, tbe_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.str16(TagType::Context(1), noc).unwrap();
+        tw.str16(TagType::Context(2), &test_vectors::ICAC1_SUCCESS)
+            .unwrap();
+        tw.str8(TagType::Context(3), &[0x33; crypto::EC_SIGNATURE_LEN_BYTES])
+            .unwrap();
+        tw.end_container().unwrap();
+        let len = wb.as_slice().len();
+
+        let mut key = [0u8; crypto::SYMM_KEY_LEN_BYTES];
+        Case::get_sigma3_key(
+            fabric.ipk.op_key(),
+            &case_session.tt_hash,
+            &case_session.shared_secret,
+            &mut key,
+        )
+        .unwrap();
+        let len = crypto::encrypt_in_place(
+            &key,
+            &crypto::NONCE_CASE_SIGMA3,
+            &[],
+            &mut tbe[..len + crypto::AEAD_MIC_LEN_BYTES],
+            len,
+        )
+        .unwrap();
+
+        let mut buf = [0u8; MAX_TX_BUF_SIZE];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.str16(TagType::Context(1), &tbe[..len]).unwrap();
+        tw.end_container().unwrap();
+        wb.as_slice().to_vec()
+    }
+
+    #[test]
+    fn test_sigma3_noc_of_other_fabric() {
+        let (mut case, _) = case_with_fabric(KeyPair::new().unwrap());
+        let mut case_session = CaseSession::new(1, 2).unwrap();
+        case_session.state = State::Sigma1Rx;
+        case_session.local_fabric_idx = 1;
+        case_session.shared_secret = [0x44; crypto::ECDH_SHARED_SECRET_LEN_BYTES];
+
+        // A NOC of the Fabric ID 2, the Sigma1 resolved to the Fabric ID 1
+        let mut other_noc = test_vectors::NOC1_SUCCESS;
+        other_noc[39] = 0x2;
+        let sigma3 = {
+            let fabric = case.fabric_mgr.get_fabric(1).unwrap();
+            sigma3(fabric.as_ref().as_ref().unwrap(), &case_session, &other_noc)
+        };
+
+        let mut exch: Exchange = Default::default();
+        exch.set_exchange_data(Box::new(case_session));
+        // General code Failure, Secure Channel, InvalidParameter
+        assert_eq!(
+            handle_on(&mut exch, &sigma3, |ctx| case.handle_casesigma3(ctx)).unwrap(),
+            [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00]
+        );
+        assert!(!exch.is_state_open());
+    }
+
+    #[test]
+    fn test_derive_shared_secret() {
+        let peer = KeyPair::new().unwrap();
+        let mut peer_pub_key = [0; crypto::EC_POINT_LEN_BYTES];
+        peer.get_public_key(&mut peer_pub_key).unwrap();
+
+        let mut our_pub_key = [0; crypto::EC_POINT_LEN_BYTES];
+        let mut shared_secret = [0; crypto::ECDH_SHARED_SECRET_LEN_BYTES];
+        Case::derive_shared_secret(
+            KeyPair::new().unwrap(),
+            &peer_pub_key,
+            &mut our_pub_key,
+            &mut shared_secret,
+        )
+        .unwrap();
+
+        let mut peer_secret = [0; crypto::ECDH_SHARED_SECRET_LEN_BYTES];
+        peer.derive_secret(&our_pub_key, &mut peer_secret).unwrap();
+        assert_eq!(shared_secret, peer_secret);
+    }
+
+    #[test]
+    fn test_debug_redacts_shared_secret() {
+        let mut case_session = CaseSession::new(1, 2).unwrap();
+        case_session.shared_secret = [0xa5; crypto::ECDH_SHARED_SECRET_LEN_BYTES];
+
+        let dbg = format!("{:?}", case_session);
+        assert!(dbg.contains("shared_secret: <redacted>"));
+        assert!(!dbg.contains(&format!("{:?}", case_session.shared_secret)));
+    }
+
+    #[test]
+    fn test_derive_shared_secret_malformed_peer_key() {
+        // Uncompressed point prefix, but the coordinates aren't on the curve
+        let mut peer_pub_key = [0xff; crypto::EC_POINT_LEN_BYTES];
+        peer_pub_key[0] = 0x04;
+
+        let mut our_pub_key = [0; crypto::EC_POINT_LEN_BYTES];
+        let mut shared_secret = [0; crypto::ECDH_SHARED_SECRET_LEN_BYTES];
+        assert_eq!(
+            Case::derive_shared_secret(
+                KeyPair::new().unwrap(),
+                &peer_pub_key,
+                &mut our_pub_key,
+                &mut shared_secret,
+            ),
+            Err(Error::Crypto)
+        );
+    }
+}
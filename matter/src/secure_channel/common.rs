@@ -32,6 +32,9 @@ pub enum SCStatusCodes {
     CloseSession = 3,
     Busy = 4,
     SessionNotFound = 5,
+    // Not in the list of Secure Channel codes, this goes along with a general code that
+    // has the details
+    GeneralFailure = 0xFFFF,
 }
 
 pub fn create_sc_status_report(
@@ -51,6 +54,8 @@ pub fn create_sc_status_report(
         | SCStatusCodes::InvalidParameter
         | SCStatusCodes::NoSharedTrustRoots
         | SCStatusCodes::SessionNotFound => GeneralCode::Failure,
+        // Only sent for a state that should never have been reached
+        SCStatusCodes::GeneralFailure => GeneralCode::Unexpected,
     };
     create_status_report(
         proto_tx,
@@ -66,3 +71,9 @@ pub fn create_mrp_standalone_ack(proto_tx: &mut Packet) {
     proto_tx.set_proto_opcode(OpCode::MRPStandAloneAck as u8);
     proto_tx.unset_reliable();
 }
+
+/// Whether the received message is a standalone acknowledgement, with no payload to process
+pub fn is_mrp_standalone_ack(proto_rx: &Packet) -> bool {
+    proto_rx.get_proto_id() == PROTO_ID_SECURE_CHANNEL as u16
+        && proto_rx.get_proto_opcode() == OpCode::MRPStandAloneAck as u8
+}
@@ -35,6 +35,11 @@ impl SecureChannel {
         self.pake.disable();
     }
 
+    /// Set the maximum number of CASE handshakes that are in progress at a time
+    pub fn set_max_case_handshakes(&mut self, max: usize) {
+        self.case.set_max_handshakes(max);
+    }
+
     fn mrpstandaloneack_handler(&mut self, _ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
         info!("In MRP StandAlone ACK Handler");
         Ok(ResponseRequired::No)
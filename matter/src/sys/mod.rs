@@ -1,3 +1,5 @@
+use crate::error::Error;
+
 #[cfg(target_os = "macos")]
 mod sys_macos;
 #[cfg(target_os = "macos")]
@@ -12,3 +14,12 @@ pub use self::sys_linux::*;
 mod posix;
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 pub use self::posix::*;
+
+/// A persistent key-value store
+///
+/// The application may provide its own implementation to choose where the
+/// state of the stack is persisted.
+pub trait KvStore: Send {
+    fn set_kv_slice(&self, key: &str, val: &[u8]) -> Result<(), Error>;
+    fn get_kv_slice(&self, key: &str, val: &mut Vec<u8>) -> Result<usize, Error>;
+}
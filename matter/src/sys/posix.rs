@@ -5,6 +5,7 @@ use std::{
     sync::{Arc, Mutex, Once},
 };
 
+use super::KvStore;
 use crate::error::Error;
 
 // The Packet Pool that is allocated from. POSIX systems can use
@@ -71,3 +72,13 @@ impl Psm {
         Ok(())
     }
 }
+
+impl KvStore for Psm {
+    fn set_kv_slice(&self, key: &str, val: &[u8]) -> Result<(), Error> {
+        Psm::set_kv_slice(self, key, val)
+    }
+
+    fn get_kv_slice(&self, key: &str, val: &mut Vec<u8>) -> Result<usize, Error> {
+        Psm::get_kv_slice(self, key, val)
+    }
+}
@@ -0,0 +1,214 @@
+//! Helpers for composing a Matter node in tests
+//!
+//! This is only available with the `test-utils` feature.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    acl::{AclEntry, AclMgr},
+    data_model::{
+        cluster_basic_information::BasicInfoConfig,
+        core::DataModel,
+        sdm::dev_att::{DataType, DevAttDataFetcher},
+    },
+    error::Error,
+    fabric::FabricMgr,
+    groups::GroupMgr,
+    interaction_model::{
+        messages::msg::{ReadReq, WriteReq},
+        read::{ReadState, ReportDataBuilder, ReportDataType},
+        InteractionConsumer, Transaction,
+    },
+    sys::KvStore,
+    tlv::{TLVWriter, TagType},
+    transport::{
+        network::Address,
+        session::{CloneData, Session, SessionMode},
+    },
+    utils::writebuf::WriteBuf,
+};
+
+/// A Device Attestation Data Fetcher that doesn't return any meaningful data
+pub struct DummyDevAtt;
+
+impl DevAttDataFetcher for DummyDevAtt {
+    fn get_devatt_data(&self, _data_type: DataType, _data: &mut [u8]) -> Result<usize, Error> {
+        Ok(2)
+    }
+}
+
+/// A Key-Value Store that only lives in memory
+#[derive(Default)]
+pub struct MemKvStore(RefCell<HashMap<String, Vec<u8>>>);
+
+impl KvStore for MemKvStore {
+    fn set_kv_slice(&self, key: &str, val: &[u8]) -> Result<(), Error> {
+        self.0.borrow_mut().insert(key.to_owned(), val.to_vec());
+        Ok(())
+    }
+
+    fn get_kv_slice(&self, key: &str, val: &mut Vec<u8>) -> Result<usize, Error> {
+        let map = self.0.borrow();
+        let v = map.get(key).ok_or(Error::NotFound)?;
+        val.extend_from_slice(v);
+        Ok(v.len())
+    }
+}
+
+/// Builder for a [MatterNode]
+///
+/// # Examples
+/// ```
+/// use matter::acl::{AclEntry, AuthMode};
+/// use matter::data_model::objects::Privilege;
+/// use matter::test_utils::MatterNodeBuilder;
+///
+/// let mut acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+/// acl.add_subject(112233).unwrap();
+/// let node = MatterNodeBuilder::new().acl(acl).build().unwrap();
+/// let mut sess = node.session(1, 112233);
+/// ```
+pub struct MatterNodeBuilder {
+    dev_det: BasicInfoConfig,
+    dev_att: Box<dyn DevAttDataFetcher>,
+    acls: Vec<AclEntry>,
+}
+
+impl Default for MatterNodeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MatterNodeBuilder {
+    pub fn new() -> Self {
+        Self {
+            dev_det: BasicInfoConfig {
+                vid: 10,
+                pid: 11,
+                hw_ver: 12,
+                sw_ver: 13,
+            },
+            dev_att: Box::new(DummyDevAtt),
+            acls: Vec::new(),
+        }
+    }
+
+    pub fn dev_det(mut self, dev_det: BasicInfoConfig) -> Self {
+        self.dev_det = dev_det;
+        self
+    }
+
+    pub fn dev_att(mut self, dev_att: Box<dyn DevAttDataFetcher>) -> Self {
+        self.dev_att = dev_att;
+        self
+    }
+
+    /// Add an ACL entry, the node starts off with no ACL entries
+    pub fn acl(mut self, entry: AclEntry) -> Self {
+        self.acls.push(entry);
+        self
+    }
+
+    pub fn build(self) -> Result<MatterNode, Error> {
+        // The fabrics and ACLs are not persisted, so that tests don't affect each other
+        let fabric_mgr = Arc::new(FabricMgr::new_with_store(Arc::new(Mutex::new(
+            MemKvStore::default(),
+        )))?);
+        let acl_mgr = Arc::new(AclMgr::new_with(false)?);
+        for entry in self.acls {
+            acl_mgr.add(entry)?;
+        }
+        let group_mgr = Arc::new(GroupMgr::new());
+        let dm = DataModel::new(
+            self.dev_det,
+            self.dev_att,
+            fabric_mgr.clone(),
+            acl_mgr.clone(),
+            group_mgr.clone(),
+        )?;
+        Ok(MatterNode {
+            dm,
+            fabric_mgr,
+            acl_mgr,
+            group_mgr,
+        })
+    }
+}
+
+/// A Matter node with its Data Model, Fabrics, ACLs and Groups
+pub struct MatterNode {
+    pub dm: DataModel,
+    pub fabric_mgr: Arc<FabricMgr>,
+    pub acl_mgr: Arc<AclMgr>,
+    pub group_mgr: Arc<GroupMgr>,
+}
+
+impl MatterNode {
+    /// Create a CASE session with a peer on the given fabric index
+    pub fn session(&self, fab_idx: u8, peer_node_id: u64) -> Session {
+        let clone_data = CloneData::new(
+            123456,
+            peer_node_id,
+            10,
+            30,
+            Address::Udp(SocketAddr::new(
+                std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                5542,
+            )),
+            SessionMode::Case(fab_idx),
+        );
+        Session::clone(&clone_data)
+    }
+
+    /// Read attributes over the session, the ReportData is encoded in `out_buf`
+    pub fn read(
+        &self,
+        sess: &mut Session,
+        read_req: &ReadReq,
+        out_buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let out_buf_len = out_buf.len();
+        let mut wb = WriteBuf::new(out_buf, out_buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        let mut report = ReportDataBuilder::new(&mut tw, ReportDataType::Read)?;
+        report.attr_reports(|tw| {
+            let mut state = ReadState::from_req(read_req);
+            self.dm
+                .consume_read_attr(&mut state, &mut Transaction::new(sess), tw)
+        })?;
+        report.finish()?;
+        Ok(wb.as_slice().len())
+    }
+
+    /// Write attributes over the session, the WriteResponse is encoded in `out_buf`
+    pub fn write(
+        &self,
+        sess: &mut Session,
+        write_req: &WriteReq,
+        out_buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.consume(out_buf, |tw| {
+            self.dm
+                .consume_write_attr(write_req, &mut Transaction::new(sess), tw)
+        })
+    }
+
+    fn consume<F>(&self, out_buf: &mut [u8], f: F) -> Result<usize, Error>
+    where
+        F: FnOnce(&mut TLVWriter) -> Result<(), Error>,
+    {
+        let out_buf_len = out_buf.len();
+        let mut wb = WriteBuf::new(out_buf, out_buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        tw.start_struct(TagType::Anonymous)?;
+        f(&mut tw)?;
+        tw.end_container()?;
+        Ok(wb.as_slice().len())
+    }
+}
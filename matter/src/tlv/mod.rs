@@ -14,6 +14,8 @@ pub const TAG_SHIFT_BITS: u8 = 5;
 pub const TAG_MASK: u8 = 0xe0;
 pub const TYPE_MASK: u8 = 0x1f;
 pub const MAX_TAG_INDEX: usize = 8;
+/// The maximum nesting of containers that is supported
+pub const MAX_CONTAINER_DEPTH: usize = 9;
 
 pub static TAG_SIZE_MAP: [usize; MAX_TAG_INDEX] = [
     0, // Anonymous
@@ -30,7 +32,7 @@ mod parser;
 mod traits;
 mod writer;
 
-pub use matter_macro_derive::{FromTLV, ToTLV};
+pub use matter_macro_derive::{FabricScoped, FromTLV, ToTLV};
 pub use parser::*;
 pub use traits::*;
 pub use writer::*;
@@ -1,10 +1,13 @@
 use crate::error::Error;
 
 use byteorder::{ByteOrder, LittleEndian};
-use log::{error, info};
-use std::fmt;
+use log::{debug, error, log_enabled, Level};
+use std::{convert::TryFrom, fmt};
 
-use super::{TagType, MAX_TAG_INDEX, TAG_MASK, TAG_SHIFT_BITS, TAG_SIZE_MAP, TYPE_MASK};
+use super::{
+    FromTLV, TagType, MAX_CONTAINER_DEPTH, MAX_TAG_INDEX, TAG_MASK, TAG_SHIFT_BITS, TAG_SIZE_MAP,
+    TYPE_MASK,
+};
 
 pub struct TLVList<'a> {
     buf: &'a [u8],
@@ -43,8 +46,8 @@ pub enum ElementType<'a> {
     Utf64l,
     Str8l(&'a [u8]),
     Str16l(&'a [u8]),
-    Str32l,
-    Str64l,
+    Str32l(&'a [u8]),
+    Str64l(&'a [u8]),
     Null,
     Struct(Pointer<'a>),
     Array(Pointer<'a>),
@@ -53,6 +56,71 @@ pub enum ElementType<'a> {
     Last,
 }
 
+impl<'a> ElementType<'a> {
+    /// The name of the type, for diagnostics
+    pub fn name(&self) -> &'static str {
+        match self {
+            ElementType::S8(_) => "i8",
+            ElementType::S16(_) => "i16",
+            ElementType::S32(_) => "i32",
+            ElementType::S64(_) => "i64",
+            ElementType::U8(_) => "u8",
+            ElementType::U16(_) => "u16",
+            ElementType::U32(_) => "u32",
+            ElementType::U64(_) => "u64",
+            ElementType::False | ElementType::True => "bool",
+            ElementType::F32(_) => "f32",
+            ElementType::F64(_) => "f64",
+            ElementType::Utf8l(_)
+            | ElementType::Utf16l(_)
+            | ElementType::Utf32l
+            | ElementType::Utf64l => "utf8 string",
+            ElementType::Str8l(_)
+            | ElementType::Str16l(_)
+            | ElementType::Str32l(_)
+            | ElementType::Str64l(_) => "octet string",
+            ElementType::Null => "null",
+            ElementType::Struct(_) => "struct",
+            ElementType::Array(_) => "array",
+            ElementType::List(_) => "list",
+            ElementType::EndCnt => "end of container",
+            ElementType::Last => "invalid",
+        }
+    }
+}
+
+/// The type that an element was expected to be, and the type that it is
+///
+/// This converts to an Error::TLVTypeMismatch that carries both types.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TypeMismatch {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl From<TypeMismatch> for Error {
+    fn from(m: TypeMismatch) -> Self {
+        Error::TLVTypeMismatch {
+            expected: m.expected,
+            found: m.found,
+        }
+    }
+}
+
+/// The kinds of TLV containers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ContainerType {
+    Struct,
+    Array,
+    List,
+}
+
 const MAX_VALUE_INDEX: usize = 25;
 
 // This is a function that takes a TLVListIterator and returns the tag type
@@ -181,9 +249,19 @@ static VALUE_EXTRACTOR: [ExtractValue; MAX_VALUE_INDEX] = [
         }
     },
     // Str32l 18
-    { |_t| (0, ElementType::Last) },
+    {
+        |t| match read_length_value(4, t) {
+            Err(_) => (0, ElementType::Last),
+            Ok((size, string)) => (size, ElementType::Str32l(string)),
+        }
+    },
     // Str64l 19
-    { |_t| (0, ElementType::Last) },
+    {
+        |t| match read_length_value(8, t) {
+            Err(_) => (0, ElementType::Last),
+            Ok((size, string)) => (size, ElementType::Str64l(string)),
+        }
+    },
     // Null  20
     { |_t| (0, ElementType::Null) },
     // Struct 21
@@ -263,9 +341,10 @@ fn read_length_value<'a>(
     t: &TLVListIterator<'a>,
 ) -> Result<(usize, &'a [u8]), Error> {
     // The current offset is the string size
-    let length: usize = LittleEndian::read_uint(&t.buf[t.current..], size_of_length_field) as usize;
+    let length = LittleEndian::read_uint(&t.buf[t.current..], size_of_length_field);
+    let length = usize::try_from(length).map_err(|_| Error::NoSpace)?;
     // We'll consume the current offset (len) + the entire string
-    if length + size_of_length_field > t.left {
+    if length > t.left - size_of_length_field {
         // Return Error
         Err(Error::NoSpace)
     } else {
@@ -367,6 +446,15 @@ impl<'a> TLVElement<'a> {
         })
     }
 
+    /// Like iter(), with the tag of each element alongside it
+    ///
+    /// The iterator moves past an element, and past all its members if it is a container,
+    /// whether the element was looked into or not. A parser ignores the fields that it
+    /// doesn't understand by just going on to the next one.
+    pub fn iter_tagged(&self) -> Option<TLVTaggedIterator<'a>> {
+        self.iter().map(TLVTaggedIterator)
+    }
+
     pub fn new(tag: TagType, value: ElementType<'a>) -> Self {
         Self {
             tag_type: tag,
@@ -377,14 +465,41 @@ impl<'a> TLVElement<'a> {
     pub fn i8(&self) -> Result<i8, Error> {
         match self.element_type {
             ElementType::S8(a) => Ok(a),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("i8").into()),
+        }
+    }
+
+    pub fn i16(&self) -> Result<i16, Error> {
+        match self.element_type {
+            ElementType::S8(a) => Ok(a.into()),
+            ElementType::S16(a) => Ok(a),
+            _ => Err(self.type_mismatch("i16").into()),
+        }
+    }
+
+    pub fn i32(&self) -> Result<i32, Error> {
+        match self.element_type {
+            ElementType::S8(a) => Ok(a.into()),
+            ElementType::S16(a) => Ok(a.into()),
+            ElementType::S32(a) => Ok(a),
+            _ => Err(self.type_mismatch("i32").into()),
+        }
+    }
+
+    pub fn i64(&self) -> Result<i64, Error> {
+        match self.element_type {
+            ElementType::S8(a) => Ok(a.into()),
+            ElementType::S16(a) => Ok(a.into()),
+            ElementType::S32(a) => Ok(a.into()),
+            ElementType::S64(a) => Ok(a),
+            _ => Err(self.type_mismatch("i64").into()),
         }
     }
 
     pub fn u8(&self) -> Result<u8, Error> {
         match self.element_type {
             ElementType::U8(a) => Ok(a),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("u8").into()),
         }
     }
 
@@ -392,7 +507,7 @@ impl<'a> TLVElement<'a> {
         match self.element_type {
             ElementType::U8(a) => Ok(a.into()),
             ElementType::U16(a) => Ok(a),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("u16").into()),
         }
     }
 
@@ -401,7 +516,7 @@ impl<'a> TLVElement<'a> {
             ElementType::U8(a) => Ok(a.into()),
             ElementType::U16(a) => Ok(a.into()),
             ElementType::U32(a) => Ok(a),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("u32").into()),
         }
     }
 
@@ -411,7 +526,35 @@ impl<'a> TLVElement<'a> {
             ElementType::U16(a) => Ok(a.into()),
             ElementType::U32(a) => Ok(a.into()),
             ElementType::U64(a) => Ok(a),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("u64").into()),
+        }
+    }
+
+    /// An unsigned integer of any width, also accepting a signed integer that isn't negative
+    ///
+    /// This is for the values that peers may encode minimally, or with a different sign.
+    pub fn get_u64_any(&self) -> Result<u64, Error> {
+        match self.element_type {
+            ElementType::S8(_)
+            | ElementType::S16(_)
+            | ElementType::S32(_)
+            | ElementType::S64(_) => {
+                u64::try_from(self.i64()?).map_err(|_| self.type_mismatch("u64").into())
+            }
+            _ => self.u64(),
+        }
+    }
+
+    /// A signed integer of any width, also accepting an unsigned integer that fits an i64
+    pub fn get_i64_any(&self) -> Result<i64, Error> {
+        match self.element_type {
+            ElementType::U8(_)
+            | ElementType::U16(_)
+            | ElementType::U32(_)
+            | ElementType::U64(_) => {
+                i64::try_from(self.u64()?).map_err(|_| self.type_mismatch("i64").into())
+            }
+            _ => self.i64(),
         }
     }
 
@@ -420,8 +563,10 @@ impl<'a> TLVElement<'a> {
             ElementType::Str8l(s)
             | ElementType::Utf8l(s)
             | ElementType::Str16l(s)
-            | ElementType::Utf16l(s) => Ok(s),
-            _ => Err(Error::TLVTypeMismatch),
+            | ElementType::Utf16l(s)
+            | ElementType::Str32l(s)
+            | ElementType::Str64l(s) => Ok(s),
+            _ => Err(self.type_mismatch("string").into()),
         }
     }
 
@@ -429,35 +574,83 @@ impl<'a> TLVElement<'a> {
         match self.element_type {
             ElementType::False => Ok(false),
             ElementType::True => Ok(true),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("bool").into()),
+        }
+    }
+
+    pub fn null(&self) -> Result<(), Error> {
+        match self.element_type {
+            ElementType::Null => Ok(()),
+            _ => Err(self.type_mismatch("null").into()),
+        }
+    }
+
+    /// Ignore the element, whatever its type, without interpreting it
+    ///
+    /// The iterator that yielded the element moves past it, and past all its members if it
+    /// is a container, on its next call.
+    pub fn skip(&self) {}
+
+    /// The name of the type of the element, for diagnostics
+    pub fn type_name(&self) -> &'static str {
+        self.element_type.name()
+    }
+
+    /// Describe how the element isn't of the 'expected' type
+    pub fn type_mismatch(&self, expected: &'static str) -> TypeMismatch {
+        TypeMismatch {
+            expected,
+            found: self.type_name(),
         }
     }
 
+    /// Returns the kind of container this element is, or None if it isn't a container
+    pub fn container_type(&self) -> Option<ContainerType> {
+        match self.element_type {
+            ElementType::Struct(_) => Some(ContainerType::Struct),
+            ElementType::Array(_) => Some(ContainerType::Array),
+            ElementType::List(_) => Some(ContainerType::List),
+            _ => None,
+        }
+    }
+
+    pub fn is_struct(&self) -> bool {
+        self.container_type() == Some(ContainerType::Struct)
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.container_type() == Some(ContainerType::Array)
+    }
+
+    pub fn is_list(&self) -> bool {
+        self.container_type() == Some(ContainerType::List)
+    }
+
     pub fn confirm_struct(&self) -> Result<TLVElement<'a>, Error> {
         match self.element_type {
             ElementType::Struct(_) => Ok(*self),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("struct").into()),
         }
     }
 
     pub fn confirm_array(&self) -> Result<TLVElement<'a>, Error> {
         match self.element_type {
             ElementType::Array(_) => Ok(*self),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("array").into()),
         }
     }
 
     pub fn confirm_list(&self) -> Result<TLVElement<'a>, Error> {
         match self.element_type {
             ElementType::List(_) => Ok(*self),
-            _ => Err(Error::TLVTypeMismatch),
+            _ => Err(self.type_mismatch("list").into()),
         }
     }
 
     pub fn find_tag(&self, tag: u32) -> Result<TLVElement<'a>, Error> {
         let match_tag: TagType = TagType::Context(tag as u8);
 
-        let iter = self.iter().ok_or(Error::TLVTypeMismatch)?;
+        let iter = self.iter().ok_or_else(|| self.type_mismatch("container"))?;
         for a in iter {
             if match_tag == a.tag_type {
                 return Ok(a);
@@ -466,10 +659,34 @@ impl<'a> TLVElement<'a> {
         Err(Error::NoTagFound)
     }
 
+    /// Find the element with the profile tag 'tag'
+    ///
+    /// The common and implicit profile tags are told apart, but not their widths, a
+    /// CommonPrf32(1) finds an element that was encoded with CommonPrf16(1).
+    pub fn find_profile_tag(&self, tag: TagType) -> Result<TLVElement<'a>, Error> {
+        let match_tag = profile_tag(tag).ok_or(Error::Invalid)?;
+
+        let iter = self.iter().ok_or_else(|| self.type_mismatch("container"))?;
+        for a in iter {
+            if profile_tag(a.tag_type) == Some(match_tag) {
+                return Ok(a);
+            }
+        }
+        Err(Error::NoTagFound)
+    }
+
     pub fn get_tag(&self) -> TagType {
         self.tag_type
     }
 
+    pub fn get_ctx_tag(&self) -> Option<u8> {
+        if let TagType::Context(tag) = self.tag_type {
+            Some(tag)
+        } else {
+            None
+        }
+    }
+
     pub fn check_ctx_tag(&self, tag: u8) -> bool {
         if let TagType::Context(our_tag) = self.tag_type {
             if our_tag == tag {
@@ -484,6 +701,17 @@ impl<'a> TLVElement<'a> {
     }
 }
 
+// Returns whether this is a common profile tag, along with the tag number
+fn profile_tag(tag: TagType) -> Option<(bool, u32)> {
+    match tag {
+        TagType::CommonPrf16(t) => Some((true, t as u32)),
+        TagType::CommonPrf32(t) => Some((true, t)),
+        TagType::ImplPrf16(t) => Some((false, t as u32)),
+        TagType::ImplPrf32(t) => Some((false, t)),
+        _ => None,
+    }
+}
+
 impl<'a> fmt::Display for TLVElement<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.tag_type {
@@ -501,7 +729,9 @@ impl<'a> fmt::Display for TLVElement<'a> {
             ElementType::Str8l(a)
             | ElementType::Utf8l(a)
             | ElementType::Str16l(a)
-            | ElementType::Utf16l(a) => {
+            | ElementType::Utf16l(a)
+            | ElementType::Str32l(a)
+            | ElementType::Str64l(a) => {
                 if let Ok(s) = std::str::from_utf8(a) {
                     write!(f, "len[{}]\"{}\"", s.len(), s)
                 } else {
@@ -549,6 +779,30 @@ impl<'a> TLVListIterator<'a> {
         Some(tag)
     }
 
+    // Whether the element at the current offset claims more bytes than are left, either
+    // through its fixed size or through its length field
+    fn is_truncated(&self) -> bool {
+        let control = self.buf[self.current];
+        let tag_type = ((control & TAG_MASK) >> TAG_SHIFT_BITS) as usize;
+        let element_type = (control & TYPE_MASK) as usize;
+        if tag_type >= MAX_TAG_INDEX || element_type >= MAX_VALUE_INDEX {
+            return false;
+        }
+        let fixed_size = 1 + TAG_SIZE_MAP[tag_type] + VALUE_SIZE_MAP[element_type];
+        if fixed_size > self.left {
+            return true;
+        }
+        // Only the strings have a length field, which is the whole of their fixed size
+        if !(12..=19).contains(&element_type) {
+            return false;
+        }
+        let length = LittleEndian::read_uint(
+            &self.buf[self.current + 1 + TAG_SIZE_MAP[tag_type]..],
+            VALUE_SIZE_MAP[element_type],
+        );
+        length > (self.left - fixed_size) as u64
+    }
+
     fn read_this_value(&mut self, element_type: u8) -> Option<ElementType<'a>> {
         if element_type as usize >= MAX_VALUE_INDEX {
             return None;
@@ -689,34 +943,103 @@ impl<'a> Iterator for TLVContainerIterator<'a> {
     }
 }
 
+/// An element of a container, along with its tag
+#[derive(Debug, Copy, Clone)]
+pub struct TaggedElement<'a> {
+    pub tag: TagType,
+    pub element: TLVElement<'a>,
+}
+
+impl<'a> TaggedElement<'a> {
+    /// The context tag, if that is what the element has
+    pub fn ctx_tag(&self) -> Option<u8> {
+        self.element.get_ctx_tag()
+    }
+
+    /// The value of the element, as a T
+    pub fn value<T: FromTLV<'a>>(&self) -> Result<T, Error> {
+        T::from_tlv(&self.element)
+    }
+}
+
+/// Iterates over the elements of a container, along with their tags
+pub struct TLVTaggedIterator<'a>(TLVContainerIterator<'a>);
+
+impl<'a> Iterator for TLVTaggedIterator<'a> {
+    type Item = TaggedElement<'a>;
+
+    fn next(&mut self) -> Option<TaggedElement<'a>> {
+        self.0.next().map(|element| TaggedElement {
+            tag: element.get_tag(),
+            element,
+        })
+    }
+}
+
+// Check that the elements of the root element, if it is a container, fit in 'b'. The
+// elements are otherwise only parsed as they are iterated over, so a malformed element
+// would just look like the end of its container. A container that isn't closed by the end
+// of 'b' is taken to end there.
+fn validate_root_node(b: &[u8]) -> Result<(), Error> {
+    let mut iter = TLVList::new(b).iter();
+    let mut depth = 0_usize;
+    loop {
+        let before = iter;
+        let element = match iter.next() {
+            Some(element) => element,
+            None if before.left == 0 => return Ok(()),
+            None if before.is_truncated() => {
+                error!(
+                    "TLV element exceeds the buffer at offset {}",
+                    before.current
+                );
+                return Err(Error::TruncatedPacket);
+            }
+            None => return Err(Error::InvalidData),
+        };
+        if is_container(element.element_type) {
+            depth += 1;
+        } else if element.element_type == ElementType::EndCnt {
+            if depth == 0 {
+                return Err(Error::InvalidData);
+            }
+            depth -= 1;
+        }
+        if depth == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the root element of the TLV in 'b'
+///
+/// The whole of the root element is checked to fit in 'b' first, a length that
+/// runs past the end of the buffer is an Error::TruncatedPacket.
 pub fn get_root_node(b: &[u8]) -> Result<TLVElement, Error> {
-    TLVList::new(b)
-        .iter()
-        .next()
-        .ok_or(Error::InvalidData)
+    if b.is_empty() {
+        return Err(Error::TruncatedPacket);
+    }
+    validate_root_node(b)?;
+    TLVList::new(b).iter().next().ok_or(Error::InvalidData)
 }
 
 pub fn get_root_node_struct(b: &[u8]) -> Result<TLVElement, Error> {
-    TLVList::new(b)
-        .iter()
-        .next()
-        .ok_or(Error::InvalidData)?
-        .confirm_struct()
+    get_root_node(b)?.confirm_struct()
 }
 
 pub fn get_root_node_list(b: &[u8]) -> Result<TLVElement, Error> {
-    TLVList::new(b)
-        .iter()
-        .next()
-        .ok_or(Error::InvalidData)?
-        .confirm_list()
+    get_root_node(b)?.confirm_list()
 }
 
+/// Dump the TLV list in `b` at the debug log level
 pub fn print_tlv_list(b: &[u8]) {
+    if !log_enabled!(Level::Debug) {
+        return;
+    }
     let tlvlist = TLVList::new(b);
 
-    const MAX_DEPTH: usize = 9;
-    info!("TLV list:");
+    const MAX_DEPTH: usize = MAX_CONTAINER_DEPTH;
+    debug!("TLV list:");
     let space_buf = "                                ";
     let space: [&str; MAX_DEPTH] = [
         &space_buf[0..0],
@@ -736,7 +1059,7 @@ pub fn print_tlv_list(b: &[u8]) {
         match a.element_type {
             ElementType::Struct(_) => {
                 if index < MAX_DEPTH {
-                    println!("{}{}", space[index], a);
+                    debug!("{}{}", space[index], a);
                     stack[index] = '}';
                     index += 1;
                 } else {
@@ -745,7 +1068,7 @@ pub fn print_tlv_list(b: &[u8]) {
             }
             ElementType::Array(_) | ElementType::List(_) => {
                 if index < MAX_DEPTH {
-                    println!("{}{}", space[index], a);
+                    debug!("{}{}", space[index], a);
                     stack[index] = ']';
                     index += 1;
                 } else {
@@ -755,24 +1078,131 @@ pub fn print_tlv_list(b: &[u8]) {
             ElementType::EndCnt => {
                 if index > 0 {
                     index -= 1;
-                    println!("{}{}", space[index], stack[index]);
+                    debug!("{}{}", space[index], stack[index]);
                 } else {
                     error!("Incorrect TLV List");
                 }
             }
-            _ => println!("{}{}", space[index], a),
+            _ => debug!("{}{}", space[index], a),
         }
     }
-    println!("---------");
+    debug!("---------");
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        get_root_node_list, get_root_node_struct, ElementType, Pointer, TLVElement, TLVList,
-        TagType,
+        get_root_node, get_root_node_list, get_root_node_struct, ContainerType, ElementType,
+        Pointer, TLVElement, TLVList, TagType, TypeMismatch,
+    };
+    use crate::{
+        error::Error,
+        tlv::{FromTLV, OctetStr},
     };
-    use crate::error::Error;
+
+    #[test]
+    fn test_type_mismatch() {
+        let t = TLVElement::new(TagType::Anonymous, ElementType::True);
+        let err = t.u64().unwrap_err();
+        assert_eq!(
+            err,
+            Error::TLVTypeMismatch {
+                expected: "u64",
+                found: "bool"
+            }
+        );
+        let msg = err.to_string();
+        assert!(msg.contains("u64") && msg.contains("bool"), "{}", msg);
+        let mismatch = t.type_mismatch("u64");
+        assert_eq!(
+            mismatch,
+            TypeMismatch {
+                expected: "u64",
+                found: "bool"
+            }
+        );
+        assert_eq!(mismatch.to_string(), "expected u64, found bool");
+        assert_eq!(Error::from(mismatch), err);
+
+        let t = TLVElement::new(TagType::Anonymous, ElementType::Utf8l(b"abc"));
+        assert_eq!(t.type_mismatch("list").found, "utf8 string");
+        assert_eq!(
+            t.confirm_list(),
+            Err(Error::TLVTypeMismatch {
+                expected: "list",
+                found: "utf8 string"
+            })
+        );
+    }
+
+    // A parser that only knows the field 1, and ignores the others
+    #[derive(Debug, PartialEq)]
+    struct KnownField(u8);
+
+    impl<'a> FromTLV<'a> for KnownField {
+        fn from_tlv(t: &TLVElement<'a>) -> Result<Self, Error> {
+            let mut value = None;
+            for t in t.confirm_struct()?.iter_tagged().ok_or(Error::Invalid)? {
+                if t.ctx_tag() == Some(1) {
+                    value = Some(t.value::<u8>()?);
+                }
+            }
+            value.map(KnownField).ok_or(Error::NoTagFound)
+        }
+    }
+
+    #[test]
+    fn test_skip_unknown_element() {
+        // { 0: true, 1: 7 }, the boolean is unknown to the parser
+        let b = [0x15, 0x29, 0x00, 0x24, 0x01, 0x07, 0x18];
+        let root = get_root_node_struct(&b).unwrap();
+        assert_eq!(KnownField::from_tlv(&root), Ok(KnownField(7)));
+    }
+
+    #[test]
+    fn test_iter_tagged() {
+        // { 0: 5, 1: "abc", 2: [ 7 ], 3: true }
+        let b = [
+            0x15, 0x24, 0x00, 0x05, 0x30, 0x01, 0x03, 0x61, 0x62, 0x63, 0x36, 0x02, 0x04, 0x07,
+            0x18, 0x29, 0x03, 0x18,
+        ];
+        let root = get_root_node_struct(&b).unwrap();
+
+        let tags: Vec<TagType> = root.iter_tagged().unwrap().map(|t| t.tag).collect();
+        assert_eq!(
+            tags,
+            vec![
+                TagType::Context(0),
+                TagType::Context(1),
+                TagType::Context(2),
+                TagType::Context(3)
+            ]
+        );
+
+        let mut iter = root.iter_tagged().unwrap();
+        let t = iter.next().unwrap();
+        assert_eq!((t.ctx_tag(), t.value::<u8>()), (Some(0), Ok(5)));
+        let t = iter.next().unwrap();
+        assert_eq!(t.ctx_tag(), Some(1));
+        assert_eq!(t.value::<OctetStr>().unwrap().0, b"abc");
+        // The type of the value has to match
+        assert_eq!(
+            t.value::<u8>(),
+            Err(Error::TLVTypeMismatch {
+                expected: "u8",
+                found: "octet string"
+            })
+        );
+        // The contents of the array are skipped over
+        let t = iter.next().unwrap();
+        assert_eq!(t.ctx_tag(), Some(2));
+        let t = iter.next().unwrap();
+        assert_eq!((t.ctx_tag(), t.value::<bool>()), (Some(3), Ok(true)));
+        assert!(iter.next().is_none());
+
+        // Only containers can be iterated over
+        assert!(t.element.iter_tagged().is_none());
+    }
 
     #[test]
     fn test_short_length_tag() {
@@ -818,6 +1248,43 @@ mod tests {
         assert_eq!(tlv_iter.next(), None);
     }
 
+    #[test]
+    fn test_root_node_empty() {
+        assert_eq!(get_root_node_struct(&[]), Err(Error::TruncatedPacket));
+        assert_eq!(get_root_node_list(&[]), Err(Error::TruncatedPacket));
+    }
+
+    #[test]
+    fn test_root_node_truncated() {
+        // The string claims 0xb bytes, but only 4 are there
+        let b = [0x15, 0x30, 0x00, 0x0b, 0x73, 0x6d, 0x61, 0x72, 0x18];
+        assert_eq!(get_root_node_struct(&b), Err(Error::TruncatedPacket));
+        // A u32 that is cut short
+        assert_eq!(
+            get_root_node(&[0x26, 0x00, 0x01]),
+            Err(Error::TruncatedPacket)
+        );
+        // A u8 that is missing altogether, at the end of a struct
+        assert_eq!(
+            get_root_node_struct(&[0x15, 0x24, 0x00, 0x01, 0x24]),
+            Err(Error::TruncatedPacket)
+        );
+        // An Str64l with a length that doesn't fit in a usize on any platform
+        let b = [0x13, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(get_root_node(&b), Err(Error::TruncatedPacket));
+    }
+
+    #[test]
+    fn test_root_node_malformed() {
+        // An end of container without a container
+        assert_eq!(get_root_node(&[0x18]), Err(Error::InvalidData));
+        // A float isn't supported
+        assert_eq!(
+            get_root_node_struct(&[0x15, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18]),
+            Err(Error::InvalidData)
+        );
+    }
+
     #[test]
     fn test_valid_tag() {
         // The 0x36 is an array with a tag, here tag is 0
@@ -1009,6 +1476,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_minimally_encoded_ints() {
+        // A struct with a u8 and an s8, as a peer would encode small values
+        let b = [0x15, 0x24, 0x0, 0x2, 0x20, 0x1, 0xfe, 0x18];
+        let root = get_root_node_struct(&b).unwrap();
+
+        let unsigned = root.find_tag(0).unwrap();
+        assert_eq!(unsigned.get_u64_any(), Ok(2));
+        assert_eq!(unsigned.get_i64_any(), Ok(2));
+        assert_eq!(u64::from_tlv(&unsigned), Ok(2));
+        assert_eq!(
+            unsigned.i64(),
+            Err(Error::TLVTypeMismatch {
+                expected: "i64",
+                found: "u8"
+            })
+        );
+
+        let signed = root.find_tag(1).unwrap();
+        assert_eq!(signed.get_i64_any(), Ok(-2));
+        let u64_from_i8 = Err(Error::TLVTypeMismatch {
+            expected: "u64",
+            found: "i8",
+        });
+        assert_eq!(signed.get_u64_any(), u64_from_i8);
+        assert_eq!(signed.u64(), u64_from_i8);
+
+        // A u64 that doesn't fit an i64, and a non-negative s8
+        let b = [
+            0x15, 0x27, 0x0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x20, 0x1, 0x7, 0x18,
+        ];
+        let root = get_root_node_struct(&b).unwrap();
+        assert_eq!(
+            root.find_tag(0).unwrap().get_i64_any(),
+            Err(Error::TLVTypeMismatch {
+                expected: "i64",
+                found: "u64"
+            })
+        );
+        assert_eq!(root.find_tag(1).unwrap().get_u64_any(), Ok(7));
+    }
+
     #[test]
     fn test_struct_find_element_mix_values() {
         // This is a struct with 3 valid values
@@ -1198,4 +1707,70 @@ mod tests {
         assert_eq!(list_iter.next(), None);
         assert_eq!(list_iter.next(), None);
     }
+
+    #[test]
+    fn test_profile_tags() {
+        let b = [
+            0x15, // Anonymous struct
+            0x44, 0x10, 0x00, 0x2a, // CommonPrf16(0x10): 42
+            0x64, 0x20, 0x00, 0x01, 0x00, 0x05, // CommonPrf32(0x10020): 5
+            0x84, 0x10, 0x00, 0x07, // ImplPrf16(0x10): 7
+            0x18,
+        ];
+        let root = get_root_node_struct(&b).unwrap();
+
+        let e = root.find_profile_tag(TagType::CommonPrf16(0x10)).unwrap();
+        assert_eq!(e.get_tag(), TagType::CommonPrf16(0x10));
+        assert_eq!(e.u8().unwrap(), 42);
+        // The width of the tag doesn't matter
+        let e = root.find_profile_tag(TagType::CommonPrf32(0x10)).unwrap();
+        assert_eq!(e.u8().unwrap(), 42);
+
+        let e = root
+            .find_profile_tag(TagType::CommonPrf32(0x10020))
+            .unwrap();
+        assert_eq!(e.get_tag(), TagType::CommonPrf32(0x10020));
+        assert_eq!(e.u8().unwrap(), 5);
+
+        let e = root.find_profile_tag(TagType::ImplPrf32(0x10)).unwrap();
+        assert_eq!(e.get_tag(), TagType::ImplPrf16(0x10));
+        assert_eq!(e.u8().unwrap(), 7);
+
+        assert_eq!(
+            root.find_profile_tag(TagType::CommonPrf16(0x20)),
+            Err(Error::NoTagFound)
+        );
+        assert_eq!(
+            root.find_profile_tag(TagType::Context(0x10)),
+            Err(Error::Invalid)
+        );
+        // Profile tags aren't context tags
+        assert_eq!(root.find_tag(0x10), Err(Error::NoTagFound));
+    }
+
+    #[test]
+    fn test_container_type() {
+        // A struct containing an array, a list, a struct and a u8
+        let b = [
+            0x15, 0x36, 0x0, 0x4, 0x1, 0x18, 0x37, 0x1, 0x18, 0x35, 0x2, 0x18, 0x24, 0x3, 0x5, 0x18,
+        ];
+        let root = get_root_node_struct(&b).unwrap();
+        assert_eq!(root.container_type(), Some(ContainerType::Struct));
+        assert!(root.is_struct() && !root.is_array() && !root.is_list());
+
+        let array = root.find_tag(0).unwrap();
+        assert_eq!(array.container_type(), Some(ContainerType::Array));
+        assert!(array.is_array() && !array.is_struct() && !array.is_list());
+
+        let list = root.find_tag(1).unwrap();
+        assert_eq!(list.container_type(), Some(ContainerType::List));
+        assert!(list.is_list() && !list.is_struct() && !list.is_array());
+
+        let inner = root.find_tag(2).unwrap();
+        assert_eq!(inner.container_type(), Some(ContainerType::Struct));
+
+        let val = root.find_tag(3).unwrap();
+        assert_eq!(val.container_type(), None);
+        assert!(!val.is_struct() && !val.is_array() && !val.is_list());
+    }
 }
@@ -52,12 +52,20 @@ macro_rules! fromtlv_for {
     };
 }
 
-fromtlv_for!(u8 u16 u32 u64 bool);
+fromtlv_for!(i8 i16 i32 i64 u8 u16 u32 u64 bool);
 
 pub trait ToTLV {
     fn to_tlv(&self, tw: &mut TLVWriter, tag: TagType) -> Result<(), Error>;
 }
 
+/// A fabric-scoped structure, that carries the FabricIndex of the fabric it belongs to
+///
+/// This is derived for structures that mark their FabricIndex with `#[tlv(fabric_index)]`
+pub trait FabricScoped {
+    fn get_fab_idx(&self) -> u8;
+    fn set_fab_idx(&mut self, fab_idx: u8);
+}
+
 macro_rules! totlv_for {
     ($($t:ident)*) => {
         $(
@@ -81,7 +89,7 @@ impl<'a, T: ToTLV, const N: usize> ToTLV for [T; N] {
 }
 
 // Generate ToTLV for standard data types
-totlv_for!(i8 u8 u16 u32 u64 bool);
+totlv_for!(i8 i16 i32 i64 u8 u16 u32 u64 bool);
 
 // We define a few common data types that will be required here
 //
@@ -103,6 +111,12 @@ impl<'a> UtfStr<'a> {
     }
 }
 
+impl<'a> FromTLV<'a> for UtfStr<'a> {
+    fn from_tlv(t: &TLVElement<'a>) -> Result<UtfStr<'a>, Error> {
+        t.slice().map(UtfStr)
+    }
+}
+
 impl<'a> ToTLV for UtfStr<'a> {
     fn to_tlv(&self, tw: &mut TLVWriter, tag: TagType) -> Result<(), Error> {
         tw.utf16(tag, self.0)
@@ -189,6 +203,45 @@ impl<T: ToTLV> ToTLV for Option<T> {
     }
 }
 
+/// A value that may be encoded as a TLV Null, as opposed to an absent value
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Nullable<T> {
+    Null,
+    NotNull(T),
+}
+
+impl<T> Nullable<T> {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Nullable::Null)
+    }
+
+    pub fn notnull(self) -> Option<T> {
+        match self {
+            Nullable::Null => None,
+            Nullable::NotNull(t) => Some(t),
+        }
+    }
+}
+
+impl<'a, T: FromTLV<'a>> FromTLV<'a> for Nullable<T> {
+    fn from_tlv(t: &TLVElement<'a>) -> Result<Nullable<T>, Error> {
+        if t.null().is_ok() {
+            Ok(Nullable::Null)
+        } else {
+            Ok(Nullable::NotNull(T::from_tlv(t)?))
+        }
+    }
+}
+
+impl<T: ToTLV> ToTLV for Nullable<T> {
+    fn to_tlv(&self, tw: &mut TLVWriter, tag: TagType) -> Result<(), Error> {
+        match self {
+            Nullable::Null => tw.null(tag),
+            Nullable::NotNull(t) => t.to_tlv(tw, tag),
+        }
+    }
+}
+
 /// Owned version of a TLVArray
 pub struct TLVArrayOwned<T>(Vec<T>);
 impl<'a, T: FromTLV<'a>> FromTLV<'a> for TLVArrayOwned<T> {
@@ -291,7 +344,7 @@ impl<'a, T> FromTLV<'a> for TLVArray<'a, T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{FromTLV, OctetStr, TLVElement, TLVWriter, TagType, ToTLV};
+    use super::{FromTLV, Nullable, OctetStr, TLVElement, TLVWriter, TagType, ToTLV};
     use crate::{error::Error, tlv::TLVList, utils::writebuf::WriteBuf};
     use matter_macro_derive::{FromTLV, ToTLV};
 
@@ -335,6 +388,53 @@ mod tests {
         assert_eq!(test.b, 20);
     }
 
+    #[test]
+    fn test_derive_fromtlv_trailing_unknown() {
+        // Tags 2 and 3 (a u8 and a struct) aren't part of the definition
+        let b = [
+            21, 37, 0, 10, 0, 38, 1, 20, 0, 0, 0, 36, 2, 5, 53, 3, 36, 0, 1, 24, 24,
+        ];
+        let root = TLVList::new(&b).iter().next().unwrap();
+        let test = TestDeriveSimple::from_tlv(&root).unwrap();
+        assert_eq!(test.a, 10);
+        assert_eq!(test.b, 20);
+    }
+
+    #[derive(FromTLV)]
+    struct TestDeriveTagGap {
+        a: u16,
+        #[tagval(0xFE)]
+        b: u8,
+    }
+
+    #[test]
+    fn test_derive_fromtlv_unknown_before_tagval() {
+        // An unknown tag 1 sits between the known tags 0 and 0xFE
+        let b = [21, 37, 0, 10, 0, 36, 1, 5, 36, 0xFE, 3, 24];
+        let root = TLVList::new(&b).iter().next().unwrap();
+        let test = TestDeriveTagGap::from_tlv(&root).unwrap();
+        assert_eq!(test.a, 10);
+        assert_eq!(test.b, 3);
+    }
+
+    #[derive(FromTLV)]
+    #[tlvargs(unordered)]
+    struct TestDeriveUnordered {
+        a: u16,
+        b: u32,
+    }
+
+    #[test]
+    fn test_derive_fromtlv_unordered_unknown() {
+        let b = [
+            21, 36, 2, 5, 38, 1, 20, 0, 0, 0, 53, 3, 36, 0, 1, 24, 37, 0, 10, 0, 24,
+        ];
+        let root = TLVList::new(&b).iter().next().unwrap();
+        let test = TestDeriveUnordered::from_tlv(&root).unwrap();
+        assert_eq!(test.a, 10);
+        assert_eq!(test.b, 20);
+    }
+
     #[derive(FromTLV)]
     #[tlvargs(lifetime = "'a")]
     struct TestDeriveStr<'a> {
@@ -368,6 +468,37 @@ mod tests {
         assert_eq!(test.c, Some(11));
     }
 
+    #[derive(FromTLV, ToTLV, Debug, PartialEq)]
+    struct TestDeriveSigned {
+        a: i8,
+        b: i32,
+        c: i64,
+    }
+
+    #[test]
+    fn test_derive_signed() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        let abc = TestDeriveSigned {
+            a: -1,
+            b: -100000,
+            c: -5,
+        };
+        abc.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        let encoded = writebuf.as_slice();
+        assert_eq!(
+            encoded,
+            [21, 32, 0, 0xff, 34, 1, 0x60, 0x79, 0xfe, 0xff, 32, 2, 0xfb, 24]
+        );
+
+        let root = TLVList::new(encoded).iter().next().unwrap();
+        let test = TestDeriveSigned::from_tlv(&root).unwrap();
+        assert_eq!(test, abc);
+    }
+
     #[derive(FromTLV, ToTLV, Debug)]
     struct TestDeriveFabScoped {
         a: u16,
@@ -398,4 +529,32 @@ mod tests {
             [21, 36, 0, 20, 36, 0xFE, 3, 24, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
         );
     }
+
+    #[derive(FromTLV, ToTLV, Debug, PartialEq)]
+    struct TestDeriveNullable {
+        a: Option<Nullable<u16>>,
+        b: Option<Nullable<u16>>,
+        c: Option<Nullable<u16>>,
+    }
+
+    #[test]
+    fn test_nullable() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        let abc = TestDeriveNullable {
+            a: Some(Nullable::Null),
+            b: Some(Nullable::NotNull(5)),
+            c: None,
+        };
+        abc.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        let encoded = writebuf.as_slice();
+        assert_eq!(encoded, [21, 52, 0, 36, 1, 5, 24]);
+
+        let root = TLVList::new(encoded).iter().next().unwrap();
+        let test = TestDeriveNullable::from_tlv(&root).unwrap();
+        assert_eq!(test, abc);
+    }
 }
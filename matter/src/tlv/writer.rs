@@ -1,6 +1,7 @@
-use super::{TagType, TAG_SHIFT_BITS, TAG_SIZE_MAP};
+use super::{TagType, ToTLV, MAX_CONTAINER_DEPTH, TAG_SHIFT_BITS, TAG_SIZE_MAP};
 use crate::{error::*, utils::writebuf::WriteBuf};
 use log::error;
+use std::{convert::TryFrom, io};
 
 #[allow(dead_code)]
 enum WriteElementType {
@@ -32,13 +33,131 @@ enum WriteElementType {
     Last,
 }
 
+// Where the encoded TLVs go
+enum Sink<'a, 'b> {
+    Buf(&'b mut WriteBuf<'a>),
+    Io {
+        w: &'b mut dyn io::Write,
+        written: usize,
+        // The space that str16_as() generates its data in, allocated on its first use and
+        // reused after
+        scratch: Vec<u8>,
+    },
+}
+
+impl<'a, 'b> Sink<'a, 'b> {
+    fn copy_from_slice(&mut self, src: &[u8]) -> Result<(), Error> {
+        match self {
+            Sink::Buf(wb) => wb.copy_from_slice(src),
+            Sink::Io { w, written, .. } => {
+                w.write_all(src)?;
+                *written += src.len();
+                Ok(())
+            }
+        }
+    }
+
+    fn le_i8(&mut self, data: i8) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_i16(&mut self, data: i16) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_i32(&mut self, data: i32) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_i64(&mut self, data: i64) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_u8(&mut self, data: u8) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_u16(&mut self, data: u16) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_u32(&mut self, data: u32) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_u64(&mut self, data: u64) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes())
+    }
+
+    fn le_uint(&mut self, nbytes: usize, data: u64) -> Result<(), Error> {
+        self.copy_from_slice(&data.to_le_bytes()[..nbytes])
+    }
+
+    fn as_write_buf(&mut self) -> Result<&mut WriteBuf<'a>, Error> {
+        match self {
+            Sink::Buf(wb) => Ok(wb),
+            Sink::Io { .. } => Err(Error::Invalid),
+        }
+    }
+
+    fn get_tail(&self) -> usize {
+        match self {
+            Sink::Buf(wb) => wb.get_tail(),
+            Sink::Io { written, .. } => *written,
+        }
+    }
+
+    fn rewind_tail_to(&mut self, anchor: usize) -> Result<(), Error> {
+        match self {
+            Sink::Buf(wb) => {
+                wb.rewind_tail_to(anchor);
+                Ok(())
+            }
+            Sink::Io { .. } => {
+                error!("Can't rewind an io::Write sink");
+                Err(Error::Invalid)
+            }
+        }
+    }
+}
+
+/// A position in the output of a [TLVWriter] that it can be rewound to
+///
+/// Along with the position, this keeps the containers that were open there, so that the
+/// containers that are started after it are dropped on a rewind too.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TLVAnchor {
+    tail: usize,
+    depth: usize,
+}
+
 pub struct TLVWriter<'a, 'b> {
-    buf: &'b mut WriteBuf<'a>,
+    buf: Sink<'a, 'b>,
+    // The number of containers that are currently open
+    depth: usize,
 }
 
 impl<'a, 'b> TLVWriter<'a, 'b> {
     pub fn new(buf: &'b mut WriteBuf<'a>) -> Self {
-        TLVWriter { buf }
+        TLVWriter {
+            buf: Sink::Buf(buf),
+            depth: 0,
+        }
+    }
+
+    /// Create a TLVWriter that writes to an `io::Write` sink instead of a fixed size buffer
+    ///
+    /// The output is written out incrementally, so [rewind_to](TLVWriter::rewind_to) isn't
+    /// supported.
+    pub fn new_io(w: &'b mut dyn io::Write) -> Self {
+        TLVWriter {
+            buf: Sink::Io {
+                w,
+                written: 0,
+                scratch: Vec::new(),
+            },
+            depth: 0,
+        }
     }
 
     // TODO: The current method of using writebuf's put methods force us to do
@@ -73,6 +192,33 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
         self.buf.le_i8(data)
     }
 
+    pub fn i16(&mut self, tag_type: TagType, data: i16) -> Result<(), Error> {
+        if let Ok(data) = i8::try_from(data) {
+            self.i8(tag_type, data)
+        } else {
+            self.put_control_tag(tag_type, WriteElementType::S16)?;
+            self.buf.le_i16(data)
+        }
+    }
+
+    pub fn i32(&mut self, tag_type: TagType, data: i32) -> Result<(), Error> {
+        if let Ok(data) = i16::try_from(data) {
+            self.i16(tag_type, data)
+        } else {
+            self.put_control_tag(tag_type, WriteElementType::S32)?;
+            self.buf.le_i32(data)
+        }
+    }
+
+    pub fn i64(&mut self, tag_type: TagType, data: i64) -> Result<(), Error> {
+        if let Ok(data) = i32::try_from(data) {
+            self.i32(tag_type, data)
+        } else {
+            self.put_control_tag(tag_type, WriteElementType::S64)?;
+            self.buf.le_i64(data)
+        }
+    }
+
     pub fn u8(&mut self, tag_type: TagType, data: u8) -> Result<(), Error> {
         self.put_control_tag(tag_type, WriteElementType::U8)?;
         self.buf.le_u8(data)
@@ -112,8 +258,8 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
     }
 
     pub fn str8(&mut self, tag_type: TagType, data: &[u8]) -> Result<(), Error> {
-        if data.len() > 256 {
-            error!("use put_str16() instead");
+        if data.len() > 0xff {
+            error!("use str16() instead");
             return Err(Error::Invalid);
         }
         self.put_control_tag(tag_type, WriteElementType::Str8l)?;
@@ -131,15 +277,42 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
         }
     }
 
+    /// Write an octet string, with the smallest length field that fits 'data'
+    pub fn octet_str(&mut self, tag_type: TagType, data: &[u8]) -> Result<(), Error> {
+        let len = data.len() as u64;
+        if len <= 0xffff {
+            return self.str16(tag_type, data);
+        } else if len <= 0xffff_ffff {
+            self.put_control_tag(tag_type, WriteElementType::Str32l)?;
+            self.buf.le_u32(len as u32)?;
+        } else {
+            self.put_control_tag(tag_type, WriteElementType::Str64l)?;
+            self.buf.le_u64(len)?;
+        }
+        self.buf.copy_from_slice(data)
+    }
+
     // This is quite hacky
     pub fn str16_as<F>(&mut self, tag_type: TagType, data_gen: F) -> Result<(), Error>
     where
         F: FnOnce(&mut [u8]) -> Result<usize, Error>,
     {
+        if let Sink::Io { scratch, .. } = &mut self.buf {
+            // There is no buffer to generate the data in-place, so use the scratch buffer
+            let mut scratch = std::mem::take(scratch);
+            scratch.resize(u16::MAX as usize, 0);
+            let len = data_gen(&mut scratch).unwrap_or_default();
+            let result = self.str16(tag_type, &scratch[..len]);
+            if let Sink::Io { scratch: s, .. } = &mut self.buf {
+                *s = scratch;
+            }
+            return result;
+        }
+
         let anchor = self.buf.get_tail();
         self.put_control_tag(tag_type, WriteElementType::Str16l)?;
 
-        let wb = self.buf.empty_as_mut_slice();
+        let wb = self.buf.as_write_buf()?.empty_as_mut_slice();
         // Reserve 2 spaces for the control and length
         let str = &mut wb[2..];
         let len = data_gen(str).unwrap_or_default();
@@ -149,13 +322,13 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
             for i in 0..len {
                 str[i] = str[i + 1];
             }
-            self.buf.rewind_tail_to(anchor);
+            self.buf.rewind_tail_to(anchor)?;
             self.put_control_tag(tag_type, WriteElementType::Str8l)?;
             self.buf.le_u8(len as u8)?;
         } else {
             self.buf.le_u16(len as u16)?;
         }
-        self.buf.forward_tail_by(len);
+        self.buf.as_write_buf()?.forward_tail_by(len);
         Ok(())
     }
 
@@ -179,20 +352,96 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
         self.put_control_tag(tag_type, element)
     }
 
+    fn start_container(
+        &mut self,
+        tag_type: TagType,
+        element: WriteElementType,
+    ) -> Result<(), Error> {
+        if self.depth >= MAX_CONTAINER_DEPTH {
+            error!("Containers nested too deep");
+            return Err(Error::NoSpace);
+        }
+        self.no_val(tag_type, element)?;
+        self.depth += 1;
+        Ok(())
+    }
+
     pub fn start_struct(&mut self, tag_type: TagType) -> Result<(), Error> {
-        self.no_val(tag_type, WriteElementType::Struct)
+        self.start_container(tag_type, WriteElementType::Struct)
     }
 
     pub fn start_array(&mut self, tag_type: TagType) -> Result<(), Error> {
-        self.no_val(tag_type, WriteElementType::Array)
+        self.start_container(tag_type, WriteElementType::Array)
     }
 
     pub fn start_list(&mut self, tag_type: TagType) -> Result<(), Error> {
-        self.no_val(tag_type, WriteElementType::List)
+        self.start_container(tag_type, WriteElementType::List)
     }
 
     pub fn end_container(&mut self) -> Result<(), Error> {
-        self.no_val(TagType::Anonymous, WriteElementType::EndCnt)
+        if self.depth == 0 {
+            error!("No open container to end");
+            return Err(Error::InvalidState);
+        }
+        self.no_val(TagType::Anonymous, WriteElementType::EndCnt)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Encode the items of `iter` as an array
+    ///
+    /// Unlike start_array(), this can't leave the array open.
+    pub fn array_from_iter<T, I>(&mut self, tag_type: TagType, iter: I) -> Result<(), Error>
+    where
+        T: ToTLV,
+        I: IntoIterator<Item = T>,
+    {
+        let fields = iter.into_iter().map(|item| (TagType::Anonymous, item));
+        self.container_from_fields(tag_type, WriteElementType::Array, fields)
+    }
+
+    /// Encode the (tag, value) pairs of `fields` as a structure
+    pub fn struct_from_fields<T, I>(&mut self, tag_type: TagType, fields: I) -> Result<(), Error>
+    where
+        T: ToTLV,
+        I: IntoIterator<Item = (TagType, T)>,
+    {
+        self.container_from_fields(tag_type, WriteElementType::Struct, fields)
+    }
+
+    /// Encode the (tag, value) pairs of `fields` as a list
+    pub fn list_from_fields<T, I>(&mut self, tag_type: TagType, fields: I) -> Result<(), Error>
+    where
+        T: ToTLV,
+        I: IntoIterator<Item = (TagType, T)>,
+    {
+        self.container_from_fields(tag_type, WriteElementType::List, fields)
+    }
+
+    fn container_from_fields<T, I>(
+        &mut self,
+        tag_type: TagType,
+        element: WriteElementType,
+        fields: I,
+    ) -> Result<(), Error>
+    where
+        T: ToTLV,
+        I: IntoIterator<Item = (TagType, T)>,
+    {
+        self.start_container(tag_type, element)?;
+        for (tag, value) in fields {
+            value.to_tlv(self, tag)?;
+        }
+        self.end_container()
+    }
+
+    /// Confirm that all the containers that were started have been ended
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.depth != 0 {
+            error!("{} containers have not been ended", self.depth);
+            return Err(Error::InvalidState);
+        }
+        Ok(())
     }
 
     pub fn null(&mut self, tag_type: TagType) -> Result<(), Error> {
@@ -211,15 +460,51 @@ impl<'a, 'b> TLVWriter<'a, 'b> {
         self.buf.get_tail()
     }
 
-    pub fn rewind_to(&mut self, anchor: usize) {
-        self.buf.rewind_tail_to(anchor);
+    /// Hold back 'len' bytes at the end of the buffer, see [WriteBuf::shrink]
+    ///
+    /// An `io::Write` sink has no end, nothing is held back for it.
+    pub fn shrink(&mut self, len: usize) -> Result<(), Error> {
+        match &mut self.buf {
+            Sink::Buf(wb) => wb.shrink(len),
+            Sink::Io { .. } => Ok(()),
+        }
+    }
+
+    /// Give back 'len' bytes that were held back with [shrink](TLVWriter::shrink)
+    pub fn expand(&mut self, len: usize) {
+        if let Sink::Buf(wb) = &mut self.buf {
+            wb.expand(len);
+        }
+    }
+
+    /// Whether a write ran out of space since the last call, see [WriteBuf::take_overflow]
+    pub fn take_overflow(&mut self) -> bool {
+        match &mut self.buf {
+            Sink::Buf(wb) => wb.take_overflow(),
+            Sink::Io { .. } => false,
+        }
+    }
+
+    /// The current position, to rewind to with [rewind_to](TLVWriter::rewind_to)
+    pub fn anchor(&self) -> TLVAnchor {
+        TLVAnchor {
+            tail: self.buf.get_tail(),
+            depth: self.depth,
+        }
+    }
+
+    /// Drop what was written since 'anchor', along with the containers that were started
+    pub fn rewind_to(&mut self, anchor: TLVAnchor) -> Result<(), Error> {
+        self.buf.rewind_tail_to(anchor.tail)?;
+        self.depth = anchor.depth;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{TLVWriter, TagType};
-    use crate::utils::writebuf::WriteBuf;
+    use super::{TLVWriter, TagType, MAX_CONTAINER_DEPTH};
+    use crate::{error::Error, tlv::get_root_node, utils::writebuf::WriteBuf};
 
     #[test]
     fn test_write_success() {
@@ -243,6 +528,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_container_helpers() {
+        let mut manual = [0u8; 30];
+        let buf_len = manual.len();
+        let mut writebuf = WriteBuf::new(&mut manual, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.start_array(TagType::Context(1)).unwrap();
+        for v in [1u16, 0x300] {
+            tw.u16(TagType::Anonymous, v).unwrap();
+        }
+        tw.end_container().unwrap();
+        tw.start_list(TagType::Context(2)).unwrap();
+        tw.u64(TagType::Context(17), 5).unwrap();
+        tw.u64(TagType::Context(21), 7).unwrap();
+        tw.end_container().unwrap();
+        tw.end_container().unwrap();
+        tw.finish().unwrap();
+        let manual_len = writebuf.as_slice().len();
+
+        let mut helpers = [0u8; 30];
+        let mut writebuf = WriteBuf::new(&mut helpers, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+        tw.start_struct(TagType::Anonymous).unwrap();
+        tw.array_from_iter(TagType::Context(1), [1u16, 0x300])
+            .unwrap();
+        tw.list_from_fields(
+            TagType::Context(2),
+            [(TagType::Context(17), 5u64), (TagType::Context(21), 7)],
+        )
+        .unwrap();
+        tw.end_container().unwrap();
+        tw.finish().unwrap();
+        assert_eq!(writebuf.as_slice().len(), manual_len);
+        assert_eq!(helpers, manual);
+    }
+
+    #[test]
+    fn test_struct_from_fields() {
+        let mut buf = [0u8; 8];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+        tw.struct_from_fields(
+            TagType::Anonymous,
+            [(TagType::Context(0), true), (TagType::Context(1), false)],
+        )
+        .unwrap();
+        tw.finish().unwrap();
+        assert_eq!(buf, [21, 41, 0, 40, 1, 24, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_signed() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        // Always the shortest encoding that holds the value
+        tw.i16(TagType::Anonymous, -2).unwrap();
+        tw.i32(TagType::Anonymous, -300).unwrap();
+        tw.i64(TagType::Anonymous, -70000).unwrap();
+        tw.i64(TagType::Anonymous, i64::MIN).unwrap();
+        assert_eq!(
+            buf,
+            [0, 0xfe, 1, 0xd4, 0xfe, 2, 0x90, 0xee, 0xfe, 0xff, 3, 0, 0, 0, 0, 0, 0, 0, 0x80, 0]
+        );
+    }
+
     #[test]
     fn test_write_overflow() {
         let mut buf: [u8; 6] = [0; 6];
@@ -302,4 +657,108 @@ mod tests {
             [36, 1, 13, 48, 2, 5, 10, 11, 12, 13, 14, 48, 3, 2, 10, 11, 36, 4, 13, 0]
         );
     }
+
+    #[test]
+    fn test_put_str16_as_io() {
+        let mut out: Vec<u8> = Vec::new();
+        let mut tw = TLVWriter::new_io(&mut out);
+
+        tw.u8(TagType::Context(1), 13).unwrap();
+        tw.str16_as(TagType::Context(3), |buf| {
+            buf[0] = 10;
+            buf[1] = 11;
+            Ok(2)
+        })
+        .unwrap();
+        // The scratch buffer is reused
+        tw.str16_as(TagType::Context(4), |buf| {
+            buf[0] = 12;
+            Ok(1)
+        })
+        .unwrap();
+        let anchor = tw.anchor();
+        assert_eq!(tw.rewind_to(anchor), Err(Error::Invalid));
+
+        assert_eq!(out, [36, 1, 13, 48, 3, 2, 10, 11, 48, 4, 1, 12]);
+    }
+
+    #[test]
+    fn test_container_too_deep() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        for _ in 0..MAX_CONTAINER_DEPTH {
+            tw.start_struct(TagType::Anonymous).unwrap();
+        }
+        assert_eq!(tw.start_array(TagType::Anonymous), Err(Error::NoSpace));
+        assert_eq!(tw.finish(), Err(Error::InvalidState));
+        for _ in 0..MAX_CONTAINER_DEPTH {
+            tw.end_container().unwrap();
+        }
+        assert_eq!(tw.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_rewind_drops_containers() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        tw.start_struct(TagType::Anonymous).unwrap();
+        let anchor = tw.anchor();
+        // The containers that are left open are dropped on every rewind, they don't add up
+        for _ in 0..MAX_CONTAINER_DEPTH {
+            tw.start_struct(TagType::Anonymous).unwrap();
+            tw.start_array(TagType::Context(1)).unwrap();
+            tw.rewind_to(anchor).unwrap();
+        }
+        assert_eq!(tw.anchor(), anchor);
+        tw.u8(TagType::Context(2), 5).unwrap();
+        tw.end_container().unwrap();
+        assert_eq!(tw.finish(), Ok(()));
+        assert_eq!(writebuf.as_slice(), [21, 36, 2, 5, 24]);
+    }
+
+    #[test]
+    fn test_end_container_premature() {
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_len = buf.len();
+        let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut writebuf);
+
+        assert_eq!(tw.end_container(), Err(Error::InvalidState));
+        tw.start_list(TagType::Anonymous).unwrap();
+        tw.u8(TagType::Anonymous, 12).unwrap();
+        tw.end_container().unwrap();
+        assert_eq!(tw.end_container(), Err(Error::InvalidState));
+        tw.finish().unwrap();
+        assert_eq!(writebuf.as_borrow_slice(), [23, 4, 12, 24]);
+    }
+
+    #[test]
+    fn test_octet_str_length_form() {
+        // (string length, control and length octets)
+        let cases: [(usize, &[u8]); 3] = [
+            (10, &[0x10, 10]),
+            (300, &[0x11, 0x2c, 0x01]),
+            (70000, &[0x12, 0x70, 0x11, 0x01, 0x00]),
+        ];
+
+        for (len, hdr) in cases.iter() {
+            let data: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+            let mut buf = vec![0; len + 10];
+            let buf_len = buf.len();
+            let mut writebuf = WriteBuf::new(&mut buf, buf_len);
+            let mut tw = TLVWriter::new(&mut writebuf);
+
+            tw.octet_str(TagType::Anonymous, &data).unwrap();
+            let out = writebuf.as_borrow_slice();
+            assert_eq!(&out[..hdr.len()], *hdr);
+            let root = get_root_node(out).unwrap();
+            assert_eq!(root.slice().unwrap(), data.as_slice());
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// How much of the session and exchange capacity of the transport is in use
+///
+/// This is shared with the diagnostics of the Data Model. It is a snapshot, which the
+/// Exchange Manager refreshes on every tick.
+#[derive(Debug, Default)]
+pub struct TransportDiag {
+    session_capacity: AtomicUsize,
+    sessions_in_use: AtomicUsize,
+    exchange_capacity: AtomicUsize,
+    exchanges_in_use: AtomicUsize,
+    // Incremented whenever any of the values changes
+    changes: AtomicU32,
+}
+
+// Returns whether 'value' changed
+fn store(value: &AtomicUsize, new: usize) -> bool {
+    value.swap(new, Ordering::Relaxed) != new
+}
+
+impl TransportDiag {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn session_capacity(&self) -> usize {
+        self.session_capacity.load(Ordering::Relaxed)
+    }
+
+    pub fn sessions_in_use(&self) -> usize {
+        self.sessions_in_use.load(Ordering::Relaxed)
+    }
+
+    pub fn exchange_capacity(&self) -> usize {
+        self.exchange_capacity.load(Ordering::Relaxed)
+    }
+
+    pub fn exchanges_in_use(&self) -> usize {
+        self.exchanges_in_use.load(Ordering::Relaxed)
+    }
+
+    /// The number of times that any of the values changed
+    pub fn changes(&self) -> u32 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn update_sessions(&self, in_use: usize, capacity: usize) {
+        let changed =
+            store(&self.sessions_in_use, in_use) | store(&self.session_capacity, capacity);
+        if changed {
+            self.changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn update_exchanges(&self, in_use: usize, capacity: usize) {
+        let changed =
+            store(&self.exchanges_in_use, in_use) | store(&self.exchange_capacity, capacity);
+        if changed {
+            self.changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -2,16 +2,24 @@ use boxslab::{BoxSlab, Slab};
 use colored::*;
 use log::{error, info, trace};
 use std::any::Any;
-use std::fmt;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use crate::error::Error;
-use crate::secure_channel;
+use crate::secure_channel::{self, case::CaseSession};
+use crate::utils::tick::{self, Tick};
 
 use heapless::LinearMap;
 
+use super::diag::TransportDiag;
 use super::packet::PacketPool;
 use super::session::CloneData;
-use super::{mrp::ReliableMessage, packet::Packet, session::SessionHandle, session::SessionMgr};
+use super::{
+    mrp::{MrpConfig, ReliableMessage},
+    packet::Packet,
+    session::{SessionHandle, SizedSessionMgr, MAX_SESSIONS},
+};
 
 pub struct ExchangeCtx<'a> {
     pub exch: &'a mut Exchange,
@@ -42,6 +50,29 @@ impl Default for State {
     }
 }
 
+/// The number of received message counters that an Exchange remembers
+pub const EXCH_RX_CTRS: usize = 8;
+
+// The message counters most recently received on an exchange. This is separate from
+// any session level counter checks, as an exchange has its own reliability scope.
+#[derive(Debug, Default)]
+struct RxCtrs {
+    ctrs: [Option<u32>; EXCH_RX_CTRS],
+    next: usize,
+}
+
+impl RxCtrs {
+    fn accept(&mut self, ctr: u32) -> bool {
+        if self.ctrs.contains(&Some(ctr)) {
+            return false;
+        }
+        // The oldest counter makes way for this one
+        self.ctrs[self.next] = Some(ctr);
+        self.next = (self.next + 1) % EXCH_RX_CTRS;
+        true
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Exchange {
     id: u16,
@@ -53,6 +84,7 @@ pub struct Exchange {
     // all 'exchanges'.
     data: Option<Box<dyn Any>>,
     mrp: ReliableMessage,
+    rx_ctrs: RxCtrs,
 }
 
 impl Exchange {
@@ -64,6 +96,7 @@ impl Exchange {
             state: State::Open,
             data: None,
             mrp: ReliableMessage::new(),
+            rx_ctrs: Default::default(),
         }
     }
 
@@ -85,6 +118,14 @@ impl Exchange {
         self.id
     }
 
+    /// Record the message counter of a received message
+    ///
+    /// Returns false if the message is a duplicate of one that was recently received on
+    /// this exchange.
+    pub fn accept_counter(&mut self, ctr: u32) -> bool {
+        self.rx_ctrs.accept(ctr)
+    }
+
     pub fn get_role(&self) -> Role {
         self.role
     }
@@ -105,10 +146,15 @@ impl Exchange {
         self.data.take()?.downcast::<T>().ok()
     }
 
+    pub fn take_exchange_data_any(&mut self) -> Option<Box<dyn Any>> {
+        self.data.take()
+    }
+
     fn send(
         &mut self,
         mut proto_tx: BoxSlab<PacketPool>,
         session: &mut SessionHandle,
+        mrp_config: &MrpConfig,
     ) -> Result<(), Error> {
         trace!("payload: {:x?}", proto_tx.as_borrow_slice());
         info!(
@@ -125,7 +171,11 @@ impl Exchange {
 
         session.pre_send(&mut proto_tx)?;
         self.mrp.pre_send(&mut proto_tx)?;
-        session.send(proto_tx)
+        session.send(&mut proto_tx)?;
+        // The peer has just been talking to us if it initiated the exchange
+        self.mrp
+            .post_send(&mut proto_tx, self.role == Role::Responder, mrp_config);
+        Ok(())
     }
 }
 
@@ -155,42 +205,102 @@ pub fn get_complementary_role(is_initiator: bool) -> Role {
     }
 }
 
-const MAX_EXCHANGES: usize = 8;
+/// The default number of exchanges in an [ExchangeMgr]
+pub const MAX_EXCHANGES: usize = 8;
+
+/// The default number of exchanges that a single session can have open in an [ExchangeMgr]
+pub const MAX_EXCHANGES_PER_SESSION: usize = 4;
+
+/// The Exchange Manager, with the default capacities
+pub type ExchangeMgr = SizedExchangeMgr<MAX_SESSIONS, MAX_EXCHANGES, MAX_EXCHANGES_PER_SESSION>;
 
-#[derive(Default)]
-pub struct ExchangeMgr {
+/// The Exchange Manager, holding up to `E` exchanges across the `N` sessions of its
+/// Session Manager, with up to `P` of them on any one session
+pub struct SizedExchangeMgr<const N: usize, const E: usize, const P: usize> {
     // keys: exch-id
-    exchanges: LinearMap<u16, Exchange, MAX_EXCHANGES>,
-    sess_mgr: SessionMgr,
+    exchanges: LinearMap<u16, Exchange, E>,
+    sess_mgr: SizedSessionMgr<N>,
+    // The ID of the next exchange that this node initiates
+    next_exch_id: u16,
+    // CASE handshakes that don't complete within this time are dropped
+    case_timeout: Duration,
+    mrp_config: MrpConfig,
+    diag: Option<Arc<TransportDiag>>,
+}
+
+impl<const N: usize, const E: usize, const P: usize> Default for SizedExchangeMgr<N, E, P> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
 }
 
 pub const MAX_MRP_ENTRIES: usize = 4;
 
-impl ExchangeMgr {
-    pub fn new(sess_mgr: SessionMgr) -> Self {
+impl<const N: usize, const E: usize, const P: usize> SizedExchangeMgr<N, E, P> {
+    pub fn new(sess_mgr: SizedSessionMgr<N>) -> Self {
         Self {
             sess_mgr,
             exchanges: Default::default(),
+            next_exch_id: rand::random(),
+            case_timeout: secure_channel::case::CASE_HANDSHAKE_TIMEOUT,
+            mrp_config: Default::default(),
+            diag: None,
+        }
+    }
+
+    /// Set the retransmission parameters for the messages sent from now on
+    pub fn set_mrp_config(&mut self, config: MrpConfig) {
+        self.mrp_config = config;
+    }
+
+    /// Set the time within which a CASE handshake must complete
+    pub fn set_case_timeout(&mut self, timeout: Duration) {
+        self.case_timeout = timeout;
+    }
+
+    /// Keep 'diag' up to date with the sessions and exchanges in use, on every tick
+    pub fn set_diag(&mut self, diag: Arc<TransportDiag>) {
+        self.diag = Some(diag);
+        self.update_diag();
+    }
+
+    /// The maximum number of exchanges, across all the sessions
+    pub fn exchange_capacity(&self) -> usize {
+        E
+    }
+
+    /// The number of exchanges on the session at 'sess_idx'
+    pub fn exchange_count(&self, sess_idx: usize) -> usize {
+        self.exchanges
+            .values()
+            .filter(|e| e.sess_idx == sess_idx)
+            .count()
+    }
+
+    fn update_diag(&self) {
+        if let Some(diag) = &self.diag {
+            diag.update_sessions(self.sess_mgr.in_use(), self.sess_mgr.capacity());
+            diag.update_exchanges(self.exchanges.len(), E);
         }
     }
 
-    pub fn get_sess_mgr(&mut self) -> &mut SessionMgr {
+    pub fn get_sess_mgr(&mut self) -> &mut SizedSessionMgr<N> {
         &mut self.sess_mgr
     }
 
     pub fn _get_with_id(
-        exchanges: &mut LinearMap<u16, Exchange, MAX_EXCHANGES>,
+        exchanges: &mut LinearMap<u16, Exchange, E>,
         exch_id: u16,
     ) -> Option<&mut Exchange> {
         exchanges.get_mut(&exch_id)
     }
 
     pub fn get_with_id(&mut self, exch_id: u16) -> Option<&mut Exchange> {
-        ExchangeMgr::_get_with_id(&mut self.exchanges, exch_id)
+        Self::_get_with_id(&mut self.exchanges, exch_id)
     }
 
     fn _get(
-        exchanges: &mut LinearMap<u16, Exchange, MAX_EXCHANGES>,
+        exchanges: &mut LinearMap<u16, Exchange, E>,
         sess_idx: usize,
         id: u16,
         role: Role,
@@ -199,7 +309,17 @@ impl ExchangeMgr {
         // I don't prefer that we scan the list twice here (once for contains_key and other)
         if !exchanges.contains_key(&(id)) {
             if create_new {
-                // If an exchange doesn't exist, create a new one
+                // If an exchange doesn't exist, create a new one, unless the session
+                // already has its share of the exchanges
+                if exchanges
+                    .values()
+                    .filter(|e| e.sess_idx == sess_idx)
+                    .count()
+                    >= P
+                {
+                    error!("Too many exchanges on session {}", sess_idx);
+                    return Err(Error::NoSpace);
+                }
                 info!("Creating new exchange");
                 let e = Exchange::new(id, sess_idx, role);
                 if exchanges.insert(id, e).is_err() {
@@ -224,11 +344,46 @@ impl ExchangeMgr {
         }
     }
 
+    /// Start a new exchange on the session at 'sess_idx', with this node as the initiator
+    ///
+    /// This is for the interactions that this node starts on its own, like the reports of
+    /// a subscription.
+    pub fn initiate(&mut self, sess_idx: usize) -> Result<ExchangeCtx<'_>, Error> {
+        let mut id = self.next_exch_id;
+        while self.exchanges.contains_key(&id) {
+            id = id.wrapping_add(1);
+        }
+        self.next_exch_id = id.wrapping_add(1);
+        let exch = Self::_get(&mut self.exchanges, sess_idx, id, Role::Initiator, true)?;
+        Ok(ExchangeCtx {
+            exch,
+            sess: self.sess_mgr.get_session_handle(sess_idx),
+        })
+    }
+
     /// The Exchange Mgr receive is like a big processing function
     pub fn recv(&mut self) -> Result<Option<(BoxSlab<PacketPool>, ExchangeCtx)>, Error> {
         // Get the session
-        let (mut proto_rx, index) = self.sess_mgr.recv()?;
+        let (proto_rx, index) = self.sess_mgr.recv()?;
+        self.post_recv(proto_rx, index)
+    }
+
+    /// Like [ExchangeMgr::recv], but gives up with None if nothing arrives within 'timeout'
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<(BoxSlab<PacketPool>, ExchangeCtx<'_>)>, Error> {
+        match self.sess_mgr.recv_timeout(timeout)? {
+            Some((proto_rx, index)) => self.post_recv(proto_rx, index),
+            None => Ok(None),
+        }
+    }
 
+    fn post_recv(
+        &mut self,
+        mut proto_rx: BoxSlab<PacketPool>,
+        index: Option<usize>,
+    ) -> Result<Option<(BoxSlab<PacketPool>, ExchangeCtx<'_>)>, Error> {
         let index = match index {
             Some(s) => s,
             None => {
@@ -244,19 +399,44 @@ impl ExchangeMgr {
         // Decrypt the message
         session.recv(&mut proto_rx)?;
 
+        // A standalone acknowledgement is only of use to an exchange that is still around
+        let standalone_ack = secure_channel::common::is_mrp_standalone_ack(&proto_rx);
+
         // Get the exchange
-        let exch = ExchangeMgr::_get(
+        let exch = match Self::_get(
             &mut self.exchanges,
             index,
             proto_rx.proto.exch_id,
             get_complementary_role(proto_rx.proto.is_initiator()),
             // We create a new exchange, only if the peer is the initiator
-            proto_rx.proto.is_initiator(),
-        )?;
+            proto_rx.proto.is_initiator() && !standalone_ack,
+        ) {
+            Ok(exch) => exch,
+            Err(_) if standalone_ack => {
+                info!(
+                    "Dropping standalone ACK for unknown exch {}",
+                    proto_rx.proto.exch_id
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
 
         // Message Reliability Protocol
         exch.mrp.recv(&proto_rx)?;
 
+        // A duplicate still gets acknowledged above, but it isn't processed again
+        if !exch.accept_counter(proto_rx.plain.ctr) {
+            info!("Dropping duplicate message, ctr: {}", proto_rx.plain.ctr);
+            return Ok(None);
+        }
+
+        // The acknowledgement was all there was to it, there is nothing to respond with
+        if standalone_ack {
+            trace!("Received standalone ACK on exch {}", exch.get_id());
+            return Ok(None);
+        }
+
         if exch.is_state_open() {
             Ok(Some((
                 proto_rx,
@@ -273,18 +453,27 @@ impl ExchangeMgr {
     }
 
     pub fn send(&mut self, exch_id: u16, proto_tx: BoxSlab<PacketPool>) -> Result<(), Error> {
-        let exchange =
-            ExchangeMgr::_get_with_id(&mut self.exchanges, exch_id).ok_or(Error::NoExchange)?;
+        let exchange = Self::_get_with_id(&mut self.exchanges, exch_id).ok_or(Error::NoExchange)?;
         let mut session = self.sess_mgr.get_session_handle(exchange.sess_idx);
-        exchange.send(proto_tx, &mut session)
+        exchange.send(proto_tx, &mut session, &self.mrp_config)
     }
 
     pub fn purge(&mut self) {
-        let mut to_purge: LinearMap<u16, (), MAX_EXCHANGES> = LinearMap::new();
+        let mut to_purge: LinearMap<u16, (), E> = LinearMap::new();
 
-        for (exch_id, exchange) in self.exchanges.iter() {
+        let case_timeout = self.case_timeout;
+        for (exch_id, exchange) in self.exchanges.iter_mut() {
+            let case_expired = matches!(exchange.get_exchange_data::<CaseSession>(),
+                                        Some(c) if c.is_expired(case_timeout));
             if exchange.is_purgeable() {
                 let _ = to_purge.insert(*exch_id, ());
+            } else if case_expired {
+                // The peer abandoned the CASE handshake, drop the exchange along with the
+                // handshake's key material. There is no Secure Channel status for this, the
+                // peer's own handshake timer takes care of its side
+                error!("CASE handshake timed out on exchange {}", exch_id);
+                exchange.close();
+                let _ = to_purge.insert(*exch_id, ());
             }
         }
         for (exch_id, _) in to_purge.iter() {
@@ -292,14 +481,96 @@ impl ExchangeMgr {
         }
     }
 
-    pub fn pending_acks(&mut self, expired_entries: &mut LinearMap<u16, (), MAX_MRP_ENTRIES>) {
+    /// A summary of the active sessions, along with their exchanges, to help with
+    /// debugging
+    pub fn summary(&self) -> String {
+        self.sess_mgr.summary_with(|sess_idx, out| {
+            for (id, e) in self.exchanges.iter() {
+                if e.sess_idx == sess_idx {
+                    writeln!(
+                        out,
+                        "  exch_id: {}, role: {:?}, state: {:?}, pending_ack: {}",
+                        id,
+                        e.role,
+                        e.state,
+                        e.mrp.is_ack_pending()
+                    )?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn pending_acks(
+        &mut self,
+        now: SystemTime,
+        expired_entries: &mut LinearMap<u16, (), MAX_MRP_ENTRIES>,
+    ) {
         for (exch_id, exchange) in self.exchanges.iter() {
-            if exchange.mrp.is_ack_ready() {
+            if exchange.mrp.is_ack_ready(now) {
                 expired_entries.insert(*exch_id, ()).unwrap();
             }
         }
     }
 
+    /// Send the standalone acknowledgements that are due at 'now'
+    fn send_acks(&mut self, now: SystemTime) {
+        let mut acks_to_send: LinearMap<u16, (), MAX_MRP_ENTRIES> = LinearMap::new();
+        self.pending_acks(now, &mut acks_to_send);
+        for exch_id in acks_to_send.keys() {
+            info!("Sending MRP Standalone ACK for  exch {}", exch_id);
+            let mut proto_tx = match Packet::new_tx().ok().and_then(Slab::<PacketPool>::new) {
+                Some(p) => p,
+                None => {
+                    error!("Error creating proto_tx");
+                    break;
+                }
+            };
+            ReliableMessage::prepare_ack(*exch_id, &mut proto_tx);
+            if let Err(e) = self.send(*exch_id, proto_tx) {
+                error!("Error in sending Ack {:?}", e);
+            }
+        }
+    }
+
+    /// Send again the reliable messages that haven't been acknowledged by 'now'
+    ///
+    /// Returns the exchanges that ran out of retransmissions, along with the error.
+    /// These exchanges are closed.
+    pub fn retransmit(&mut self, now: SystemTime) -> Vec<(u16, Error)> {
+        let mut failed = Vec::new();
+        for (exch_id, exchange) in self.exchanges.iter_mut() {
+            match exchange.mrp.retrans_due(now, &self.mrp_config) {
+                Ok(Some(raw)) => {
+                    info!("Retransmitting on exch {}", exch_id);
+                    let mut session = self.sess_mgr.get_session_handle(exchange.sess_idx);
+                    if let Err(e) = session.resend(raw) {
+                        error!("Error in retransmitting {:?}", e);
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    exchange.close();
+                    failed.push((*exch_id, e));
+                }
+            }
+        }
+        failed
+    }
+
+    /// The time at which an acknowledgement, a retransmission, or the timeout of a CASE
+    /// handshake is next due, on any of the exchanges
+    pub fn next_deadline(&mut self) -> Option<SystemTime> {
+        let case_timeout = self.case_timeout;
+        self.exchanges.values_mut().fold(None, |next, exchange| {
+            let case_expiry = exchange
+                .get_exchange_data::<CaseSession>()
+                .map(|c| c.expires_at(case_timeout));
+            let next = tick::earliest(next, case_expiry);
+            tick::earliest(next, exchange.mrp.next_deadline())
+        })
+    }
+
     pub fn evict_session(&mut self, index: usize) -> Result<(), Error> {
         info!("Sessions full, vacating session with index: {}", index);
         // If we enter here, we have an LRU session that needs to be reclaimed
@@ -321,7 +592,7 @@ impl ExchangeMgr {
             // Send Close_session on this exchange, and then close the session
             // Should this be done for all exchanges?
             error!("Sending Close Session");
-            exchange.send(tx, &mut session)?;
+            exchange.send(tx, &mut session, &self.mrp_config)?;
             // TODO: This wouldn't actually send it out, because 'transport' isn't owned yet.
         }
 
@@ -348,6 +619,14 @@ impl ExchangeMgr {
         Ok(())
     }
 
+    /// Evict all the CASE sessions established on the given fabric
+    pub fn evict_fabric_sessions(&mut self, fab_idx: u8) -> Result<(), Error> {
+        while let Some(index) = self.sess_mgr.get_index_on_fabric(fab_idx) {
+            self.evict_session(index)?;
+        }
+        Ok(())
+    }
+
     pub fn add_session(&mut self, clone_data: CloneData) -> Result<SessionHandle, Error> {
         let sess_idx = match self.sess_mgr.clone_session(&clone_data) {
             Ok(idx) => idx,
@@ -364,7 +643,24 @@ impl ExchangeMgr {
     }
 }
 
-impl fmt::Display for ExchangeMgr {
+impl<const N: usize, const E: usize, const P: usize> Tick for SizedExchangeMgr<N, E, P> {
+    /// Send the acknowledgements and the retransmissions that are due, and purge the
+    /// exchanges that are done with
+    fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+        self.send_acks(now);
+        for (exch_id, e) in self.retransmit(now) {
+            error!(
+                "Message on exch {} was never acknowledged: {:?}",
+                exch_id, e
+            );
+        }
+        self.purge();
+        self.update_diag();
+        self.next_deadline()
+    }
+}
+
+impl<const N: usize, const E: usize, const P: usize> fmt::Display for SizedExchangeMgr<N, E, P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{{  Session Mgr: {},", self.sess_mgr)?;
         writeln!(f, "  Exchanges: [")?;
@@ -379,15 +675,64 @@ impl fmt::Display for ExchangeMgr {
 #[cfg(test)]
 mod tests {
 
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+        rc::Rc,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use boxslab::Slab;
+
     use crate::{
+        data_model::sdm::failsafe::FailSafe,
         error::Error,
+        secure_channel::case::CaseSession,
         transport::{
+            diag::TransportDiag,
+            mrp::MrpConfig,
             network::{Address, NetworkInterface},
-            session::{CloneData, SessionMgr, SessionMode, MAX_SESSIONS},
+            packet::{Packet, PacketPool},
+            session::{CloneData, SessionMgr, SessionMode, SizedSessionMgr, MAX_SESSIONS},
         },
+        utils::tick::{Tick, Ticker},
     };
 
-    use super::{ExchangeMgr, Role};
+    use super::{Exchange, ExchangeMgr, Role, SizedExchangeMgr, EXCH_RX_CTRS};
+
+    #[test]
+    fn test_accept_counter() {
+        let mut exch = Exchange::new(1, 0, Role::Responder);
+        assert!(exch.accept_counter(100));
+        assert!(!exch.accept_counter(100));
+        assert!(exch.accept_counter(101));
+        assert!(!exch.accept_counter(100));
+
+        // Only the most recent counters are remembered
+        for ctr in 102..(101 + EXCH_RX_CTRS as u32) {
+            assert!(exch.accept_counter(ctr));
+        }
+        assert!(!exch.accept_counter(101));
+        assert!(exch.accept_counter(100));
+    }
+
+    #[test]
+    fn test_purge_abandoned_case() {
+        let mut mgr = ExchangeMgr::new(SessionMgr::new());
+        let e = ExchangeMgr::_get(&mut mgr.exchanges, 1, 2, Role::Responder, true).unwrap();
+        e.set_exchange_data(Box::new(CaseSession::new(1, 2).unwrap()));
+
+        // The handshake is still within its time
+        mgr.purge();
+        assert!(ExchangeMgr::_get(&mut mgr.exchanges, 1, 2, Role::Responder, false).is_ok());
+
+        // No Sigma3 arrived in time, the exchange is dropped
+        mgr.set_case_timeout(Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        mgr.purge();
+        assert!(ExchangeMgr::_get(&mut mgr.exchanges, 1, 2, Role::Responder, false).is_err());
+    }
 
     #[test]
     fn test_purge() {
@@ -448,6 +793,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_evict_fabric_sessions() {
+        let mut sess_mgr = SessionMgr::new();
+        sess_mgr
+            .add_network_interface(Box::new(DummyNetwork::new()))
+            .unwrap();
+        let mut mgr = ExchangeMgr::new(sess_mgr);
+
+        let modes = [
+            SessionMode::Case(1),
+            SessionMode::Case(2),
+            SessionMode::Case(1),
+            SessionMode::Pase,
+        ];
+        for (i, mode) in modes.iter().enumerate() {
+            let clone_data = CloneData::new(
+                12341234,
+                43211234,
+                100 + i as u16,
+                1 + i as u16,
+                Address::default(),
+                *mode,
+            );
+            mgr.add_session(clone_data).unwrap();
+        }
+
+        mgr.evict_fabric_sessions(1).unwrap();
+        assert!(mgr.sess_mgr.get_with_id(1).is_none());
+        assert!(mgr.sess_mgr.get_with_id(2).is_some());
+        assert!(mgr.sess_mgr.get_with_id(3).is_none());
+        assert!(mgr.sess_mgr.get_with_id(4).is_some());
+    }
+
     pub struct DummyNetwork;
     impl DummyNetwork {
         pub fn new() -> Self {
@@ -530,4 +908,237 @@ mod tests {
         }
         //        println!("Session mgr {}", mgr.sess_mgr);
     }
+
+    // A network interface that counts the messages sent through it
+    struct CountingNetwork(Rc<Cell<usize>>);
+
+    impl NetworkInterface for CountingNetwork {
+        fn recv(&self, _in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+            Ok((0, Address::default()))
+        }
+
+        fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+            self.0.set(self.0.get() + 1);
+            Ok(out_buf.len())
+        }
+    }
+
+    #[test]
+    fn test_retransmit() {
+        let sent = Rc::new(Cell::new(0));
+        let mut sess_mgr = SessionMgr::new();
+        sess_mgr
+            .add_network_interface(Box::new(CountingNetwork(sent.clone())))
+            .unwrap();
+        let mut mgr = ExchangeMgr::new(sess_mgr);
+        mgr.set_mrp_config(MrpConfig {
+            initial_retrans_ms: 1,
+            active_retrans_ms: 1,
+            max_retrans_count: 2,
+            backoff_multiplier: 1.0,
+            jitter: 0.0,
+        });
+        mgr.add_session(get_clone_data(100, 1)).unwrap();
+        let _ = ExchangeMgr::_get(&mut mgr.exchanges, 0, 7, Role::Initiator, true).unwrap();
+
+        let mut tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        tx.set_reliable();
+        mgr.send(7, tx).unwrap();
+        assert_eq!(sent.get(), 1);
+
+        // The message is never acknowledged
+        let failed = loop {
+            std::thread::sleep(Duration::from_millis(2));
+            let failed = mgr.retransmit(SystemTime::now());
+            if !failed.is_empty() {
+                break failed;
+            }
+        };
+        assert_eq!(sent.get(), 3);
+        assert_eq!(failed, vec![(7, Error::NoSpaceRetransTable)]);
+
+        // The exchange is done with
+        mgr.purge();
+        assert!(mgr.get_with_id(7).is_none());
+    }
+
+    #[test]
+    fn test_tick_failsafe_and_retransmit() {
+        let sent = Rc::new(Cell::new(0));
+        let mut sess_mgr = SessionMgr::new();
+        sess_mgr
+            .add_network_interface(Box::new(CountingNetwork(sent.clone())))
+            .unwrap();
+        let mut mgr = ExchangeMgr::new(sess_mgr);
+        mgr.set_mrp_config(MrpConfig {
+            jitter: 0.0,
+            ..Default::default()
+        });
+        mgr.add_session(get_clone_data(100, 1)).unwrap();
+        let _ = ExchangeMgr::_get(&mut mgr.exchanges, 0, 7, Role::Initiator, true).unwrap();
+        let mut tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        tx.set_reliable();
+        let start = SystemTime::now();
+        mgr.send(7, tx).unwrap();
+
+        let failsafe = Arc::new(FailSafe::new());
+        failsafe.arm(1, SessionMode::Case(1)).unwrap();
+        let mut ticker = Ticker::new();
+        ticker.register(Box::new(failsafe.clone()));
+        ticker.register(Box::new(mgr));
+
+        // The retransmission is due before the Fail-Safe expires
+        let retrans_ms = MrpConfig::default().initial_retrans_ms;
+        let next = ticker.tick(start).unwrap();
+        assert!(next >= start + Duration::from_millis(retrans_ms));
+        assert!(next < start + Duration::from_secs(1));
+        assert_eq!(sent.get(), 1);
+
+        // Past the expiry of the Fail-Safe, only the next retransmission is left
+        let now = start + Duration::from_secs(2);
+        let next = ticker.tick(now).unwrap();
+        assert!(!failsafe.is_armed());
+        assert_eq!(sent.get(), 2);
+        assert!(next > now);
+        assert!(next <= now + Duration::from_secs(1));
+    }
+
+    // One end of a pair of connected network interfaces
+    struct Pipe {
+        tx: Rc<RefCell<VecDeque<Vec<u8>>>>,
+        rx: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    }
+
+    fn pipe_pair() -> (Pipe, Pipe) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            Pipe {
+                tx: a.clone(),
+                rx: b.clone(),
+            },
+            Pipe { tx: b, rx: a },
+        )
+    }
+
+    impl NetworkInterface for Pipe {
+        fn recv(&self, in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+            let msg = self.rx.borrow_mut().pop_front().ok_or(Error::NotFound)?;
+            in_buf[..msg.len()].copy_from_slice(&msg);
+            Ok((msg.len(), Address::default()))
+        }
+
+        fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+            self.tx.borrow_mut().push_back(out_buf.to_vec());
+            Ok(out_buf.len())
+        }
+    }
+
+    #[test]
+    fn test_standalone_ack_cancels_retransmission() {
+        let (local_end, peer_end) = pipe_pair();
+        let mut sess_mgr = SessionMgr::new();
+        sess_mgr.add_network_interface(Box::new(local_end)).unwrap();
+        let mut mgr = ExchangeMgr::new(sess_mgr);
+        let mut sess_mgr = SessionMgr::new();
+        sess_mgr.add_network_interface(Box::new(peer_end)).unwrap();
+        let mut peer = ExchangeMgr::new(sess_mgr);
+
+        // The two ends of the same session
+        mgr.add_session(get_clone_data(100, 1)).unwrap();
+        peer.add_session(CloneData::new(
+            43211234,
+            12341234,
+            1,
+            100,
+            Address::default(),
+            SessionMode::Pase,
+        ))
+        .unwrap();
+
+        let _ = ExchangeMgr::_get(&mut mgr.exchanges, 0, 7, Role::Initiator, true).unwrap();
+        let mut tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        tx.set_reliable();
+        mgr.send(7, tx).unwrap();
+        assert!(mgr.next_deadline().is_some());
+
+        // The peer has no reply to piggyback the ACK on, it sends it standalone
+        assert!(peer.recv().unwrap().is_some());
+        peer.tick(SystemTime::now() + Duration::from_secs(1));
+
+        // The standalone ACK isn't passed on for processing, and there is nothing left
+        // to retransmit
+        assert!(mgr.recv().unwrap().is_none());
+        assert!(mgr.get_with_id(7).unwrap().mrp.is_empty());
+        assert_eq!(mgr.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut mgr = ExchangeMgr::new(SessionMgr::new());
+        let mut clone_data = get_clone_data(100, 1);
+        clone_data.dec_key = [0xa5; 16];
+        clone_data.enc_key = [0xa5; 16];
+        mgr.add_session(clone_data).unwrap();
+        let _ = ExchangeMgr::_get(&mut mgr.exchanges, 0, 7, Role::Responder, true).unwrap();
+
+        // The keys aren't part of the summary
+        assert_eq!(
+            mgr.summary(),
+            format!(
+                "sess_id: 1, peer_sess_id: 100, peer: {:?}, peer_nodeid: Some(43211234), \
+                 mode: Pase, fabric: None\n  \
+                 exch_id: 7, role: Responder, state: Open, pending_ack: false\n",
+                Address::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_exchanges_per_session() {
+        let mut mgr = SizedExchangeMgr::<4, 8, 2>::new(SizedSessionMgr::new());
+        let _ = SizedExchangeMgr::<4, 8, 2>::_get(&mut mgr.exchanges, 0, 1, Role::Responder, true)
+            .unwrap();
+        let _ = SizedExchangeMgr::<4, 8, 2>::_get(&mut mgr.exchanges, 0, 2, Role::Responder, true)
+            .unwrap();
+        assert_eq!(
+            SizedExchangeMgr::<4, 8, 2>::_get(&mut mgr.exchanges, 0, 3, Role::Responder, true)
+                .err(),
+            Some(Error::NoSpace)
+        );
+        // The existing exchanges can still be looked up, and the other sessions aren't
+        // affected
+        assert!(
+            SizedExchangeMgr::<4, 8, 2>::_get(&mut mgr.exchanges, 0, 2, Role::Responder, true)
+                .is_ok()
+        );
+        assert!(
+            SizedExchangeMgr::<4, 8, 2>::_get(&mut mgr.exchanges, 1, 3, Role::Responder, true)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_diag() {
+        let mut mgr = SizedExchangeMgr::<4, 8, 4>::new(SizedSessionMgr::new());
+        let diag = Arc::new(TransportDiag::new());
+        mgr.set_diag(diag.clone());
+        assert_eq!(diag.session_capacity(), 4);
+        assert_eq!(diag.exchange_capacity(), 8);
+        assert_eq!(diag.sessions_in_use(), 0);
+
+        mgr.add_session(get_clone_data(100, 1)).unwrap();
+        let _ = SizedExchangeMgr::<4, 8, 4>::_get(&mut mgr.exchanges, 0, 7, Role::Responder, true)
+            .unwrap();
+        let _ = SizedExchangeMgr::<4, 8, 4>::_get(&mut mgr.exchanges, 0, 8, Role::Responder, true)
+            .unwrap();
+        assert_eq!(mgr.exchange_count(0), 2);
+        assert_eq!(mgr.exchange_count(1), 0);
+
+        // The counts are refreshed on the next tick
+        assert_eq!(diag.exchanges_in_use(), 0);
+        mgr.tick(SystemTime::now());
+        assert_eq!(diag.sessions_in_use(), 1);
+        assert_eq!(diag.exchanges_in_use(), 2);
+    }
 }
@@ -1,16 +1,24 @@
 use async_channel::Receiver;
 use boxslab::{BoxSlab, Slab};
-use heapless::LinearMap;
 use log::{debug, error, info};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use crate::error::*;
 
-use crate::transport::mrp::ReliableMessage;
+use crate::transport::mrp::MrpConfig;
 use crate::transport::packet::PacketPool;
 use crate::transport::{exchange, packet::Packet, proto_demux, queue, session, udp};
+use crate::utils::tick::{self, Tick};
 
+use super::diag::TransportDiag;
+use super::network::NetworkInterface;
 use super::proto_demux::ProtoCtx;
-use super::queue::Msg;
+use super::queue::{DeferredResponse, Msg};
+
+// The longest that the transport waits for a message, so that the queued messages don't
+// linger when nothing else is due
+const MAX_RX_WAIT: Duration = Duration::from_millis(100);
 
 pub struct Mgr {
     exch_mgr: exchange::ExchangeMgr,
@@ -20,9 +28,13 @@ pub struct Mgr {
 
 impl Mgr {
     pub fn new() -> Result<Mgr, Error> {
+        Self::new_with_network(Box::new(udp::UdpListener::new()?))
+    }
+
+    /// Creates a transport that talks over 'network' instead of the Matter UDP port
+    pub fn new_with_network(network: Box<dyn NetworkInterface>) -> Result<Mgr, Error> {
         let mut sess_mgr = session::SessionMgr::new();
-        let udp_transport = Box::new(udp::UdpListener::new()?);
-        sess_mgr.add_network_interface(udp_transport)?;
+        sess_mgr.add_network_interface(network)?;
         Ok(Mgr {
             proto_demux: proto_demux::ProtoDemux::new(),
             exch_mgr: exchange::ExchangeMgr::new(sess_mgr),
@@ -38,6 +50,16 @@ impl Mgr {
         self.proto_demux.register(proto_id_handle)
     }
 
+    /// Set the retransmission parameters of the Message Reliability Protocol
+    pub fn set_mrp_config(&mut self, config: MrpConfig) {
+        self.exch_mgr.set_mrp_config(config);
+    }
+
+    /// Keep 'diag' up to date with the sessions and exchanges in use
+    pub fn set_diag(&mut self, diag: Arc<TransportDiag>) {
+        self.exch_mgr.set_diag(diag);
+    }
+
     fn send_to_exchange(
         &mut self,
         exch_id: u16,
@@ -46,15 +68,30 @@ impl Mgr {
         self.exch_mgr.send(exch_id, proto_tx)
     }
 
-    fn handle_rxtx(&mut self) -> Result<(), Error> {
-        let result = self.exch_mgr.recv().map_err(|e| {
+    fn send_deferred(&mut self, resp: DeferredResponse) -> Result<(), Error> {
+        let mut proto_tx = Self::new_tx()?;
+        proto_tx.set_proto_id(resp.proto_id);
+        proto_tx.set_proto_opcode(resp.proto_opcode);
+        proto_tx.get_writebuf()?.copy_from_slice(&resp.payload)?;
+        self.send_to_exchange(resp.exch_id, proto_tx)?;
+
+        // The deferred response completes the interaction
+        if let Some(exch) = self.exch_mgr.get_with_id(resp.exch_id) {
+            exch.close();
+        }
+        Ok(())
+    }
+
+    // Returns whether a message was there to process
+    fn handle_rxtx(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let result = self.exch_mgr.recv_timeout(timeout).map_err(|e| {
             error!("Error in recv: {:?}", e);
             e
         })?;
 
         if result.is_none() {
             // Nothing to process, return quietly
-            return Ok(());
+            return Ok(false);
         }
         // result contains something worth processing, we can safely unwrap
         // as we already checked for none above
@@ -69,7 +106,7 @@ impl Mgr {
             Ok(r) => {
                 if let proto_demux::ResponseRequired::No = r {
                     // We need to send the Ack if reliability is enabled, in this case
-                    return Ok(());
+                    return Ok(true);
                 }
             }
             Err(e) => {
@@ -91,7 +128,7 @@ impl Mgr {
             e
         })?;
 
-        Ok(())
+        Ok(true)
     }
 
     fn handle_queue_msgs(&mut self) -> Result<(), Error> {
@@ -104,6 +141,18 @@ impl Mgr {
                         .add_session(clone_data)
                         .map_err(|e| error!("Error adding new session {:?}", e));
                 }
+                Msg::FabricUpdated(fab_idx) => {
+                    // The sessions with the earlier credentials must not be used anymore
+                    let _ = self
+                        .exch_mgr
+                        .evict_fabric_sessions(fab_idx)
+                        .map_err(|e| error!("Error evicting sessions {:?}", e));
+                }
+                Msg::DeferredResponse(resp) => {
+                    let _ = self
+                        .send_deferred(resp)
+                        .map_err(|e| error!("Error sending deferred response {:?}", e));
+                }
                 _ => {
                     error!("Queue Message Type not yet handled {:?}", msg);
                 }
@@ -112,47 +161,212 @@ impl Mgr {
         Ok(())
     }
 
+    /// Service the time-based parts of the transport at 'now'
+    ///
+    /// The protocols send what is due first, for the retransmissions of what they send to
+    /// be accounted for. Returns the time by which the transport next needs to be serviced,
+    /// if any.
+    pub fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+        let next = self.proto_demux.tick(now, &mut self.exch_mgr);
+        tick::earliest(next, self.exch_mgr.tick(now))
+    }
+
+    /// Process the messages that arrive until 'deadline', at most one from the network
+    ///
+    /// This returns early if a message is processed, the caller is expected to call
+    /// [Mgr::tick] before polling again.
+    pub fn poll(&mut self, deadline: Option<SystemTime>) {
+        let timeout = deadline
+            .map(|d| d.duration_since(SystemTime::now()).unwrap_or_default())
+            .map_or(MAX_RX_WAIT, |t| t.min(MAX_RX_WAIT));
+
+        // Handle network operations
+        match self.handle_rxtx(timeout) {
+            Ok(true) => info!("Exchange Mgr: {}", self.exch_mgr),
+            Ok(false) => (),
+            Err(_) => error!("Error in handle_rxtx"),
+        }
+
+        if self.handle_queue_msgs().is_err() {
+            error!("Error in handle_queue_msg");
+        }
+    }
+
     pub fn start(&mut self) -> Result<(), Error> {
         loop {
-            // Handle network operations
-            if self.handle_rxtx().is_err() {
-                error!("Error in handle_rxtx");
-                continue;
-            }
+            // Handle the pending acknowledgements, retransmissions and exchange purging
+            let next = self.tick(SystemTime::now());
+            self.poll(next);
+        }
+    }
 
-            if self.handle_queue_msgs().is_err() {
-                error!("Error in handle_queue_msg");
-                continue;
-            }
+    fn new_tx() -> Result<BoxSlab<PacketPool>, Error> {
+        Slab::<PacketPool>::new(Packet::new_tx()?).ok_or(Error::PacketPoolExhaust)
+    }
+}
 
-            // Handle any pending acknowledgement send
-            let mut acks_to_send: LinearMap<u16, (), { exchange::MAX_MRP_ENTRIES }> =
-                LinearMap::new();
-            self.exch_mgr.pending_acks(&mut acks_to_send);
-            for exch_id in acks_to_send.keys() {
-                info!("Sending MRP Standalone ACK for  exch {}", exch_id);
-                let mut proto_tx = match Self::new_tx() {
-                    Ok(p) => p,
-                    Err(e) => {
-                        error!("Error creating proto_tx {:?}", e);
-                        break;
-                    }
-                };
-                ReliableMessage::prepare_ack(*exch_id, &mut proto_tx);
-                if let Err(e) = self.send_to_exchange(*exch_id, proto_tx) {
-                    error!("Error in sending Ack {:?}", e);
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use super::*;
+    use crate::secure_channel::common::{is_mrp_standalone_ack, OpCode, PROTO_ID_SECURE_CHANNEL};
+    use crate::transport::network::Address;
+    use crate::transport::proto_demux::{HandleProto, ResponseRequired};
+
+    // A network interface that waits out the timeout when there is nothing to receive
+    struct QueuedNetwork {
+        rx: Rc<RefCell<VecDeque<Vec<u8>>>>,
+        tx: Rc<RefCell<Vec<Vec<u8>>>>,
+    }
+
+    impl NetworkInterface for QueuedNetwork {
+        fn recv(&self, _in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+            Err(Error::Network)
+        }
+
+        fn recv_timeout(
+            &self,
+            in_buf: &mut [u8],
+            timeout: Duration,
+        ) -> Result<Option<(usize, Address)>, Error> {
+            match self.rx.borrow_mut().pop_front() {
+                Some(msg) => {
+                    in_buf[..msg.len()].copy_from_slice(&msg);
+                    Ok(Some((msg.len(), Address::default())))
+                }
+                None => {
+                    std::thread::sleep(timeout);
+                    Ok(None)
                 }
             }
+        }
 
-            // Handle exchange purging
-            //    This need not be done in each turn of the loop, maybe once in 5 times or so?
-            self.exch_mgr.purge();
+        fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+            self.tx.borrow_mut().push(out_buf.to_vec());
+            Ok(out_buf.len())
+        }
+    }
+
+    // A Secure Channel that responds reliably to every message, if at all
+    struct TestProto {
+        respond: bool,
+    }
+
+    impl HandleProto for TestProto {
+        fn handle_proto_id(&mut self, proto_ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
+            if !self.respond {
+                return Ok(ResponseRequired::No);
+            }
+            proto_ctx.tx.set_proto_id(PROTO_ID_SECURE_CHANNEL as u16);
+            proto_ctx
+                .tx
+                .set_proto_opcode(OpCode::PBKDFParamResponse as u8);
+            proto_ctx.tx.set_reliable();
+            Ok(ResponseRequired::Yes)
+        }
 
-            info!("Exchange Mgr: {}", self.exch_mgr);
+        fn get_proto_id(&self) -> usize {
+            PROTO_ID_SECURE_CHANNEL
         }
     }
 
-    fn new_tx() -> Result<BoxSlab<PacketPool>, Error> {
-        Slab::<PacketPool>::new(Packet::new_tx()?).ok_or(Error::PacketPoolExhaust)
+    // An unencrypted, reliable PBKDFParamRequest that starts the exchange 7
+    const MSG: [u8; 22] = [
+        0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+        0x08, 0x05, 0x20, 0x07, 0x00, 0x00, 0x00,
+    ];
+
+    // Returns the transport that receives 'rx' in that order, and what it sends
+    fn new_mgr(rx: Vec<Vec<u8>>, respond: bool) -> (Mgr, Rc<RefCell<Vec<Vec<u8>>>>) {
+        let tx = Rc::new(RefCell::new(Vec::new()));
+        let mut mgr = Mgr::new_with_network(Box::new(QueuedNetwork {
+            rx: Rc::new(RefCell::new(VecDeque::from(rx))),
+            tx: tx.clone(),
+        }))
+        .unwrap();
+        mgr.register_protocol(Box::new(TestProto { respond }))
+            .unwrap();
+        (mgr, tx)
+    }
+
+    // Runs the loop of the daemon until 'sent' messages have gone out
+    fn run_until_sent(mgr: &mut Mgr, tx: &Rc<RefCell<Vec<Vec<u8>>>>, sent: usize) {
+        let start = SystemTime::now();
+        while tx.borrow().len() < sent {
+            assert!(start.elapsed().unwrap() < Duration::from_secs(2));
+            let next = mgr.tick(SystemTime::now());
+            mgr.poll(next);
+        }
+    }
+
+    #[test]
+    fn test_standalone_ack_without_rx() {
+        let (mut mgr, tx) = new_mgr(vec![MSG.to_vec()], false);
+        let start = SystemTime::now();
+
+        // Nothing else arrives after the message, the ACK still goes out once it is due
+        run_until_sent(&mut mgr, &tx, 1);
+        assert!(start.elapsed().unwrap() >= Duration::from_millis(200));
+
+        let sent = &tx.borrow()[0];
+        let mut ack = Packet::new_rx().unwrap();
+        ack.as_borrow_slice()[..sent.len()].copy_from_slice(sent);
+        ack.get_parsebuf().unwrap().set_len(sent.len());
+        ack.plain_hdr_decode().unwrap();
+        ack.proto_decode(0, None).unwrap();
+        assert!(is_mrp_standalone_ack(&ack));
+        assert_eq!(ack.proto.get_ack_msg_ctr(), Some(1));
+        assert_eq!(ack.proto.exch_id, 7);
+    }
+
+    #[test]
+    fn test_send_deferred() {
+        let (mut mgr, tx) = new_mgr(vec![MSG.to_vec()], false);
+        // The request opens the exchange, the protocol doesn't respond to it right away
+        mgr.poll(Some(SystemTime::now()));
+        assert!(mgr.exch_mgr.get_with_id(7).unwrap().is_state_open());
+        assert!(tx.borrow().is_empty());
+
+        mgr.send_deferred(DeferredResponse {
+            exch_id: 7,
+            proto_id: PROTO_ID_SECURE_CHANNEL as u16,
+            proto_opcode: OpCode::PBKDFParamResponse as u8,
+            payload: vec![0x15, 0x18],
+        })
+        .unwrap();
+
+        let sent = &tx.borrow()[0];
+        let mut resp = Packet::new_rx().unwrap();
+        resp.as_borrow_slice()[..sent.len()].copy_from_slice(sent);
+        resp.get_parsebuf().unwrap().set_len(sent.len());
+        resp.plain_hdr_decode().unwrap();
+        resp.proto_decode(0, None).unwrap();
+        assert_eq!(resp.proto.exch_id, 7);
+        assert_eq!(resp.get_proto_id(), PROTO_ID_SECURE_CHANNEL as u16);
+        assert_eq!(resp.get_proto_opcode(), OpCode::PBKDFParamResponse as u8);
+        assert_eq!(resp.as_borrow_slice(), [0x15, 0x18]);
+
+        // The response completes the exchange
+        assert!(!mgr.exch_mgr.get_with_id(7).unwrap().is_state_open());
+    }
+
+    #[test]
+    fn test_retransmit_after_rx_error() {
+        // The message is followed by one that can't be decoded, and then by nothing at all
+        let (mut mgr, tx) = new_mgr(vec![MSG.to_vec(), vec![0x00]], true);
+        mgr.set_mrp_config(MrpConfig {
+            initial_retrans_ms: 10,
+            active_retrans_ms: 10,
+            max_retrans_count: 2,
+            backoff_multiplier: 1.0,
+            jitter: 0.0,
+        });
+
+        // The response is retransmitted all the same, as it isn't acknowledged
+        run_until_sent(&mut mgr, &tx, 3);
+        let tx = tx.borrow();
+        assert_eq!(tx[0], tx[1]);
+        assert_eq!(tx[1], tx[2]);
     }
 }
@@ -1,3 +1,4 @@
+pub mod diag;
 pub mod exchange;
 pub mod mgr;
 pub mod mrp;
@@ -1,27 +1,86 @@
 use std::time::Duration;
 use std::time::SystemTime;
 
-use crate::{error::*, secure_channel, transport::packet::Packet};
+use crate::{error::*, secure_channel, transport::packet::Packet, utils::tick};
 use log::error;
 
 // 200 ms
 const MRP_STANDALONE_ACK_TIMEOUT: u64 = 200;
 
+/// The parameters of the retransmission backoff
+///
+/// The defaults are the ones from the spec. The interval before retransmission 'n' is
+/// the base interval, scaled by 'backoff_multiplier' for every retransmission after the
+/// first, plus a random extra of up to 'jitter' times that.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MrpConfig {
+    /// The base interval, in ms, when the peer may be idle
+    pub initial_retrans_ms: u64,
+    /// The base interval, in ms, when the peer is known to be active
+    pub active_retrans_ms: u64,
+    /// The number of retransmissions before giving up on a message
+    pub max_retrans_count: u8,
+    pub backoff_multiplier: f32,
+    pub jitter: f32,
+}
+
+impl Default for MrpConfig {
+    fn default() -> Self {
+        Self {
+            initial_retrans_ms: 500,
+            active_retrans_ms: 300,
+            max_retrans_count: 4,
+            backoff_multiplier: 1.6,
+            jitter: 0.25,
+        }
+    }
+}
+
+impl MrpConfig {
+    fn retrans_interval(&self, peer_active: bool, retrans_count: u8) -> Duration {
+        let base = if peer_active {
+            self.active_retrans_ms
+        } else {
+            self.initial_retrans_ms
+        };
+        let backoff = self
+            .backoff_multiplier
+            .powi(retrans_count.saturating_sub(1) as i32);
+        let jitter = 1.0 + self.jitter * rand::random::<f32>();
+        Duration::from_millis((base as f32 * backoff * jitter).round() as u64)
+    }
+}
+
 #[derive(Debug)]
 pub struct RetransEntry {
     // The msg counter that we are waiting to be acknowledged
     msg_ctr: u32,
-    // This will additionally have retransmission count and periods once we implement it
+    // The encoded message, as it was sent out
+    raw: Vec<u8>,
+    peer_active: bool,
+    // The number of retransmissions so far
+    retrans_count: u8,
+    next_retrans: SystemTime,
 }
 
 impl RetransEntry {
-    pub fn new(msg_ctr: u32) -> Self {
-        Self { msg_ctr }
+    pub fn new(msg_ctr: u32, raw: &[u8], peer_active: bool, config: &MrpConfig) -> Self {
+        Self {
+            msg_ctr,
+            raw: raw.to_vec(),
+            peer_active,
+            retrans_count: 0,
+            next_retrans: SystemTime::now() + config.retrans_interval(peer_active, 0),
+        }
     }
 
     pub fn get_msg_ctr(&self) -> u32 {
         self.msg_ctr
     }
+
+    pub fn get_retrans_count(&self) -> u8 {
+        self.retrans_count
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -50,8 +109,8 @@ impl AckEntry {
         self.msg_ctr
     }
 
-    pub fn has_timed_out(&self) -> bool {
-        self.ack_timeout > SystemTime::now()
+    pub fn has_timed_out(&self, now: SystemTime) -> bool {
+        self.ack_timeout <= now
     }
 }
 
@@ -72,16 +131,33 @@ impl ReliableMessage {
         self.retrans.is_none() && self.ack.is_none()
     }
 
-    // Check any pending acknowledgements / retransmissions and take action
-    pub fn is_ack_ready(&self) -> bool {
+    /// Returns true if a received message is yet to be acknowledged
+    pub fn is_ack_pending(&self) -> bool {
+        self.ack.is_some()
+    }
+
+    /// Returns true if a standalone acknowledgement is due at 'now'
+    ///
+    /// The acknowledgement is only sent on its own if no message went out in the
+    /// meantime to piggyback it on.
+    pub fn is_ack_ready(&self, now: SystemTime) -> bool {
         // Acknowledgements
         if let Some(ack_entry) = self.ack {
-            ack_entry.has_timed_out()
+            ack_entry.has_timed_out(now)
         } else {
             false
         }
     }
 
+    /// The time at which a standalone acknowledgement or a retransmission is next due,
+    /// if either is pending
+    pub fn next_deadline(&self) -> Option<SystemTime> {
+        tick::earliest(
+            self.ack.map(|a| a.ack_timeout),
+            self.retrans.as_ref().map(|r| r.next_retrans),
+        )
+    }
+
     pub fn prepare_ack(_exch_id: u16, proto_tx: &mut Packet) {
         secure_channel::common::create_mrp_standalone_ack(proto_tx);
     }
@@ -105,11 +181,53 @@ impl ReliableMessage {
             error!("Previous retrans entry for this exchange already exists");
             return Err(Error::Invalid);
         }
-
-        self.retrans = Some(RetransEntry::new(proto_tx.plain.ctr));
         Ok(())
     }
 
+    /// Keep a reliable message that was just sent, until it is acknowledged
+    ///
+    /// 'peer_active' picks the active retransmission interval over the initial one.
+    pub fn post_send(&mut self, proto_tx: &mut Packet, peer_active: bool, config: &MrpConfig) {
+        if proto_tx.is_reliable() {
+            self.retrans = Some(RetransEntry::new(
+                proto_tx.plain.ctr,
+                proto_tx.as_borrow_slice(),
+                peer_active,
+                config,
+            ));
+        }
+    }
+
+    /// Returns the message to be sent again, if its retransmission is due at 'now'
+    ///
+    /// Once the message has been retransmitted 'max_retrans_count' times without an
+    /// acknowledgement, it is dropped and Error::NoSpaceRetransTable is returned.
+    pub fn retrans_due(
+        &mut self,
+        now: SystemTime,
+        config: &MrpConfig,
+    ) -> Result<Option<&[u8]>, Error> {
+        match &self.retrans {
+            Some(entry) if entry.next_retrans <= now => {
+                if entry.retrans_count >= config.max_retrans_count {
+                    error!(
+                        "Giving up on msg counter {} after {} retransmissions",
+                        entry.msg_ctr, entry.retrans_count
+                    );
+                    self.retrans = None;
+                    return Err(Error::NoSpaceRetransTable);
+                }
+            }
+            _ => return Ok(None),
+        }
+
+        // The entry is there, and due, from the check above
+        let entry = self.retrans.as_mut().ok_or(Error::Invalid)?;
+        entry.retrans_count += 1;
+        entry.next_retrans = now + config.retrans_interval(entry.peer_active, entry.retrans_count);
+        Ok(Some(&entry.raw))
+    }
+
     /* A note about Message ACKs, it is a bit asymmetric in the sense that:
      * -  there can be only one pending ACK per exchange (so this is per-exchange)
      * -  there can be only one pending retransmission per exchange (so this is per-exchange)
@@ -119,12 +237,17 @@ impl ReliableMessage {
         if proto_rx.proto.is_ack() {
             // Handle received Acks
             let ack_msg_ctr = proto_rx.proto.get_ack_msg_ctr().ok_or(Error::Invalid)?;
-            if let Some(entry) = &self.retrans {
-                if entry.get_msg_ctr() != ack_msg_ctr {
-                    // TODO: XXX Fix this
-                    error!("Mismatch in retrans-table's msg counter and received msg counter: received {}, expected {}. This is expected for the timebeing", ack_msg_ctr, entry.get_msg_ctr());
+            match &self.retrans {
+                Some(entry) if entry.get_msg_ctr() == ack_msg_ctr => self.retrans = None,
+                Some(entry) => {
+                    // A stale acknowledgement, the pending message still needs one
+                    error!(
+                        "Mismatch in retrans-table's msg counter and received msg counter: received {}, expected {}",
+                        ack_msg_ctr,
+                        entry.get_msg_ctr()
+                    );
                 }
-                self.retrans = None;
+                None => (),
             }
         }
 
@@ -141,3 +264,54 @@ impl ReliableMessage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{MrpConfig, ReliableMessage};
+    use crate::{error::Error, transport::packet::Packet};
+
+    #[test]
+    fn test_retrans_exhausted() {
+        let config = MrpConfig {
+            initial_retrans_ms: 1,
+            active_retrans_ms: 1,
+            max_retrans_count: 2,
+            backoff_multiplier: 1.0,
+            jitter: 0.0,
+        };
+        let mut tx = Packet::new_tx().unwrap();
+        tx.set_reliable();
+        let mut mrp = ReliableMessage::new();
+        mrp.pre_send(&mut tx).unwrap();
+        mrp.post_send(&mut tx, false, &config);
+
+        let mut retransmissions = 0;
+        let result = loop {
+            std::thread::sleep(Duration::from_millis(2));
+            match mrp.retrans_due(SystemTime::now(), &config) {
+                Ok(Some(_)) => retransmissions += 1,
+                Ok(None) => (),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(retransmissions, 2);
+        assert_eq!(result, Error::NoSpaceRetransTable);
+        assert!(mrp.is_empty());
+    }
+
+    #[test]
+    fn test_retrans_interval() {
+        let config = MrpConfig {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.retrans_interval(false, 0),
+            Duration::from_millis(500)
+        );
+        assert_eq!(config.retrans_interval(true, 1), Duration::from_millis(300));
+        assert_eq!(config.retrans_interval(true, 2), Duration::from_millis(480));
+    }
+}
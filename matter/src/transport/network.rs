@@ -1,6 +1,7 @@
 use std::{
     fmt::{Debug, Display},
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
 };
 
 use crate::error::Error;
@@ -34,5 +35,17 @@ impl Debug for Address {
 
 pub trait NetworkInterface {
     fn recv(&self, in_buf: &mut [u8]) -> Result<(usize, Address), Error>;
+
+    /// Like [NetworkInterface::recv], but gives up with None if nothing arrives within 'timeout'
+    ///
+    /// The default implementation waits for as long as [NetworkInterface::recv] does.
+    fn recv_timeout(
+        &self,
+        in_buf: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<Option<(usize, Address)>, Error> {
+        self.recv(in_buf).map(Some)
+    }
+
     fn send(&self, out_buf: &[u8], addr: Address) -> Result<usize, Error>;
 }
@@ -15,8 +15,19 @@ use super::{
     proto_hdr::{self, ProtoHdr},
 };
 
-pub const MAX_RX_BUF_SIZE: usize = 1583;
-type Buffer = [u8; MAX_RX_BUF_SIZE];
+// The IPv6 minimum MTU less the IPv6 and UDP headers
+#[cfg(not(feature = "large_messages"))]
+const MAX_MSG_SIZE: usize = 1280 - 40 - 8;
+#[cfg(feature = "large_messages")]
+const MAX_MSG_SIZE: usize = 64000;
+
+pub const MAX_RX_BUF_SIZE: usize = MAX_MSG_SIZE;
+pub const MAX_TX_BUF_SIZE: usize = MAX_MSG_SIZE;
+
+// One byte more than the largest message, so that oversized datagrams can be
+// detected instead of being silently truncated by the network
+const BUFFER_SIZE: usize = MAX_RX_BUF_SIZE + 1;
+type Buffer = [u8; BUFFER_SIZE];
 
 // TODO: I am not very happy with this construction, need to find another way to do this
 pub struct BufferPool {
@@ -41,10 +52,12 @@ impl BufferPool {
     pub fn alloc() -> Option<(usize, &'static mut Buffer)> {
         trace!("Buffer Alloc called\n");
 
-        let mut pool = BufferPool::get().lock().unwrap();
+        // The pool only tracks which buffers are in use, that stays consistent even if
+        // a holder of the lock panicked
+        let mut pool = BufferPool::get().lock().unwrap_or_else(|e| e.into_inner());
         for i in 0..MAX_PACKET_POOL_SIZE {
             if pool.buffers[i].is_none() {
-                pool.buffers[i] = Some([0; MAX_RX_BUF_SIZE]);
+                pool.buffers[i] = Some([0; BUFFER_SIZE]);
                 // Sigh! to by-pass the borrow-checker telling us we are stealing a mutable reference
                 // from under the lock
                 // In this case the lock only protects against the setting of Some/None,
@@ -58,7 +71,7 @@ impl BufferPool {
 
     pub fn free(index: usize) {
         trace!("Buffer Free called\n");
-        let mut pool = BufferPool::get().lock().unwrap();
+        let mut pool = BufferPool::get().lock().unwrap_or_else(|e| e.into_inner());
         if pool.buffers[index].is_some() {
             pool.buffers[index] = None;
         }
@@ -102,9 +115,8 @@ impl<'a> Packet<'a> {
 
     pub fn new_tx() -> Result<Self, Error> {
         let (buffer_index, buffer) = BufferPool::alloc().ok_or(Error::NoSpace)?;
-        let buf_len = buffer.len();
 
-        let mut wb = WriteBuf::new(buffer, buf_len);
+        let mut wb = WriteBuf::new(buffer, MAX_TX_BUF_SIZE);
         wb.reserve(Packet::HDR_RESERVE)?;
 
         let mut p = Self {
@@ -142,6 +154,25 @@ impl<'a> Packet<'a> {
         }
     }
 
+    /// Split the packet into its plain header, and the rest of the packet after it
+    ///
+    /// The plain header is the AAD of the encrypted rest of the packet, so this lets the
+    /// payload be encrypted or decrypted in place while the header is read. A Tx packet
+    /// must already have its plain header prepended, and an Rx packet must have only its
+    /// plain header decoded.
+    pub fn split_header_payload(&mut self) -> Result<(&[u8], &mut [u8]), Error> {
+        match &mut self.data {
+            Direction::Rx(pb, state) => {
+                if *state == RxState::PlainDecode {
+                    Ok(pb.split_parsed_mut())
+                } else {
+                    Err(Error::InvalidState)
+                }
+            }
+            Direction::Tx(wb) => wb.split_at_mut(self.plain.encoded_len()),
+        }
+    }
+
     pub fn get_proto_id(&self) -> u16 {
         self.proto.proto_id
     }
@@ -150,6 +181,14 @@ impl<'a> Packet<'a> {
         self.proto.proto_id = proto_id;
     }
 
+    pub fn get_proto_vendor_id(&self) -> Option<u16> {
+        if self.proto.is_vendor() {
+            self.proto.proto_vendor_id
+        } else {
+            None
+        }
+    }
+
     pub fn get_proto_opcode(&self) -> u8 {
         self.proto.proto_opcode
     }
@@ -220,3 +259,56 @@ impl<'a> Drop for Packet<'a> {
 }
 
 box_slab!(PacketPool, Packet<'static>, MAX_PACKET_POOL_SIZE);
+
+#[cfg(test)]
+mod tests {
+    use super::Packet;
+    use crate::utils::writebuf::WriteBuf;
+
+    // The slices are disjoint, and the header is right before the payload
+    fn check_adjacent(header: &[u8], payload: &[u8]) {
+        let header_end = header.as_ptr() as usize + header.len();
+        assert_eq!(header_end, payload.as_ptr() as usize);
+    }
+
+    #[test]
+    fn test_split_header_payload_tx() {
+        let mut tx = Packet::new_tx().unwrap();
+        tx.plain.ctr = 0x12345678;
+        tx.plain.set_dest_u64(0x0102030405060708);
+
+        // The destination node id makes for a 16 byte header
+        let mut hdr = [0u8; 16];
+        tx.plain.encode(&mut WriteBuf::new(&mut hdr, 16)).unwrap();
+        let wb = tx.get_writebuf().unwrap();
+        wb.append(&[0xaa, 0xbb, 0xcc]).unwrap();
+        wb.prepend(&hdr).unwrap();
+
+        let (header, payload) = tx.split_header_payload().unwrap();
+        assert_eq!(header, hdr);
+        assert_eq!(payload, [0xaa, 0xbb, 0xcc]);
+        check_adjacent(header, payload);
+
+        // The payload can be written while the header is read
+        payload[0] = header[0];
+        assert_eq!(tx.as_borrow_slice()[16], hdr[0]);
+    }
+
+    #[test]
+    fn test_split_header_payload_rx() {
+        let mut rx = Packet::new_rx().unwrap();
+        // No source or destination node ids, so the header is 8 bytes
+        let msg = [0x0, 0x11, 0x0, 0x0, 0x29, 0x0, 0x0, 0x0, 0x5, 0x8, 0x70];
+        rx.as_borrow_slice()[..msg.len()].copy_from_slice(&msg);
+        rx.get_parsebuf().unwrap().set_len(msg.len());
+
+        // The header has to be decoded first
+        assert!(rx.split_header_payload().is_err());
+        rx.plain_hdr_decode().unwrap();
+
+        let (header, payload) = rx.split_header_payload().unwrap();
+        assert_eq!(header, &msg[..8]);
+        assert_eq!(payload, &msg[8..]);
+        check_adjacent(header, payload);
+    }
+}
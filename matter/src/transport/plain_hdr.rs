@@ -25,25 +25,38 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Default)]
+    pub struct SecFlags: u8 {
+        const GROUP_SESSION = 0x01;
+        const MSG_EXTENSIONS = 0x20;
+        const CONTROL_MSG = 0x40;
+        const PRIVACY = 0x80;
+    }
+}
+
 // This is the unencrypted message
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 pub struct PlainHdr {
     pub flags: MsgFlags,
+    pub sec_flags: SecFlags,
     pub sess_type: SessionType,
     pub sess_id: u16,
     pub ctr: u32,
-    peer_nodeid: Option<u64>,
+    src_nodeid: Option<u64>,
+    dst_nodeid: Option<u64>,
+    dst_group_id: Option<u16>,
 }
 
 impl PlainHdr {
     pub fn set_dest_u64(&mut self, id: u64) {
         self.flags |= MsgFlags::DSIZ_UNICAST_NODEID;
-        self.peer_nodeid = Some(id);
+        self.dst_nodeid = Some(id);
     }
 
     pub fn get_src_u64(&self) -> Option<u64> {
         if self.flags.contains(MsgFlags::SRC_ADDR_PRESENT) {
-            self.peer_nodeid
+            self.src_nodeid
         } else {
             None
         }
@@ -53,9 +66,20 @@ impl PlainHdr {
 impl PlainHdr {
     // it will have an additional 'message length' field first
     pub fn decode(&mut self, msg: &mut ParseBuf) -> Result<(), Error> {
+        // flags, session id, security flags and message counter
+        if msg.remaining() < 8 {
+            return Err(Error::TruncatedPacket);
+        }
         self.flags = MsgFlags::from_bits(msg.le_u8()?).ok_or(Error::Invalid)?;
+        if self
+            .flags
+            .contains(MsgFlags::DSIZ_UNICAST_NODEID | MsgFlags::DSIZ_GROUPCAST_NODEID)
+        {
+            // This destination size is reserved
+            return Err(Error::Invalid);
+        }
         self.sess_id = msg.le_u16()?;
-        let _sec_flags = msg.le_u8()?;
+        self.sec_flags = SecFlags::from_bits(msg.le_u8()?).ok_or(Error::Invalid)?;
         self.sess_type = if self.sess_id != 0 {
             SessionType::Encrypted
         } else {
@@ -63,8 +87,32 @@ impl PlainHdr {
         };
         self.ctr = msg.le_u32()?;
 
-        if self.flags.contains(MsgFlags::SRC_ADDR_PRESENT) {
-            self.peer_nodeid = Some(msg.le_u64()?);
+        let src_len = if self.flags.contains(MsgFlags::SRC_ADDR_PRESENT) {
+            8
+        } else {
+            0
+        };
+        let dst_len = if self.flags.contains(MsgFlags::DSIZ_UNICAST_NODEID) {
+            8
+        } else if self.flags.contains(MsgFlags::DSIZ_GROUPCAST_NODEID) {
+            2
+        } else {
+            0
+        };
+        if msg.remaining() < src_len + dst_len {
+            return Err(Error::TruncatedPacket);
+        }
+        self.src_nodeid = if src_len != 0 {
+            Some(msg.le_u64()?)
+        } else {
+            None
+        };
+        self.dst_nodeid = None;
+        self.dst_group_id = None;
+        match dst_len {
+            8 => self.dst_nodeid = Some(msg.le_u64()?),
+            2 => self.dst_group_id = Some(msg.le_u16()?),
+            _ => (),
         }
 
         info!(
@@ -74,17 +122,41 @@ impl PlainHdr {
         Ok(())
     }
 
+    // The node ids are encoded as the flags say, the flags must have a node id to go
+    // with them
     pub fn encode(&mut self, resp_buf: &mut WriteBuf) -> Result<(), Error> {
         resp_buf.le_u8(self.flags.bits())?;
         resp_buf.le_u16(self.sess_id)?;
-        resp_buf.le_u8(0)?;
+        resp_buf.le_u8(self.sec_flags.bits())?;
         resp_buf.le_u32(self.ctr)?;
-        if let Some(d) = self.peer_nodeid {
-            resp_buf.le_u64(d)?;
+        if self.flags.contains(MsgFlags::SRC_ADDR_PRESENT) {
+            resp_buf.le_u64(self.src_nodeid.ok_or(Error::Invalid)?)?;
+        }
+        if self.flags.contains(MsgFlags::DSIZ_UNICAST_NODEID) {
+            resp_buf.le_u64(self.dst_nodeid.ok_or(Error::Invalid)?)?;
+        } else if self.flags.contains(MsgFlags::DSIZ_GROUPCAST_NODEID) {
+            resp_buf.le_u16(self.dst_group_id.ok_or(Error::Invalid)?)?;
         }
         Ok(())
     }
 
+    /// The length of the header once it is encoded
+    pub fn encoded_len(&self) -> usize {
+        let src_len = if self.flags.contains(MsgFlags::SRC_ADDR_PRESENT) {
+            8
+        } else {
+            0
+        };
+        let dst_len = if self.flags.contains(MsgFlags::DSIZ_UNICAST_NODEID) {
+            8
+        } else if self.flags.contains(MsgFlags::DSIZ_GROUPCAST_NODEID) {
+            2
+        } else {
+            0
+        };
+        8 + src_len + dst_len
+    }
+
     pub fn is_encrypted(&self) -> bool {
         self.sess_type == SessionType::Encrypted
     }
@@ -106,3 +178,135 @@ pub const fn max_plain_hdr_len() -> usize {
     // [optional] destination node ID
         8
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // With the source node id and a unicast destination node id
+        let mut msg: [u8; 26] = [
+            0x05, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x07, 0x08, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0xaa, 0xbb,
+        ];
+        let mut pb = ParseBuf::new(&mut msg, 26);
+        let mut plain = PlainHdr::default();
+        plain.decode(&mut pb).unwrap();
+        assert_eq!(plain.ctr, 0x12345678);
+        assert_eq!(plain.get_src_u64(), Some(0x0807060504030201));
+        assert_eq!(pb.as_slice(), [0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        // The fixed part of the header is cut short
+        let mut msg: [u8; 7] = [0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34];
+        let mut pb = ParseBuf::new(&mut msg, 7);
+        assert_eq!(
+            PlainHdr::default().decode(&mut pb),
+            Err(Error::TruncatedPacket)
+        );
+
+        // The source node id is cut short
+        let mut msg: [u8; 12] = [
+            0x04, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12, 0x01, 0x02, 0x03, 0x04,
+        ];
+        let mut pb = ParseBuf::new(&mut msg, 12);
+        assert_eq!(
+            PlainHdr::default().decode(&mut pb),
+            Err(Error::TruncatedPacket)
+        );
+
+        // The group destination id is cut short
+        let mut msg: [u8; 9] = [0x02, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12, 0x01];
+        let mut pb = ParseBuf::new(&mut msg, 9);
+        assert_eq!(
+            PlainHdr::default().decode(&mut pb),
+            Err(Error::TruncatedPacket)
+        );
+    }
+
+    // Encode 'hdr', and check that it decodes back to the same header
+    fn roundtrip_check(hdr: &mut PlainHdr) {
+        let mut buf = [0u8; max_plain_hdr_len()];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        hdr.encode(&mut wb).unwrap();
+        let len = wb.as_borrow_slice().len();
+
+        let mut pb = ParseBuf::new(&mut buf, len);
+        let mut decoded = PlainHdr::default();
+        decoded.decode(&mut pb).unwrap();
+        assert_eq!(&decoded, hdr);
+        assert!(pb.as_slice().is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let sec_flags = [
+            SecFlags::empty(),
+            SecFlags::PRIVACY,
+            SecFlags::CONTROL_MSG | SecFlags::MSG_EXTENSIONS,
+            SecFlags::GROUP_SESSION | SecFlags::PRIVACY,
+        ];
+        for sess_id in [0, 0x1234] {
+            for sec_flags in sec_flags {
+                for src_nodeid in [None, Some(0x0807060504030201)] {
+                    for dst in 0..3 {
+                        let mut hdr = PlainHdr {
+                            sec_flags,
+                            sess_id,
+                            ctr: 0x12345678,
+                            src_nodeid,
+                            ..Default::default()
+                        };
+                        if sess_id != 0 {
+                            hdr.sess_type = SessionType::Encrypted;
+                        }
+                        if src_nodeid.is_some() {
+                            hdr.flags |= MsgFlags::SRC_ADDR_PRESENT;
+                        }
+                        match dst {
+                            1 => hdr.set_dest_u64(0x1112131415161718),
+                            2 => {
+                                hdr.flags |= MsgFlags::DSIZ_GROUPCAST_NODEID;
+                                hdr.dst_group_id = Some(0xabcd);
+                            }
+                            _ => (),
+                        }
+                        roundtrip_check(&mut hdr);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_missing_node_id() {
+        let mut buf = [0u8; max_plain_hdr_len()];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut hdr = PlainHdr {
+            flags: MsgFlags::SRC_ADDR_PRESENT,
+            ..Default::default()
+        };
+        assert_eq!(hdr.encode(&mut wb), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn test_decode_reserved() {
+        // Both the destination size bits
+        let mut msg: [u8; 16] = [
+            0x03, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x07, 0x08,
+        ];
+        let mut pb = ParseBuf::new(&mut msg, 16);
+        assert_eq!(PlainHdr::default().decode(&mut pb), Err(Error::Invalid));
+
+        // A reserved session type
+        let mut msg: [u8; 8] = [0x00, 0x00, 0x00, 0x02, 0x78, 0x56, 0x34, 0x12];
+        let mut pb = ParseBuf::new(&mut msg, 8);
+        assert_eq!(PlainHdr::default().decode(&mut pb), Err(Error::Invalid));
+    }
+}
@@ -1,8 +1,13 @@
+use std::convert::TryFrom;
+use std::time::SystemTime;
+
 use boxslab::BoxSlab;
+use heapless::LinearMap;
+use log::error;
 
-use crate::error::*;
+use crate::{error::*, secure_channel::common::PROTO_ID_SECURE_CHANNEL, utils::tick};
 
-use super::exchange::ExchangeCtx;
+use super::exchange::{ExchangeCtx, ExchangeMgr};
 use super::packet::PacketPool;
 
 const MAX_PROTOCOLS: usize = 4;
@@ -12,8 +17,13 @@ pub enum ResponseRequired {
     Yes,
     No,
 }
+
+// A protocol is identified by its protocol ID, scoped by the vendor ID for vendor-specific
+// protocols
+type ProtoKey = (Option<u16>, u16);
+
 pub struct ProtoDemux {
-    proto_id_handlers: [Option<Box<dyn HandleProto>>; MAX_PROTOCOLS],
+    proto_id_handlers: LinearMap<ProtoKey, Box<dyn HandleProto>, MAX_PROTOCOLS>,
 }
 
 /// This is the context in which a receive packet is being processed
@@ -41,9 +51,21 @@ pub trait HandleProto {
 
     fn get_proto_id(&self) -> usize;
 
+    /// The vendor ID of the protocol, only for vendor-specific protocols
+    fn get_proto_vendor_id(&self) -> Option<u16> {
+        None
+    }
+
     fn handle_session_event(&self) -> Result<(), Error> {
         Ok(())
     }
+
+    /// Send what is due at 'now', on the exchanges that the protocol initiates in 'exch_mgr'
+    ///
+    /// Returns the time by which the protocol next needs to be serviced, if any.
+    fn handle_tick(&mut self, _now: SystemTime, _exch_mgr: &mut ExchangeMgr) -> Option<SystemTime> {
+        None
+    }
 }
 
 impl Default for ProtoDemux {
@@ -55,24 +77,192 @@ impl Default for ProtoDemux {
 impl ProtoDemux {
     pub fn new() -> ProtoDemux {
         ProtoDemux {
-            proto_id_handlers: [None, None, None, None],
+            proto_id_handlers: LinearMap::new(),
         }
     }
 
+    /// Register the handler for a protocol
+    ///
+    /// The handler receives all the messages whose protocol ID (and vendor ID, for vendor-specific
+    /// protocols) matches the one it reports. Only one handler can be registered per protocol.
     pub fn register(&mut self, proto_id_handle: Box<dyn HandleProto>) -> Result<(), Error> {
-        let proto_id = proto_id_handle.get_proto_id();
-        self.proto_id_handlers[proto_id] = Some(proto_id_handle);
+        let proto_id = u16::try_from(proto_id_handle.get_proto_id()).map_err(|_| Error::Invalid)?;
+        let key = (proto_id_handle.get_proto_vendor_id(), proto_id);
+        if self.proto_id_handlers.contains_key(&key) {
+            error!("Protocol {:?} is already registered", key);
+            return Err(Error::Invalid);
+        }
+        self.proto_id_handlers
+            .insert(key, proto_id_handle)
+            .map_err(|_| Error::NoSpace)?;
         Ok(())
     }
 
+    /// Service all the protocols at 'now'
+    ///
+    /// Returns the earliest time by which any of the protocols next needs to be serviced.
+    pub fn tick(&mut self, now: SystemTime, exch_mgr: &mut ExchangeMgr) -> Option<SystemTime> {
+        self.proto_id_handlers.values_mut().fold(None, |next, h| {
+            tick::earliest(next, h.handle_tick(now, exch_mgr))
+        })
+    }
+
+    /// Dispatch the message to the handler of its protocol
+    ///
+    /// Unencrypted sessions only exist to establish the secure sessions, so only the Secure
+    /// Channel protocol is accepted on them.
     pub fn handle(&mut self, proto_ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
-        let proto_id = proto_ctx.rx.get_proto_id() as usize;
-        if proto_id >= MAX_PROTOCOLS {
+        let key = (
+            proto_ctx.rx.get_proto_vendor_id(),
+            proto_ctx.rx.get_proto_id(),
+        );
+        if !proto_ctx.exch_ctx.sess.is_encrypted() && key != (None, PROTO_ID_SECURE_CHANNEL as u16)
+        {
+            error!(
+                "Protocol {:?}, opcode {:#x} isn't allowed on an unencrypted session",
+                key,
+                proto_ctx.rx.get_proto_opcode()
+            );
             return Err(Error::Invalid);
         }
-        return self.proto_id_handlers[proto_id]
-            .as_mut()
-            .ok_or(Error::NoHandler)?
-            .handle_proto_id(proto_ctx);
+        match self.proto_id_handlers.get_mut(&key) {
+            Some(handler) => handler.handle_proto_id(proto_ctx),
+            None => {
+                error!("No handler registered for protocol {:?}", key);
+                Err(Error::NoHandler)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use boxslab::Slab;
+
+    use super::{HandleProto, ProtoCtx, ProtoDemux, ResponseRequired};
+    use crate::{
+        error::Error,
+        interaction_model::core::OpCode,
+        transport::{
+            exchange::{Exchange, ExchangeCtx},
+            network::Address,
+            packet::{Packet, PacketPool},
+            session::{CloneData, SessionMgr, SessionMode},
+        },
+    };
+
+    const TEST_VENDOR_ID: u16 = 0xFFF1;
+    const TEST_PROTO_ID: usize = 0x0001;
+
+    struct TestProto {
+        rx_count: Arc<AtomicUsize>,
+        vendor_id: Option<u16>,
+    }
+
+    impl HandleProto for TestProto {
+        fn handle_proto_id(&mut self, _ctx: &mut ProtoCtx) -> Result<ResponseRequired, Error> {
+            self.rx_count.fetch_add(1, Ordering::SeqCst);
+            Ok(ResponseRequired::No)
+        }
+
+        fn get_proto_id(&self) -> usize {
+            TEST_PROTO_ID
+        }
+
+        fn get_proto_vendor_id(&self) -> Option<u16> {
+            self.vendor_id
+        }
+    }
+
+    fn handle_msg(
+        demux: &mut ProtoDemux,
+        vendor_id: Option<u16>,
+        encrypted: bool,
+    ) -> Result<(), Error> {
+        let mut exch: Exchange = Default::default();
+        let mut sess_mgr: SessionMgr = Default::default();
+        let peer_addr = Address::Udp(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            5542,
+        ));
+        let sess_idx = if encrypted {
+            let clone_data = CloneData::new(1, 2, 10, 30, peer_addr, SessionMode::Case(1));
+            sess_mgr.clone_session(&clone_data).unwrap()
+        } else {
+            sess_mgr.get_or_add(0, peer_addr, None, false).unwrap()
+        };
+        let exch_ctx = ExchangeCtx {
+            exch: &mut exch,
+            sess: sess_mgr.get_session_handle(sess_idx),
+        };
+        let mut rx = Slab::<PacketPool>::new(Packet::new_rx().unwrap()).unwrap();
+        let tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        rx.set_proto_id(TEST_PROTO_ID as u16);
+        rx.set_proto_opcode(OpCode::ReadRequest as u8);
+        if let Some(v) = vendor_id {
+            rx.proto.set_vendor(v);
+        }
+
+        let mut ctx = ProtoCtx::new(exch_ctx, rx, tx);
+        demux.handle(&mut ctx).map(|_| ())
+    }
+
+    #[test]
+    fn test_vendor_proto() {
+        let rx_count = Arc::new(AtomicUsize::new(0));
+        let mut demux = ProtoDemux::new();
+        demux
+            .register(Box::new(TestProto {
+                rx_count: rx_count.clone(),
+                vendor_id: Some(TEST_VENDOR_ID),
+            }))
+            .unwrap();
+        // Only one handler per protocol
+        assert_eq!(
+            demux.register(Box::new(TestProto {
+                rx_count: rx_count.clone(),
+                vendor_id: Some(TEST_VENDOR_ID),
+            })),
+            Err(Error::Invalid)
+        );
+
+        assert_eq!(handle_msg(&mut demux, Some(TEST_VENDOR_ID), true), Ok(()));
+        assert_eq!(rx_count.load(Ordering::SeqCst), 1);
+
+        // The same protocol ID in the Matter (or another vendor's) namespace isn't ours
+        assert_eq!(handle_msg(&mut demux, None, true), Err(Error::NoHandler));
+        assert_eq!(
+            handle_msg(&mut demux, Some(0xFFF2), true),
+            Err(Error::NoHandler)
+        );
+        assert_eq!(rx_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unencrypted_session() {
+        // The Interaction Model protocol
+        let rx_count = Arc::new(AtomicUsize::new(0));
+        let mut demux = ProtoDemux::new();
+        demux
+            .register(Box::new(TestProto {
+                rx_count: rx_count.clone(),
+                vendor_id: None,
+            }))
+            .unwrap();
+
+        // A Read Request on an unencrypted session never reaches the handler
+        assert_eq!(handle_msg(&mut demux, None, false), Err(Error::Invalid));
+        assert_eq!(rx_count.load(Ordering::SeqCst), 0);
+
+        assert_eq!(handle_msg(&mut demux, None, true), Ok(()));
+        assert_eq!(rx_count.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,12 +1,12 @@
 use bitflags::bitflags;
 use std::fmt;
 
-use crate::transport::plain_hdr;
+use crate::transport::{packet::Packet, plain_hdr};
 use crate::utils::parsebuf::ParseBuf;
 use crate::utils::writebuf::WriteBuf;
 use crate::{crypto, error::*};
 
-use log::{info, trace};
+use log::{error, info, trace};
 
 bitflags! {
     #[derive(Default)]
@@ -35,7 +35,7 @@ impl ProtoHdr {
     }
 
     pub fn set_vendor(&mut self, proto_vendor_id: u16) {
-        self.exch_flags |= ExchFlags::RELIABLE;
+        self.exch_flags |= ExchFlags::VENDOR;
         self.proto_vendor_id = Some(proto_vendor_id);
     }
 
@@ -86,6 +86,10 @@ impl ProtoHdr {
         if let Some(d) = dec_key {
             // We decrypt only if the decryption key is valid
             decrypt_in_place(plain_hdr.ctr, peer_nodeid, parsebuf, d)?;
+        } else if plain_hdr.is_encrypted() {
+            // Nothing of an encrypted message is parsed before it is authenticated
+            error!("No key to decrypt the message with");
+            return Err(Error::Invalid);
         }
 
         self.exch_flags = ExchFlags::from_bits(parsebuf.le_u8()?).ok_or(Error::Invalid)?;
@@ -146,31 +150,21 @@ impl fmt::Display for ProtoHdr {
     }
 }
 
-fn get_iv(recvd_ctr: u32, peer_nodeid: u64, iv: &mut [u8]) -> Result<(), Error> {
-    // The IV is the source address (64-bit) followed by the message counter (32-bit)
-    let mut write_buf = WriteBuf::new(iv, iv.len());
-    // For some reason, this is 0 in the 'bypass' mode
-    write_buf.le_u8(0)?;
-    write_buf.le_u32(recvd_ctr)?;
-    write_buf.le_u64(peer_nodeid)?;
-    Ok(())
-}
-
+// Encrypt everything after the plain header of the packet, the plain header is the AAD
 pub fn encrypt_in_place(
     send_ctr: u32,
     peer_nodeid: u64,
-    plain_hdr: &[u8],
-    writebuf: &mut WriteBuf,
+    proto_tx: &mut Packet,
     key: &[u8],
 ) -> Result<(), Error> {
     // IV
-    let mut iv = [0_u8; crypto::AEAD_NONCE_LEN_BYTES];
-    get_iv(send_ctr, peer_nodeid, &mut iv)?;
+    //   The security flags are 0 for unicast sessions
+    let iv = crypto::build_nonce(0, send_ctr, peer_nodeid);
 
     // Cipher Text
     let tag_space = [0u8; crypto::AEAD_MIC_LEN_BYTES];
-    writebuf.append(&tag_space)?;
-    let cipher_text = writebuf.as_mut_slice();
+    proto_tx.get_writebuf()?.append(&tag_space)?;
+    let (plain_hdr, cipher_text) = proto_tx.split_header_payload()?;
 
     crypto::encrypt_in_place(
         key,
@@ -203,9 +197,8 @@ fn decrypt_in_place(
     }
 
     // IV:
-    //   the specific way for creating IV is in get_iv
-    let mut iv = [0_u8; crypto::AEAD_NONCE_LEN_BYTES];
-    get_iv(recvd_ctr, peer_nodeid, &mut iv)?;
+    //   the specific way for creating IV is in crypto::build_nonce
+    let iv = crypto::build_nonce(0, recvd_ctr, peer_nodeid);
 
     let cipher_text = parsebuf.as_borrow_slice();
     //println!("AAD: {:x?}", aad);
@@ -272,31 +265,48 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_decode_encrypted_without_key() {
+        // The payload of an encrypted message isn't looked at without a key to
+        // authenticate it with
+        let mut input_buf: [u8; 6] = [0x5, 0x8, 0x70, 0x0, 0x1, 0x0];
+        let mut parsebuf = ParseBuf::new(&mut input_buf, 6);
+        let mut plain = plain_hdr::PlainHdr::default();
+        plain.sess_type = plain_hdr::SessionType::Encrypted;
+        let mut proto = ProtoHdr::default();
+        assert_eq!(
+            proto.decrypt_and_decode(&plain, &mut parsebuf, 0, None),
+            Err(Error::Invalid)
+        );
+        assert_eq!(parsebuf.remaining(), 6);
+    }
+
     #[test]
     pub fn test_encrypt_success() {
         // These values are captured from an execution run of the chip-tool binary
         let send_ctr = 41;
 
-        let mut main_buf: [u8; 52] = [0; 52];
-        let main_buf_len = main_buf.len();
-        let mut writebuf = WriteBuf::new(&mut main_buf, main_buf_len);
-
+        let mut proto_tx = Packet::new_tx().unwrap();
         let plain_hdr: [u8; 8] = [0x0, 0x11, 0x0, 0x0, 0x29, 0x0, 0x0, 0x0];
 
         let plain_text: [u8; 28] = [
             5, 8, 0x58, 0x28, 0x01, 0x00, 0x15, 0x36, 0x00, 0x15, 0x37, 0x00, 0x24, 0x00, 0x01,
             0x24, 0x02, 0x06, 0x24, 0x03, 0x01, 0x18, 0x35, 0x01, 0x18, 0x18, 0x18, 0x18,
         ];
+        let writebuf = proto_tx.get_writebuf().unwrap();
         writebuf.append(&plain_text).unwrap();
+        writebuf.prepend(&plain_hdr).unwrap();
 
         let key = [
             0x44, 0xd4, 0x3c, 0x91, 0xd2, 0x27, 0xf3, 0xba, 0x08, 0x24, 0xc5, 0xd8, 0x7c, 0xb8,
             0x1b, 0x33,
         ];
 
-        encrypt_in_place(send_ctr, 0, &plain_hdr, &mut writebuf, &key).unwrap();
+        encrypt_in_place(send_ctr, 0, &mut proto_tx, &key).unwrap();
+        let (aad, cipher_text) = proto_tx.split_header_payload().unwrap();
+        assert_eq!(aad, plain_hdr);
         assert_eq!(
-            writebuf.as_slice(),
+            cipher_text,
             [
                 189, 83, 250, 121, 38, 87, 97, 17, 153, 78, 243, 20, 36, 11, 131, 142, 136, 165,
                 227, 107, 204, 129, 193, 153, 42, 131, 138, 254, 22, 190, 76, 244, 116, 45, 156,
@@ -6,11 +6,23 @@ use crate::error::Error;
 
 use super::session::CloneData;
 
+/// The response to a deferred command, to be sent on the exchange 'exch_id'
+#[derive(Debug)]
+pub struct DeferredResponse {
+    pub exch_id: u16,
+    pub proto_id: u16,
+    pub proto_opcode: u8,
+    pub payload: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub enum Msg {
     Tx(),
     Rx(),
     NewSession(CloneData),
+    // The credentials of the fabric at this index were updated
+    FabricUpdated(u8),
+    DeferredResponse(DeferredResponse),
 }
 
 #[derive(Clone)]
@@ -2,26 +2,30 @@ use core::fmt;
 use std::{
     any::Any,
     ops::{Deref, DerefMut},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use crate::{
+    cert::NocCatIds,
     error::*,
     transport::{plain_hdr, proto_hdr},
     utils::writebuf::WriteBuf,
 };
 use boxslab::{BoxSlab, Slab};
 use colored::*;
-use log::{info, trace};
+use log::{error, info, trace};
 use rand::Rng;
 
 use super::{
     network::{Address, NetworkInterface},
-    packet::{Packet, PacketPool},
+    packet::{Packet, PacketPool, MAX_RX_BUF_SIZE},
 };
 
 const MATTER_AES128_KEY_SIZE: usize = 16;
 
+/// A received packet, along with the index of its session if there was room for one
+pub type SessionRx = (BoxSlab<PacketPool>, Option<usize>);
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SessionMode {
     // The Case session will capture the local fabric index
@@ -36,11 +40,11 @@ impl Default for SessionMode {
     }
 }
 
-#[derive(Debug)]
 pub struct Session {
     peer_addr: Address,
     local_nodeid: u64,
     peer_nodeid: Option<u64>,
+    peer_cats: NocCatIds,
     // I find the session initiator/responder role getting confused with exchange initiator/responder
     // So, we might keep this as enc_key and dec_key for now
     dec_key: [u8; MATTER_AES128_KEY_SIZE],
@@ -54,7 +58,6 @@ pub struct Session {
     last_use: SystemTime,
 }
 
-#[derive(Debug)]
 pub struct CloneData {
     pub dec_key: [u8; MATTER_AES128_KEY_SIZE],
     pub enc_key: [u8; MATTER_AES128_KEY_SIZE],
@@ -63,6 +66,7 @@ pub struct CloneData {
     peer_sess_id: u16,
     local_nodeid: u64,
     peer_nodeid: u64,
+    pub peer_cats: NocCatIds,
     peer_addr: Address,
     mode: SessionMode,
 }
@@ -81,6 +85,7 @@ impl CloneData {
             att_challenge: [0; MATTER_AES128_KEY_SIZE],
             local_nodeid,
             peer_nodeid,
+            peer_cats: Default::default(),
             peer_addr,
             peer_sess_id,
             local_sess_id,
@@ -89,6 +94,54 @@ impl CloneData {
     }
 }
 
+// The key material is kept out of the Debug output, so that it doesn't end up in the logs
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("peer_addr", &self.peer_addr)
+            .field("local_nodeid", &self.local_nodeid)
+            .field("peer_nodeid", &self.peer_nodeid)
+            .field("peer_cats", &self.peer_cats)
+            .field("dec_key", &REDACTED)
+            .field("enc_key", &REDACTED)
+            .field("att_challenge", &REDACTED)
+            .field("local_sess_id", &self.local_sess_id)
+            .field("peer_sess_id", &self.peer_sess_id)
+            .field("msg_ctr", &self.msg_ctr)
+            .field("mode", &self.mode)
+            .field("last_use", &self.last_use)
+            .finish()
+    }
+}
+
+impl fmt::Debug for CloneData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CloneData")
+            .field("dec_key", &REDACTED)
+            .field("enc_key", &REDACTED)
+            .field("att_challenge", &REDACTED)
+            .field("local_sess_id", &self.local_sess_id)
+            .field("peer_sess_id", &self.peer_sess_id)
+            .field("local_nodeid", &self.local_nodeid)
+            .field("peer_nodeid", &self.peer_nodeid)
+            .field("peer_cats", &self.peer_cats)
+            .field("peer_addr", &self.peer_addr)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+/// Placeholder for secrets in Debug output
+pub(crate) struct Redacted;
+
+pub(crate) const REDACTED: Redacted = Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
 const MATTER_MSG_CTR_RANGE: u32 = 0x0fffffff;
 
 impl Session {
@@ -97,6 +150,7 @@ impl Session {
             peer_addr,
             local_nodeid: 0,
             peer_nodeid,
+            peer_cats: Default::default(),
             dec_key: [0; MATTER_AES128_KEY_SIZE],
             enc_key: [0; MATTER_AES128_KEY_SIZE],
             att_challenge: [0; MATTER_AES128_KEY_SIZE],
@@ -115,6 +169,7 @@ impl Session {
             peer_addr: clone_from.peer_addr,
             local_nodeid: clone_from.local_nodeid,
             peer_nodeid: Some(clone_from.peer_nodeid),
+            peer_cats: clone_from.peer_cats,
             dec_key: clone_from.dec_key,
             enc_key: clone_from.enc_key,
             att_challenge: clone_from.att_challenge,
@@ -171,6 +226,11 @@ impl Session {
         self.peer_nodeid
     }
 
+    /// The CASE Authenticated Tags from the peer's NOC
+    pub fn get_peer_cat_ids(&self) -> &NocCatIds {
+        &self.peer_cats
+    }
+
     pub fn get_local_fabric_idx(&self) -> Option<u8> {
         match self.mode {
             SessionMode::Case(a) => Some(a),
@@ -202,10 +262,20 @@ impl Session {
         }
     }
 
+    #[deprecated(note = "use Session::attestation_challenge()")]
     pub fn get_att_challenge(&self) -> &[u8] {
         &self.att_challenge
     }
 
+    /// The attestation challenge of the session
+    ///
+    /// This is derived along with the session keys, when the PASE or CASE session is
+    /// established. The device signs its attestation and CSR responses over it, which
+    /// binds them to this session.
+    pub fn attestation_challenge(&self) -> &[u8; MATTER_AES128_KEY_SIZE] {
+        &self.att_challenge
+    }
+
     pub fn recv(&mut self, proto_rx: &mut Packet) -> Result<(), Error> {
         self.last_use = SystemTime::now();
         proto_rx.proto_decode(self.peer_nodeid.unwrap_or_default(), self.get_dec_key())
@@ -240,22 +310,16 @@ impl Session {
         let mut tmp_buf: [u8; plain_hdr::max_plain_hdr_len()] = [0; plain_hdr::max_plain_hdr_len()];
         let mut write_buf = WriteBuf::new(&mut tmp_buf[..], plain_hdr::max_plain_hdr_len());
         proto_tx.plain.encode(&mut write_buf)?;
-        let plain_hdr_bytes = write_buf.as_slice();
+        proto_tx.get_writebuf()?.prepend(write_buf.as_slice())?;
 
         trace!("unencrypted packet: {:x?}", proto_tx.as_borrow_slice());
         let ctr = proto_tx.plain.ctr;
         let enc_key = self.get_enc_key();
         if let Some(e) = enc_key {
-            proto_hdr::encrypt_in_place(
-                ctr,
-                self.local_nodeid,
-                plain_hdr_bytes,
-                proto_tx.get_writebuf()?,
-                e,
-            )?;
+            // The plain header, that is already in the packet, is the AAD
+            proto_hdr::encrypt_in_place(ctr, self.local_nodeid, proto_tx, e)?;
         }
 
-        proto_tx.get_writebuf()?.prepend(plain_hdr_bytes)?;
         trace!("Full encrypted packet: {:x?}", proto_tx.as_borrow_slice());
         Ok(())
     }
@@ -277,23 +341,52 @@ impl fmt::Display for Session {
     }
 }
 
+impl Session {
+    // A one line summary, for diagnostics. The key material isn't part of this
+    fn write_summary(&self, f: &mut dyn fmt::Write) -> fmt::Result {
+        write!(
+            f,
+            "sess_id: {}, peer_sess_id: {}, peer: {:?}, peer_nodeid: {:?}, mode: {:?}, fabric: {:?}",
+            self.local_sess_id,
+            self.peer_sess_id,
+            self.peer_addr,
+            self.peer_nodeid,
+            self.mode,
+            self.get_local_fabric_idx(),
+        )
+    }
+}
+
+/// The default number of sessions in a [SessionMgr]
 pub const MAX_SESSIONS: usize = 16;
-pub struct SessionMgr {
+
+/// The Session Manager, with the default capacity of [MAX_SESSIONS]
+pub type SessionMgr = SizedSessionMgr<MAX_SESSIONS>;
+
+/// The Session Manager, holding up to `N` sessions
+///
+/// Deployments that need more (or fewer) sessions can pick the capacity:
+/// ```
+/// use matter::transport::session::SizedSessionMgr;
+///
+/// let sess_mgr = SizedSessionMgr::<4>::new();
+/// ```
+pub struct SizedSessionMgr<const N: usize> {
     next_sess_id: u16,
-    sessions: [Option<Session>; MAX_SESSIONS],
+    sessions: [Option<Session>; N],
     network: Option<Box<dyn NetworkInterface>>,
 }
 
-impl Default for SessionMgr {
+impl<const N: usize> Default for SizedSessionMgr<N> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl SessionMgr {
-    pub fn new() -> SessionMgr {
-        SessionMgr {
-            sessions: Default::default(),
+impl<const N: usize> SizedSessionMgr<N> {
+    pub fn new() -> Self {
+        Self {
+            sessions: [(); N].map(|_| None),
             next_sess_id: 1,
             network: None,
         }
@@ -311,27 +404,23 @@ impl SessionMgr {
         }
     }
 
-    pub fn mut_by_index(&mut self, index: usize) -> Option<&mut Session> {
-        self.sessions[index].as_mut()
+    /// The maximum number of sessions
+    pub fn capacity(&self) -> usize {
+        N
     }
 
-    fn get_next_sess_id(&mut self) -> u16 {
-        let mut next_sess_id: u16;
-        loop {
-            next_sess_id = self.next_sess_id;
+    /// The number of sessions that are in use
+    pub fn in_use(&self) -> usize {
+        self.sessions.iter().filter(|s| s.is_some()).count()
+    }
 
-            // Increment next sess id
-            self.next_sess_id = self.next_sess_id.overflowing_add(1).0;
-            if self.next_sess_id == 0 {
-                self.next_sess_id = 1;
-            }
+    pub fn mut_by_index(&mut self, index: usize) -> Option<&mut Session> {
+        self.sessions[index].as_mut()
+    }
 
-            // Ensure the currently selected id doesn't match any existing session
-            if self.get_with_id(next_sess_id).is_none() {
-                break;
-            }
-        }
-        next_sess_id
+    /// Reserve a local session id that no session is using
+    pub fn get_next_sess_id(&mut self) -> u16 {
+        get_next_sess_id(&mut self.next_sess_id, &self.sessions)
     }
 
     fn get_empty_slot(&self) -> Option<usize> {
@@ -341,7 +430,7 @@ impl SessionMgr {
     pub fn get_lru(&mut self) -> usize {
         let mut lru_index = 0;
         let mut lru_ts = SystemTime::now();
-        for i in 0..MAX_SESSIONS {
+        for i in 0..N {
             if let Some(s) = &self.sessions[i] {
                 if s.last_use < lru_ts {
                     lru_ts = s.last_use;
@@ -404,11 +493,44 @@ impl SessionMgr {
         })
     }
 
+    pub fn get_index_on_fabric(&self, fab_idx: u8) -> Option<usize> {
+        self.sessions
+            .iter()
+            .position(|x| x.as_ref().and_then(|s| s.get_local_fabric_idx()) == Some(fab_idx))
+    }
+
+    /// The CASE session with the node 'peer_node_id' of the fabric at 'fab_idx', if any
+    pub fn get_index_with_peer(&self, fab_idx: u8, peer_node_id: u64) -> Option<usize> {
+        self.sessions.iter().position(|x| {
+            matches!(x, Some(s) if s.get_local_fabric_idx() == Some(fab_idx)
+                && s.peer_nodeid == Some(peer_node_id))
+        })
+    }
+
+    /// A summary of the active sessions, to help with debugging
+    pub fn summary(&self) -> String {
+        self.summary_with(|_, _| Ok(()))
+    }
+
+    // The summary of the active sessions, 'f' appends the details of the session at
+    // the given index
+    pub(crate) fn summary_with<F>(&self, mut f: F) -> String
+    where
+        F: FnMut(usize, &mut String) -> fmt::Result,
+    {
+        let mut out = String::new();
+        for (i, s) in self.sessions.iter().enumerate() {
+            if let Some(s) = s {
+                let _ = s.write_summary(&mut out);
+                out.push('\n');
+                let _ = f(i, &mut out);
+            }
+        }
+        out
+    }
+
     pub fn get_with_id(&mut self, sess_id: u16) -> Option<SessionHandle> {
-        let index = self
-            .sessions
-            .iter_mut()
-            .position(|x| x.as_ref().map(|s| s.local_sess_id) == Some(sess_id))?;
+        let index = get_index_with_id(&self.sessions, sess_id)?;
         Some(self.get_session_handle(index))
     }
 
@@ -448,12 +570,37 @@ impl SessionMgr {
         Ok(sess_index)
     }
 
-    pub fn recv(&mut self) -> Result<(BoxSlab<PacketPool>, Option<usize>), Error> {
+    pub fn recv(&mut self) -> Result<SessionRx, Error> {
         let mut rx = Slab::<PacketPool>::new(Packet::new_rx()?).ok_or(Error::PacketPoolExhaust)?;
 
         let network = self.network.as_ref().ok_or(Error::NoNetworkInterface)?;
 
         let (len, src) = network.recv(rx.as_borrow_slice())?;
+        self.post_network_recv(rx, len, src)
+    }
+
+    /// Like [SessionMgr::recv], but gives up with None if nothing arrives within 'timeout'
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<SessionRx>, Error> {
+        let mut rx = Slab::<PacketPool>::new(Packet::new_rx()?).ok_or(Error::PacketPoolExhaust)?;
+
+        let network = self.network.as_ref().ok_or(Error::NoNetworkInterface)?;
+
+        match network.recv_timeout(rx.as_borrow_slice(), timeout)? {
+            Some((len, src)) => self.post_network_recv(rx, len, src).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn post_network_recv(
+        &mut self,
+        mut rx: BoxSlab<PacketPool>,
+        len: usize,
+        src: Address,
+    ) -> Result<SessionRx, Error> {
+        if len > MAX_RX_BUF_SIZE {
+            error!("Dropping oversized datagram of len {} from {}", len, src);
+            return Err(Error::TruncatedPacket);
+        }
         rx.get_parsebuf()?.set_len(len);
         rx.peer = src;
 
@@ -473,27 +620,45 @@ impl SessionMgr {
         sess_idx: usize,
         mut proto_tx: BoxSlab<PacketPool>,
     ) -> Result<(), Error> {
-        self.sessions[sess_idx]
-            .as_mut()
-            .ok_or(Error::NoSession)?
-            .do_send(&mut proto_tx)?;
-
-        let network = self.network.as_ref().ok_or(Error::NoNetworkInterface)?;
-        let peer = proto_tx.peer;
-        network.send(proto_tx.as_borrow_slice(), peer)?;
-        println!("Message Sent to {}", peer);
-        Ok(())
+        self.get_session_handle(sess_idx).send(&mut proto_tx)
     }
 
     pub fn get_session_handle(&mut self, sess_idx: usize) -> SessionHandle {
         SessionHandle {
-            sess_mgr: self,
+            sessions: &mut self.sessions,
+            next_sess_id: &mut self.next_sess_id,
+            network: self.network.as_deref(),
             sess_idx,
         }
     }
 }
 
-impl fmt::Display for SessionMgr {
+fn get_next_sess_id(next_sess_id: &mut u16, sessions: &[Option<Session>]) -> u16 {
+    let mut sess_id: u16;
+    loop {
+        sess_id = *next_sess_id;
+
+        // Increment next sess id
+        *next_sess_id = next_sess_id.overflowing_add(1).0;
+        if *next_sess_id == 0 {
+            *next_sess_id = 1;
+        }
+
+        // Ensure the currently selected id doesn't match any existing session
+        if get_index_with_id(sessions, sess_id).is_none() {
+            break;
+        }
+    }
+    sess_id
+}
+
+fn get_index_with_id(sessions: &[Option<Session>], sess_id: u16) -> Option<usize> {
+    sessions
+        .iter()
+        .position(|x| x.as_ref().map(|s| s.local_sess_id) == Some(sess_id))
+}
+
+impl<const N: usize> fmt::Display for SizedSessionMgr<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{{[")?;
         for s in self.sessions.iter().flatten() {
@@ -504,18 +669,47 @@ impl fmt::Display for SessionMgr {
     }
 }
 
+/// A handle to a session in the [SessionMgr]
+///
+/// This borrows the parts of the Session Manager that it needs, so that it doesn't depend on
+/// the Session Manager's capacity
 pub struct SessionHandle<'a> {
-    sess_mgr: &'a mut SessionMgr,
+    sessions: &'a mut [Option<Session>],
+    next_sess_id: &'a mut u16,
+    network: Option<&'a dyn NetworkInterface>,
     sess_idx: usize,
 }
 
 impl<'a> SessionHandle<'a> {
     pub fn reserve_new_sess_id(&mut self) -> u16 {
-        self.sess_mgr.get_next_sess_id()
+        get_next_sess_id(self.next_sess_id, self.sessions)
     }
 
-    pub fn send(&mut self, proto_tx: BoxSlab<PacketPool>) -> Result<(), Error> {
-        self.sess_mgr.send(self.sess_idx, proto_tx)
+    pub fn send(&mut self, proto_tx: &mut Packet) -> Result<(), Error> {
+        self.sessions[self.sess_idx]
+            .as_mut()
+            .ok_or(Error::NoSession)?
+            .do_send(proto_tx)?;
+
+        let network = self.network.ok_or(Error::NoNetworkInterface)?;
+        let peer = proto_tx.peer;
+        network.send(proto_tx.as_borrow_slice(), peer)?;
+        trace!("Message Sent to {}", peer);
+        Ok(())
+    }
+
+    /// Send an already encoded message again, as is
+    pub fn resend(&mut self, raw: &[u8]) -> Result<(), Error> {
+        let session = self.sessions[self.sess_idx]
+            .as_mut()
+            .ok_or(Error::NoSession)?;
+        session.last_use = SystemTime::now();
+        let peer = session.peer_addr;
+
+        let network = self.network.ok_or(Error::NoNetworkInterface)?;
+        network.send(raw, peer)?;
+        trace!("Message Resent to {}", peer);
+        Ok(())
     }
 }
 
@@ -523,23 +717,47 @@ impl<'a> Deref for SessionHandle<'a> {
     type Target = Session;
     fn deref(&self) -> &Self::Target {
         // There is no other option but to panic if this is None
-        self.sess_mgr.sessions[self.sess_idx].as_ref().unwrap()
+        self.sessions[self.sess_idx].as_ref().unwrap()
     }
 }
 
 impl<'a> DerefMut for SessionHandle<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         // There is no other option but to panic if this is None
-        self.sess_mgr.sessions[self.sess_idx].as_mut().unwrap()
+        self.sessions[self.sess_idx].as_mut().unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::transport::network::Address;
+    use crate::{
+        crypto::{self, KeyPair},
+        data_model::sdm::dev_att::{self, DevAttDataFetcher, HardCodedDevAtt},
+        error::Error,
+        transport::{
+            network::{Address, NetworkInterface},
+            packet::MAX_RX_BUF_SIZE,
+        },
+    };
+
+    use super::{CloneData, Session, SessionMgr, SessionMode, SizedSessionMgr};
+
+    // A network interface that always receives a datagram of the given length
+    struct DummyNetwork {
+        rx_len: usize,
+    }
+
+    impl NetworkInterface for DummyNetwork {
+        fn recv(&self, in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+            let len = usize::min(self.rx_len, in_buf.len());
+            Ok((len, Address::default()))
+        }
 
-    use super::SessionMgr;
+        fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+            Ok(out_buf.len())
+        }
+    }
 
     #[test]
     fn test_next_sess_id_doesnt_reuse() {
@@ -567,4 +785,124 @@ mod tests {
         assert_eq!(sm.get_next_sess_id(), 65535);
         assert_eq!(sm.get_next_sess_id(), 2);
     }
+
+    #[test]
+    fn test_in_use() {
+        let mut sm = SizedSessionMgr::<4>::new();
+        assert_eq!(sm.capacity(), 4);
+        assert_eq!(sm.in_use(), 0);
+
+        let first = sm.add(Address::default(), None).unwrap();
+        let clone_data = CloneData::new(1, 2, 3, 4, Address::default(), SessionMode::Pase);
+        let second = sm.clone_session(&clone_data).unwrap();
+        assert_eq!(sm.in_use(), 2);
+
+        sm.remove(first);
+        assert_eq!(sm.in_use(), 1);
+        // The free slot is taken again
+        assert_eq!(sm.add(Address::default(), None).unwrap(), first);
+        assert_eq!(sm.in_use(), 2);
+        sm.remove(second);
+        sm.remove(first);
+        assert_eq!(sm.in_use(), 0);
+    }
+
+    #[test]
+    fn test_oversized_datagram_rejected() {
+        let mut sm = SessionMgr::new();
+        sm.add_network_interface(Box::new(DummyNetwork {
+            rx_len: MAX_RX_BUF_SIZE + 1,
+        }))
+        .unwrap();
+        assert_eq!(sm.recv().err(), Some(Error::TruncatedPacket));
+    }
+
+    #[test]
+    fn test_debug_redacts_keys() {
+        let mut clone_data = CloneData::new(1, 2, 3, 4, Address::default(), SessionMode::Case(1));
+        clone_data.dec_key = [0xa1; 16];
+        clone_data.enc_key = [0xb2; 16];
+        clone_data.att_challenge = [0xc3; 16];
+        let sess = Session::clone(&clone_data);
+
+        for dbg in [format!("{:?}", clone_data), format!("{:?}", sess)] {
+            assert!(dbg.contains("enc_key: <redacted>"));
+            for key in [
+                clone_data.dec_key,
+                clone_data.enc_key,
+                clone_data.att_challenge,
+            ] {
+                assert!(!dbg.contains(&format!("{:?}", key)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_capacity() {
+        let mut sm = SizedSessionMgr::<2>::new();
+        sm.add(Address::default(), None).unwrap();
+        sm.add(Address::default(), None).unwrap();
+        assert_eq!(sm.add(Address::default(), None), Err(Error::NoSpace));
+
+        // Once a session is evicted, there is space again
+        let evict_index = sm.get_lru();
+        sm.remove(evict_index);
+        assert_eq!(sm.add(Address::default(), None), Ok(evict_index));
+    }
+
+    #[test]
+    fn test_attestation_challenge() {
+        let mut sm = SizedSessionMgr::<2>::new();
+        let mut clone_data = CloneData::new(1, 2, 3, 4, Address::default(), SessionMode::Pase);
+        clone_data.att_challenge = [0xc3; 16];
+        let idx = sm.clone_session(&clone_data).unwrap();
+        let mut clone_data = CloneData::new(1, 2, 5, 6, Address::default(), SessionMode::Pase);
+        clone_data.att_challenge = [0x3c; 16];
+        let other_idx = sm.clone_session(&clone_data).unwrap();
+
+        // The challenge stays the same for as long as the session is around
+        let challenge = *sm.get_session_handle(idx).attestation_challenge();
+        assert_eq!(challenge, [0xc3; 16]);
+        assert_eq!(
+            sm.get_session_handle(idx).attestation_challenge(),
+            &challenge
+        );
+
+        // A response signed over the challenge verifies only for this session
+        let dev_att = HardCodedDevAtt {
+            dac: vec![],
+            pai: vec![],
+            cd: vec![],
+            dac_key: KeyPair::new().unwrap(),
+        };
+        let attest_elements = [0x15, 0x30, 0x01, 0x01, 0xaa, 0x18];
+        let mut signature = [0; crypto::EC_SIGNATURE_LEN_BYTES];
+        dev_att
+            .sign_attestation(&attest_elements, &challenge, &mut signature)
+            .unwrap();
+        let mut dac_pubkey = [0; crypto::EC_POINT_LEN_BYTES];
+        let len = dev_att
+            .get_devatt_data(dev_att::DataType::DACPubKey, &mut dac_pubkey)
+            .unwrap();
+        let dac_pubkey = &dac_pubkey[..len];
+
+        let session = sm.get_session_handle(idx);
+        assert_eq!(
+            dev_att::verify_attestation(
+                dac_pubkey,
+                &attest_elements,
+                session.attestation_challenge(),
+                &signature
+            ),
+            Ok(())
+        );
+        let other = sm.get_session_handle(other_idx);
+        assert!(dev_att::verify_attestation(
+            dac_pubkey,
+            &attest_elements,
+            other.attestation_challenge(),
+            &signature
+        )
+        .is_err());
+    }
 }
@@ -1,5 +1,8 @@
 use crate::error::*;
+use log::error;
 use smol::net::{Ipv6Addr, UdpSocket};
+use smol::Timer;
+use std::time::Duration;
 
 use super::network::{Address, NetworkInterface};
 
@@ -9,9 +12,6 @@ pub struct UdpListener {
     socket: UdpSocket,
 }
 
-// Currently matches with the one in connectedhomeip repo
-pub const MAX_RX_BUF_SIZE: usize = 1583;
-
 /* The Matter Port */
 const MATTER_PORT: u16 = 5540;
 
@@ -26,12 +26,32 @@ impl UdpListener {
 impl NetworkInterface for UdpListener {
     fn recv(&self, in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
         let (size, addr) = smol::block_on(self.socket.recv_from(in_buf)).map_err(|e| {
-            println!("Error on the network: {:?}", e);
+            error!("Error on the network: {:?}", e);
             Error::Network
         })?;
         Ok((size, Address::Udp(addr)))
     }
 
+    fn recv_timeout(
+        &self,
+        in_buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<Option<(usize, Address)>, Error> {
+        let recv = async { Some(self.socket.recv_from(in_buf).await) };
+        let timer = async {
+            Timer::after(timeout).await;
+            None
+        };
+        match smol::block_on(smol::future::or(recv, timer)) {
+            Some(Ok((size, addr))) => Ok(Some((size, Address::Udp(addr)))),
+            Some(Err(e)) => {
+                error!("Error on the network: {:?}", e);
+                Err(Error::Network)
+            }
+            None => Ok(None),
+        }
+    }
+
     fn send(&self, out_buf: &[u8], addr: Address) -> Result<usize, Error> {
         match addr {
             Address::Udp(addr) => Ok(smol::block_on(self.socket.send_to(out_buf, addr))?),
@@ -1,2 +1,4 @@
 pub mod parsebuf;
+pub mod rand;
+pub mod tick;
 pub mod writebuf;
@@ -30,10 +30,21 @@ impl<'a> ParseBuf<'a> {
         &mut self.buf[self.read_off..(self.read_off + self.left)]
     }
 
+    // The number of bytes that are yet to be parsed
+    pub fn remaining(&self) -> usize {
+        self.left
+    }
+
     pub fn parsed_as_slice(&self) -> &[u8] {
         &self.buf[0..self.read_off]
     }
 
+    // Return the data that is already parsed, along with the data that is yet to be parsed
+    pub fn split_parsed_mut(&mut self) -> (&[u8], &mut [u8]) {
+        let (parsed, rest) = self.buf.split_at_mut(self.read_off);
+        (parsed, &mut rest[..self.left])
+    }
+
     pub fn tail(&mut self, size: usize) -> Result<&[u8], Error> {
         if size <= self.left {
             let end_offset = self.read_off + self.left;
@@ -61,6 +72,24 @@ impl<'a> ParseBuf<'a> {
         Err(Error::TruncatedPacket)
     }
 
+    // Consume the next 'size' bytes
+    pub fn slice(&mut self, size: usize) -> Result<&[u8], Error> {
+        if size <= self.left {
+            let start = self.read_off;
+            self.advance(size);
+            return Ok(&self.buf[start..(start + size)]);
+        }
+        Err(Error::TruncatedPacket)
+    }
+
+    // Return the next byte without consuming it
+    pub fn peek_u8(&self) -> Result<u8, Error> {
+        if self.left >= 1 {
+            return Ok(self.buf[self.read_off]);
+        }
+        Err(Error::TruncatedPacket)
+    }
+
     pub fn le_u8(&mut self) -> Result<u8, Error> {
         self.parse_head_with(1, |x| x.buf[x.read_off])
     }
@@ -151,6 +180,41 @@ mod tests {
         assert_eq!(buf.tail(2).unwrap(), [0xc, 0xd]);
     }
 
+    #[test]
+    fn test_peek_and_remaining() {
+        let mut test_slice: [u8; 3] = [0x01, 0x02, 0x03];
+        let mut buf = ParseBuf::new(&mut test_slice, 3);
+
+        assert_eq!(buf.remaining(), 3);
+        assert_eq!(buf.peek_u8().unwrap(), 0x01);
+        assert_eq!(buf.peek_u8().unwrap(), 0x01);
+        assert_eq!(buf.remaining(), 3);
+        assert_eq!(buf.le_u8().unwrap(), 0x01);
+        assert_eq!(buf.peek_u8().unwrap(), 0x02);
+        assert_eq!(buf.remaining(), 2);
+
+        assert_eq!(buf.tail(1).unwrap(), [0x03]);
+        assert_eq!(buf.remaining(), 1);
+        assert_eq!(buf.le_u8().unwrap(), 0x02);
+        assert_eq!(buf.remaining(), 0);
+        assert_eq!(buf.peek_u8(), Err(Error::TruncatedPacket));
+    }
+
+    #[test]
+    fn test_slice() {
+        let mut test_slice: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut buf = ParseBuf::new(&mut test_slice, 5);
+
+        assert_eq!(buf.slice(2).unwrap(), [0x01, 0x02]);
+        assert_eq!(buf.remaining(), 3);
+        assert_eq!(buf.slice(4), Err(Error::TruncatedPacket));
+        // A failed slice doesn't consume anything
+        assert_eq!(buf.remaining(), 3);
+        assert_eq!(buf.slice(3).unwrap(), [0x03, 0x04, 0x05]);
+        assert_eq!(buf.slice(0).unwrap(), []);
+        assert_eq!(buf.parsed_as_slice(), [0x01, 0x02, 0x03, 0x04, 0x05]);
+    }
+
     #[test]
     fn test_parsed_as_slice() {
         let mut test_slice: [u8; 11] = [0x01, 65, 0, 0xbe, 0xba, 0xfe, 0xca, 0xa, 0xb, 0xc, 0xd];
@@ -0,0 +1,20 @@
+use rand::RngCore;
+
+/// A source of random bytes
+///
+/// The nodes use the thread-local RNG of the `rand` crate, tests can plug in a deterministic
+/// source to get reproducible handshakes.
+pub trait RandomSource: Send {
+    /// Fill 'buf' with random bytes
+    fn fill(&mut self, buf: &mut [u8]);
+}
+
+/// The thread-local RNG of the `rand` crate
+#[derive(Default)]
+pub struct ThreadRandom;
+
+impl RandomSource for ThreadRandom {
+    fn fill(&mut self, buf: &mut [u8]) {
+        rand::thread_rng().fill_bytes(buf);
+    }
+}
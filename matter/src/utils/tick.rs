@@ -0,0 +1,103 @@
+use std::time::SystemTime;
+
+/// A subsystem that has to be serviced from time to time, like the retransmissions of
+/// the Message Reliability Protocol, or the expiry of the Fail-Safe
+pub trait Tick {
+    /// Service whatever is due at 'now'
+    ///
+    /// Returns the time by which the subsystem next needs to be serviced, or None if there
+    /// is nothing pending.
+    fn tick(&mut self, now: SystemTime) -> Option<SystemTime>;
+}
+
+/// The earlier of the two deadlines, if any
+pub fn earliest(a: Option<SystemTime>, b: Option<SystemTime>) -> Option<SystemTime> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Services a set of subsystems together, from a single event loop
+#[derive(Default)]
+pub struct Ticker {
+    subsystems: Vec<Box<dyn Tick>>,
+}
+
+impl Ticker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Service 'subsystem' on every tick from now on
+    pub fn register(&mut self, subsystem: Box<dyn Tick>) {
+        self.subsystems.push(subsystem);
+    }
+
+    /// Service all the subsystems at 'now'
+    ///
+    /// Returns the earliest time by which any of the subsystems next needs to be serviced.
+    /// The event loop can sleep until then, unless something else wakes it up.
+    pub fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+        self.subsystems
+            .iter_mut()
+            .fold(None, |next, s| earliest(next, s.tick(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{earliest, Tick, Ticker};
+
+    // Due every 'period', from 'next'
+    struct Periodic {
+        next: SystemTime,
+        period: Duration,
+    }
+
+    impl Tick for Periodic {
+        fn tick(&mut self, now: SystemTime) -> Option<SystemTime> {
+            while self.next <= now {
+                self.next += self.period;
+            }
+            Some(self.next)
+        }
+    }
+
+    #[test]
+    fn test_earliest() {
+        let t = SystemTime::UNIX_EPOCH;
+        let later = t + Duration::from_secs(1);
+        assert_eq!(earliest(Some(later), Some(t)), Some(t));
+        assert_eq!(earliest(None, Some(t)), Some(t));
+        assert_eq!(earliest(Some(t), None), Some(t));
+        assert_eq!(earliest(None, None), None);
+    }
+
+    #[test]
+    fn test_ticker_empty() {
+        let mut ticker = Ticker::new();
+        assert_eq!(ticker.tick(SystemTime::now()), None);
+    }
+
+    #[test]
+    fn test_ticker_earliest() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut ticker = Ticker::new();
+        for secs in [3, 2] {
+            ticker.register(Box::new(Periodic {
+                next: start + Duration::from_secs(secs),
+                period: Duration::from_secs(secs),
+            }));
+        }
+        assert_eq!(ticker.tick(start), Some(start + Duration::from_secs(2)));
+        // Both are serviced, the one with the shorter period is due again first
+        assert_eq!(
+            ticker.tick(start + Duration::from_secs(3)),
+            Some(start + Duration::from_secs(4))
+        );
+    }
+}
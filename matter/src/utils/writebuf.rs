@@ -1,11 +1,24 @@
 use crate::error::*;
 use byteorder::{ByteOrder, LittleEndian};
 
+/// A placeholder in a WriteBuf, that is filled once its contents are known
+///
+/// This is typically used for length fields that precede a body of unknown size.
+#[derive(Debug)]
+pub struct Reservation {
+    offset: usize,
+    len: usize,
+}
+
 #[derive(Debug)]
 pub struct WriteBuf<'a> {
     buf: &'a mut [u8],
     start: usize,
     end: usize,
+    // The end of the space that appends can use, see shrink()
+    limit: usize,
+    // Whether an append ran out of space, see take_overflow()
+    overflow: bool,
 }
 
 impl<'a> WriteBuf<'a> {
@@ -14,6 +27,8 @@ impl<'a> WriteBuf<'a> {
             buf: &mut buf[..len],
             start: 0,
             end: 0,
+            limit: len,
+            overflow: false,
         }
     }
 
@@ -29,6 +44,31 @@ impl<'a> WriteBuf<'a> {
         self.end += new_offset
     }
 
+    /// Hold back 'len' bytes at the end of the buffer, that appends can't use
+    ///
+    /// This keeps room for what is to be written after the data that follows. The bytes
+    /// are given back with expand().
+    pub fn shrink(&mut self, len: usize) -> Result<(), Error> {
+        if self.end + len > self.limit {
+            return Err(Error::NoSpace);
+        }
+        self.limit -= len;
+        Ok(())
+    }
+
+    /// Give back 'len' bytes that were held back with shrink()
+    pub fn expand(&mut self, len: usize) {
+        self.limit = usize::min(self.limit + len, self.buf.len());
+    }
+
+    /// Whether an append ran out of space since the last call
+    ///
+    /// This catches the appends whose errors were dropped, by code that encodes into the
+    /// buffer without returning a Result.
+    pub fn take_overflow(&mut self) -> bool {
+        std::mem::take(&mut self.overflow)
+    }
+
     pub fn as_borrow_slice(&self) -> &[u8] {
         &self.buf[self.start..self.end]
     }
@@ -41,8 +81,17 @@ impl<'a> WriteBuf<'a> {
         &mut self.buf[self.start..self.end]
     }
 
+    /// Split the data at 'mid', into the head that is read, and the rest that is written
+    pub fn split_at_mut(&mut self, mid: usize) -> Result<(&[u8], &mut [u8]), Error> {
+        if mid > self.end - self.start {
+            return Err(Error::NoSpace);
+        }
+        let (head, rest) = self.as_mut_slice().split_at_mut(mid);
+        Ok((head, rest))
+    }
+
     pub fn empty_as_mut_slice(&mut self) -> &mut [u8] {
-        &mut self.buf[self.end..]
+        &mut self.buf[self.end..self.limit]
     }
 
     pub fn reset(&mut self, reserve: usize) {
@@ -58,6 +107,39 @@ impl<'a> WriteBuf<'a> {
         Ok(())
     }
 
+    /// Reserve 'len' bytes at the tail, to be filled later with fill_reservation()
+    ///
+    /// The reservation is invalid once the tail is rewound before it.
+    pub fn reserve_tail(&mut self, len: usize) -> Result<Reservation, Error> {
+        let offset = self.end;
+        self.append_with(len, |x| x.buf[x.end..(x.end + len)].fill(0))?;
+        Ok(Reservation { offset, len })
+    }
+
+    /// Write 'src' at the reservation, 'src' must be exactly as long as the reservation
+    pub fn fill_reservation(&mut self, r: Reservation, src: &[u8]) -> Result<(), Error> {
+        if src.len() != r.len || r.offset + r.len > self.end {
+            return Err(Error::Invalid);
+        }
+        self.buf[r.offset..(r.offset + r.len)].copy_from_slice(src);
+        Ok(())
+    }
+
+    /// Shrink the reservation to 'len' bytes, moving everything written after it
+    /// back by as much
+    pub fn shrink_reservation(&mut self, r: Reservation, len: usize) -> Result<Reservation, Error> {
+        if len > r.len || r.offset + r.len > self.end {
+            return Err(Error::Invalid);
+        }
+        self.buf
+            .copy_within((r.offset + r.len)..self.end, r.offset + len);
+        self.end -= r.len - len;
+        Ok(Reservation {
+            offset: r.offset,
+            len,
+        })
+    }
+
     pub fn prepend_with<F>(&mut self, size: usize, f: F) -> Result<(), Error>
     where
         F: FnOnce(&mut Self),
@@ -81,11 +163,12 @@ impl<'a> WriteBuf<'a> {
     where
         F: FnOnce(&mut Self),
     {
-        if self.end + size <= self.buf.len() {
+        if self.end + size <= self.limit {
             f(self);
             self.end += size;
             return Ok(());
         }
+        self.overflow = true;
         Err(Error::NoSpace)
     }
 
@@ -136,6 +219,7 @@ impl<'a> WriteBuf<'a> {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::utils::writebuf::*;
 
     #[test]
@@ -306,4 +390,63 @@ mod tests {
         buf.le_u16(66).unwrap();
         assert_eq!(buf.as_borrow_slice(), [65, 0, 66, 0,]);
     }
+
+    #[test]
+    fn test_fill_reservation() {
+        let mut test_slice: [u8; 20] = [0; 20];
+        let mut buf = WriteBuf::new(&mut test_slice, 20);
+        buf.reserve(5).unwrap();
+
+        buf.le_u8(1).unwrap();
+        let len = buf.reserve_tail(2).unwrap();
+        let body_start = buf.get_tail();
+        let body: [u8; 5] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee];
+        buf.copy_from_slice(&body).unwrap();
+        let body_len = (buf.get_tail() - body_start) as u16;
+        buf.fill_reservation(len, &body_len.to_le_bytes()).unwrap();
+        assert_eq!(
+            buf.as_borrow_slice(),
+            [1, 5, 0, 0xaa, 0xbb, 0xcc, 0xdd, 0xee]
+        );
+
+        // Only exactly as many bytes as were reserved can be filled
+        let r = buf.reserve_tail(2).unwrap();
+        assert_eq!(buf.fill_reservation(r, &[1, 2, 3]), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn test_shrink_reservation() {
+        let mut test_slice: [u8; 20] = [0; 20];
+        let mut buf = WriteBuf::new(&mut test_slice, 20);
+
+        let r = buf.reserve_tail(3).unwrap();
+        buf.le_u16(65).unwrap();
+        let r = buf.shrink_reservation(r, 1).unwrap();
+        buf.fill_reservation(r, &[2]).unwrap();
+        buf.le_u8(66).unwrap();
+        assert_eq!(buf.as_borrow_slice(), [2, 65, 0, 66]);
+    }
+
+    #[test]
+    fn test_shrink_expand() {
+        let mut test_slice: [u8; 8] = [0; 8];
+        let mut buf = WriteBuf::new(&mut test_slice, 8);
+
+        buf.le_u16(65).unwrap();
+        buf.shrink(4).unwrap();
+        // Only the 2 bytes that aren't held back can be used
+        assert_eq!(buf.le_u32(66), Err(Error::NoSpace));
+        buf.le_u16(67).unwrap();
+        assert_eq!(buf.le_u8(68), Err(Error::NoSpace));
+        // More than what is left can't be held back
+        assert_eq!(buf.shrink(1), Err(Error::NoSpace));
+
+        assert!(buf.take_overflow());
+        assert!(!buf.take_overflow());
+
+        buf.expand(4);
+        buf.le_u32(69).unwrap();
+        assert!(!buf.take_overflow());
+        assert_eq!(buf.as_borrow_slice(), [65, 0, 67, 0, 69, 0, 0, 0]);
+    }
 }
@@ -5,6 +5,12 @@ use matter::{
 
 /// Assert that the data received in the outbuf matches our expectations
 pub fn assert_attr_report(out_buf: &[u8], expected: &[AttrResp]) {
+    assert_attr_report_chunk(out_buf, expected, false)
+}
+
+/// Assert that the data received in the outbuf matches our expectations, for a report
+/// that may be followed by more chunks
+pub fn assert_attr_report_chunk(out_buf: &[u8], expected: &[AttrResp], more_chunks: bool) {
     tlv::print_tlv_list(out_buf);
     let root = tlv::get_root_node_struct(out_buf).unwrap();
 
@@ -42,6 +48,19 @@ pub fn assert_attr_report(out_buf: &[u8], expected: &[AttrResp]) {
         index += 1;
     }
     assert_eq!(index, expected.len());
+
+    // Only the last chunk of a read report doesn't expect a StatusResponse from the peer
+    let supress_response = root
+        .find_tag(msg::ReportDataTag::SupressResponse as u32)
+        .unwrap()
+        .bool()
+        .unwrap();
+    assert_eq!(supress_response, !more_chunks);
+    let more_chunked_msgs = match root.find_tag(msg::ReportDataTag::MoreChunkedMsgs as u32) {
+        Ok(t) => t.bool().unwrap(),
+        Err(_) => false,
+    };
+    assert_eq!(more_chunked_msgs, more_chunks);
 }
 
 // We have to hard-code this here, and it should match the tag
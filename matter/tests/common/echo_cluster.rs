@@ -1,12 +1,18 @@
 use matter::{
+    data_model::cluster_on_off,
     data_model::objects::{
-        Access, AttrValue, Attribute, Cluster, ClusterType, EncodeValue, Encoder, Quality,
+        Access, AttrDetails, AttrValue, Attribute, Cluster, ClusterType, EncodeValue, Encoder,
+        Quality,
     },
     error::Error,
-    interaction_model::{command::CommandReq, core::IMStatusCode, messages::ib},
-    tlv::{TLVWriter, TagType, ToTLV},
+    interaction_model::{
+        command::{CommandReq, CommandStatus},
+        core::IMStatusCode,
+    },
+    tlv::{TLVWriter, TagType},
 };
 use num_derive::FromPrimitive;
+use std::{thread, time::Duration};
 
 pub const ID: u32 = 0xABCD;
 
@@ -14,6 +20,8 @@ pub const ID: u32 = 0xABCD;
 pub enum Commands {
     EchoReq = 0x00,
     EchoResp = 0x01,
+    DeferredEchoReq = 0x02,
+    EchoOnOffReq = 0x03,
 }
 
 pub struct EchoCluster {
@@ -41,8 +49,8 @@ impl ClusterType for EchoCluster {
         &mut self.base
     }
 
-    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr_id: u16) {
-        match num::FromPrimitive::from_u16(attr_id) {
+    fn read_custom_attribute(&self, encoder: &mut dyn Encoder, attr: &AttrDetails) {
+        match num::FromPrimitive::from_u16(attr.attr_id) {
             Some(Attributes::AttCustom) => encoder.encode(EncodeValue::Closure(&|tag, tw| {
                 let _ = tw.u32(tag, ATTR_CUSTOM_VALUE);
             })),
@@ -50,7 +58,7 @@ impl ClusterType for EchoCluster {
         }
     }
 
-    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<(), IMStatusCode> {
+    fn handle_command(&mut self, cmd_req: &mut CommandReq) -> Result<CommandStatus, IMStatusCode> {
         let cmd = cmd_req
             .cmd
             .path
@@ -63,9 +71,6 @@ impl ClusterType for EchoCluster {
             // with data multiplied by the multiplier
             Commands::EchoReq => {
                 let a = cmd_req.data.u8().unwrap();
-                let mut echo_response = cmd_req.cmd;
-                echo_response.path.leaf = Some(Commands::EchoResp as u32);
-
                 let cmd_data = |tag: TagType, t: &mut TLVWriter| {
                     let _ = t.start_struct(tag);
                     // Echo = input * self.multiplier
@@ -73,18 +78,61 @@ impl ClusterType for EchoCluster {
                     let _ = t.end_container();
                 };
 
-                let invoke_resp = ib::InvResp::Cmd(ib::CmdData::new(
-                    echo_response,
+                let _ = cmd_req.respond(
+                    &self.base,
+                    Commands::EchoResp as u16,
+                    EncodeValue::Closure(&cmd_data),
+                );
+                cmd_req.trans.complete();
+            }
+            // The same as EchoReq, but the response is sent from another thread,
+            // after the handler has returned
+            Commands::DeferredEchoReq => {
+                let a = cmd_req.data.u8().unwrap();
+                let echo = a * self.multiplier;
+                let deferred = cmd_req.defer().map_err(|_| IMStatusCode::Failure)?;
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    let cmd_data = |tag: TagType, t: &mut TLVWriter| {
+                        let _ = t.start_struct(tag);
+                        let _ = t.u8(TagType::Context(0), echo);
+                        let _ = t.end_container();
+                    };
+                    let _ = deferred
+                        .respond(Commands::EchoResp as u16, EncodeValue::Closure(&cmd_data));
+                });
+                return Ok(CommandStatus::Deferred);
+            }
+            // Echo the OnOff attribute of the On/Off cluster of the same endpoint
+            Commands::EchoOnOffReq => {
+                let endpoint = cmd_req.cmd.path.endpoint.ok_or(IMStatusCode::Failure)?;
+                let on_off = cmd_req
+                    .node
+                    .get_cluster(endpoint, cluster_on_off::ID)
+                    .map_err(|_| IMStatusCode::UnsupportedCluster)?
+                    .base()
+                    .read_attribute_raw(cluster_on_off::Attributes::OnOff as u16)?;
+                let on_off = match on_off {
+                    AttrValue::Bool(b) => *b as u8,
+                    _ => return Err(IMStatusCode::Failure),
+                };
+                let cmd_data = |tag: TagType, t: &mut TLVWriter| {
+                    let _ = t.start_struct(tag);
+                    let _ = t.u8(TagType::Context(0), on_off);
+                    let _ = t.end_container();
+                };
+                let _ = cmd_req.respond(
+                    &self.base,
+                    Commands::EchoResp as u16,
                     EncodeValue::Closure(&cmd_data),
-                ));
-                let _ = invoke_resp.to_tlv(cmd_req.resp, TagType::Anonymous);
+                );
                 cmd_req.trans.complete();
             }
             _ => {
                 return Err(IMStatusCode::UnsupportedCommand);
             }
         }
-        Ok(())
+        Ok(CommandStatus::Complete)
     }
 }
 
@@ -94,6 +142,8 @@ impl EchoCluster {
             base: Cluster::new(ID)?,
             multiplier,
         });
+        c.base
+            .set_generated_commands(&[Commands::EchoResp as u16])?;
         c.base.add_attribute(Attribute::new(
             Attributes::Att1 as u16,
             AttrValue::Uint16(0x1234),
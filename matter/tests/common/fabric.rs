@@ -0,0 +1,18 @@
+use matter::{
+    cert::{test_vectors, Cert},
+    crypto::KeyPair,
+    fabric::Fabric,
+};
+
+// A fabric for the Node ID 0xbc5c02 on the Fabric ID 1, with the given VendorID
+pub fn test_fabric(vendor_id: u16) -> Fabric {
+    Fabric::new(
+        KeyPair::new().unwrap(),
+        Cert::new(&test_vectors::RCA1_SUCCESS).unwrap(),
+        Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+        Cert::new(&test_vectors::NOC1_SUCCESS).unwrap(),
+        &[0x5a; 16],
+        vendor_id,
+    )
+    .unwrap()
+}
@@ -1,20 +1,25 @@
-use crate::common::echo_cluster;
+use crate::common::{echo_cluster, many_attrs_cluster::ManyAttrsCluster};
 use boxslab::Slab;
 use matter::{
     acl::{AclEntry, AclMgr, AuthMode},
     data_model::{
-        cluster_basic_information::BasicInfoConfig,
         core::DataModel,
         device_types::device_type_add_on_off_light,
-        objects::Privilege,
-        sdm::dev_att::{DataType, DevAttDataFetcher},
+        objects::{AttrValue, EncodeValue, Privilege, CLUSTERS_PER_ENDPT},
     },
     error::Error,
     fabric::FabricMgr,
-    interaction_model::{core::OpCode, messages::ib::CmdPath, messages::msg, InteractionModel},
-    tlv::{TLVWriter, TagType, ToTLV},
-    transport::packet::Packet,
-    transport::proto_demux::HandleProto,
+    interaction_model::{
+        core::OpCode,
+        messages::ib::{AttrData, AttrPath, AttrResp, CmdPath},
+        messages::msg::{self, ReadReq, WriteReq},
+        messages::GenericPath,
+        InteractionModel, Transaction,
+    },
+    test_utils::MatterNodeBuilder,
+    tlv::{self, TLVWriter, TagType, ToTLV},
+    transport::packet::{Packet, MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+    transport::proto_demux::{HandleProto, ResponseRequired},
     transport::{
         exchange::{self, Exchange, ExchangeCtx},
         network::Address,
@@ -27,20 +32,17 @@ use matter::{
 use std::{
     net::{Ipv4Addr, SocketAddr},
     sync::Arc,
+    time::SystemTime,
 };
 
-pub struct DummyDevAtt {}
-impl DevAttDataFetcher for DummyDevAtt {
-    fn get_devatt_data(&self, _data_type: DataType, _data: &mut [u8]) -> Result<usize, Error> {
-        Ok(2)
-    }
-}
-
 /// An Interaction Model Engine to facilitate easy testing
 pub struct ImEngine {
     pub dm: DataModel,
     pub acl_mgr: Arc<AclMgr>,
+    pub fabric_mgr: Arc<FabricMgr>,
     pub im: Box<InteractionModel>,
+    // The exchange is retained across transactions that span multiple messages
+    exch: Exchange,
 }
 
 pub struct ImInput<'a> {
@@ -50,6 +52,10 @@ pub struct ImInput<'a> {
 }
 
 pub const IM_ENGINE_PEER_ID: u64 = 445566;
+
+/// The first cluster of the endpoint that [ImEngine::new_chunked] adds
+pub const MANY_ATTRS_CLUSTER: u32 = 0xfc00;
+
 impl<'a> ImInput<'a> {
     pub fn new(action: OpCode, data_in: &'a [u8]) -> Self {
         Self {
@@ -67,21 +73,13 @@ impl<'a> ImInput<'a> {
 impl ImEngine {
     /// Create the interaction model engine
     pub fn new() -> Self {
-        let dev_det = BasicInfoConfig {
-            vid: 10,
-            pid: 11,
-            hw_ver: 12,
-            sw_ver: 13,
-        };
-        let dev_att = Box::new(DummyDevAtt {});
-        let fabric_mgr = Arc::new(FabricMgr::new().unwrap());
-        let acl_mgr = Arc::new(AclMgr::new_with(false).unwrap());
-        acl_mgr.erase_all();
         let mut default_acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
         // Only allow the standard peer node id of the IM Engine
         default_acl.add_subject(IM_ENGINE_PEER_ID).unwrap();
-        acl_mgr.add(default_acl).unwrap();
-        let dm = DataModel::new(dev_det, dev_att, fabric_mgr.clone(), acl_mgr.clone()).unwrap();
+        let node = MatterNodeBuilder::new().acl(default_acl).build().unwrap();
+        let dm = node.dm;
+        let acl_mgr = node.acl_mgr;
+        let fabric_mgr = node.fabric_mgr;
 
         {
             let mut d = dm.node.write().unwrap();
@@ -94,30 +92,40 @@ impl ImEngine {
 
         let im = Box::new(InteractionModel::new(Box::new(dm.clone())));
 
-        Self { dm, acl_mgr, im }
+        Self {
+            dm,
+            acl_mgr,
+            fabric_mgr,
+            im,
+            exch: Exchange::new(1, 0, exchange::Role::Responder),
+        }
+    }
+
+    /// Create an engine with an extra endpoint, whose attributes don't fit in a single report
+    pub fn new_chunked() -> Self {
+        let im = Self::new();
+        {
+            let mut node = im.dm.node.write().unwrap();
+            // The new endpoint takes up one of its clusters with the Descriptor
+            let endpoint = node.add_endpoint().unwrap();
+            for i in 0..(CLUSTERS_PER_ENDPT - 1) as u32 {
+                node.add_cluster(endpoint, ManyAttrsCluster::new(MANY_ATTRS_CLUSTER + i))
+                    .unwrap();
+            }
+        }
+        im
     }
 
     /// Run a transaction through the interaction model engine
     pub fn process(&mut self, input: &ImInput, data_out: &mut [u8]) -> usize {
-        let mut exch = Exchange::new(1, 0, exchange::Role::Responder);
-
-        let mut sess_mgr: SessionMgr = Default::default();
-
-        let clone_data = CloneData::new(
-            123456,
-            input.peer_id,
-            10,
-            30,
-            Address::Udp(SocketAddr::new(
-                std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-                5542,
-            )),
-            SessionMode::Case(1),
-        );
-        let sess_idx = sess_mgr.clone_session(&clone_data).unwrap();
+        if !self.exch.is_state_open() {
+            self.exch = Exchange::new(1, 0, exchange::Role::Responder);
+        }
+
+        let (mut sess_mgr, sess_idx) = new_session(input.peer_id);
         let sess = sess_mgr.get_session_handle(sess_idx);
         let exch_ctx = ExchangeCtx {
-            exch: &mut exch,
+            exch: &mut self.exch,
             sess,
         };
         let mut rx = Slab::<PacketPool>::new(Packet::new_rx().unwrap()).unwrap();
@@ -137,6 +145,100 @@ impl ImEngine {
         data_out[..out_data_len].copy_from_slice(ctx.tx.as_borrow_slice());
         out_data_len
     }
+
+    /// Process an Invoke Request that was addressed to the group 'group_id'
+    pub fn process_group_invoke(&mut self, group_id: u16, data_in: &[u8]) -> ResponseRequired {
+        let (mut sess_mgr, sess_idx) = new_session(IM_ENGINE_PEER_ID);
+        let mut sess = sess_mgr.get_session_handle(sess_idx);
+        let mut trans = Transaction::new(&mut sess);
+        self.im
+            .handle_group_invoke_req(&mut trans, group_id, data_in)
+            .unwrap()
+    }
+
+    /// Read the attribute at 'path', and hand its AttributeDataIB to 'f'
+    ///
+    /// The attribute must be reported with its data, not a status.
+    pub fn read_attr<F, T>(&mut self, path: &GenericPath, fabric_filtered: bool, f: F) -> T
+    where
+        F: FnOnce(AttrData) -> T,
+    {
+        let mut buf = [0u8; MAX_RX_BUF_SIZE];
+        let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut tw = TLVWriter::new(&mut wb);
+        ReadReq::new(fabric_filtered)
+            .set_attr_requests(&[AttrPath::new(path)])
+            .to_tlv(&mut tw, TagType::Anonymous)
+            .unwrap();
+
+        let input = ImInput::new(OpCode::ReadRequest, wb.as_borrow_slice());
+        let out_len = self.process(&input, &mut out_buf);
+        let root = tlv::get_root_node_struct(&out_buf[..out_len]).unwrap();
+        let report = root
+            .find_tag(msg::ReportDataTag::AttributeReports as u32)
+            .unwrap()
+            .confirm_array()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .next()
+            .unwrap();
+        match AttrResp::from_tlv(&report).unwrap() {
+            AttrResp::Data(d) => f(d),
+            AttrResp::Status(s) => panic!("Invalid response {:?}, expected AttrResp::Data", s),
+        }
+    }
+
+    /// Generate the report of a subscription, if one is due at time 'now'
+    ///
+    /// The report goes out on a new exchange, the StatusResponses to its chunks are then
+    /// processed on that exchange.
+    pub fn report_subscription(
+        &mut self,
+        id: u32,
+        now: SystemTime,
+        data_out: &mut [u8],
+    ) -> Option<usize> {
+        let (mut sess_mgr, sess_idx) = new_session(IM_ENGINE_PEER_ID);
+        let mut sess = sess_mgr.get_session_handle(sess_idx);
+        let mut tx = Slab::<PacketPool>::new(Packet::new_tx().unwrap()).unwrap();
+        let mut trans = Transaction::new(&mut sess);
+        if !self
+            .im
+            .report_subscription(id, now, &mut trans, &mut tx)
+            .unwrap()
+        {
+            return None;
+        }
+        self.exch = Exchange::new(2, 0, exchange::Role::Initiator);
+        if let Some(data) = trans.data.take() {
+            self.exch.set_exchange_data(data);
+        }
+        let out_data_len = tx.as_borrow_slice().len();
+        data_out[..out_data_len].copy_from_slice(tx.as_borrow_slice());
+        Some(out_data_len)
+    }
+}
+
+// A CASE session with the peer, as the IM expects
+fn new_session(peer_id: u64) -> (SessionMgr, usize) {
+    let mut sess_mgr: SessionMgr = Default::default();
+
+    let clone_data = CloneData::new(
+        123456,
+        peer_id,
+        10,
+        30,
+        Address::Udp(SocketAddr::new(
+            std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            5542,
+        )),
+        SessionMode::Case(1),
+    );
+    let sess_idx = sess_mgr.clone_session(&clone_data).unwrap();
+    (sess_mgr, sess_idx)
 }
 
 // Create an Interaction Model, Data Model and run a rx/tx transaction through it
@@ -158,7 +260,14 @@ impl<'a, 'b> TestData<'a, 'b> {
         }
     }
 
-    pub fn commands(&mut self, cmds: &[(CmdPath, Option<u8>)]) -> Result<(), Error> {
+    /// Encode an Invoke Request of the commands at 'cmds'
+    ///
+    /// 'data' encodes the CommandFields of the command at the given index, with the given
+    /// tag, or nothing at all for a command without fields.
+    pub fn commands<F>(&mut self, cmds: &[CmdPath], mut data: F) -> Result<(), Error>
+    where
+        F: FnMut(usize, TagType, &mut TLVWriter) -> Result<(), Error>,
+    {
         self.tw.start_struct(TagType::Anonymous)?;
         self.tw.bool(
             TagType::Context(msg::InvReqTag::SupressResponse as u8),
@@ -169,16 +278,28 @@ impl<'a, 'b> TestData<'a, 'b> {
         self.tw
             .start_array(TagType::Context(msg::InvReqTag::InvokeRequests as u8))?;
 
-        for (cmd, data) in cmds {
+        for (i, cmd) in cmds.iter().enumerate() {
             self.tw.start_struct(TagType::Anonymous)?;
             cmd.to_tlv(&mut self.tw, TagType::Context(0))?;
-            if let Some(d) = *data {
-                self.tw.u8(TagType::Context(1), d)?;
-            }
+            data(i, TagType::Context(1), &mut self.tw)?;
             self.tw.end_container()?;
         }
 
         self.tw.end_container()?;
         self.tw.end_container()
     }
+
+    pub fn read_attrs(&mut self, attrs: &[AttrPath]) -> Result<(), Error> {
+        ReadReq::new(true)
+            .set_attr_requests(attrs)
+            .to_tlv(&mut self.tw, TagType::Anonymous)
+    }
+
+    pub fn write_attrs(&mut self, attrs: &[(AttrPath, AttrValue)]) -> Result<(), Error> {
+        let attr_data: Vec<AttrData> = attrs
+            .iter()
+            .map(|(path, value)| AttrData::new(None, *path, EncodeValue::Value(value)))
+            .collect();
+        WriteReq::new(false, &attr_data).to_tlv(&mut self.tw, TagType::Anonymous)
+    }
 }
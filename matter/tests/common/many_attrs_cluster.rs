@@ -0,0 +1,41 @@
+use matter::data_model::objects::{
+    Access, AttrValue, Attribute, Cluster, ClusterType, Quality, ATTRS_PER_CLUSTER,
+};
+
+/// A cluster with as many attributes as a cluster can have
+///
+/// Attribute 'n' has the value 'n', the AttributeList takes up the last one.
+pub struct ManyAttrsCluster {
+    base: Cluster,
+}
+
+impl ManyAttrsCluster {
+    pub fn new(id: u32) -> Box<Self> {
+        let mut c = Box::new(Self {
+            base: Cluster::new(id).unwrap(),
+        });
+        for attr_id in 0..(ATTRS_PER_CLUSTER - 1) as u16 {
+            c.base
+                .add_attribute(
+                    Attribute::new(
+                        attr_id,
+                        AttrValue::Uint16(attr_id),
+                        Access::RV,
+                        Quality::NONE,
+                    )
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+        c
+    }
+}
+
+impl ClusterType for ManyAttrsCluster {
+    fn base(&self) -> &Cluster {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut Cluster {
+        &mut self.base
+    }
+}
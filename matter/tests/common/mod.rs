@@ -1,3 +1,5 @@
 pub mod attributes;
 pub mod echo_cluster;
+pub mod fabric;
 pub mod im_engine;
+pub mod many_attrs_cluster;
@@ -1,6 +1,10 @@
 use matter::{
     acl::{AclEntry, AuthMode, Target},
-    data_model::objects::{AttrValue, EncodeValue, Privilege},
+    data_model::{
+        cluster_basic_information::{self, BasicInfoConfig},
+        objects::{AttrValue, EncodeValue, Privilege},
+        system_model::access_control::{self, AclExtension},
+    },
     interaction_model::{
         core::{IMStatusCode, OpCode},
         messages::{
@@ -9,7 +13,11 @@ use matter::{
         },
         messages::{msg, GenericPath},
     },
-    tlv::{self, ElementType, FromTLV, TLVElement, TLVWriter, TagType, ToTLV},
+    test_utils::{MatterNode, MatterNodeBuilder},
+    tlv::{
+        self, ElementType, FabricScoped, FromTLV, Nullable, TLVElement, TLVWriter, TagType, ToTLV,
+    },
+    transport::packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
     utils::writebuf::WriteBuf,
 };
 
@@ -29,12 +37,12 @@ fn handle_read_reqs(
     input: &[AttrPath],
     expected: &[AttrResp],
 ) {
-    let mut buf = [0u8; 400];
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
 
     let buf_len = buf.len();
     let mut wb = WriteBuf::new(&mut buf, buf_len);
     let mut tw = TLVWriter::new(&mut wb);
-    let mut out_buf = [0u8; 400];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
 
     let read_req = ReadReq::new(true).set_attr_requests(input);
     read_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
@@ -54,8 +62,8 @@ fn handle_write_reqs(
     input: &[AttrData],
     expected: &[AttrStatus],
 ) {
-    let mut buf = [0u8; 400];
-    let mut out_buf = [0u8; 400];
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
 
     let buf_len = buf.len();
     let mut wb = WriteBuf::new(&mut buf, buf_len);
@@ -186,10 +194,10 @@ fn read_cluster_id_write_attr(im: &ImEngine, endpoint: u16) -> AttrValue {
     let node = im.dm.node.read().unwrap();
     let echo = node.get_cluster(endpoint, echo_cluster::ID).unwrap();
 
-    *echo
-        .base()
+    echo.base()
         .read_attribute_raw(echo_cluster::Attributes::AttWrite as u16)
         .unwrap()
+        .clone()
 }
 
 #[test]
@@ -263,7 +271,7 @@ fn wc_write_attribute() {
         &mut im,
         peer,
         input0,
-        &[AttrStatus::new(&ep0_att, IMStatusCode::Sucess, 0)],
+        &[AttrStatus::new(&ep0_att, IMStatusCode::Success, 0)],
     );
     assert_eq!(AttrValue::Uint16(val0), read_cluster_id_write_attr(&im, 0));
     assert_eq!(
@@ -284,8 +292,8 @@ fn wc_write_attribute() {
         peer,
         input1,
         &[
-            AttrStatus::new(&ep0_att, IMStatusCode::Sucess, 0),
-            AttrStatus::new(&ep1_att, IMStatusCode::Sucess, 0),
+            AttrStatus::new(&ep0_att, IMStatusCode::Success, 0),
+            AttrStatus::new(&ep1_att, IMStatusCode::Success, 0),
         ],
     );
     assert_eq!(AttrValue::Uint16(val1), read_cluster_id_write_attr(&im, 0));
@@ -318,7 +326,7 @@ fn exact_write_attribute() {
         IMStatusCode::UnsupportedAccess,
         0,
     )];
-    let expected_success = &[AttrStatus::new(&ep0_att, IMStatusCode::Sucess, 0)];
+    let expected_success = &[AttrStatus::new(&ep0_att, IMStatusCode::Success, 0)];
 
     let peer = 98765;
     let mut im = ImEngine::new();
@@ -386,3 +394,337 @@ fn insufficient_perms_write() {
         read_cluster_id_write_attr(&im, 0)
     );
 }
+
+#[test]
+/// Ensure that the ACLs are matched against the fabric of the session
+fn read_attribute_as_fabric() {
+    let _ = env_logger::try_init();
+
+    let ep0_vid = GenericPath::new(
+        Some(0),
+        Some(cluster_basic_information::ID),
+        Some(cluster_basic_information::Attributes::VendorId as u32),
+    );
+    let input = &[AttrPath::new(&ep0_vid)];
+    let read_req = ReadReq::new(true).set_attr_requests(input);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    // Allow our peer to access the node only on the second fabric
+    let peer = 98765;
+    let mut acl = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
+    acl.add_subject(peer).unwrap();
+    let node = MatterNodeBuilder::new()
+        .dev_det(BasicInfoConfig {
+            vid: 0x8002,
+            pid: 0xFFF1,
+            hw_ver: 2,
+            sw_ver: 1,
+        })
+        .acl(acl)
+        .build()
+        .unwrap();
+
+    // Test1: Access is denied to our peer on the first fabric
+    let mut sess = node.session(1, peer);
+    let out_buf_len = node.read(&mut sess, &read_req, &mut out_buf).unwrap();
+    assert_attr_report(
+        &out_buf[..out_buf_len],
+        &[attr_status!(&ep0_vid, IMStatusCode::UnsupportedAccess)],
+    );
+
+    // Test2: Access is granted to our peer on the second fabric
+    let mut sess = node.session(2, peer);
+    let out_buf_len = node.read(&mut sess, &read_req, &mut out_buf).unwrap();
+    assert_attr_report(
+        &out_buf[..out_buf_len],
+        &[attr_data!(ep0_vid, ElementType::U16(0x8002))],
+    );
+}
+
+// The path of an attribute of the Access Control cluster
+fn acl_attr_path(attr: access_control::Attributes) -> GenericPath {
+    GenericPath::new(Some(0), Some(access_control::ID), Some(attr as u32))
+}
+
+// Write to a list attribute of the Access Control cluster over the session, and check
+// the status of the write
+fn write_list(
+    node: &MatterNode,
+    fab_idx: u8,
+    peer: u64,
+    attr: access_control::Attributes,
+    list_index: Option<Nullable<u16>>,
+    data: EncodeValue,
+    expected: IMStatusCode,
+) {
+    let path = acl_attr_path(attr);
+    let mut attr_path = AttrPath::new(&path);
+    attr_path.list_index = list_index;
+    let input = &[AttrData::new(None, attr_path, data)];
+
+    // The Data Model only accepts the attribute data in its TLV form
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    WriteReq::new(false, input)
+        .to_tlv(&mut tw, TagType::Anonymous)
+        .unwrap();
+    let root = tlv::get_root_node_struct(wb.as_borrow_slice()).unwrap();
+    let write_req = WriteReq::from_tlv(&root).unwrap();
+
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let mut sess = node.session(fab_idx, peer);
+    let out_buf_len = node.write(&mut sess, &write_req, &mut out_buf).unwrap();
+    let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+    let response = root
+        .find_tag(msg::WriteRespTag::WriteResponses as u32)
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    let status = AttrStatus::from_tlv(&response).unwrap();
+    assert_eq!(status, AttrStatus::new(&path, expected, 0));
+}
+
+// Append an entry to the ACL attribute over the session
+fn append_acl(node: &MatterNode, fab_idx: u8, peer: u64, entry: &AclEntry, expected: IMStatusCode) {
+    write_list(
+        node,
+        fab_idx,
+        peer,
+        access_control::Attributes::Acl,
+        Some(Nullable::Null),
+        EncodeValue::Value(entry),
+        expected,
+    )
+}
+
+// Read a list attribute of the Access Control cluster over the session, returning the
+// entries in the list
+fn read_list<T: for<'a> FromTLV<'a>>(
+    node: &MatterNode,
+    fab_idx: u8,
+    peer: u64,
+    attr: access_control::Attributes,
+    fabric_filtered: bool,
+) -> Vec<T> {
+    let input = &[AttrPath::new(&acl_attr_path(attr))];
+    let read_req = ReadReq::new(fabric_filtered).set_attr_requests(input);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let mut sess = node.session(fab_idx, peer);
+    let out_buf_len = node.read(&mut sess, &read_req, &mut out_buf).unwrap();
+    let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+    let report = root
+        .find_tag(msg::ReportDataTag::AttributeReports as u32)
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match AttrResp::from_tlv(&report).unwrap() {
+        AttrResp::Data(d) => d
+            .data
+            .unwrap_tlv()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|e| T::from_tlv(&e).unwrap())
+            .collect(),
+        AttrResp::Status(_) => panic!("Invalid response, expected AttrResp::Data"),
+    }
+}
+
+// Read the ACL attribute over the session, returning the entries in the list
+fn read_acl(node: &MatterNode, fab_idx: u8, peer: u64, fabric_filtered: bool) -> Vec<AclEntry> {
+    read_list(
+        node,
+        fab_idx,
+        peer,
+        access_control::Attributes::Acl,
+        fabric_filtered,
+    )
+}
+
+#[test]
+/// Ensure that an ACL entry that is appended to the ACL attribute can be read back,
+/// and that fabric filtering only reports the entries of the accessor's fabric
+fn write_acl_read_back() {
+    let _ = env_logger::try_init();
+
+    let admin = 112233;
+    let peer = 98765;
+    let mut admin_acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+    admin_acl.add_subject(admin).unwrap();
+    let mut other_fabric_acl = AclEntry::new(2, Privilege::VIEW, AuthMode::Case);
+    other_fabric_acl.add_subject(peer).unwrap();
+    let node = MatterNodeBuilder::new()
+        .acl(admin_acl)
+        .acl(other_fabric_acl)
+        .build()
+        .unwrap();
+
+    // Test1: Subject 0 isn't a valid node id, this entry is rejected
+    let mut invalid = AclEntry::new(1, Privilege::OPERATE, AuthMode::Case);
+    invalid.add_subject(0).unwrap();
+    append_acl(&node, 1, admin, &invalid, IMStatusCode::ConstraintError);
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl]);
+
+    // Test2: Append an entry, the fabric index that is written is ignored in favour of
+    // the accessor's fabric index
+    let mut new = AclEntry::new(5, Privilege::OPERATE, AuthMode::Case);
+    new.add_subject(peer).unwrap();
+    new.add_target(Target::new(Some(1), None, None)).unwrap();
+    append_acl(&node, 1, admin, &new, IMStatusCode::Success);
+
+    new.set_fab_idx(1);
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl, new]);
+    assert_eq!(
+        read_acl(&node, 1, admin, false),
+        &[admin_acl, other_fabric_acl, new]
+    );
+}
+
+#[test]
+/// Ensure that a list index past the fabric's entries is rejected, rather than wrapping
+/// around to an existing entry
+fn write_acl_index_out_of_range() {
+    let _ = env_logger::try_init();
+
+    let admin = 112233;
+    let mut admin_acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+    admin_acl.add_subject(admin).unwrap();
+    let node = MatterNodeBuilder::new().acl(admin_acl).build().unwrap();
+
+    let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+    new.add_subject(98765).unwrap();
+    for index in [1, 256] {
+        // Edit, and then delete, the entry at 'index'
+        write_list(
+            &node,
+            1,
+            admin,
+            access_control::Attributes::Acl,
+            Some(Nullable::NotNull(index)),
+            EncodeValue::Value(&new),
+            IMStatusCode::ConstraintError,
+        );
+        write_list(
+            &node,
+            1,
+            admin,
+            access_control::Attributes::Acl,
+            Some(Nullable::NotNull(index)),
+            EncodeValue::Closure(&|tag, tw| {
+                let _ = tw.null(tag);
+            }),
+            IMStatusCode::ConstraintError,
+        );
+    }
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl]);
+}
+
+#[test]
+/// Ensure that a write of the whole ACL list replaces the fabric's entries, or leaves them
+/// as they are if any of the new entries is invalid or doesn't fit
+fn write_acl_list_replace() {
+    let _ = env_logger::try_init();
+
+    let admin = 112233;
+    let peer = 98765;
+    let mut admin_acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+    admin_acl.add_subject(admin).unwrap();
+    let node = MatterNodeBuilder::new().acl(admin_acl).build().unwrap();
+    let write = |entries: &[AclEntry], expected| {
+        write_list(
+            &node,
+            1,
+            admin,
+            access_control::Attributes::Acl,
+            None,
+            EncodeValue::Closure(&|tag, tw| {
+                let _ = tw.array_from_iter(tag, entries.iter().copied());
+            }),
+            expected,
+        )
+    };
+
+    let mut new = AclEntry::new(1, Privilege::VIEW, AuthMode::Case);
+    new.add_subject(peer).unwrap();
+    let mut invalid = AclEntry::new(1, Privilege::OPERATE, AuthMode::Case);
+    invalid.add_subject(0).unwrap();
+
+    // Test1: The last entry is invalid
+    write(&[new, invalid], IMStatusCode::ConstraintError);
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl]);
+
+    // Test2: More entries than a fabric can have
+    write(&[admin_acl, new, new, new], IMStatusCode::ResourceExhausted);
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl]);
+
+    // Test3: The entries are replaced
+    write(&[admin_acl, new], IMStatusCode::Success);
+    assert_eq!(read_acl(&node, 1, admin, true), &[admin_acl, new]);
+}
+
+#[test]
+/// Ensure that each fabric can write one entry to the Extension attribute, and read it back
+fn write_extension_read_back() {
+    let _ = env_logger::try_init();
+
+    let admin = 112233;
+    let peer = 98765;
+    let mut admin_acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+    admin_acl.add_subject(admin).unwrap();
+    let mut other_fabric_acl = AclEntry::new(2, Privilege::ADMIN, AuthMode::Case);
+    other_fabric_acl.add_subject(peer).unwrap();
+    let node = MatterNodeBuilder::new()
+        .acl(admin_acl)
+        .acl(other_fabric_acl)
+        .build()
+        .unwrap();
+    let append = |fab_idx, peer, extension: &AclExtension, expected| {
+        write_list(
+            &node,
+            fab_idx,
+            peer,
+            access_control::Attributes::Extension,
+            Some(Nullable::Null),
+            EncodeValue::Value(extension),
+            expected,
+        )
+    };
+    let read = |fab_idx, peer, fabric_filtered| -> Vec<AclExtension> {
+        read_list(
+            &node,
+            fab_idx,
+            peer,
+            access_control::Attributes::Extension,
+            fabric_filtered,
+        )
+    };
+
+    let ext1 = AclExtension {
+        data: vec![0x15, 0x24, 0x01, 0x2a, 0x18],
+        fab_idx: 1,
+    };
+    let ext2 = AclExtension {
+        data: vec![0x15, 0x18],
+        fab_idx: 2,
+    };
+    append(1, admin, &ext1, IMStatusCode::Success);
+    append(2, peer, &ext2, IMStatusCode::Success);
+
+    // A fabric has one entry at most, and the data is limited in length
+    append(1, admin, &ext1, IMStatusCode::ConstraintError);
+    let too_long = AclExtension {
+        data: vec![0; access_control::MAX_EXTENSION_DATA_LEN + 1],
+        fab_idx: 2,
+    };
+    append(2, peer, &too_long, IMStatusCode::ConstraintError);
+
+    assert_eq!(read(1, admin, true), [ext1.clone()]);
+    assert_eq!(read(2, peer, false), [ext1, ext2]);
+}
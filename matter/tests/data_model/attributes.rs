@@ -1,34 +1,50 @@
 use matter::{
     data_model::{
+        cluster_basic_information::{self, NODE_LABEL_MAX_LEN},
         cluster_on_off,
         core::DataModel,
-        objects::{AttrValue, EncodeValue, GlobalElements},
+        objects::{
+            Access, AttrValue, Attribute, EncodeValue, GlobalElements, Quality, ATTRS_PER_CLUSTER,
+            CLUSTERS_PER_ENDPT,
+        },
     },
+    error::Error,
     interaction_model::{
         core::{IMStatusCode, OpCode},
         messages::{
             ib::{AttrData, AttrPath, AttrResp, AttrStatus},
-            msg::{ReadReq, WriteReq},
+            msg::{ReadReq, StatusResp, WriteReq},
         },
         messages::{msg, GenericPath},
+        read::ReadState,
+        InteractionConsumer, Transaction,
     },
     tlv::{self, ElementType, FromTLV, TLVElement, TLVList, TLVWriter, TagType, ToTLV},
+    transport::{
+        network::Address,
+        packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+        session::Session,
+    },
     utils::writebuf::WriteBuf,
 };
 
 use crate::{
     attr_data, attr_status,
-    common::{attributes::*, echo_cluster, im_engine::im_engine},
+    common::{
+        attributes::*,
+        echo_cluster,
+        im_engine::{im_engine, ImEngine, ImInput, TestData, MANY_ATTRS_CLUSTER},
+    },
 };
 
 // Helper for handling Read Req sequences
 fn handle_read_reqs(input: &[AttrPath], expected: &[AttrResp]) {
-    let mut buf = [0u8; 400];
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
 
     let buf_len = buf.len();
     let mut wb = WriteBuf::new(&mut buf, buf_len);
     let mut tw = TLVWriter::new(&mut wb);
-    let mut out_buf = [0u8; 400];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
 
     let read_req = ReadReq::new(true).set_attr_requests(input);
     read_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
@@ -40,8 +56,15 @@ fn handle_read_reqs(input: &[AttrPath], expected: &[AttrResp]) {
 
 // Helper for handling Write Attribute sequences
 fn handle_write_reqs(input: &[AttrData], expected: &[AttrStatus]) -> DataModel {
-    let mut buf = [0u8; 400];
-    let mut out_buf = [0u8; 400];
+    let mut im = ImEngine::new();
+    handle_write_reqs_on(&mut im, input, expected);
+    im.dm
+}
+
+// Helper for handling Write Attribute sequences on an existing engine
+fn handle_write_reqs_on(im: &mut ImEngine, input: &[AttrData], expected: &[AttrStatus]) {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
 
     let buf_len = buf.len();
     let mut wb = WriteBuf::new(&mut buf, buf_len);
@@ -50,7 +73,8 @@ fn handle_write_reqs(input: &[AttrData], expected: &[AttrStatus]) -> DataModel {
     let write_req = WriteReq::new(false, input);
     write_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
 
-    let (dm, out_buf_len) = im_engine(OpCode::WriteRequest, wb.as_borrow_slice(), &mut out_buf);
+    let input = ImInput::new(OpCode::WriteRequest, wb.as_borrow_slice());
+    let out_buf_len = im.process(&input, &mut out_buf);
     let out_buf = &out_buf[..out_buf_len];
     tlv::print_tlv_list(out_buf);
     let root = tlv::get_root_node_struct(out_buf).unwrap();
@@ -71,7 +95,6 @@ fn handle_write_reqs(input: &[AttrData], expected: &[AttrStatus]) -> DataModel {
         index += 1;
     }
     assert_eq!(index, expected.len());
-    dm
 }
 
 #[test]
@@ -113,6 +136,216 @@ fn test_read_success() {
     handle_read_reqs(input, expected);
 }
 
+// The paths of a read of ImEngine::new_chunked() that takes more than a single report,
+// along with their reports
+//
+// The paths are all distinct, an attribute is reported only once in a report
+fn chunked_read_paths() -> (Vec<GenericPath>, Vec<AttrResp<'static>>) {
+    let mut paths = Vec::new();
+    let mut expected = Vec::new();
+    for endpoint in 0..2 {
+        for (attr, resp) in [
+            (
+                echo_cluster::Attributes::Att1,
+                Some(ElementType::U16(0x1234)),
+            ),
+            (
+                echo_cluster::Attributes::Att2,
+                Some(ElementType::U16(0x5678)),
+            ),
+            (
+                echo_cluster::Attributes::AttCustom,
+                Some(ElementType::U32(echo_cluster::ATTR_CUSTOM_VALUE)),
+            ),
+            // AttWrite can't be read
+            (echo_cluster::Attributes::AttWrite, None),
+        ] {
+            let path = GenericPath::new(Some(endpoint), Some(echo_cluster::ID), Some(attr as u32));
+            paths.push(path);
+            expected.push(match resp {
+                Some(data) => attr_data!(path, data),
+                None => attr_status!(&path, IMStatusCode::UnsupportedAccess),
+            });
+        }
+    }
+    for i in 0..(CLUSTERS_PER_ENDPT - 1) as u32 {
+        for attr in 0..(ATTRS_PER_CLUSTER - 1) as u16 {
+            let path = GenericPath::new(Some(2), Some(MANY_ATTRS_CLUSTER + i), Some(attr as u32));
+            paths.push(path);
+            // The values are small enough to be encoded in a single byte
+            expected.push(attr_data!(path, ElementType::U8(attr as u8)));
+        }
+    }
+    (paths, expected)
+}
+
+// Read 'paths', acknowledging each chunk of the report with a StatusResponse, and
+// check the chunks against 'expected'
+//
+// Returns the number of chunks of the report.
+fn handle_chunked_read(im: &mut ImEngine, paths: &[GenericPath], expected: &[AttrResp]) -> usize {
+    let input: Vec<AttrPath> = paths.iter().map(AttrPath::new).collect();
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let read_req = ReadReq::new(true).set_attr_requests(&input);
+    read_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    let mut opcode = OpCode::ReadRequest;
+    let mut chunks = 0;
+    let mut reported = 0;
+    loop {
+        let out_buf_len = im.process(&ImInput::new(opcode, wb.as_borrow_slice()), &mut out_buf);
+        let out_buf = &out_buf[..out_buf_len];
+        chunks += 1;
+
+        let root = tlv::get_root_node_struct(out_buf).unwrap();
+        let count = root
+            .find_tag(msg::ReportDataTag::AttributeReports as u32)
+            .unwrap()
+            .confirm_array()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .count();
+        let more_chunks = reported + count < expected.len();
+        assert_attr_report_chunk(
+            out_buf,
+            &expected[reported..(reported + count)],
+            more_chunks,
+        );
+        reported += count;
+        if !more_chunks {
+            return chunks;
+        }
+
+        // The next chunk is sent only once this one is acknowledged
+        wb.reset(0);
+        let mut tw = TLVWriter::new(&mut wb);
+        let status_resp = StatusResp::new(IMStatusCode::Success);
+        status_resp.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        opcode = OpCode::StatusResponse;
+    }
+}
+
+#[test]
+fn test_read_chunked() {
+    // More Attr Read Requests than fit in a single report
+    // - the first chunk is as large as the packet allows
+    // - the next chunk is sent only once the first one is acknowledged with a StatusResponse
+    let _ = env_logger::try_init();
+
+    let (paths, expected) = chunked_read_paths();
+    let mut im = ImEngine::new_chunked();
+    assert!(handle_chunked_read(&mut im, &paths, &expected) > 1);
+}
+
+#[test]
+fn test_read_chunked_overlap() {
+    // The last path overlaps with an attribute that the first chunk reported, it isn't
+    // reported again in the last chunk
+    let _ = env_logger::try_init();
+
+    let (mut paths, expected) = chunked_read_paths();
+    paths.push(paths[0]);
+
+    let mut im = ImEngine::new_chunked();
+    assert!(handle_chunked_read(&mut im, &paths, &expected) > 1);
+}
+
+#[test]
+fn test_read_chunked_limit() {
+    // The attributes of the first chunk count towards the limit of the read, the last
+    // chunk is out of attributes
+    let _ = env_logger::try_init();
+
+    let (mut paths, mut expected) = chunked_read_paths();
+    let on_off = GenericPath::new(
+        Some(1),
+        Some(cluster_on_off::ID),
+        Some(cluster_on_off::Attributes::OnOff as u32),
+    );
+    paths.push(on_off);
+    expected.push(attr_status!(&on_off, IMStatusCode::PathsExhausted));
+
+    let mut im = ImEngine::new_chunked();
+    im.dm.set_read_attr_limit(paths.len() - 1);
+    assert!(handle_chunked_read(&mut im, &paths, &expected) > 1);
+}
+
+#[test]
+fn test_read_wildcard_resumed() {
+    // The attributes of the whole node don't fit in a single report, the first chunk ends in
+    // the middle of the wildcard path and the next chunks resume it from there
+    let _ = env_logger::try_init();
+
+    let wildcard = GenericPath::new(None, None, None);
+    let mut expected = Vec::new();
+    let mut im = ImEngine::new_chunked();
+    im.dm
+        .node
+        .read()
+        .unwrap()
+        .for_each_attribute(&wildcard, |path, _| {
+            // The write-only attribute isn't reported
+            let write_only = GenericPath::new(
+                path.endpoint,
+                Some(echo_cluster::ID),
+                Some(echo_cluster::Attributes::AttWrite as u32),
+            );
+            if *path != write_only {
+                expected.push(*path);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let input = [AttrPath::new(&wildcard)];
+    let read_req = ReadReq::new(true).set_attr_requests(&input);
+    read_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    let mut opcode = OpCode::ReadRequest;
+    let mut chunks = 0;
+    let mut visited = Vec::new();
+    loop {
+        let out_buf_len = im.process(&ImInput::new(opcode, wb.as_borrow_slice()), &mut out_buf);
+        chunks += 1;
+        let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+        let reports = root
+            .find_tag(msg::ReportDataTag::AttributeReports as u32)
+            .unwrap();
+        for report in reports.confirm_array().unwrap().iter().unwrap() {
+            match AttrResp::from_tlv(&report).unwrap() {
+                AttrResp::Data(d) => visited.push(d.path.to_gp()),
+                AttrResp::Status(s) => panic!("Unexpected status {:?}", s),
+            }
+        }
+        let more_chunks = root
+            .find_tag(msg::ReportDataTag::MoreChunkedMsgs as u32)
+            .is_ok_and(|t| t.bool().unwrap());
+        if !more_chunks {
+            break;
+        }
+
+        wb.reset(0);
+        let mut tw = TLVWriter::new(&mut wb);
+        let status_resp = StatusResp::new(IMStatusCode::Success);
+        status_resp.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+        opcode = OpCode::StatusResponse;
+    }
+
+    // Every attribute is reported exactly once, across the chunks
+    assert!(chunks > 1);
+    assert_eq!(visited, expected);
+}
+
 #[test]
 fn test_read_unsupported_fields() {
     // 6 reads
@@ -195,6 +428,123 @@ fn test_read_wc_endpoint_all_have_clusters() {
     handle_read_reqs(input, expected);
 }
 
+#[test]
+fn test_read_overlapping_paths() {
+    // 3 Attr Read Requests
+    // - endpoint 0, att1
+    // - wildcard endpoint, att1, which also covers the first path
+    // - endpoint 0, att1 again
+    // - each attribute is reported once
+    let _ = env_logger::try_init();
+
+    let ep0_att1 = GenericPath::new(
+        Some(0),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Attributes::Att1 as u32),
+    );
+    let ep1_att1 = GenericPath::new(
+        Some(1),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Attributes::Att1 as u32),
+    );
+    let wc_ep_att1 = GenericPath::new(
+        None,
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Attributes::Att1 as u32),
+    );
+    let input = &[
+        AttrPath::new(&ep0_att1),
+        AttrPath::new(&wc_ep_att1),
+        AttrPath::new(&ep0_att1),
+    ];
+    let expected = &[
+        attr_data!(ep0_att1, ElementType::U16(0x1234)),
+        attr_data!(ep1_att1, ElementType::U16(0x1234)),
+    ];
+    handle_read_reqs(input, expected);
+}
+
+#[test]
+fn test_read_paths_exhausted() {
+    // 2 Attr Read Requests, with a limit of 3 attributes
+    // - wildcard endpoint, att1 expands to 2 attributes
+    // - wildcard endpoint, att2 expands to 2 more, only 1 of which fits
+    let _ = env_logger::try_init();
+
+    let att = |endpoint, attr: echo_cluster::Attributes| {
+        GenericPath::new(endpoint, Some(echo_cluster::ID), Some(attr as u32))
+    };
+    let wc_ep_att2 = att(None, echo_cluster::Attributes::Att2);
+    let input = [
+        AttrPath::new(&att(None, echo_cluster::Attributes::Att1)),
+        AttrPath::new(&wc_ep_att2),
+    ];
+    let expected = &[
+        attr_data!(
+            att(Some(0), echo_cluster::Attributes::Att1),
+            ElementType::U16(0x1234)
+        ),
+        attr_data!(
+            att(Some(1), echo_cluster::Attributes::Att1),
+            ElementType::U16(0x1234)
+        ),
+        attr_data!(
+            att(Some(0), echo_cluster::Attributes::Att2),
+            ElementType::U16(0x5678)
+        ),
+        attr_status!(&wc_ep_att2, IMStatusCode::PathsExhausted),
+    ];
+
+    let mut im = ImEngine::new();
+    im.dm.set_read_attr_limit(3);
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let read_req = ReadReq::new(true).set_attr_requests(&input);
+    read_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::ReadRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    assert_attr_report(&out_buf[..out_buf_len], expected);
+}
+
+#[test]
+fn test_read_poisoned_node() {
+    // A handler that panics with the node locked doesn't take the reads down with it
+    let _ = env_logger::try_init();
+    let im = ImEngine::new();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _node = im.dm.node.write().unwrap();
+        panic!("Simulated panic with the node locked");
+    }));
+    assert!(result.is_err());
+
+    let ep0_att1 = GenericPath::new(
+        Some(0),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Attributes::Att1 as u32),
+    );
+    let input = [AttrPath::new(&ep0_att1)];
+    let read_req = ReadReq::new(true).set_attr_requests(&input);
+
+    let mut buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut sess = Session::new(Address::default(), None);
+    let mut trans = Transaction::new(&mut sess);
+    assert_eq!(
+        im.dm
+            .consume_read_attr(&mut ReadState::from_req(&read_req), &mut trans, &mut tw),
+        Err(Error::RwLock)
+    );
+}
+
 #[test]
 fn test_read_wc_endpoint_only_1_has_cluster() {
     // 1 Attr Read Requests
@@ -246,11 +596,11 @@ fn test_read_wc_endpoint_wc_attribute() {
     let attr_list_tlvs = get_tlvs(
         &mut buf,
         &[
-            GlobalElements::AttributeList as u16,
             echo_cluster::Attributes::Att1 as u16,
             echo_cluster::Attributes::Att2 as u16,
             echo_cluster::Attributes::AttWrite as u16,
             echo_cluster::Attributes::AttCustom as u16,
+            GlobalElements::AttributeList as u16,
         ],
     );
 
@@ -323,6 +673,37 @@ fn test_read_wc_endpoint_wc_attribute() {
     handle_read_reqs(input, expected);
 }
 
+#[test]
+fn test_write_invalid_data_type() {
+    // A string written to the integer AttWrite is an InvalidDataType, and the value is untouched
+    let _ = env_logger::try_init();
+    let attr_data = |tag, t: &mut TLVWriter| {
+        let _ = t.utf8(tag, b"ten");
+    };
+    let ep0_att = GenericPath::new(
+        Some(0),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Attributes::AttWrite as u32),
+    );
+    let input = &[AttrData::new(
+        None,
+        AttrPath::new(&ep0_att),
+        EncodeValue::Closure(&attr_data),
+    )];
+    let expected = &[AttrStatus::new(&ep0_att, IMStatusCode::InvalidDataType, 0)];
+
+    let dm = handle_write_reqs(input, expected);
+    assert_eq!(
+        AttrValue::Uint16(echo_cluster::ATTR_WRITE_DEFAULT_VALUE),
+        dm.read_attribute_raw(
+            0,
+            echo_cluster::ID,
+            echo_cluster::Attributes::AttWrite as u16
+        )
+        .unwrap()
+    );
+}
+
 #[test]
 fn test_write_success() {
     // 2 Attr Write Request
@@ -362,8 +743,8 @@ fn test_write_success() {
         ),
     ];
     let expected = &[
-        AttrStatus::new(&ep0_att, IMStatusCode::Sucess, 0),
-        AttrStatus::new(&ep1_att, IMStatusCode::Sucess, 0),
+        AttrStatus::new(&ep0_att, IMStatusCode::Success, 0),
+        AttrStatus::new(&ep1_att, IMStatusCode::Success, 0),
     ];
 
     let dm = handle_write_reqs(input, expected);
@@ -386,6 +767,153 @@ fn test_write_success() {
     );
 }
 
+#[test]
+fn test_write_then_read() {
+    // A Write Request, and then a Read Request, of a readable and writable attribute
+    let _ = env_logger::try_init();
+    const ATT_RW: u16 = 0x10;
+    let mut im = ImEngine::new();
+    {
+        let mut node = im.dm.node.write().unwrap();
+        let echo = node.get_cluster_mut(0, echo_cluster::ID).unwrap();
+        echo.base_mut()
+            .add_attribute(
+                Attribute::new(ATT_RW, AttrValue::Uint16(0), Access::RWVA, Quality::NONE).unwrap(),
+            )
+            .unwrap();
+    }
+    let ep0_att = GenericPath::new(Some(0), Some(echo_cluster::ID), Some(ATT_RW as u32));
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let mut td = TestData::new(&mut wb);
+    td.write_attrs(&[(AttrPath::new(&ep0_att), AttrValue::Uint16(0xabcd))])
+        .unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::WriteRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+    let status = root
+        .find_tag(msg::WriteRespTag::WriteResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    assert_eq!(
+        AttrStatus::from_tlv(&status).unwrap(),
+        AttrStatus::new(&ep0_att, IMStatusCode::Success, 0)
+    );
+
+    wb.reset(0);
+    let mut td = TestData::new(&mut wb);
+    td.read_attrs(&[AttrPath::new(&ep0_att)]).unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::ReadRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    assert_attr_report(
+        &out_buf[..out_buf_len],
+        &[attr_data!(ep0_att, ElementType::U16(0xabcd))],
+    );
+}
+
+#[test]
+fn test_write_node_label_max_len() {
+    // A NodeLabel of the maximum length is written, while a longer one is a ConstraintError
+    let _ = env_logger::try_init();
+    let mut im = ImEngine::new();
+    let node_label = GenericPath::new(
+        Some(0),
+        Some(cluster_basic_information::ID),
+        Some(cluster_basic_information::Attributes::NodeLabel as u32),
+    );
+    let at_max = AttrValue::CharString("a".repeat(NODE_LABEL_MAX_LEN));
+    let over_max = AttrValue::CharString("b".repeat(NODE_LABEL_MAX_LEN + 1));
+
+    let input = &[
+        AttrData::new(
+            None,
+            AttrPath::new(&node_label),
+            EncodeValue::Value(&at_max),
+        ),
+        AttrData::new(
+            None,
+            AttrPath::new(&node_label),
+            EncodeValue::Value(&over_max),
+        ),
+    ];
+    let expected = &[
+        AttrStatus::new(&node_label, IMStatusCode::Success, 0),
+        AttrStatus::new(&node_label, IMStatusCode::ConstraintError, 0),
+    ];
+    handle_write_reqs_on(&mut im, input, expected);
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let mut td = TestData::new(&mut wb);
+    td.read_attrs(&[AttrPath::new(&node_label)]).unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::ReadRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    let label = "a".repeat(NODE_LABEL_MAX_LEN);
+    assert_attr_report(
+        &out_buf[..out_buf_len],
+        &[attr_data!(node_label, ElementType::Utf8l(label.as_bytes()))],
+    );
+}
+
+// Read the attribute at 'path', returning the data version that is reported with it
+fn read_data_ver(im: &mut ImEngine, path: &GenericPath) -> u32 {
+    im.read_attr(path, true, |d| d.data_ver.unwrap())
+}
+
+#[test]
+fn test_write_increments_data_version() {
+    // The data version that is reported with an attribute changes with a write to it
+    let _ = env_logger::try_init();
+    const ATT_RW: u16 = 0x10;
+    let mut im = ImEngine::new();
+    {
+        let mut node = im.dm.node.write().unwrap();
+        let echo = node.get_cluster_mut(0, echo_cluster::ID).unwrap();
+        echo.base_mut()
+            .add_attribute(
+                Attribute::new(ATT_RW, AttrValue::Uint16(0), Access::RWVA, Quality::NONE).unwrap(),
+            )
+            .unwrap();
+    }
+    let ep0_att = GenericPath::new(Some(0), Some(echo_cluster::ID), Some(ATT_RW as u32));
+    let data_ver = read_data_ver(&mut im, &ep0_att);
+
+    let write = |im: &mut ImEngine, val: u16| {
+        let input = &[AttrData::new(
+            None,
+            AttrPath::new(&ep0_att),
+            EncodeValue::Value(&val),
+        )];
+        let expected = &[AttrStatus::new(&ep0_att, IMStatusCode::Success, 0)];
+        handle_write_reqs_on(im, input, expected);
+    };
+
+    // Test1: A write that changes the value
+    write(&mut im, 0xabcd);
+    assert_eq!(read_data_ver(&mut im, &ep0_att), data_ver.wrapping_add(1));
+
+    // Test2: A write of the same value, doesn't change the data version
+    write(&mut im, 0xabcd);
+    assert_eq!(read_data_ver(&mut im, &ep0_att), data_ver.wrapping_add(1));
+}
+
 #[test]
 fn test_write_wc_endpoint() {
     // 1 Attr Write Request
@@ -419,8 +947,8 @@ fn test_write_wc_endpoint() {
         Some(echo_cluster::Attributes::AttWrite as u32),
     );
     let expected = &[
-        AttrStatus::new(&ep0_att, IMStatusCode::Sucess, 0),
-        AttrStatus::new(&ep1_att, IMStatusCode::Sucess, 0),
+        AttrStatus::new(&ep0_att, IMStatusCode::Success, 0),
+        AttrStatus::new(&ep1_att, IMStatusCode::Success, 0),
     ];
 
     let dm = handle_write_reqs(input, expected);
@@ -444,6 +972,53 @@ fn test_write_wc_endpoint() {
     );
 }
 
+#[test]
+fn test_write_wc_endpoint_only_1_has_attribute() {
+    // 1 Attr Write Request
+    // - wildcard endpoint, an attribute that is only present on endpoint 0
+    // - only a single success status for endpoint 0 is reported
+    let val0 = 10;
+    let _ = env_logger::try_init();
+    let attr_data0 = |tag, t: &mut TLVWriter| {
+        let _ = t.u16(tag, val0);
+    };
+    const ATT_EP0_ONLY: u16 = 0x10;
+
+    let mut im = ImEngine::new();
+    {
+        let mut node = im.dm.node.write().unwrap();
+        let echo = node.get_cluster_mut(0, echo_cluster::ID).unwrap();
+        echo.base_mut()
+            .add_attribute(
+                Attribute::new(
+                    ATT_EP0_ONLY,
+                    AttrValue::Uint16(0),
+                    Access::WRITE | Access::NEED_ADMIN,
+                    Quality::NONE,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+    }
+
+    let wc_ep_att = GenericPath::new(None, Some(echo_cluster::ID), Some(ATT_EP0_ONLY as u32));
+    let ep0_att = GenericPath::new(Some(0), Some(echo_cluster::ID), Some(ATT_EP0_ONLY as u32));
+    let input = &[AttrData::new(
+        None,
+        AttrPath::new(&wc_ep_att),
+        EncodeValue::Closure(&attr_data0),
+    )];
+    let expected = &[AttrStatus::new(&ep0_att, IMStatusCode::Success, 0)];
+    handle_write_reqs_on(&mut im, input, expected);
+
+    assert_eq!(
+        AttrValue::Uint16(val0),
+        im.dm
+            .read_attribute_raw(0, echo_cluster::ID, ATT_EP0_ONLY)
+            .unwrap()
+    );
+}
+
 #[test]
 fn test_write_unsupported_fields() {
     // 7 writes
@@ -1,17 +1,29 @@
 use matter::{
-    data_model::{cluster_on_off, objects::EncodeValue},
+    data_model::{
+        cluster_groups::{self, GroupsCluster},
+        cluster_on_off,
+        device_types::device_type_add_on_off_light,
+        objects::{AttrValue, EncodeValue},
+    },
     interaction_model::{
         core::{IMStatusCode, OpCode},
         messages::ib::{CmdPath, CmdStatus, InvResp},
         messages::msg,
     },
-    tlv,
+    tlv::{self, TagType},
+    transport::{
+        packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+        proto_demux::ResponseRequired,
+        queue::{Msg, WorkQ},
+    },
     utils::writebuf::WriteBuf,
 };
 
+use std::{env, process::Command, sync::Mutex, thread, time::Duration};
+
 use crate::common::{
     echo_cluster,
-    im_engine::{im_engine, TestData},
+    im_engine::{im_engine, ImEngine, ImInput, TestData},
 };
 
 enum ExpectedInvResp {
@@ -21,14 +33,19 @@ enum ExpectedInvResp {
 
 // Helper for handling Invoke Command sequences
 fn handle_commands(input: &[(CmdPath, Option<u8>)], expected: &[ExpectedInvResp]) {
-    let mut buf = [0u8; 400];
-    let mut out_buf = [0u8; 400];
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
 
     let buf_len = buf.len();
     let mut wb = WriteBuf::new(&mut buf, buf_len);
     let mut td = TestData::new(&mut wb);
 
-    td.commands(input).unwrap();
+    let paths: Vec<CmdPath> = input.iter().map(|(path, _)| *path).collect();
+    td.commands(&paths, |i, tag, tw| match input[i].1 {
+        Some(d) => tw.u8(tag, d),
+        None => Ok(()),
+    })
+    .unwrap();
 
     let (_, out_buf_len) = im_engine(OpCode::InvokeRequest, wb.as_borrow_slice(), &mut out_buf);
     let out_buf = &out_buf[..out_buf_len];
@@ -114,6 +131,114 @@ fn test_invoke_cmds_success() {
     handle_commands(input, expected);
 }
 
+#[test]
+fn test_invoke_cmd_reads_sibling_cluster() {
+    // A command on the echo cluster that reads the On/Off cluster of its endpoint
+    // while the node is locked for the invoke
+    let _ = env_logger::try_init();
+
+    let echo_on_off = CmdPath::new(
+        Some(1),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Commands::EchoOnOffReq as u16),
+    );
+    let on = CmdPath::new(
+        Some(1),
+        Some(cluster_on_off::ID),
+        Some(cluster_on_off::Commands::On as u16),
+    );
+    let input = &[
+        (echo_on_off, Some(0)),
+        (on, Some(1)),
+        (echo_on_off, Some(0)),
+    ];
+    let expected = &[
+        echo_resp!(1, 0),
+        ExpectedInvResp::Status(CmdStatus::new(on, IMStatusCode::Success, 0)),
+        echo_resp!(1, 1),
+    ];
+    handle_commands(input, expected);
+
+    // Endpoint 0 doesn't have the On/Off cluster
+    let echo_on_off = CmdPath::new(
+        Some(0),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Commands::EchoOnOffReq as u16),
+    );
+    let input = &[(echo_on_off, Some(0))];
+    let expected = &[ExpectedInvResp::Status(CmdStatus::new(
+        echo_on_off,
+        IMStatusCode::UnsupportedCluster,
+        0,
+    ))];
+    handle_commands(input, expected);
+}
+
+#[test]
+fn test_invoke_cmd_deferred() {
+    // An echo Request whose response is sent after the handler has returned
+    let _ = env_logger::try_init();
+    let rx_q = WorkQ::init().unwrap();
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut td = TestData::new(&mut wb);
+    let deferred_req = CmdPath::new(
+        Some(0),
+        Some(echo_cluster::ID),
+        Some(echo_cluster::Commands::DeferredEchoReq as u16),
+    );
+    td.commands(&[deferred_req], |_, tag, tw| tw.u8(tag, 5))
+        .unwrap();
+
+    // Nothing is sent while the command is in progress
+    let (_, out_buf_len) = im_engine(OpCode::InvokeRequest, wb.as_borrow_slice(), &mut out_buf);
+    assert_eq!(out_buf_len, 0);
+
+    let mut resp = None;
+    for _ in 0..100 {
+        if let Ok(Msg::DeferredResponse(r)) = rx_q.try_recv() {
+            resp = Some(r);
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let resp = resp.expect("No response for the deferred command");
+    assert_eq!(resp.exch_id, 1);
+    assert_eq!(resp.proto_id, 0x01);
+    assert_eq!(resp.proto_opcode, OpCode::InvokeResponse as u8);
+
+    let root = tlv::get_root_node_struct(&resp.payload).unwrap();
+    let inv_response = root
+        .find_tag(msg::InvRespTag::InvokeResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match InvResp::from_tlv(&inv_response).unwrap() {
+        InvResp::Cmd(c) => {
+            assert_eq!(
+                c.path,
+                CmdPath::new(
+                    Some(0),
+                    Some(echo_cluster::ID),
+                    Some(echo_cluster::Commands::EchoResp as u16),
+                )
+            );
+            match c.data {
+                EncodeValue::Tlv(t) => assert_eq!(t.find_tag(0).unwrap().u8().unwrap(), 10),
+                _ => panic!("Incorrect CmdDataType"),
+            }
+        }
+        _ => panic!("Invalid response, expected InvResponse::Cmd"),
+    }
+}
+
 #[test]
 fn test_invoke_cmds_unsupported_fields() {
     // 5 commands
@@ -206,8 +331,194 @@ fn test_invoke_cmd_wc_endpoint_only_1_has_cluster() {
     let input = &[(target, Some(1))];
     let expected = &[ExpectedInvResp::Status(CmdStatus::new(
         expected_path,
-        IMStatusCode::Sucess,
+        IMStatusCode::Success,
         0,
     ))];
     handle_commands(input, expected);
 }
+
+#[test]
+fn test_invoke_cmd_wc_endpoint_no_match() {
+    // 1 command with wildcard endpoint that no cluster supports
+    // should generate a well-formed response, with an empty InvokeResponses array
+    let _ = env_logger::try_init();
+
+    let input = &[(
+        CmdPath::new(None, Some(echo_cluster::ID), Some(0x1234)),
+        Some(5),
+    )];
+    handle_commands(input, &[]);
+}
+
+// Add 'endpoint' to the group through the Groups cluster
+fn add_group(engine: &mut ImEngine, endpoint: u16, group_id: u16) {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let path = CmdPath::new(
+        Some(endpoint),
+        Some(cluster_groups::ID),
+        Some(cluster_groups::Commands::AddGroup as u16),
+    );
+    TestData::new(&mut wb)
+        .commands(&[path], |_, tag, tw| {
+            tw.start_struct(tag)?;
+            tw.u16(TagType::Context(0), group_id)?;
+            tw.utf8(TagType::Context(1), b"")?;
+            tw.end_container()
+        })
+        .unwrap();
+
+    let input = ImInput::new(OpCode::InvokeRequest, wb.as_borrow_slice());
+    let out_len = engine.process(&input, &mut out_buf);
+    let root = tlv::get_root_node_struct(&out_buf[..out_len]).unwrap();
+    let resp = root
+        .find_tag(msg::InvRespTag::InvokeResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match InvResp::from_tlv(&resp).unwrap() {
+        InvResp::Cmd(c) => match c.data {
+            // The status of the AddGroup Response
+            EncodeValue::Tlv(t) => assert_eq!(t.find_tag(0).unwrap().u8().unwrap(), 0),
+            _ => panic!("Incorrect CmdDataType"),
+        },
+        _ => panic!("Invalid response, expected InvResponse::Cmd"),
+    }
+}
+
+#[test]
+fn test_invoke_group_cmd() {
+    // An On command to a group with two member endpoints
+    let _ = env_logger::try_init();
+    const GROUP_ID: u16 = 0x10;
+    const EMPTY_GROUP_ID: u16 = 0x11;
+
+    let mut engine = ImEngine::new();
+    let lights = {
+        let mut node = engine.dm.node.write().unwrap();
+        let lights = [1, device_type_add_on_off_light(&mut node).unwrap() as u16];
+        for light in lights.iter() {
+            node.add_cluster(
+                *light as u32,
+                GroupsCluster::new(engine.dm.group_mgr()).unwrap(),
+            )
+            .unwrap();
+        }
+        lights
+    };
+    add_group(&mut engine, lights[0], GROUP_ID);
+    add_group(&mut engine, lights[1], GROUP_ID);
+
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut td = TestData::new(&mut wb);
+    let on = CmdPath::new(
+        None,
+        Some(cluster_on_off::ID),
+        Some(cluster_on_off::Commands::On as u16),
+    );
+    td.commands(&[on], |_, tag, tw| tw.u8(tag, 1)).unwrap();
+
+    let on_off = |engine: &ImEngine, light: u16| {
+        engine
+            .dm
+            .read_attribute_raw(
+                light,
+                cluster_on_off::ID,
+                cluster_on_off::Attributes::OnOff as u16,
+            )
+            .unwrap()
+    };
+
+    // A group without any members
+    let result = engine.process_group_invoke(EMPTY_GROUP_ID, wb.as_borrow_slice());
+    assert!(result == ResponseRequired::No);
+    assert_eq!(on_off(&engine, lights[0]), AttrValue::Bool(false));
+    assert_eq!(on_off(&engine, lights[1]), AttrValue::Bool(false));
+
+    // Group commands don't have a response
+    let result = engine.process_group_invoke(GROUP_ID, wb.as_borrow_slice());
+    assert!(result == ResponseRequired::No);
+    assert_eq!(on_off(&engine, lights[0]), AttrValue::Bool(true));
+    assert_eq!(on_off(&engine, lights[1]), AttrValue::Bool(true));
+}
+
+// Records every log message, so the child run can check that command
+// handling went through the logger
+struct CaptureLogger(Mutex<Vec<String>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(format!("{}", record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+const STDOUT_CHILD_ENV: &str = "MATTER_TEST_STDOUT_CHILD";
+
+#[test]
+fn test_invoke_cmds_no_stdout() {
+    // The test harness swallows stdout, so re-run this test in a child
+    // process with --nocapture and check what actually reaches stdout
+    if env::var(STDOUT_CHILD_ENV).is_ok() {
+        let logger: &'static CaptureLogger =
+            Box::leak(Box::new(CaptureLogger(Mutex::new(Vec::new()))));
+        log::set_logger(logger).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let mut buf = [0u8; MAX_RX_BUF_SIZE];
+        let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+        let buf_len = buf.len();
+        let mut wb = WriteBuf::new(&mut buf, buf_len);
+        let mut td = TestData::new(&mut wb);
+        let on = CmdPath::new(
+            Some(1),
+            Some(cluster_on_off::ID),
+            Some(cluster_on_off::Commands::On as u16),
+        );
+        let (echo, _) = echo_req!(0, 5);
+        td.commands(&[echo, on], |i, tag, tw| tw.u8(tag, [5, 1][i]))
+            .unwrap();
+        im_engine(OpCode::InvokeRequest, wb.as_borrow_slice(), &mut out_buf);
+
+        let logs = logger.0.lock().unwrap();
+        assert!(logs.iter().any(|l| l.contains("Handling Command")));
+        assert!(logs.iter().any(|l| l.contains("TLV list:")));
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .args([
+            "data_model::commands::test_invoke_cmds_no_stdout",
+            "--exact",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(STDOUT_CHILD_ENV, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Anything other than the harness' own report came from the code under test
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1 passed"));
+    for line in stdout.lines() {
+        assert!(
+            line.is_empty() || line.starts_with("running ") || line.starts_with("test "),
+            "unexpected stdout output: {}",
+            line
+        );
+    }
+}
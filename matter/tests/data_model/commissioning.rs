@@ -0,0 +1,358 @@
+use matter::{
+    data_model::{
+        objects::{AttrValue, EncodeValue},
+        sdm::{
+            failsafe::FailSafe,
+            general_commissioning::{
+                self, Attributes, Commands, GenCommCluster, RegLocationType,
+                FAILSAFE_BUSY_RETRY_DELAY_MS, MAX_CUMULATIVE_FAILSAFE_SECS,
+                MAX_FAILSAFE_EXPIRY_SECS,
+            },
+        },
+    },
+    error::Error,
+    interaction_model::{
+        core::IMStatusCode,
+        core::OpCode,
+        messages::ib::{CmdPath, CmdStatus, InvResp},
+        messages::{msg, GenericPath},
+    },
+    tlv::{self, TLVWriter, TagType},
+    transport::{
+        packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+        session::SessionMode,
+    },
+    utils::{tick::Tick, writebuf::WriteBuf},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::common::im_engine::{ImEngine, ImInput, TestData};
+
+// The General Commissioning cluster is added to an endpoint of its own, so the test
+// can get to its Fail-Safe
+const COMM_ENDPOINT: u16 = 1;
+
+const ERR_OK: u8 = 0;
+const ERR_VALUE_OUTSIDE_RANGE: u8 = 1;
+const ERR_INVALID_AUTH: u8 = 2;
+
+fn engine_with_capability(location_capability: RegLocationType) -> (ImEngine, Arc<FailSafe>) {
+    engine_with_cluster(GenCommCluster::new(location_capability).unwrap())
+}
+
+fn engine_with_cluster(cluster: Box<GenCommCluster>) -> (ImEngine, Arc<FailSafe>) {
+    let engine = ImEngine::new();
+    let failsafe = cluster.failsafe();
+    engine
+        .dm
+        .node
+        .write()
+        .unwrap()
+        .add_cluster(COMM_ENDPOINT as u32, cluster)
+        .unwrap();
+    (engine, failsafe)
+}
+
+fn engine_with_failsafe() -> (ImEngine, Arc<FailSafe>) {
+    engine_with_capability(RegLocationType::IndoorOutdoor)
+}
+
+fn cmd_path(cmd: Commands) -> CmdPath {
+    CmdPath::new(
+        Some(COMM_ENDPOINT),
+        Some(general_commissioning::ID),
+        Some(cmd as u16),
+    )
+}
+
+// Invoke the command, with the fields that 'data' encodes, returning the error code of
+// its response, or the status if the command failed
+fn invoke<F>(engine: &mut ImEngine, cmd: Commands, data: F) -> Result<u8, CmdStatus>
+where
+    F: Fn(&mut TLVWriter) -> Result<(), Error>,
+{
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    TestData::new(&mut wb)
+        .commands(&[cmd_path(cmd)], |_, tag, tw| {
+            tw.start_struct(tag)?;
+            data(tw)?;
+            tw.end_container()
+        })
+        .unwrap();
+
+    let input = ImInput::new(OpCode::InvokeRequest, wb.as_borrow_slice());
+    let out_len = engine.process(&input, &mut out_buf);
+    let root = tlv::get_root_node_struct(&out_buf[..out_len]).unwrap();
+    let resp = root
+        .find_tag(msg::InvRespTag::InvokeResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match InvResp::from_tlv(&resp).unwrap() {
+        InvResp::Cmd(c) => match c.data {
+            EncodeValue::Tlv(t) => Ok(t.find_tag(0).unwrap().u8().unwrap()),
+            _ => panic!("Incorrect CmdDataType"),
+        },
+        InvResp::Status(s) => Err(s),
+    }
+}
+
+fn commissioning_complete(engine: &mut ImEngine) -> u8 {
+    // The command has no fields
+    invoke(engine, Commands::CommissioningComplete, |_| Ok(())).unwrap()
+}
+
+fn set_regulatory_config(engine: &mut ImEngine, config: RegLocationType) -> u8 {
+    invoke(engine, Commands::SetRegulatoryConfig, |tw| {
+        tw.u8(TagType::Context(0), config as u8)?;
+        tw.utf8(TagType::Context(1), b"XX")?;
+        tw.u64(TagType::Context(2), 0)
+    })
+    .unwrap()
+}
+
+fn reg_config(engine: &ImEngine) -> AttrValue {
+    engine
+        .dm
+        .node
+        .read()
+        .unwrap()
+        .get_cluster(COMM_ENDPOINT, general_commissioning::ID)
+        .unwrap()
+        .base()
+        .read_attribute_raw(Attributes::RegConfig as u16)
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn test_commissioning_complete() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    // The IM Engine's session is a CASE session on fabric 1
+    failsafe.arm(60, SessionMode::Case(1)).unwrap();
+    failsafe.record_add_noc(1).unwrap();
+    assert_eq!(commissioning_complete(&mut engine), ERR_OK);
+    assert!(!failsafe.is_armed());
+
+    // There is nothing to complete anymore
+    assert_eq!(commissioning_complete(&mut engine), ERR_INVALID_AUTH);
+}
+
+#[test]
+fn test_commissioning_complete_failsafe_expired() {
+    let _ = env_logger::try_init();
+    let (mut engine, mut failsafe) = engine_with_failsafe();
+
+    failsafe.arm(1, SessionMode::Case(1)).unwrap();
+    failsafe.record_add_noc(1).unwrap();
+    // The tick is given a time past the expiry, instead of the test waiting for it
+    let expired_at = SystemTime::now() + Duration::from_secs(2);
+    assert_eq!(failsafe.tick(expired_at), None);
+
+    // The session is still on the fabric of the AddNOC, but the Fail-Safe has expired
+    assert_eq!(commissioning_complete(&mut engine), ERR_INVALID_AUTH);
+    assert!(!failsafe.is_armed());
+}
+
+#[test]
+fn test_set_regulatory_config_within_capability() {
+    let _ = env_logger::try_init();
+    let (mut engine, _) = engine_with_failsafe();
+    assert_eq!(
+        reg_config(&engine),
+        AttrValue::Uint8(RegLocationType::IndoorOutdoor as u8)
+    );
+
+    assert_eq!(
+        set_regulatory_config(&mut engine, RegLocationType::Outdoor),
+        ERR_OK
+    );
+    assert_eq!(
+        reg_config(&engine),
+        AttrValue::Uint8(RegLocationType::Outdoor as u8)
+    );
+}
+
+#[test]
+fn test_set_regulatory_config_exceeds_capability() {
+    let _ = env_logger::try_init();
+    let (mut engine, _) = engine_with_capability(RegLocationType::Indoor);
+    // Both attributes start out as the location capability
+    assert_eq!(
+        reg_config(&engine),
+        AttrValue::Uint8(RegLocationType::Indoor as u8)
+    );
+
+    assert_eq!(
+        set_regulatory_config(&mut engine, RegLocationType::Outdoor),
+        ERR_VALUE_OUTSIDE_RANGE
+    );
+    assert_eq!(
+        set_regulatory_config(&mut engine, RegLocationType::IndoorOutdoor),
+        ERR_VALUE_OUTSIDE_RANGE
+    );
+    assert_eq!(
+        reg_config(&engine),
+        AttrValue::Uint8(RegLocationType::Indoor as u8)
+    );
+
+    assert_eq!(
+        set_regulatory_config(&mut engine, RegLocationType::Indoor),
+        ERR_OK
+    );
+}
+
+// Read the BasicCommissioningInfo, returning its FailSafeExpiryLengthSeconds and
+// MaxCumulativeFailsafeSeconds
+fn basic_comm_info(engine: &mut ImEngine) -> (u16, u16) {
+    let path = GenericPath::new(
+        Some(COMM_ENDPOINT),
+        Some(general_commissioning::ID),
+        Some(Attributes::BasicCommissioningInfo as u32),
+    );
+    engine.read_attr(&path, true, |d| {
+        let info = d.data.unwrap_tlv().unwrap().confirm_struct().unwrap();
+        (
+            info.find_tag(0).unwrap().u16().unwrap(),
+            info.find_tag(1).unwrap().u16().unwrap(),
+        )
+    })
+}
+
+#[test]
+fn test_basic_commissioning_info() {
+    let _ = env_logger::try_init();
+    let (mut engine, _) = engine_with_failsafe();
+    assert_eq!(
+        basic_comm_info(&mut engine),
+        (MAX_FAILSAFE_EXPIRY_SECS, MAX_CUMULATIVE_FAILSAFE_SECS)
+    );
+
+    let mut cluster = GenCommCluster::new(RegLocationType::Indoor).unwrap();
+    cluster.set_max_cumulative_failsafe(600);
+    let (mut engine, _) = engine_with_cluster(cluster);
+    assert_eq!(
+        basic_comm_info(&mut engine),
+        (MAX_FAILSAFE_EXPIRY_SECS, 600)
+    );
+}
+
+fn arm_failsafe(engine: &mut ImEngine, expiry_len: u16) -> Result<u8, CmdStatus> {
+    invoke(engine, Commands::ArmFailsafe, |tw| {
+        tw.u16(TagType::Context(0), expiry_len)?;
+        tw.u64(TagType::Context(1), 0)
+    })
+}
+
+#[test]
+fn test_arm_failsafe() {
+    let _ = env_logger::try_init();
+    let (mut engine, mut failsafe) = engine_with_failsafe();
+
+    let start = SystemTime::now();
+    assert_eq!(arm_failsafe(&mut engine, 60), Ok(ERR_OK));
+    // The Fail-Safe expires after the time that was asked for
+    let expiry = failsafe.tick(SystemTime::now()).unwrap();
+    assert!(expiry >= start + Duration::from_secs(60));
+    assert!(expiry <= SystemTime::now() + Duration::from_secs(60));
+}
+
+#[test]
+fn test_arm_failsafe_over_max() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    assert_eq!(
+        arm_failsafe(&mut engine, MAX_FAILSAFE_EXPIRY_SECS + 1),
+        Ok(ERR_VALUE_OUTSIDE_RANGE)
+    );
+    assert!(!failsafe.is_armed());
+    assert_eq!(
+        arm_failsafe(&mut engine, MAX_FAILSAFE_EXPIRY_SECS),
+        Ok(ERR_OK)
+    );
+    assert!(failsafe.is_armed());
+}
+
+#[test]
+fn test_arm_failsafe_zero_disarms() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    // Nothing to disarm
+    assert_eq!(arm_failsafe(&mut engine, 0), Ok(ERR_OK));
+    assert!(!failsafe.is_armed());
+
+    assert_eq!(arm_failsafe(&mut engine, 60), Ok(ERR_OK));
+    assert!(failsafe.is_armed());
+    assert_eq!(arm_failsafe(&mut engine, 0), Ok(ERR_OK));
+    assert!(!failsafe.is_armed());
+
+    // Only the session that armed the Fail-Safe can disarm it
+    failsafe.arm(60, SessionMode::Case(2)).unwrap();
+    assert!(arm_failsafe(&mut engine, 0).is_err());
+    assert!(failsafe.is_armed());
+}
+
+#[test]
+fn test_arm_failsafe_missing_field() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    // The Breadcrumb is mandatory
+    let result = invoke(&mut engine, Commands::ArmFailsafe, |tw| {
+        tw.u16(TagType::Context(0), 60)
+    });
+    assert_eq!(
+        result,
+        Err(CmdStatus::new(
+            cmd_path(Commands::ArmFailsafe),
+            IMStatusCode::InvalidCommand,
+            0
+        ))
+    );
+    assert!(!failsafe.is_armed());
+}
+
+#[test]
+fn test_arm_failsafe_unknown_field() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    // A field that isn't known yet is ignored
+    let result = invoke(&mut engine, Commands::ArmFailsafe, |tw| {
+        tw.u16(TagType::Context(0), 60)?;
+        tw.u64(TagType::Context(1), 0)?;
+        tw.u8(TagType::Context(5), 1)
+    });
+    assert_eq!(result, Ok(ERR_OK));
+    assert!(failsafe.is_armed());
+}
+
+#[test]
+fn test_arm_failsafe_busy() {
+    let _ = env_logger::try_init();
+    let (mut engine, failsafe) = engine_with_failsafe();
+
+    // Armed by a session on another fabric, the peer is asked to retry later
+    failsafe.arm(60, SessionMode::Case(2)).unwrap();
+    let result = invoke(&mut engine, Commands::ArmFailsafe, |tw| {
+        tw.u16(TagType::Context(0), 60)?;
+        tw.u64(TagType::Context(1), 0)
+    });
+    let status = *result.unwrap_err().status();
+    assert_eq!(status.status, IMStatusCode::Busy);
+    assert_eq!(status.retry_delay, Some(FAILSAFE_BUSY_RETRY_DELAY_MS));
+}
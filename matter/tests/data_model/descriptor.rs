@@ -0,0 +1,50 @@
+use matter::{
+    data_model::{
+        cluster_on_off::{self, OnOffCluster},
+        device_types::DEV_TYPE_ON_OFF_LIGHT,
+        system_model::descriptor,
+    },
+    interaction_model::messages::GenericPath,
+};
+
+use crate::common::im_engine::ImEngine;
+
+// Read the ServerList of 'endpoint'
+fn read_server_list(im: &mut ImEngine, endpoint: u16) -> Vec<u32> {
+    let path = GenericPath::new(
+        Some(endpoint),
+        Some(descriptor::ID),
+        Some(descriptor::Attributes::ServerList as u32),
+    );
+    im.read_attr(&path, true, |d| {
+        d.data
+            .unwrap_tlv()
+            .unwrap()
+            .confirm_array()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|c| c.u32().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_add_endpoint_server_list() {
+    let _ = env_logger::try_init();
+    let mut im = ImEngine::new();
+
+    let endpoint = im.dm.add_endpoint(&[DEV_TYPE_ON_OFF_LIGHT]).unwrap();
+    // The root node and the light of the IM Engine are there already
+    assert_eq!(endpoint, 2);
+    assert_eq!(read_server_list(&mut im, endpoint), vec![descriptor::ID]);
+
+    im.dm
+        .add_cluster(endpoint, OnOffCluster::new().unwrap())
+        .unwrap();
+    assert_eq!(
+        read_server_list(&mut im, endpoint),
+        vec![descriptor::ID, cluster_on_off::ID]
+    );
+    im.dm.node.read().unwrap().validate_device_types().unwrap();
+}
@@ -0,0 +1,189 @@
+use matter::{
+    data_model::{
+        cluster_level_control::{self, LevelControlCluster, StepMode},
+        objects::AttrValue,
+    },
+    interaction_model::{
+        core::{IMStatusCode, OpCode},
+        messages::ib::{CmdPath, CmdStatus, InvResp},
+        messages::msg,
+    },
+    tlv::{self, TagType},
+    transport::packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+    utils::writebuf::WriteBuf,
+};
+
+use crate::common::im_engine::{ImEngine, ImInput, TestData};
+
+const LIGHT_ENDPOINT: u16 = 1;
+const MIN_LEVEL: u8 = 1;
+const MAX_LEVEL: u8 = 254;
+
+fn engine_with_level_control() -> ImEngine {
+    let engine = ImEngine::new();
+    engine
+        .dm
+        .node
+        .write()
+        .unwrap()
+        .add_cluster(
+            LIGHT_ENDPOINT as u32,
+            LevelControlCluster::new(MIN_LEVEL, MAX_LEVEL).unwrap(),
+        )
+        .unwrap();
+    engine
+}
+
+// Invoke 'cmd' with the command fields 'args', and check the status of the response
+fn invoke(
+    engine: &mut ImEngine,
+    cmd: cluster_level_control::Commands,
+    args: &[u8],
+    expected: IMStatusCode,
+) {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let path = CmdPath::new(
+        Some(LIGHT_ENDPOINT),
+        Some(cluster_level_control::ID),
+        Some(cmd as u16),
+    );
+    TestData::new(&mut wb)
+        .commands(&[path], |_, tag, tw| {
+            tw.start_struct(tag)?;
+            for (tag, arg) in args.iter().enumerate() {
+                tw.u8(TagType::Context(tag as u8), *arg)?;
+            }
+            tw.end_container()
+        })
+        .unwrap();
+
+    let input = ImInput::new(OpCode::InvokeRequest, wb.as_borrow_slice());
+    let out_len = engine.process(&input, &mut out_buf);
+    let root = tlv::get_root_node_struct(&out_buf[..out_len]).unwrap();
+    let resp = root
+        .find_tag(msg::InvRespTag::InvokeResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match InvResp::from_tlv(&resp).unwrap() {
+        InvResp::Status(s) => assert_eq!(s, CmdStatus::new(path, expected, 0)),
+        _ => panic!("Invalid response, expected InvResponse::Status"),
+    }
+}
+
+fn current_level(engine: &ImEngine) -> AttrValue {
+    engine
+        .dm
+        .read_attribute_raw(
+            LIGHT_ENDPOINT,
+            cluster_level_control::ID,
+            cluster_level_control::Attributes::CurrentLevel as u16,
+        )
+        .unwrap()
+}
+
+fn take_dirty(engine: &ImEngine) -> Vec<u16> {
+    engine
+        .dm
+        .node
+        .write()
+        .unwrap()
+        .get_cluster_mut(LIGHT_ENDPOINT, cluster_level_control::ID)
+        .unwrap()
+        .base_mut()
+        .take_dirty()
+}
+
+#[test]
+fn test_move_to_level() {
+    let _ = env_logger::try_init();
+    let mut engine = engine_with_level_control();
+    assert_eq!(current_level(&engine), AttrValue::Null);
+
+    // Out of range levels are rejected, and leave the level as it was
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::MoveToLevel,
+        &[MAX_LEVEL + 1],
+        IMStatusCode::ConstraintError,
+    );
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::MoveToLevel,
+        &[MIN_LEVEL - 1],
+        IMStatusCode::ConstraintError,
+    );
+    assert_eq!(current_level(&engine), AttrValue::Null);
+    assert!(take_dirty(&engine).is_empty());
+
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::MoveToLevel,
+        &[100],
+        IMStatusCode::Success,
+    );
+    assert_eq!(current_level(&engine), AttrValue::Uint8(100));
+    assert_eq!(
+        take_dirty(&engine),
+        vec![cluster_level_control::Attributes::CurrentLevel as u16]
+    );
+}
+
+#[test]
+fn test_step() {
+    let _ = env_logger::try_init();
+    let mut engine = engine_with_level_control();
+
+    // There is no level to step from yet
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::Step,
+        &[StepMode::Up as u8, 10],
+        IMStatusCode::Failure,
+    );
+
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::MoveToLevel,
+        &[100],
+        IMStatusCode::Success,
+    );
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::Step,
+        &[StepMode::Down as u8, 10],
+        IMStatusCode::Success,
+    );
+    assert_eq!(current_level(&engine), AttrValue::Uint8(90));
+
+    // Steps past the limits stop at the limit
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::Step,
+        &[StepMode::Up as u8, 200],
+        IMStatusCode::Success,
+    );
+    assert_eq!(current_level(&engine), AttrValue::Uint8(MAX_LEVEL));
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::Step,
+        &[StepMode::Down as u8, 255],
+        IMStatusCode::Success,
+    );
+    assert_eq!(current_level(&engine), AttrValue::Uint8(MIN_LEVEL));
+
+    // An unknown step mode
+    invoke(
+        &mut engine,
+        cluster_level_control::Commands::Step,
+        &[2, 10],
+        IMStatusCode::ConstraintError,
+    );
+}
@@ -0,0 +1,170 @@
+use matter::{
+    data_model::{
+        objects::EncodeValue,
+        sdm::noc::{self, Attributes, Commands},
+    },
+    interaction_model::{
+        core::{IMStatusCode, OpCode},
+        messages::ib::{CmdPath, CmdStatus, InvResp},
+        messages::{msg, GenericPath},
+    },
+    tlv::{self, TLVElement, TagType},
+    transport::packet::{MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+    utils::writebuf::WriteBuf,
+};
+
+use crate::common::{
+    fabric::test_fabric,
+    im_engine::{ImEngine, ImInput, TestData},
+};
+
+const STATUS_OK: u8 = 0;
+const STATUS_LABEL_CONFLICT: u8 = 10;
+
+// The fields of an entry of the Fabrics attribute
+#[derive(Debug, PartialEq)]
+struct FabricDesc {
+    vendor_id: u16,
+    label: String,
+    fab_idx: u8,
+}
+
+impl FabricDesc {
+    fn new(vendor_id: u16, label: &str, fab_idx: u8) -> Self {
+        Self {
+            vendor_id,
+            label: label.to_owned(),
+            fab_idx,
+        }
+    }
+
+    fn from_tlv(t: &TLVElement) -> Self {
+        let label = t.find_tag(5).unwrap().slice().unwrap();
+        Self {
+            vendor_id: t.find_tag(2).unwrap().u16().unwrap(),
+            label: String::from_utf8(label.to_vec()).unwrap(),
+            fab_idx: t.find_tag(0xFE).unwrap().u8().unwrap(),
+        }
+    }
+}
+
+fn update_fabric_label_path() -> CmdPath {
+    CmdPath::new(
+        Some(0),
+        Some(noc::ID),
+        Some(Commands::UpdateFabricLabel as u16),
+    )
+}
+
+// Invoke UpdateFabricLabel, returning the status code and fabric index of the NOCResponse,
+// or the status if the command failed
+fn update_fabric_label(im: &mut ImEngine, label: &str) -> Result<(u8, u8), CmdStatus> {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    TestData::new(&mut wb)
+        .commands(&[update_fabric_label_path()], |_, tag, tw| {
+            tw.start_struct(tag)?;
+            tw.utf8(TagType::Context(0), label.as_bytes())?;
+            tw.end_container()
+        })
+        .unwrap();
+
+    let input = ImInput::new(OpCode::InvokeRequest, wb.as_borrow_slice());
+    let out_len = im.process(&input, &mut out_buf);
+    let root = tlv::get_root_node_struct(&out_buf[..out_len]).unwrap();
+    let resp = root
+        .find_tag(msg::InvRespTag::InvokeResponses as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap()
+        .next()
+        .unwrap();
+    match InvResp::from_tlv(&resp).unwrap() {
+        InvResp::Cmd(c) => match c.data {
+            EncodeValue::Tlv(t) => Ok((
+                t.find_tag(0).unwrap().u8().unwrap(),
+                t.find_tag(1).unwrap().u8().unwrap(),
+            )),
+            _ => panic!("Incorrect CmdDataType"),
+        },
+        InvResp::Status(s) => Err(s),
+    }
+}
+
+// Read the Fabrics attribute, returning the entries in the list
+fn read_fabrics(im: &mut ImEngine, fabric_filtered: bool) -> Vec<FabricDesc> {
+    let path = GenericPath::new(Some(0), Some(noc::ID), Some(Attributes::Fabrics as u32));
+    im.read_attr(&path, fabric_filtered, |d| {
+        d.data
+            .unwrap_tlv()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|e| FabricDesc::from_tlv(&e))
+            .collect()
+    })
+}
+
+// The Data Version of the Operational Credentials cluster
+fn read_dataver(im: &mut ImEngine) -> u32 {
+    let path = GenericPath::new(Some(0), Some(noc::ID), Some(Attributes::Fabrics as u32));
+    im.read_attr(&path, true, |d| d.data_ver.unwrap())
+}
+
+#[test]
+/// Ensure that the label set with UpdateFabricLabel is reported in the Fabrics attribute,
+/// and that fabric filtering only reports the accessor's fabric
+fn test_update_fabric_label() {
+    let _ = env_logger::try_init();
+    let mut im = ImEngine::new();
+    // The IM Engine accesses the node over the first fabric
+    assert_eq!(im.fabric_mgr.add(test_fabric(0xfff1)).unwrap(), 1);
+    assert_eq!(im.fabric_mgr.add(test_fabric(0xfff2)).unwrap(), 2);
+    im.fabric_mgr.set_label(2, "Office").unwrap();
+
+    assert_eq!(
+        read_fabrics(&mut im, true),
+        &[FabricDesc::new(0xfff1, "", 1)]
+    );
+
+    // Test1: Set the label of the accessor's fabric, that changes the cluster's Data Version
+    let dataver = read_dataver(&mut im);
+    assert_eq!(update_fabric_label(&mut im, "Home"), Ok((STATUS_OK, 1)));
+    assert_eq!(read_dataver(&mut im), dataver.wrapping_add(1));
+    assert_eq!(
+        read_fabrics(&mut im, true),
+        &[FabricDesc::new(0xfff1, "Home", 1)]
+    );
+    assert_eq!(
+        read_fabrics(&mut im, false),
+        &[
+            FabricDesc::new(0xfff1, "Home", 1),
+            FabricDesc::new(0xfff2, "Office", 2)
+        ]
+    );
+
+    // Test2: The label of another fabric can't be reused
+    assert_eq!(
+        update_fabric_label(&mut im, "Office"),
+        Ok((STATUS_LABEL_CONFLICT, 0))
+    );
+    assert_eq!(read_dataver(&mut im), dataver.wrapping_add(1));
+
+    // Test3: The label is too long
+    assert_eq!(
+        update_fabric_label(&mut im, &"x".repeat(33)),
+        Err(CmdStatus::new(
+            update_fabric_label_path(),
+            IMStatusCode::ConstraintError,
+            0
+        ))
+    );
+    assert_eq!(
+        read_fabrics(&mut im, true),
+        &[FabricDesc::new(0xfff1, "Home", 1)]
+    );
+}
@@ -0,0 +1,382 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use matter::{
+    data_model::{objects::EncodeValue, sdm::general_commissioning},
+    error::Error,
+    interaction_model::{
+        core::{IMStatusCode, OpCode},
+        messages::{
+            ib::{AttrData, AttrPath, AttrResp},
+            msg::{self, ReadReq, StatusResp, SubscribeReq, SubscribeResp, WriteReq},
+            GenericPath,
+        },
+    },
+    tlv::{self, FromTLV, TLVWriter, TagType, ToTLV},
+    transport::{
+        exchange::ExchangeMgr,
+        network::{Address, NetworkInterface},
+        packet::{Packet, MAX_RX_BUF_SIZE, MAX_TX_BUF_SIZE},
+        proto_demux::HandleProto,
+        session::{CloneData, SessionMgr, SessionMode},
+    },
+    utils::writebuf::WriteBuf,
+};
+
+use crate::common::im_engine::{ImEngine, ImInput, IM_ENGINE_PEER_ID};
+
+const MIN_INTERVAL_FLOOR: u16 = 5;
+const MAX_INTERVAL_CEIL: u16 = 60;
+
+fn breadcrumb_path() -> GenericPath {
+    GenericPath::new(
+        Some(0),
+        Some(general_commissioning::ID),
+        Some(general_commissioning::Attributes::BreadCrumb as u32),
+    )
+}
+
+// Returns the subscription id and the Breadcrumb value carried by the report
+fn parse_report(out_buf: &[u8]) -> (u32, u64) {
+    tlv::print_tlv_list(out_buf);
+    let root = tlv::get_root_node_struct(out_buf).unwrap();
+    let subs_id = root
+        .find_tag(msg::ReportDataTag::SubscriptionId as u32)
+        .unwrap()
+        .u32()
+        .unwrap();
+    // Subscription reports always expect a StatusResponse from the peer
+    assert!(!root
+        .find_tag(msg::ReportDataTag::SupressResponse as u32)
+        .unwrap()
+        .bool()
+        .unwrap());
+
+    let mut reports = root
+        .find_tag(msg::ReportDataTag::AttributeReports as u32)
+        .unwrap()
+        .confirm_array()
+        .unwrap()
+        .iter()
+        .unwrap();
+    let value = match AttrResp::from_tlv(&reports.next().unwrap()).unwrap() {
+        AttrResp::Data(d) => match d.data {
+            EncodeValue::Tlv(t) => t.u64().unwrap(),
+            _ => panic!("Incorrect data type"),
+        },
+        _ => panic!("Invalid response, expected AttrResp::Data"),
+    };
+    assert!(reports.next().is_none());
+    (subs_id, value)
+}
+
+// Acknowledge the last report, returns the length of what is sent in response
+fn ack(im: &mut ImEngine, out_buf: &mut [u8]) -> usize {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let status_resp = StatusResp::new(IMStatusCode::Success);
+    status_resp.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    im.process(
+        &ImInput::new(OpCode::StatusResponse, wb.as_borrow_slice()),
+        out_buf,
+    )
+}
+
+// Acknowledge the chunks of a report, the first of which is in 'out_buf', up to the last one
+//
+// Returns the subscription id of the chunks, the number of chunks, and the length of what
+// is sent in response to the acknowledgement of the last chunk.
+fn ack_chunks(im: &mut ImEngine, out_buf: &mut [u8], mut len: usize) -> (u32, usize, usize) {
+    let mut subs_id = None;
+    let mut chunks = 0;
+    loop {
+        let root = tlv::get_root_node_struct(&out_buf[..len]).unwrap();
+        let id = root
+            .find_tag(msg::ReportDataTag::SubscriptionId as u32)
+            .unwrap()
+            .u32()
+            .unwrap();
+        assert_eq!(*subs_id.get_or_insert(id), id);
+        // Every chunk expects a StatusResponse, the last one included
+        assert!(!root
+            .find_tag(msg::ReportDataTag::SupressResponse as u32)
+            .unwrap()
+            .bool()
+            .unwrap());
+        let more_chunks = root
+            .find_tag(msg::ReportDataTag::MoreChunkedMsgs as u32)
+            .is_ok_and(|t| t.bool().unwrap());
+        chunks += 1;
+
+        len = ack(im, out_buf);
+        if !more_chunks {
+            return (id, chunks, len);
+        }
+    }
+}
+
+fn subscribe(im: &mut ImEngine) -> u32 {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    // The peer asks for no floor at all
+    let paths = [AttrPath::new(&breadcrumb_path())];
+    let req = SubscribeReq::new(true, 0, MAX_INTERVAL_CEIL).set_attr_requests(&paths);
+    req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::SubscribeRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    let (subs_id, value) = parse_report(&out_buf[..out_buf_len]);
+    assert_eq!(value, 0);
+
+    // The subscription is confirmed once the priming report is acknowledged
+    let out_buf_len = ack(im, &mut out_buf);
+    let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+    let resp = SubscribeResp::from_tlv(&root).unwrap();
+    assert_eq!(resp, SubscribeResp::new(subs_id, MAX_INTERVAL_CEIL));
+    subs_id
+}
+
+fn write_breadcrumb(im: &mut ImEngine, val: u64) {
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let attr_data = |tag, t: &mut TLVWriter| {
+        let _ = t.u64(tag, val);
+    };
+    let input = &[AttrData::new(
+        None,
+        AttrPath::new(&breadcrumb_path()),
+        EncodeValue::Closure(&attr_data),
+    )];
+    let write_req = WriteReq::new(false, input);
+    write_req.to_tlv(&mut tw, TagType::Anonymous).unwrap();
+    im.process(
+        &ImInput::new(OpCode::WriteRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+}
+
+#[test]
+fn test_subscribe_min_interval_floor() {
+    // Two changes within the min interval
+    // - are not reported until the min interval elapses
+    // - are then reported once, with the final value
+    let _ = env_logger::try_init();
+    let mut im = ImEngine::new();
+    im.im.set_min_interval_floor(MIN_INTERVAL_FLOOR);
+    let subs_id = subscribe(&mut im);
+    let start = SystemTime::now();
+
+    let subscription = im.im.get_subscription(subs_id).unwrap();
+    assert_eq!(subscription.get_min_interval(), MIN_INTERVAL_FLOOR);
+    assert_eq!(subscription.get_max_interval(), MAX_INTERVAL_CEIL);
+
+    write_breadcrumb(&mut im, 1);
+    write_breadcrumb(&mut im, 2);
+
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+    assert_eq!(im.report_subscription(subs_id, start, &mut out_buf), None);
+
+    let now = start + Duration::from_secs(MIN_INTERVAL_FLOOR as u64);
+    let out_buf_len = im.report_subscription(subs_id, now, &mut out_buf).unwrap();
+    assert_eq!(parse_report(&out_buf[..out_buf_len]), (subs_id, 2));
+
+    // Nothing more to report until something changes again
+    let now = now + Duration::from_secs(MIN_INTERVAL_FLOOR as u64);
+    assert_eq!(im.report_subscription(subs_id, now, &mut out_buf), None);
+}
+
+#[test]
+fn test_priming_report_matches_read() {
+    let _ = env_logger::try_init();
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut read_out = [0u8; MAX_TX_BUF_SIZE];
+    let mut priming_out = [0u8; MAX_TX_BUF_SIZE];
+
+    // All the attributes of the cluster, along with one of them again
+    let paths = [
+        AttrPath::new(&GenericPath::new(
+            Some(0),
+            Some(general_commissioning::ID),
+            None,
+        )),
+        AttrPath::new(&breadcrumb_path()),
+    ];
+
+    let mut im = ImEngine::new();
+    ReadReq::new(true)
+        .set_attr_requests(&paths)
+        .to_tlv(&mut tw, TagType::Anonymous)
+        .unwrap();
+    let read_len = im.process(
+        &ImInput::new(OpCode::ReadRequest, wb.as_borrow_slice()),
+        &mut read_out,
+    );
+
+    wb.reset(0);
+    let mut tw = TLVWriter::new(&mut wb);
+    SubscribeReq::new(true, 0, MAX_INTERVAL_CEIL)
+        .set_attr_requests(&paths)
+        .to_tlv(&mut tw, TagType::Anonymous)
+        .unwrap();
+    // On the same node, so that the clusters report the same data versions
+    let priming_len = im.process(
+        &ImInput::new(OpCode::SubscribeRequest, wb.as_borrow_slice()),
+        &mut priming_out,
+    );
+    let read = &read_out[..read_len];
+    let priming = &priming_out[..priming_len];
+
+    let subs_id = tlv::get_root_node_struct(priming)
+        .unwrap()
+        .find_tag(msg::ReportDataTag::SubscriptionId as u32)
+        .unwrap()
+        .u32()
+        .unwrap();
+    let mut subs_id_buf = [0u8; 8];
+    let subs_id_len = subs_id_buf.len();
+    let mut subs_id_wb = WriteBuf::new(&mut subs_id_buf, subs_id_len);
+    TLVWriter::new(&mut subs_id_wb)
+        .u32(
+            TagType::Context(msg::ReportDataTag::SubscriptionId as u8),
+            subs_id,
+        )
+        .unwrap();
+    let subs_id_tlv = subs_id_wb.as_borrow_slice();
+
+    // The priming report is the read report, with the SubscriptionId ahead of the
+    // AttributeReports, and with the SupressResponse flag clear. What differs is the
+    // struct start, and the flag along with the struct end.
+    let (start, end) = (1, 3);
+    assert_eq!(priming.len(), read.len() + subs_id_tlv.len());
+    assert_eq!(priming[..start], read[..start]);
+    assert_eq!(&priming[start..start + subs_id_tlv.len()], subs_id_tlv);
+    assert_eq!(
+        priming[start + subs_id_tlv.len()..priming.len() - end],
+        read[start..read.len() - end]
+    );
+    let flag = |report: &[u8]| {
+        tlv::get_root_node_struct(report)
+            .unwrap()
+            .find_tag(msg::ReportDataTag::SupressResponse as u32)
+            .unwrap()
+            .bool()
+            .unwrap()
+    };
+    assert!(flag(read));
+    assert!(!flag(priming));
+}
+
+#[test]
+fn test_subscribe_chunked() {
+    // The attributes of the whole node don't fit in a single report
+    // - the priming report goes out in chunks, the SubscribeResponse follows the last one
+    // - so does the report at the max interval, its exchange is done with the last chunk
+    let _ = env_logger::try_init();
+    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut out_buf = [0u8; MAX_TX_BUF_SIZE];
+
+    let mut im = ImEngine::new_chunked();
+    let paths = [AttrPath::new(&GenericPath::new(None, None, None))];
+    SubscribeReq::new(true, 0, MAX_INTERVAL_CEIL)
+        .set_attr_requests(&paths)
+        .to_tlv(&mut tw, TagType::Anonymous)
+        .unwrap();
+    let out_buf_len = im.process(
+        &ImInput::new(OpCode::SubscribeRequest, wb.as_borrow_slice()),
+        &mut out_buf,
+    );
+    let (subs_id, chunks, out_buf_len) = ack_chunks(&mut im, &mut out_buf, out_buf_len);
+    assert!(chunks > 1);
+    let root = tlv::get_root_node_struct(&out_buf[..out_buf_len]).unwrap();
+    let resp = SubscribeResp::from_tlv(&root).unwrap();
+    assert_eq!(resp, SubscribeResp::new(subs_id, MAX_INTERVAL_CEIL));
+
+    let now = SystemTime::now() + Duration::from_secs(MAX_INTERVAL_CEIL as u64);
+    let out_buf_len = im.report_subscription(subs_id, now, &mut out_buf).unwrap();
+    let report = ack_chunks(&mut im, &mut out_buf, out_buf_len);
+    assert_eq!(report, (subs_id, chunks, 0));
+}
+
+// A network that keeps the messages that are sent
+struct SentMessages(Rc<RefCell<Vec<Vec<u8>>>>);
+
+impl NetworkInterface for SentMessages {
+    fn recv(&self, _in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+        Err(Error::Network)
+    }
+
+    fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+        self.0.borrow_mut().push(out_buf.to_vec());
+        Ok(out_buf.len())
+    }
+}
+
+#[test]
+fn test_subscription_report_on_tick() {
+    // Without any change, the tick sends a report once the max interval has elapsed, on a
+    // new exchange with the subscriber
+    let _ = env_logger::try_init();
+    let mut im = ImEngine::new();
+    let subs_id = subscribe(&mut im);
+    let start = SystemTime::now();
+    let max_int = Duration::from_secs(MAX_INTERVAL_CEIL as u64);
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let mut sess_mgr = SessionMgr::new();
+    sess_mgr
+        .add_network_interface(Box::new(SentMessages(sent.clone())))
+        .unwrap();
+    let clone_data = CloneData::new(
+        123456,
+        IM_ENGINE_PEER_ID,
+        10,
+        30,
+        Address::default(),
+        SessionMode::Case(1),
+    );
+    let sess_idx = sess_mgr.clone_session(&clone_data).unwrap();
+    let mut exch_mgr = ExchangeMgr::new(sess_mgr);
+
+    let next = im.im.handle_tick(start, &mut exch_mgr).unwrap();
+    assert!(sent.borrow().is_empty());
+    assert!(next > start && next <= start + max_int);
+
+    assert_eq!(im.im.handle_tick(next, &mut exch_mgr), Some(next + max_int));
+    assert_eq!(sent.borrow().len(), 1);
+    assert_eq!(exch_mgr.exchange_count(sess_idx), 1);
+
+    let msg = &sent.borrow()[0];
+    let mut rx = Packet::new_rx().unwrap();
+    rx.as_borrow_slice()[..msg.len()].copy_from_slice(msg);
+    rx.get_parsebuf().unwrap().set_len(msg.len());
+    rx.plain_hdr_decode().unwrap();
+    rx.proto_decode(123456, Some(&clone_data.enc_key)).unwrap();
+    assert!(rx.proto.is_initiator());
+    assert_eq!(rx.get_proto_opcode(), OpCode::ReportData as u8);
+    assert_eq!(parse_report(rx.as_borrow_slice()), (subs_id, 0));
+
+    // Once the subscriber has no session left, the subscription is dropped
+    let mut exch_mgr = ExchangeMgr::new(SessionMgr::new());
+    assert_eq!(im.im.handle_tick(next + max_int, &mut exch_mgr), None);
+    assert!(im.im.get_subscription(subs_id).is_none());
+}
@@ -4,4 +4,9 @@ mod data_model {
     mod acl;
     mod attributes;
     mod commands;
+    mod commissioning;
+    mod descriptor;
+    mod level_control;
+    mod operational_credentials;
+    mod subscribe;
 }
@@ -0,0 +1,5 @@
+#[test]
+fn test_sequential_tag_overflow() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/tlv_tag_overflow.rs");
+}
@@ -0,0 +1,87 @@
+use matter::{
+    cert::{test_vectors, Cert},
+    error::Error,
+    fabric::FabricMgr,
+    test_utils::MemKvStore,
+    transport::{
+        diag::TransportDiag,
+        mgr::Mgr,
+        network::{Address, NetworkInterface},
+        queue::{Msg, WorkQ},
+        session::{CloneData, SessionMode},
+    },
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+// The fabrics tell the transport about their updates through the process-wide work queue,
+// so these tests are a binary of their own
+#[path = "common/fabric.rs"]
+mod fabric;
+
+// A network that never receives anything
+struct NoNetwork;
+
+impl NetworkInterface for NoNetwork {
+    fn recv(&self, _in_buf: &mut [u8]) -> Result<(usize, Address), Error> {
+        Err(Error::Network)
+    }
+
+    fn recv_timeout(
+        &self,
+        _in_buf: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<Option<(usize, Address)>, Error> {
+        Ok(None)
+    }
+
+    fn send(&self, out_buf: &[u8], _addr: Address) -> Result<usize, Error> {
+        Ok(out_buf.len())
+    }
+}
+
+// Process the queued messages, and return the number of sessions in use after that
+fn sessions_in_use(mgr: &mut Mgr, diag: &TransportDiag, msgs: usize) -> usize {
+    for _ in 0..msgs {
+        mgr.poll(Some(SystemTime::now()));
+    }
+    mgr.tick(SystemTime::now());
+    diag.sessions_in_use()
+}
+
+#[test]
+fn test_update_noc_evicts_sessions() {
+    let _ = env_logger::try_init();
+    let mut mgr = Mgr::new_with_network(Box::new(NoNetwork)).unwrap();
+    let diag = Arc::new(TransportDiag::new());
+    mgr.set_diag(diag.clone());
+
+    let fabric_mgr =
+        FabricMgr::new_with_store(Arc::new(Mutex::new(MemKvStore::default()))).unwrap();
+    let fab_idx = fabric_mgr.add(fabric::test_fabric(0xfff1)).unwrap();
+
+    // A CASE session established under the current NOC, and one on another fabric
+    let q = WorkQ::get().unwrap();
+    for (sess_id, mode) in [(1, SessionMode::Case(fab_idx)), (2, SessionMode::Case(2))] {
+        let clone_data =
+            CloneData::new(0xbc5c02, 0x1234, sess_id, sess_id, Address::default(), mode);
+        q.sync_send(Msg::NewSession(clone_data)).unwrap();
+    }
+    assert_eq!(sessions_in_use(&mut mgr, &diag, 2), 2);
+
+    // The same NOC, but for another node ID
+    let mut noc = test_vectors::NOC1_SUCCESS;
+    noc[33] = 0x3;
+    fabric_mgr
+        .update_noc(
+            fab_idx as usize,
+            Cert::new(&noc).unwrap(),
+            Some(Cert::new(&test_vectors::ICAC1_SUCCESS).unwrap()),
+        )
+        .unwrap();
+
+    // Only the session under the earlier NOC is evicted
+    assert_eq!(sessions_in_use(&mut mgr, &diag, 1), 1);
+}
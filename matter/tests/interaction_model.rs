@@ -2,8 +2,8 @@ use boxslab::Slab;
 use matter::error::Error;
 use matter::interaction_model::core::OpCode;
 use matter::interaction_model::messages::msg::InvReq;
-use matter::interaction_model::messages::msg::ReadReq;
 use matter::interaction_model::messages::msg::WriteReq;
+use matter::interaction_model::read::ReadState;
 use matter::interaction_model::InteractionConsumer;
 use matter::interaction_model::InteractionModel;
 use matter::interaction_model::Transaction;
@@ -74,9 +74,18 @@ impl InteractionConsumer for DataModel {
         Ok(())
     }
 
+    fn consume_group_invoke_cmd(
+        &self,
+        _group_id: u16,
+        _req: &InvReq,
+        _trans: &mut Transaction,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn consume_read_attr(
         &self,
-        _req: &ReadReq,
+        _state: &mut ReadState,
         _trans: &mut Transaction,
         _tlvwriter: &mut TLVWriter,
     ) -> Result<(), Error> {
@@ -94,6 +103,15 @@ impl InteractionConsumer for DataModel {
 }
 
 fn handle_data(action: OpCode, data_in: &[u8], data_out: &mut [u8]) -> (DataModel, usize) {
+    let (data_model, result) = try_handle_data(action, data_in, data_out);
+    (data_model, result.unwrap())
+}
+
+fn try_handle_data(
+    action: OpCode,
+    data_in: &[u8],
+    data_out: &mut [u8],
+) -> (DataModel, Result<usize, Error>) {
     let data_model = DataModel::new(Node {
         endpoint: 0,
         cluster: 0,
@@ -128,14 +146,16 @@ fn handle_data(action: OpCode, data_in: &[u8], data_out: &mut [u8]) -> (DataMode
     let in_data_len = data_in.len();
     let rx_buf = rx.as_borrow_slice();
     rx_buf[..in_data_len].copy_from_slice(data_in);
+    rx.get_parsebuf().unwrap().set_len(in_data_len);
 
     let mut ctx = ProtoCtx::new(exch_ctx, rx, tx);
 
-    interaction_model.handle_proto_id(&mut ctx).unwrap();
-
-    let out_len = ctx.tx.as_borrow_slice().len();
-    data_out[..out_len].copy_from_slice(ctx.tx.as_borrow_slice());
-    (data_model, out_len)
+    let result = interaction_model.handle_proto_id(&mut ctx).map(|_| {
+        let out_len = ctx.tx.as_borrow_slice().len();
+        data_out[..out_len].copy_from_slice(ctx.tx.as_borrow_slice());
+        out_len
+    });
+    (data_model, result)
 }
 
 #[test]
@@ -157,3 +177,14 @@ fn test_valid_invoke_cmd() -> Result<(), Error> {
     assert_eq!(data.variable, 5);
     Ok(())
 }
+
+#[test]
+fn test_truncated_read_req() {
+    let mut out_buf: [u8; 20] = [0; 20];
+
+    let (_, result) = try_handle_data(OpCode::ReadRequest, &[], &mut out_buf);
+    assert_eq!(result, Err(Error::TruncatedPacket));
+
+    let (_, result) = try_handle_data(OpCode::ReadRequest, &[0x15], &mut out_buf);
+    assert!(result.is_err());
+}
@@ -0,0 +1,150 @@
+use matter::{
+    acl::{AclEntry, AuthMode},
+    data_model::objects::{Privilege, ATTRS_PER_CLUSTER},
+    interaction_model::{
+        messages::{ib::AttrPath, msg::ReadReq, GenericPath},
+        read::ReadState,
+        InteractionConsumer, Transaction,
+    },
+    test_utils::{MatterNode, MatterNodeBuilder},
+    tlv::{self, TLVWriter, TagType},
+    utils::writebuf::WriteBuf,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+// The counting allocator replaces the allocator of the whole test binary, so these tests
+// are a binary of their own
+#[path = "common/many_attrs_cluster.rs"]
+mod many_attrs_cluster;
+
+use many_attrs_cluster::ManyAttrsCluster;
+
+// Counts the allocations of the threads that enabled counting, the tests run in parallel
+struct CountingAlloc;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_alloc() {
+    if COUNTING.try_with(|c| c.get()).unwrap_or(false) {
+        let _ = ALLOCS.try_with(|a| a.set(a.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_alloc();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_alloc();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// The allocations made by 'f'
+fn count_allocs<F: FnOnce()>(f: F) -> usize {
+    ALLOCS.with(|a| a.set(0));
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCS.with(|a| a.get())
+}
+
+const FIRST_CLUSTER: u32 = 0xfc00;
+// The root endpoint has room for one more cluster, and two new endpoints for six each
+const CLUSTERS: u32 = 13;
+const PEER_ID: u64 = 112233;
+
+fn node_with_attrs() -> MatterNode {
+    let mut acl = AclEntry::new(1, Privilege::ADMIN, AuthMode::Case);
+    acl.add_subject(PEER_ID).unwrap();
+    let matter = MatterNodeBuilder::new().acl(acl).build().unwrap();
+    {
+        let mut node = matter.dm.node.write().unwrap();
+        let (ep1, ep2) = (node.add_endpoint().unwrap(), node.add_endpoint().unwrap());
+        for i in 0..CLUSTERS {
+            let endpoint = match i {
+                0 => 0,
+                1..=6 => ep1,
+                _ => ep2,
+            };
+            node.add_cluster(endpoint, ManyAttrsCluster::new(FIRST_CLUSTER + i))
+                .unwrap();
+        }
+    }
+    matter
+}
+
+// Read 'paths', returning the number of attributes reported and the allocations made
+fn read(matter: &MatterNode, paths: &[AttrPath]) -> (usize, usize) {
+    let read_req = ReadReq::new(true).set_attr_requests(paths);
+    let mut state = ReadState::from_req(&read_req);
+    let mut buf = vec![0u8; 8192];
+    let buf_len = buf.len();
+    let mut wb = WriteBuf::new(&mut buf, buf_len);
+    let mut tw = TLVWriter::new(&mut wb);
+    let mut sess = matter.session(1, PEER_ID);
+    let mut trans = Transaction::new(&mut sess);
+
+    // The reports are encoded into the AttributeReports array of the ReportData
+    tw.start_array(TagType::Anonymous).unwrap();
+    let allocs = count_allocs(|| {
+        matter
+            .dm
+            .consume_read_attr(&mut state, &mut trans, &mut tw)
+            .unwrap()
+    });
+    tw.end_container().unwrap();
+
+    let len = wb.as_borrow_slice().len();
+    let reports = tlv::get_root_node(&buf[..len]).unwrap();
+    let count = reports.confirm_array().unwrap().iter().unwrap().count();
+    (count, allocs)
+}
+
+#[test]
+fn test_read_many_attrs_allocs() {
+    let matter = node_with_attrs();
+
+    let one = [AttrPath::new(&GenericPath::new(
+        None,
+        Some(FIRST_CLUSTER),
+        Some(0),
+    ))];
+    let (count, allocs_one) = read(&matter, &one);
+    assert_eq!(count, 1);
+
+    let all: Vec<AttrPath> = (0..CLUSTERS)
+        .map(|i| AttrPath::new(&GenericPath::new(None, Some(FIRST_CLUSTER + i), None)))
+        .collect();
+    let (count, allocs_all) = read(&matter, &all);
+    assert_eq!(count, CLUSTERS as usize * ATTRS_PER_CLUSTER);
+    assert!(count >= 100);
+
+    // Reading more attributes doesn't take any more allocations
+    assert_eq!(allocs_all, allocs_one);
+
+    // The attributes that were reported are kept track of only once there are some
+    let none = [AttrPath::new(&GenericPath::new(
+        Some(0),
+        Some(FIRST_CLUSTER),
+        Some(0xfff0),
+    ))];
+    let (count, allocs_none) = read(&matter, &none);
+    assert_eq!(count, 1);
+    assert!(allocs_none < allocs_one);
+}
@@ -0,0 +1,15 @@
+use matter::tlv::{FromTLV, ToTLV};
+
+// The sixth field would get the tag 255
+#[derive(ToTLV, FromTLV)]
+#[tlvargs(start = 250)]
+struct TooManyFields {
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+}
+
+fn main() {}
@@ -5,7 +5,7 @@ use syn::Lit::{Int, Str};
 use syn::NestedMeta::{Meta, Lit};
 use syn::{parse_macro_input, DeriveInput, Lifetime};
 use syn::{
-    Meta::{List, NameValue},
+    Meta::{List, NameValue, Path},
     MetaList, MetaNameValue, Type,
 };
 
@@ -39,7 +39,11 @@ fn parse_tlvargs(ast: &DeriveInput) -> TlvArgs {
         {
             if path.is_ident("tlvargs") {
                 for a in nested {
-                    if let Meta(NameValue(MetaNameValue {
+                    if let Meta(Path(key_path)) = &a {
+                        if key_path.is_ident("unordered") {
+                            tlvargs.unordered = true;
+                        }
+                    } else if let Meta(NameValue(MetaNameValue {
                         path: key_path,
                         eq_token: _,
                         lit: key_val,
@@ -58,8 +62,6 @@ fn parse_tlvargs(ast: &DeriveInput) -> TlvArgs {
                             if let Str(litstr) = key_val {
                                 tlvargs.datatype = litstr.value();
                             }
-                        } else if key_path.is_ident("unordered") {
-                            tlvargs.unordered = true;
                         }
                     }
                 }
@@ -69,13 +71,16 @@ fn parse_tlvargs(ast: &DeriveInput) -> TlvArgs {
     tlvargs
 }
 
+// The tag of the FabricIndex global element, in fabric-scoped structures
+const FABRIC_INDEX_TAG: u8 = 0xFE;
+
 fn parse_tag_val(field: &syn::Field) -> Option<u8> {
-    if field.attrs.len() > 0 {
-        if let List(MetaList {
+    for attr in field.attrs.iter() {
+        if let Ok(List(MetaList {
             path,
             paren_token: _,
             nested,
-        }) = field.attrs[0].parse_meta().unwrap()
+        })) = attr.parse_meta()
         {
             if path.is_ident("tagval") {
                 for a in nested {
@@ -86,9 +91,62 @@ fn parse_tag_val(field: &syn::Field) -> Option<u8> {
             }
         }
     }
+    if is_fabric_index(field) {
+        return Some(FABRIC_INDEX_TAG);
+    }
     None
+}
+
+// The highest tag that the fields are numbered up to, the tags above it are reserved
+const MAX_SEQUENTIAL_TAG: u8 = 254;
 
+// The tags of the fields, sequentially from 'start' for the fields that don't have a
+// tagval of their own. A field whose sequential tag would go past MAX_SEQUENTIAL_TAG
+// is an error, rather than having its tag wrap around.
+fn field_tags(fields: &syn::FieldsNamed, start: u8) -> Result<Vec<u8>, syn::Error> {
+    let mut tag_start = start as u16;
+    let mut tags = Vec::new();
+    for field in fields.named.iter() {
+        if let Some(a) = parse_tag_val(field) {
+            // TODO: The current limitation with this is that a hard-coded integer
+            // value has to be mentioned in the tagval attribute. This is because
+            // our tags vector is for integers, and pushing an 'identifier' on it
+            // wouldn't work.
+            tags.push(a);
+        } else {
+            if tag_start > MAX_SEQUENTIAL_TAG as u16 {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!(
+                        "the sequential tag {} of this field exceeds the maximum of {}",
+                        tag_start, MAX_SEQUENTIAL_TAG
+                    ),
+                ));
+            }
+            tags.push(tag_start as u8);
+            tag_start += 1;
+        }
+    }
+    Ok(tags)
+}
 
+// Whether the field is marked as the FabricIndex of the structure, with #[tlv(fabric_index)]
+fn is_fabric_index(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|attr| {
+        if let Ok(List(MetaList {
+            path,
+            paren_token: _,
+            nested,
+        })) = attr.parse_meta()
+        {
+            path.is_ident("tlv")
+                && nested
+                    .iter()
+                    .any(|a| matches!(a, Meta(Path(p)) if p.is_ident("fabric_index")))
+        } else {
+            false
+        }
+    })
 }
 
 /// Derive ToTLV Macro
@@ -101,7 +159,8 @@ fn parse_tag_val(field: &syn::Field) -> Option<u8> {
 ///  #[tlvargs(start = 1, datatype = "list")]
 ///
 /// start: This can be used to override the default tag from which the
-///        encoding starts (Default: 0)
+///        encoding starts (Default: 0). The sequential tags can't go past
+///        254, a structure that would need more is a compile error.
 /// datatype: This can be used to define whether this data structure is
 ///        to be encoded as a structure or list. Possible values: list
 ///        (Default: struct)
@@ -113,14 +172,19 @@ fn parse_tag_val(field: &syn::Field) -> Option<u8> {
 ///  name: u8,
 /// In the above case, the 'name' attribute will be encoded/decoded with
 /// the tag 22
+///
+/// The FabricIndex of a fabric-scoped structure is marked with the tlv
+/// attribute, it is encoded with the FabricIndex tag (0xFE)
+/// For example:
+///  #[tlv(fabric_index)]
+///  fab_idx: u8,
 
-#[proc_macro_derive(ToTLV, attributes(tlvargs, tagval))]
+#[proc_macro_derive(ToTLV, attributes(tlvargs, tagval, tlv))]
 pub fn derive_totlv(item: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(item as DeriveInput);
     let struct_name = &ast.ident;
 
     let tlvargs = parse_tlvargs(&ast);
-    let mut tag_start = tlvargs.start;
     let datatype = format_ident!("start_{}", tlvargs.datatype);
 
     let generics = ast.generics;
@@ -135,8 +199,11 @@ pub fn derive_totlv(item: TokenStream) -> TokenStream {
         panic!("Derive ToTLV - Only supported Struct for now")
     };
 
+    let tags = match field_tags(fields, tlvargs.start) {
+        Ok(tags) => tags,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let mut idents = Vec::new();
-    let mut tags = Vec::new();
 
     for field in fields.named.iter() {
         //        let field_name: &syn::Ident = field.ident.as_ref().unwrap();
@@ -146,12 +213,6 @@ pub fn derive_totlv(item: TokenStream) -> TokenStream {
         //        keys.push(quote! { #literal_key_str });
         idents.push(&field.ident);
         //        types.push(type_name.to_token_stream());
-        if let Some(a) = parse_tag_val(&field) {
-            tags.push(a);
-        } else {
-            tags.push(tag_start);
-            tag_start += 1;
-        }
     }
 
     let expanded = quote! {
@@ -179,7 +240,8 @@ pub fn derive_totlv(item: TokenStream) -> TokenStream {
 ///  #[tlvargs(lifetime = "'a", start = 1, datatype = "list", unordered)]
 ///
 /// start: This can be used to override the default tag from which the
-///        decoding starts (Default: 0)
+///        decoding starts (Default: 0). The sequential tags can't go past
+///        254, a structure that would need more is a compile error.
 /// datatype: This can be used to define whether this data structure is
 ///        to be decoded as a structure or list. Possible values: list
 ///        (Default: struct)
@@ -189,6 +251,10 @@ pub fn derive_totlv(item: TokenStream) -> TokenStream {
 /// unordered: By default, the decoder expects that the tags are in
 ///        sequentially increasing order. Set this if that is not the case.
 ///
+/// Any context tags that are not part of the structure (for example,
+/// fields added by a newer revision of the spec) are skipped over in
+/// both modes, as required for forward compatibility.
+///
 /// Additionally, structure members can use the tagval attribute to
 /// define a specific tag to be used
 /// For example:
@@ -196,14 +262,21 @@ pub fn derive_totlv(item: TokenStream) -> TokenStream {
 ///  name: u8,
 /// In the above case, the 'name' attribute will be encoded/decoded with
 /// the tag 22
+///
+/// The FabricIndex of a fabric-scoped structure is marked with the tlv
+/// attribute, it is decoded from the FabricIndex tag (0xFE). Clients may
+/// leave it out of writes, in which case it is decoded as 0, the write path
+/// populates it with the accessor's fabric index anyway.
+/// For example:
+///  #[tlv(fabric_index)]
+///  fab_idx: u8,
 
-#[proc_macro_derive(FromTLV, attributes(tlvargs, tagval))]
+#[proc_macro_derive(FromTLV, attributes(tlvargs, tagval, tlv))]
 pub fn derive_fromtlv(item: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(item as DeriveInput);
     let struct_name = &ast.ident;
 
     let tlvargs = parse_tlvargs(&ast);
-    let mut tag_start = tlvargs.start;
     let lifetime = tlvargs.lifetime;
     let datatype = format_ident!("confirm_{}", tlvargs.datatype);
 
@@ -219,26 +292,26 @@ pub fn derive_fromtlv(item: TokenStream) -> TokenStream {
         panic!("Derive FromTLV - Only supported Struct for now")
     };
 
+    let tags = match field_tags(fields, tlvargs.start) {
+        Ok(tags) => tags,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let mut idents = Vec::new();
     let mut types = Vec::new();
-    let mut tags = Vec::new();
+    let mut not_found = Vec::new();
 
     for field in fields.named.iter() {
         let type_name = &field.ty;
-        if let Some(a) = parse_tag_val(&field) {
-            // TODO: The current limitation with this is that a hard-coded integer
-            // value has to be mentioned in the tagval attribute. This is because
-            // our tags vector is for integers, and pushing an 'identifier' on it
-            // wouldn't work.
-            tags.push(a);
-        } else {
-            tags.push(tag_start);
-            tag_start += 1;
-        }
         idents.push(&field.ident);
 
         if let Type::Path(path) = type_name {
-            types.push(&path.path.segments[0].ident);
+            let ty = &path.path.segments[0].ident;
+            types.push(ty);
+            if is_fabric_index(field) {
+                not_found.push(quote! { Ok(#ty::default()) });
+            } else {
+                not_found.push(quote! { #ty::tlv_not_found() });
+            }
         } else {
             panic!("Don't know what to do {:?}", type_name);
         }
@@ -255,14 +328,22 @@ pub fn derive_fromtlv(item: TokenStream) -> TokenStream {
                    let mut t_iter = t.#datatype ()?.iter().ok_or(Error::Invalid)?;
                    let mut item = t_iter.next();
                    #(
+                       // Skip over any unknown tags that precede this one
+                       while let Some(Some(tag)) = item.map(|x| x.get_ctx_tag()) {
+                           if tag >= #tags {
+                               break;
+                           }
+                           item = t_iter.next();
+                       }
                        let #idents = if Some(true) == item.map(|x| x.check_ctx_tag(#tags)) {
                            let backup = item;
                            item = t_iter.next();
                            #types::from_tlv(&backup.unwrap())
                        } else {
-                           #types::tlv_not_found()
+                           #not_found
                        }?;
                    )*
+                   // Whatever is left over are unknown trailing tags, ignore them
                    Ok(Self {
                        #(#idents,
                        )*
@@ -278,7 +359,7 @@ pub fn derive_fromtlv(item: TokenStream) -> TokenStream {
                        let #idents = if let Ok(s) = t.find_tag(#tags as u32) {
                            #types::from_tlv(&s)
                        } else {
-                           #types::tlv_not_found()
+                           #not_found
                        }?;
                    )*
 
@@ -293,3 +374,49 @@ pub fn derive_fromtlv(item: TokenStream) -> TokenStream {
     //        panic!("The generated code is {}", expanded);
     expanded.into()
 }
+
+/// Derive FabricScoped Macro
+///
+/// This macro works for fabric-scoped structures. It will create an
+/// implementation of the FabricScoped trait for that structure, that
+/// accesses the member that is marked as the FabricIndex
+/// For example:
+///  #[tlv(fabric_index)]
+///  fab_idx: u8,
+
+#[proc_macro_derive(FabricScoped, attributes(tlv))]
+pub fn derive_fabric_scoped(item: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(item as DeriveInput);
+    let struct_name = &ast.ident;
+    let generics = ast.generics;
+
+    let fields = if let syn::Data::Struct(syn::DataStruct {
+        fields: syn::Fields::Named(ref fields),
+        ..
+    }) = ast.data
+    {
+        fields
+    } else {
+        panic!("Derive FabricScoped - Only supported Struct for now")
+    };
+
+    let ident = fields
+        .named
+        .iter()
+        .find(|f| is_fabric_index(f))
+        .map(|f| &f.ident)
+        .expect("Derive FabricScoped - No member is marked with #[tlv(fabric_index)]");
+
+    let expanded = quote! {
+        impl #generics FabricScoped for #struct_name #generics {
+            fn get_fab_idx(&self) -> u8 {
+                self.#ident
+            }
+
+            fn set_fab_idx(&mut self, fab_idx: u8) {
+                self.#ident = fab_idx;
+            }
+        }
+    };
+    expanded.into()
+}